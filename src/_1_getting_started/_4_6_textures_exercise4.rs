@@ -2,6 +2,7 @@ use std::ffi::CString;
 use std::mem;
 use gl::types::*;
 use glfw::{Action, Context, Key};
+use crate::gl_debug::{self, gl_check};
 use crate::shader::Shader;
 
 // settings
@@ -16,6 +17,8 @@ pub fn main_1_4_6() {
     glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
     #[cfg(target_os = "macos")]
     glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+    // GL errors are reported in debug builds, see gl_debug
+    gl_debug::request_debug_context(&mut glfw);
 
     // glfw window creation
     // --------------------
@@ -29,6 +32,7 @@ pub fn main_1_4_6() {
     // gl: load all OpenGL function pointers
     // ---------------------------------------
     gl::load_with(|s| glfw.get_proc_address_raw(s));
+    gl_debug::init();
 
     // build and compile our shader program
     // ------------------------------------
@@ -111,10 +115,11 @@ pub fn main_1_4_6() {
         .expect("Failed to load texture").flipv();
     let data = img.as_bytes();
     unsafe {
-        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as GLint, img.width() as GLsizei, img.height() as GLsizei,
-                       0, gl::RGB, gl::UNSIGNED_BYTE, data.as_ptr() as *const GLvoid);
+        gl_check!(gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as GLint, img.width() as GLsizei, img.height() as GLsizei,
+                                 0, gl::RGB, gl::UNSIGNED_BYTE, data.as_ptr() as *const GLvoid));
         gl::GenerateMipmap(gl::TEXTURE_2D);
     }
+    gl_debug::label(gl::TEXTURE, texture1, "container.jpg");
 
     // texture 2
     // ---------
@@ -134,10 +139,11 @@ pub fn main_1_4_6() {
     let data = img.as_bytes();
     unsafe {
         // note that the awesomeface.png has transparency and thus an alpha channel, so make sure to tell OpenGL the data type is of GL_RGBA
-        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as GLint, img.width() as GLsizei, img.height() as GLsizei,
-                       0, gl::RGBA, gl::UNSIGNED_BYTE, data.as_ptr() as *const GLvoid);
+        gl_check!(gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as GLint, img.width() as GLsizei, img.height() as GLsizei,
+                                 0, gl::RGBA, gl::UNSIGNED_BYTE, data.as_ptr() as *const GLvoid));
         gl::GenerateMipmap(gl::TEXTURE_2D);
     }
+    gl_debug::label(gl::TEXTURE, texture2, "awesomeface.png");
 
     // tell opengl for each sampler to which texture unit it belongs to (only has to be done once)
     // -------------------------------------------------------------------------------------------
@@ -297,12 +297,7 @@ pub fn main_1_7_3() {
                     pitch += offset_y;
 
                     // make sure that when pitch is out of bounds, screen doesn't get flipped
-                    if pitch > 89.0 {
-                        pitch = 89.0;
-                    }
-                    if pitch < -89.0 {
-                        pitch = -89.0;
-                    }
+                    pitch = pitch.clamp(-89.0, 89.0);
 
                     let front = glm::vec3(
                         yaw.to_radians().cos() * pitch.to_radians().cos(),
@@ -314,13 +309,7 @@ pub fn main_1_7_3() {
                 }
                 glfw::WindowEvent::Scroll(_offset_x, offset_y) => {
                     fov -= offset_y as f32;
-                    if fov < 1.0 {
-                        fov = 1.0;
-                    }
-
-                    if fov > 45.0 {
-                        fov = 45.0
-                    }
+                    fov = fov.clamp(1.0, 45.0);
                 }
                 glfw::WindowEvent::FramebufferSize(width, height) => {
                     // make sure the viewport matches the new window dimensions; note that width and
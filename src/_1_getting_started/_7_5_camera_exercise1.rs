@@ -5,6 +5,7 @@ use nalgebra_glm as glm;
 use crate::shader::Shader;
 use crate::camera::Camera;
 use crate::camera::CameraMovement;
+use crate::camera_path::CameraPathDriver;
 
 // settings
 const SCR_WIDTH: u32 = 800;
@@ -199,6 +200,9 @@ pub fn main_1_7_5() {
     let mut last_y = SCR_HEIGHT as f32 / 2.0;
     let mut first_mouse = true;
 
+    // camera path recording/playback, see CameraPathDriver
+    let mut camera_path = CameraPathDriver::from_args();
+
     // timing
     let mut delta_time; // time between current frame and last frame
     let mut last_frame = 0.0;
@@ -212,6 +216,10 @@ pub fn main_1_7_5() {
         delta_time = current_frame - last_frame;
         last_frame = current_frame;
 
+        if let Some(camera_path) = &mut camera_path {
+            camera_path.update(current_frame, &mut camera);
+        }
+
         unsafe {
             // render
             // ------
@@ -228,7 +236,7 @@ pub fn main_1_7_5() {
             shader.use_program();
 
             // pass projection matrix to shader (note that in this case it could change every frame)
-            let projection = glm::perspective(SCR_WIDTH as f32 / SCR_HEIGHT as f32, camera.zoom.to_radians(), 0.1, 100.0);
+            let projection = camera.get_projection_matrix();
             shader.set_mat4("projection", &projection);
 
             // camera/view transformation
@@ -294,6 +302,8 @@ pub fn main_1_7_5() {
                     camera.process_mouse_scroll(offset_y as f32);
                 }
                 glfw::WindowEvent::FramebufferSize(width, height) => {
+                    camera.process_framebuffer_size(width, height);
+
                     // make sure the viewport matches the new window dimensions; note that width and
                     // height will be significantly larger than specified on retina displays.
                     unsafe {
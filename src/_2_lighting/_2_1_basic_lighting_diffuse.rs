@@ -5,6 +5,7 @@ use nalgebra_glm as glm;
 use crate::shader::Shader;
 use crate::camera::Camera;
 use crate::camera::CameraMovement;
+use crate::camera_path::CameraPathDriver;
 
 // settings
 const SCR_WIDTH: u32 = 800;
@@ -151,6 +152,9 @@ pub fn main_2_2_1() {
     let mut last_y = SCR_HEIGHT as f32 / 2.0;
     let mut first_mouse = true;
 
+    // camera path recording/playback, see CameraPathDriver
+    let mut camera_path = CameraPathDriver::from_args();
+
     // timing
     let mut delta_time; // time between current frame and last frame
     let mut last_frame = 0.0;
@@ -167,6 +171,10 @@ pub fn main_2_2_1() {
         delta_time = current_frame - last_frame;
         last_frame = current_frame;
 
+        if let Some(camera_path) = &mut camera_path {
+            camera_path.update(current_frame, &mut camera);
+        }
+
         unsafe {
             // render
             // ------
@@ -180,7 +188,7 @@ pub fn main_2_2_1() {
             lighting_shader.set_vec3v("lightPos", &light_position);
 
             // view/projection transformations
-            let projection = glm::perspective(SCR_WIDTH as f32 / SCR_HEIGHT as f32, camera.zoom.to_radians(), 0.1, 100.0);
+            let projection = camera.get_projection_matrix();
             let view = camera.get_view_matrix();
             lighting_shader.set_mat4("projection", &projection);
             lighting_shader.set_mat4("view", &view);
@@ -250,6 +258,8 @@ pub fn main_2_2_1() {
                     camera.process_mouse_scroll(offset_y as f32);
                 }
                 glfw::WindowEvent::FramebufferSize(width, height) => {
+                    camera.process_framebuffer_size(width, height);
+
                     // make sure the viewport matches the new window dimensions; note that width and
                     // height will be significantly larger than specified on retina displays.
                     unsafe {
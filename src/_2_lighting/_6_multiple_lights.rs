@@ -6,11 +6,20 @@ use nalgebra_glm as glm;
 use crate::shader::Shader;
 use crate::camera::Camera;
 use crate::camera::CameraMovement;
+use crate::camera_path::CameraPathDriver;
+use crate::gl_debug;
+use crate::light::{DirLight, LightSet, LightsBlock, PointLight, SpotLight};
+use crate::profiler::{self, Profiler};
+use crate::uniform_buffer::{CameraMatrices, UniformBuffer};
 
 // settings
 const SCR_WIDTH: u32 = 800;
 const SCR_HEIGHT: u32 = 600;
 
+// uniform buffer binding points
+const MATRICES_BINDING: u32 = 0;
+const LIGHTS_BINDING: u32 = 1;
+
 pub fn main_2_6() {
     // glfw: initialize and configure
     // ------------------------------
@@ -19,6 +28,8 @@ pub fn main_2_6() {
     glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
     #[cfg(target_os = "macos")]
     glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+    // GL errors are reported in debug builds, see gl_debug
+    gl_debug::request_debug_context(&mut glfw);
 
     // glfw window creation
     // --------------------
@@ -35,6 +46,7 @@ pub fn main_2_6() {
     // gl: load all OpenGL function pointers
     // ---------------------------------------
     gl::load_with(|s| glfw.get_proc_address_raw(s));
+    gl_debug::init();
 
     // configure global opengl state
     // -----------------------------
@@ -43,7 +55,7 @@ pub fn main_2_6() {
     // build and compile our shader program
     // ------------------------------------
     let lighting_shader = match Shader::new( // you can name your shader files however you like
-                                             "src/_2_lighting/shaders/4.1.lighting_maps.vert",
+                                             "src/_2_lighting/shaders/6.multiple_lights.vert",
                                              "src/_2_lighting/shaders/6.multiple_lights.frag")
     {
         Ok(shader) => shader,
@@ -54,7 +66,7 @@ pub fn main_2_6() {
     };
 
     let light_cube_shader = match Shader::new( // you can name your shader files however you like
-                                               "src/_2_lighting/shaders/1.light_cube.vert",
+                                               "src/_2_lighting/shaders/6.light_cube.vert",
                                                "src/_2_lighting//shaders/1.light_cube.frag")
     {
         Ok(shader) => shader,
@@ -176,6 +188,22 @@ pub fn main_2_6() {
     lighting_shader.set_int("material.diffuse", 0);
     lighting_shader.set_int("material.specular", 1);
 
+    // uniform buffers: the camera matrices and the lights are shared by both shaders and uploaded once per frame
+    // ---------------------------------------------------------------------------------------------------------
+    let matrices_ubo = UniformBuffer::<CameraMatrices>::new(MATRICES_BINDING);
+    matrices_ubo.bind_to_shaders(&[&lighting_shader, &light_cube_shader]);
+
+    let lights_ubo = UniformBuffer::<LightsBlock>::new(LIGHTS_BINDING);
+    lights_ubo.bind_to_shaders(&[&lighting_shader]);
+
+    // lights
+    // ------
+    let mut lights = LightSet {
+        dir_lights: vec![DirLight::default()],
+        point_lights: point_light_positions.iter().map(|&position| PointLight { position, ..PointLight::with_range(50.0) }).collect(),
+        spot_lights: vec![SpotLight::default()],
+    };
+
     // camera
     let mut camera = Camera {
         position: glm::vec3(0.0, 0.0, 3.0),
@@ -185,6 +213,13 @@ pub fn main_2_6() {
     let mut last_y = SCR_HEIGHT as f32 / 2.0;
     let mut first_mouse = true;
 
+    // camera path recording/playback, see CameraPathDriver
+    let mut camera_path = CameraPathDriver::from_args();
+
+    // frame timings with --profile, see Profiler
+    let profiler = Profiler::from_args();
+    profiler.set_texture_memory(profiler::texture_memory(&[diffuse_map, specular_map]));
+
     // timing
     let mut delta_time; // time between current frame and last frame
     let mut last_frame = 0.0;
@@ -197,6 +232,11 @@ pub fn main_2_6() {
         let current_frame = glfw.get_time() as f32;
         delta_time = current_frame - last_frame;
         last_frame = current_frame;
+        profiler.begin_frame();
+
+        if let Some(camera_path) = &mut camera_path {
+            camera_path.update(current_frame, &mut camera);
+        }
 
         unsafe {
             // render
@@ -204,76 +244,33 @@ pub fn main_2_6() {
             gl::ClearColor(0.1, 0.1, 0.1, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
+            // view/projection transformations
+            let projection = camera.get_projection_matrix();
+            let view = camera.get_view_matrix();
+            matrices_ubo.update(&CameraMatrices { projection, view, view_pos: camera.position.into() });
+
             // be sure to activate shader when setting uniforms/drawing objects
             lighting_shader.use_program();
-            lighting_shader.set_vec3v("viewPos", &camera.position);
             lighting_shader.set_float("material.shininess", 32.0);
 
             /*
-            Here we set all the uniforms for the 5/6 types of lights we have. We have to set them manually and index
-            the proper PointLight struct in the array to set each uniform variable. This can be done more code-friendly
-            by defining light types as classes and set their values in there, or by using a more efficient uniform approach
-            by using 'Uniform buffer objects', but that is something we'll discuss in the 'Advanced GLSL' tutorial.
+            Here we set all the lights. Every light type is a Rust struct that knows its layout in the 'Uniform buffer
+            object' (see the 'Advanced GLSL' tutorial), so the whole set is uploaded at once. The spot light is the
+            flashlight of the camera and has to follow it every frame.
             */
-            // directional light
-            lighting_shader.set_vec3("dirLight.direction", -0.2, -1.0, -0.3);
-            lighting_shader.set_vec3("dirLight.ambient", 0.05, 0.05, 0.05);
-            lighting_shader.set_vec3("dirLight.diffuse", 0.4, 0.4, 0.4);
-            lighting_shader.set_vec3("dirLight.specular", 0.5, 0.5, 0.5);
-            // point light 1
-            lighting_shader.set_vec3v("pointLights[0].position", &point_light_positions[0]);
-            lighting_shader.set_vec3("pointLights[0].ambient", 0.05, 0.05, 0.05);
-            lighting_shader.set_vec3("pointLights[0].diffuse", 0.8, 0.8, 0.8);
-            lighting_shader.set_vec3("pointLights[0].specular", 1.0, 1.0, 1.0);
-            lighting_shader.set_float("pointLights[0].constant", 1.0);
-            lighting_shader.set_float("pointLights[0].linear", 0.09);
-            lighting_shader.set_float("pointLights[0].quadratic", 0.032);
-            // point light 2
-            lighting_shader.set_vec3v("pointLights[1].position", &point_light_positions[1]);
-            lighting_shader.set_vec3("pointLights[1].ambient", 0.05, 0.05, 0.05);
-            lighting_shader.set_vec3("pointLights[1].diffuse", 0.8, 0.8, 0.8);
-            lighting_shader.set_vec3("pointLights[1].specular", 1.0, 1.0, 1.0);
-            lighting_shader.set_float("pointLights[1].constant", 1.0);
-            lighting_shader.set_float("pointLights[1].linear", 0.09);
-            lighting_shader.set_float("pointLights[1].quadratic", 0.032);
-            // point light 3
-            lighting_shader.set_vec3v("pointLights[2].position", &point_light_positions[2]);
-            lighting_shader.set_vec3("pointLights[2].ambient", 0.05, 0.05, 0.05);
-            lighting_shader.set_vec3("pointLights[2].diffuse", 0.8, 0.8, 0.8);
-            lighting_shader.set_vec3("pointLights[2].specular", 1.0, 1.0, 1.0);
-            lighting_shader.set_float("pointLights[2].constant", 1.0);
-            lighting_shader.set_float("pointLights[2].linear", 0.09);
-            lighting_shader.set_float("pointLights[2].quadratic", 0.032);
-            // point light 4
-            lighting_shader.set_vec3v("pointLights[3].position", &point_light_positions[3]);
-            lighting_shader.set_vec3("pointLights[3].ambient", 0.05, 0.05, 0.05);
-            lighting_shader.set_vec3("pointLights[3].diffuse", 0.8, 0.8, 0.8);
-            lighting_shader.set_vec3("pointLights[3].specular", 1.0, 1.0, 1.0);
-            lighting_shader.set_float("pointLights[3].constant", 1.0);
-            lighting_shader.set_float("pointLights[3].linear", 0.09);
-            lighting_shader.set_float("pointLights[3].quadratic", 0.032);
-            // spotLight
-            lighting_shader.set_vec3v("spotLight.position", &camera.position);
-            lighting_shader.set_vec3v("spotLight.direction", &camera.front);
-            lighting_shader.set_vec3("spotLight.ambient", 0.0, 0.0, 0.0);
-            lighting_shader.set_vec3("spotLight.diffuse", 1.0, 1.0, 1.0);
-            lighting_shader.set_vec3("spotLight.specular", 1.0, 1.0, 1.0);
-            lighting_shader.set_float("spotLight.constant", 1.0);
-            lighting_shader.set_float("spotLight.linear", 0.09);
-            lighting_shader.set_float("spotLight.quadratic", 0.032);
-            lighting_shader.set_float("spotLight.cutOff", 12.5_f32.to_radians().cos());
-            lighting_shader.set_float("spotLight.outerCutOff",15.0_f32.to_radians().cos());
-
-            // view/projection transformations
-            let projection = glm::perspective(SCR_WIDTH as f32 / SCR_HEIGHT as f32, camera.zoom.to_radians(), 0.1, 100.0);
-            let view = camera.get_view_matrix();
-            lighting_shader.set_mat4("projection", &projection);
-            lighting_shader.set_mat4("view", &view);
+            lights.spot_lights[0].position = camera.position;
+            lights.spot_lights[0].direction = camera.front;
+            {
+                let _scope = profiler.scope("upload lights");
+                lights.upload(&lights_ubo);
+            }
 
             // world transformation
             let mut model: glm::Mat4 = glm::identity();
             lighting_shader.set_mat4("model", &model);
 
+            let containers = profiler.pass("containers");
+
             // bind diffuse map
             gl::ActiveTexture(gl::TEXTURE0);
             gl::BindTexture(gl::TEXTURE_2D, diffuse_map);
@@ -291,12 +288,13 @@ pub fn main_2_6() {
                 lighting_shader.set_mat4("model", &model);
 
                 gl::DrawArrays(gl::TRIANGLES, 0, 36);
+                profiler::record_draw(12);
             }
+            drop(containers);
 
             // also draw the lamp object(s)
+            let lamps = profiler.pass("lamps");
             light_cube_shader.use_program();
-            light_cube_shader.set_mat4("projection", &projection);
-            light_cube_shader.set_mat4("view", &view);
 
             // we now draw as many light bulbs as we have point lights.
             gl::BindVertexArray(light_cube_vao);
@@ -305,9 +303,15 @@ pub fn main_2_6() {
                 model = glm::scale(&model, &glm::vec3(0.2, 0.2, 0.2)); // Make it a smaller cube
                 light_cube_shader.set_mat4("model", &model);
                 gl::DrawArrays(gl::TRIANGLES, 0, 36);
+                profiler::record_draw(12);
             }
+            drop(lamps);
         }
 
+        // the timings of the passes, with --profile-overlay
+        let (width, height) = window.get_framebuffer_size();
+        profiler.draw_overlay(width, height);
+
         // glfw: swap buffers and poll IO events (keys pressed/released, mouse moved etc.)
         // -------------------------------------------------------------------------------
         window.swap_buffers();
@@ -353,6 +357,8 @@ pub fn main_2_6() {
                     camera.process_mouse_scroll(offset_y as f32);
                 }
                 glfw::WindowEvent::FramebufferSize(width, height) => {
+                    camera.process_framebuffer_size(width, height);
+
                     // make sure the viewport matches the new window dimensions; note that width and
                     // height will be significantly larger than specified on retina displays.
                     unsafe {
@@ -370,6 +376,8 @@ pub fn main_2_6() {
         gl::DeleteVertexArrays(1, &cube_vao);
         gl::DeleteVertexArrays(1, &light_cube_vao);
         gl::DeleteBuffers(1, &vbo);
+        gl::DeleteBuffers(1, &matrices_ubo.id);
+        gl::DeleteBuffers(1, &lights_ubo.id);
     }
 }
 
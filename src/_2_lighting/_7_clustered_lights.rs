@@ -0,0 +1,436 @@
+use std::mem;
+use gl::types::*;
+use glfw::{Action, Context, Key};
+use image::DynamicImage;
+use nalgebra_glm as glm;
+use crate::shader::Shader;
+use crate::camera::Camera;
+use crate::camera::CameraMovement;
+use crate::camera_path::CameraPathDriver;
+use crate::cluster::{ClusterGrid, ClusteredLights};
+use crate::light::{Attenuation, DirLight, LightSet, PointLight, SpotLight};
+use crate::uniform_buffer::{CameraMatrices, UniformBuffer};
+
+// settings
+const SCR_WIDTH: u32 = 800;
+const SCR_HEIGHT: u32 = 600;
+
+// uniform buffer binding points
+const MATRICES_BINDING: u32 = 0;
+
+// the number of point lights flying over the floor
+const NR_POINT_LIGHTS: usize = 1000;
+// the size of the floor, in containers
+const FLOOR_SIZE: i32 = 40;
+
+// the cluster grid: tiles along x and y, slices along the depth
+const CLUSTER_TILES_X: u32 = 16;
+const CLUSTER_TILES_Y: u32 = 9;
+const CLUSTER_SLICES: u32 = 24;
+
+pub fn main_2_7() {
+    // glfw: initialize and configure
+    // ------------------------------
+    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+    glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+    #[cfg(target_os = "macos")]
+    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+
+    // glfw window creation
+    // --------------------
+    let (mut window, events) = glfw
+        .create_window(SCR_WIDTH, SCR_HEIGHT, "LearnOpenGL", glfw::WindowMode::Windowed)
+        .expect("Failed to create GLFW window");
+    window.make_current();
+    window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_scroll_polling(true);
+    window.set_cursor_mode(glfw::CursorMode::Disabled);
+
+    // gl: load all OpenGL function pointers
+    // ---------------------------------------
+    gl::load_with(|s| glfw.get_proc_address_raw(s));
+
+    // configure global opengl state
+    // -----------------------------
+    unsafe { gl::Enable(gl::DEPTH_TEST) };
+
+    // build and compile our shader program
+    // ------------------------------------
+    let lighting_shader = match Shader::new( // you can name your shader files however you like
+                                             "src/_2_lighting/shaders/7.clustered_lights.vert",
+                                             "src/_2_lighting/shaders/7.clustered_lights.frag")
+    {
+        Ok(shader) => shader,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(-1);
+        }
+    };
+
+    let light_cube_shader = match Shader::new( // you can name your shader files however you like
+                                               "src/_2_lighting/shaders/6.light_cube.vert",
+                                               "src/_2_lighting/shaders/7.light_cube.frag")
+    {
+        Ok(shader) => shader,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(-1);
+        }
+    };
+
+    // set up vertex data (and buffer(s)) and configure vertex attributes
+    // ------------------------------------------------------------------
+    // Under macOS, the default type is 'f64', so we have to specific to 'f32'
+    let vertices:[f32;288] = [
+        // positions       // normals        // texture coords
+        -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  0.0,  0.0,
+         0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  1.0,  0.0,
+         0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  1.0,  1.0,
+         0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  1.0,  1.0,
+        -0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  0.0,  1.0,
+        -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  0.0,  0.0,
+
+        -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  0.0,  0.0,
+         0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  1.0,  0.0,
+         0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  1.0,  1.0,
+         0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  1.0,  1.0,
+        -0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  0.0,  1.0,
+        -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  0.0,  0.0,
+
+        -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,  1.0,  0.0,
+        -0.5,  0.5, -0.5, -1.0,  0.0,  0.0,  1.0,  1.0,
+        -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,  0.0,  1.0,
+        -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,  0.0,  1.0,
+        -0.5, -0.5,  0.5, -1.0,  0.0,  0.0,  0.0,  0.0,
+        -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,  1.0,  0.0,
+
+         0.5,  0.5,  0.5,  1.0,  0.0,  0.0,  1.0,  0.0,
+         0.5,  0.5, -0.5,  1.0,  0.0,  0.0,  1.0,  1.0,
+         0.5, -0.5, -0.5,  1.0,  0.0,  0.0,  0.0,  1.0,
+         0.5, -0.5, -0.5,  1.0,  0.0,  0.0,  0.0,  1.0,
+         0.5, -0.5,  0.5,  1.0,  0.0,  0.0,  0.0,  0.0,
+         0.5,  0.5,  0.5,  1.0,  0.0,  0.0,  1.0,  0.0,
+
+        -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  0.0,  1.0,
+         0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  1.0,  1.0,
+         0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  1.0,  0.0,
+         0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  1.0,  0.0,
+        -0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  0.0,  0.0,
+        -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  0.0,  1.0,
+
+        -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  0.0,  1.0,
+         0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  1.0,  1.0,
+         0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  1.0,  0.0,
+         0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  1.0,  0.0,
+        -0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  0.0,  0.0,
+        -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  0.0,  1.0,
+    ];
+
+    // positions of the containers: a floor with a few stacks of containers standing on it
+    let mut cube_positions: Vec<glm::Vec3> = Vec::new();
+    for x in -FLOOR_SIZE / 2..FLOOR_SIZE / 2 {
+        for z in -FLOOR_SIZE / 2..FLOOR_SIZE / 2 {
+            cube_positions.push(glm::vec3(x as f32, -1.0, z as f32));
+            if x % 6 == 0 && z % 6 == 0 {
+                for y in 0..3 {
+                    cube_positions.push(glm::vec3(x as f32, y as f32, z as f32));
+                }
+            }
+        }
+    }
+
+    // a small xorshift generator, returns values in [0, 1)
+    let mut seed = (glfw.get_time() * 1000.0) as u32 | 1;
+    let mut random = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        (seed % 10000) as f32 / 10000.0
+    };
+
+    // the point lights: small, colorful and spread over the whole floor, each bobbing up and down at its own phase
+    let half_size = FLOOR_SIZE as f32 / 2.0;
+    let mut point_light_positions: Vec<glm::Vec3> = Vec::with_capacity(NR_POINT_LIGHTS);
+    let mut point_light_phases: Vec<f32> = Vec::with_capacity(NR_POINT_LIGHTS);
+    let mut point_lights: Vec<PointLight> = Vec::with_capacity(NR_POINT_LIGHTS);
+    for _ in 0..NR_POINT_LIGHTS {
+        let position = glm::vec3(random() * 2.0 * half_size - half_size, random() * 1.5, random() * 2.0 * half_size - half_size);
+        let color = glm::vec3(random(), random(), random()).normalize() * 2.0;
+        point_light_positions.push(position);
+        point_light_phases.push(random() * std::f32::consts::TAU);
+        point_lights.push(PointLight {
+            position,
+            attenuation: Attenuation::InverseSquare { radius: 2.5 },
+            ambient: glm::vec3(0.0, 0.0, 0.0),
+            diffuse: color,
+            specular: color,
+        });
+    }
+
+    let (mut vbo, mut cube_vao, mut light_cube_vao): (GLuint, GLuint, GLuint) = (0, 0, 0);
+
+    unsafe {
+        // first, configure the cube's VAO (and VBO)
+        gl::GenVertexArrays(1, &mut cube_vao);
+        gl::GenBuffers(1, &mut vbo);
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(gl::ARRAY_BUFFER,
+                       (vertices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
+                       vertices.as_ptr() as *const GLvoid,
+                       gl::STATIC_DRAW);
+
+        gl::BindVertexArray(cube_vao);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, (8 * mem::size_of::<GLfloat>()) as GLsizei, std::ptr::null::<GLvoid>());
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, (8 * mem::size_of::<GLfloat>()) as GLsizei, (3 * mem::size_of::<GLfloat>()) as *const GLvoid);
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, (8 * mem::size_of::<GLfloat>()) as GLsizei, (6 * mem::size_of::<GLfloat>()) as *const GLvoid);
+        gl::EnableVertexAttribArray(2);
+
+        // second, configure the light's VAO (VBO stays the same; the vertices are the same for the light object which is also a 3D cube)
+        gl::GenVertexArrays(1, &mut light_cube_vao);
+        gl::BindVertexArray(light_cube_vao);
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        // note that we update the lamp's position attribute's stride to reflect the updated buffer data
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, (8 * mem::size_of::<GLfloat>()) as GLsizei, std::ptr::null::<GLvoid>());
+        gl::EnableVertexAttribArray(0);
+    }
+
+    // load textures (we now use a utility function to keep the code more organized)
+    // -----------------------------------------------------------------------------
+    let diffuse_map = load_texture("resources/textures/container2.png");
+    let specular_map = load_texture("resources/textures/container2_specular.png");
+
+    // shader configuration
+    // --------------------
+    lighting_shader.use_program();
+    lighting_shader.set_int("material.diffuse", 0);
+    lighting_shader.set_int("material.specular", 1);
+
+    // the camera matrices are shared by both shaders through a uniform buffer
+    let matrices_ubo = UniformBuffer::<CameraMatrices>::new(MATRICES_BINDING);
+    matrices_ubo.bind_to_shaders(&[&lighting_shader, &light_cube_shader]);
+
+    // lights: a dim directional light, the point lights and the flashlight of the camera
+    // -------------------------------------------------------------------------------------
+    let dir_light = DirLight {
+        ambient: glm::vec3(0.02, 0.02, 0.02),
+        diffuse: glm::vec3(0.05, 0.05, 0.05),
+        specular: glm::vec3(0.1, 0.1, 0.1),
+        ..DirLight::default()
+    };
+    let mut lights = LightSet {
+        dir_lights: Vec::new(),
+        point_lights,
+        spot_lights: vec![SpotLight::default()],
+    };
+
+    // camera
+    let mut camera = Camera {
+        position: glm::vec3(0.0, 2.0, 10.0),
+        ..Camera::default()
+    };
+
+    // the clusters have to be rebuilt whenever the projection changes, see the FramebufferSize event
+    let (mut screen_width, mut screen_height) = window.get_framebuffer_size();
+    let cluster_grid = |camera: &Camera| {
+        ClusterGrid::new(CLUSTER_TILES_X, CLUSTER_TILES_Y, CLUSTER_SLICES,
+                         camera.projection.near, camera.projection.far, &camera.get_projection_matrix())
+    };
+    let mut clustered_lights = ClusteredLights::new(cluster_grid(&camera));
+    let mut last_x = SCR_WIDTH as f32 / 2.0;
+    let mut last_y = SCR_HEIGHT as f32 / 2.0;
+    let mut first_mouse = true;
+
+    // camera path recording/playback, see CameraPathDriver
+    let mut camera_path = CameraPathDriver::from_args();
+
+    // timing
+    let mut delta_time; // time between current frame and last frame
+    let mut last_frame = 0.0;
+
+    // render loop
+    // -----------
+    while !window.should_close() {
+        // per-frame time logic
+        // --------------------
+        let current_frame = glfw.get_time() as f32;
+        delta_time = current_frame - last_frame;
+        last_frame = current_frame;
+
+        if let Some(camera_path) = &mut camera_path {
+            camera_path.update(current_frame, &mut camera);
+        }
+
+        unsafe {
+            // render
+            // ------
+            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+            // view/projection transformations
+            let projection = camera.get_projection_matrix();
+            let view = camera.get_view_matrix();
+            matrices_ubo.update(&CameraMatrices { projection, view, view_pos: camera.position.into() });
+
+            // move the lights and assign them to the clusters, the flashlight follows the camera
+            for ((light, position), phase) in lights.point_lights.iter_mut().zip(&point_light_positions).zip(&point_light_phases) {
+                light.position = position + glm::vec3(0.0, (current_frame + phase).sin() * 0.5, 0.0);
+            }
+            lights.spot_lights[0].position = camera.position;
+            lights.spot_lights[0].direction = camera.front;
+            clustered_lights.update(&view, &lights);
+
+            // be sure to activate shader when setting uniforms/drawing objects
+            lighting_shader.use_program();
+            lighting_shader.set_float("material.shininess", 32.0);
+            dir_light.set_uniforms(&lighting_shader, "dirLight");
+            clustered_lights.bind(&lighting_shader, 2, screen_width as f32, screen_height as f32);
+
+            // world transformation
+            let mut model: glm::Mat4 = glm::identity();
+            lighting_shader.set_mat4("model", &model);
+
+            // bind diffuse map
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, diffuse_map);
+            // bind specular map
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, specular_map);
+
+            // render containers
+            gl::BindVertexArray(cube_vao);
+            for cube_position in cube_positions.iter() {
+                // calculate the model matrix for each object and pass it to shader before drawing
+                let model = glm::translate(&glm::identity(), cube_position);
+                lighting_shader.set_mat4("model", &model);
+
+                gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            }
+
+            // also draw the lamp object(s)
+            light_cube_shader.use_program();
+
+            // we now draw as many light bulbs as we have point lights.
+            gl::BindVertexArray(light_cube_vao);
+            for point_light in lights.point_lights.iter() {
+                model = glm::translate(&glm::identity(), &point_light.position);
+                model = glm::scale(&model, &glm::vec3(0.05, 0.05, 0.05)); // Make it a smaller cube
+                light_cube_shader.set_mat4("model", &model);
+                light_cube_shader.set_vec3v("lightColor", &point_light.diffuse);
+                gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            }
+        }
+
+        // glfw: swap buffers and poll IO events (keys pressed/released, mouse moved etc.)
+        // -------------------------------------------------------------------------------
+        window.swap_buffers();
+        glfw.poll_events();
+
+        // events
+        // ------
+        for (_, event) in glfw::flush_messages(&events) {
+            match event {
+                glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
+                    window.set_should_close(true);
+                }
+                glfw::WindowEvent::Key(Key::W, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::Forward, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::S, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::Backward, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::A, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::Left, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::D, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::Right, delta_time);
+                }
+                glfw::WindowEvent::CursorPos(x, y) => {
+                    let (x, y) = (x as f32, y as f32);
+
+                    if first_mouse {
+                        last_x = x;
+                        last_y = y;
+                        first_mouse = false;
+                    }
+
+                    let offset_x = x - last_x;
+                    let offset_y = last_y - y; // reversed since y-coordinates go from bottom to top
+
+                    last_x = x;
+                    last_y = y;
+
+                    camera.process_mouse_movement(offset_x, offset_y, true);
+                }
+                glfw::WindowEvent::Scroll(_offset_x, offset_y) => {
+                    camera.process_mouse_scroll(offset_y as f32);
+                    // the zoom changes the projection, so the clusters have to follow
+                    clustered_lights.grid = cluster_grid(&camera);
+                }
+                glfw::WindowEvent::FramebufferSize(width, height) => {
+                    camera.process_framebuffer_size(width, height);
+                    clustered_lights.grid = cluster_grid(&camera);
+                    screen_width = width;
+                    screen_height = height;
+
+                    // make sure the viewport matches the new window dimensions; note that width and
+                    // height will be significantly larger than specified on retina displays.
+                    unsafe {
+                        gl::Viewport(0, 0, width, height);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // optional: de-allocate all resources once they've outlived their purpose:
+    // ------------------------------------------------------------------------
+    unsafe {
+        gl::DeleteVertexArrays(1, &cube_vao);
+        gl::DeleteVertexArrays(1, &light_cube_vao);
+        gl::DeleteBuffers(1, &vbo);
+        gl::DeleteBuffers(1, &matrices_ubo.id);
+        clustered_lights.delete();
+    }
+}
+
+// utility function for loading a 2D texture from file
+// ---------------------------------------------------
+pub fn load_texture(path: &str) -> GLuint {
+    let mut texture_id: GLuint = 0;
+
+    unsafe { gl::GenTextures(1, &mut texture_id) };
+
+    let image = image::open(path).expect("Texture failed to load at path: {path}");
+
+    let format = match image {
+        DynamicImage::ImageLuma8(_) => gl::RED,
+        DynamicImage::ImageRgb8(_) => gl::RGB,
+        DynamicImage::ImageRgba8(_) => gl::RGBA,
+        _ => gl::RGB,
+    };
+
+    let data = image.as_bytes();
+
+    unsafe {
+        gl::GenTextures(1, &mut texture_id);
+        gl::BindTexture(gl::TEXTURE_2D, texture_id);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, format as GLint, image.width() as GLsizei, image.height() as GLsizei,
+                       0, format, gl::UNSIGNED_BYTE, data.as_ptr() as *const GLvoid);
+        gl::GenerateMipmap(gl::TEXTURE_2D);
+
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+    }
+
+    texture_id
+}
@@ -15,6 +15,7 @@ mod _5_2_light_casters_point;
 mod _5_3_light_casters_spot;
 mod _5_4_light_casters_spot_soft;
 mod _6_multiple_lights;
+mod _7_clustered_lights;
 
 pub use _1_colors::*;
 pub use _2_1_basic_lighting_diffuse::*;
@@ -33,3 +34,4 @@ pub use _5_2_light_casters_point::*;
 pub use _5_3_light_casters_spot::*;
 pub use _5_4_light_casters_spot_soft::*;
 pub use _6_multiple_lights::*;
+pub use _7_clustered_lights::*;
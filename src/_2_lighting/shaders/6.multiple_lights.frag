@@ -18,9 +18,11 @@ struct DirLight {
 struct PointLight {
     vec3 position;
 
+    // a radius above 0 selects the windowed inverse square attenuation, otherwise the polynomial one is used
     float constant;
     float linear;
     float quadratic;
+    float radius;
 
     vec3 ambient;
     vec3 diffuse;
@@ -29,35 +31,56 @@ struct PointLight {
 
 struct SpotLight {
     vec3 position;
-    vec3 direction;
     float cutOff;
+    vec3 direction;
     float outerCutOff;
 
+    // a radius above 0 selects the windowed inverse square attenuation, otherwise the polynomial one is used
     float constant;
     float linear;
     float quadratic;
+    float radius;
 
     vec3 ambient;
     vec3 diffuse;
     vec3 specular;
 };
 
-#define NR_POINT_LIGHTS 4
+// these have to match the constants in light.rs
+#define MAX_DIR_LIGHTS 4
+#define MAX_POINT_LIGHTS 128
+#define MAX_SPOT_LIGHTS 16
 
 in vec3 FragPos;
 in vec3 Normal;
 in vec2 TexCoords;
 
-uniform vec3 viewPos;
-uniform DirLight dirLight;
-uniform PointLight pointLights[NR_POINT_LIGHTS];
-uniform SpotLight spotLight;
+// the camera and the lights come from uniform buffers shared with the other shaders, only the first nr*Lights entries
+// of each array are used, so the number of lights can change without editing the shader
+layout (std140) uniform Matrices
+{
+    mat4 projection;
+    mat4 view;
+    vec3 viewPos;
+};
+
+layout (std140) uniform Lights
+{
+    int nrDirLights;
+    int nrPointLights;
+    int nrSpotLights;
+    DirLight dirLights[MAX_DIR_LIGHTS];
+    PointLight pointLights[MAX_POINT_LIGHTS];
+    SpotLight spotLights[MAX_SPOT_LIGHTS];
+};
+
 uniform Material material;
 
 // function prototypes
 vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir);
 vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
 vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
+float CalcAttenuation(float constant, float linear, float quadratic, float radius, float distance);
 
 void main()
 {
@@ -66,18 +89,21 @@ void main()
     vec3 viewDir = normalize(viewPos - FragPos);
 
     // == =====================================================
-    // Our lighting is set up in 3 phases: directional, point lights and an optional flashlight
+    // Our lighting is set up in 3 phases: directional, point and spot lights
     // For each phase, a calculate function is defined that calculates the corresponding color
     // per lamp. In the main() function we take all the calculated colors and sum them up for
     // this fragment's final color.
     // == =====================================================
+    vec3 result = vec3(0.0);
     // phase 1: directional lighting
-    vec3 result = CalcDirLight(dirLight, norm, viewDir);
+    for(int i = 0; i < nrDirLights; i++)
+    result += CalcDirLight(dirLights[i], norm, viewDir);
     // phase 2: point lights
-    for(int i = 0; i < NR_POINT_LIGHTS; i++)
+    for(int i = 0; i < nrPointLights; i++)
     result += CalcPointLight(pointLights[i], norm, FragPos, viewDir);
-    // phase 3: spot light
-    result += CalcSpotLight(spotLight, norm, FragPos, viewDir);
+    // phase 3: spot lights
+    for(int i = 0; i < nrSpotLights; i++)
+    result += CalcSpotLight(spotLights[i], norm, FragPos, viewDir);
 
     FragColor = vec4(result, 1.0);
 }
@@ -109,7 +135,7 @@ vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
     float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
     // attenuation
     float distance = length(light.position - fragPos);
-    float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
+    float attenuation = CalcAttenuation(light.constant, light.linear, light.quadratic, light.radius, distance);
     // combine results
     vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoords));
     vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoords));
@@ -131,7 +157,7 @@ vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
     float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
     // attenuation
     float distance = length(light.position - fragPos);
-    float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
+    float attenuation = CalcAttenuation(light.constant, light.linear, light.quadratic, light.radius, distance);
     // spotlight intensity
     float theta = dot(lightDir, normalize(-light.direction));
     float epsilon = light.cutOff - light.outerCutOff;
@@ -145,3 +171,15 @@ vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
     specular *= attenuation * intensity;
     return (ambient + diffuse + specular);
 }
+
+// calculates how much of the light reaches the given distance, see Attenuation in light.rs
+float CalcAttenuation(float constant, float linear, float quadratic, float radius, float distance)
+{
+    if (radius > 0.0)
+    {
+        // inverse square falloff, faded to zero at the radius
+        float window = clamp(1.0 - pow(distance / radius, 4.0), 0.0, 1.0);
+        return window * window / (distance * distance + 1.0);
+    }
+    return 1.0 / (constant + linear * distance + quadratic * (distance * distance));
+}
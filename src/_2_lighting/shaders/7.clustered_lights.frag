@@ -0,0 +1,172 @@
+#version 330 core
+out vec4 FragColor;
+
+struct Material {
+    sampler2D diffuse;
+    sampler2D specular;
+    float shininess;
+};
+
+struct DirLight {
+    vec3 direction;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+};
+
+// a point or spot light as it is stored in the light buffer, see ClusteredLights in cluster.rs
+struct Light {
+    vec3 position;
+    vec3 direction;
+    bool spot;
+    float cutOff;
+    float outerCutOff;
+
+    float constant;
+    float linear;
+    float quadratic;
+    float radius;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+};
+
+#define TEXELS_PER_LIGHT 6
+
+in vec3 FragPos;
+in vec3 Normal;
+in vec2 TexCoords;
+in float ViewDepth;
+
+layout (std140) uniform Matrices
+{
+    mat4 projection;
+    mat4 view;
+    vec3 viewPos;
+};
+
+uniform DirLight dirLight;
+uniform Material material;
+
+// the clusters: the lights, the offset and count of every cluster into the light indices, and the light indices
+uniform samplerBuffer clusterLights;
+uniform usamplerBuffer clusterTable;
+uniform usamplerBuffer clusterLightIndices;
+uniform vec3 clusterCounts;
+uniform vec2 clusterDepthScaleBias;
+uniform vec2 screenSize;
+
+// function prototypes
+vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir);
+vec3 CalcLight(Light light, vec3 normal, vec3 fragPos, vec3 viewDir);
+Light FetchLight(int index);
+float CalcAttenuation(float constant, float linear, float quadratic, float radius, float distance);
+
+void main()
+{
+    // properties
+    vec3 norm = normalize(Normal);
+    vec3 viewDir = normalize(viewPos - FragPos);
+
+    // phase 1: directional lighting
+    vec3 result = CalcDirLight(dirLight, norm, viewDir);
+
+    // phase 2: find the cluster of the fragment from its position on the screen and its depth
+    vec2 tile = clamp(floor(gl_FragCoord.xy / screenSize * clusterCounts.xy), vec2(0.0), clusterCounts.xy - 1.0);
+    float slice = clamp(floor(log(ViewDepth) * clusterDepthScaleBias.x + clusterDepthScaleBias.y), 0.0, clusterCounts.z - 1.0);
+    int cluster = int(tile.x + clusterCounts.x * (tile.y + clusterCounts.y * slice));
+
+    // phase 3: the point and spot lights that reach into the cluster
+    uvec2 lights = texelFetch(clusterTable, cluster).xy;
+    for(uint i = 0u; i < lights.y; i++)
+    result += CalcLight(FetchLight(int(texelFetch(clusterLightIndices, int(lights.x + i)).x)), norm, FragPos, viewDir);
+
+    FragColor = vec4(result, 1.0);
+}
+
+// reads a light from the light buffer
+Light FetchLight(int index)
+{
+    int base = index * TEXELS_PER_LIGHT;
+    vec4 texel0 = texelFetch(clusterLights, base);
+    vec4 texel1 = texelFetch(clusterLights, base + 1);
+    vec4 texel2 = texelFetch(clusterLights, base + 2);
+    vec4 texel3 = texelFetch(clusterLights, base + 3);
+    vec4 texel4 = texelFetch(clusterLights, base + 4);
+    vec4 texel5 = texelFetch(clusterLights, base + 5);
+
+    Light light;
+    light.position = texel0.xyz;
+    light.radius = texel0.w;
+    light.constant = texel1.x;
+    light.linear = texel1.y;
+    light.quadratic = texel1.z;
+    light.spot = texel1.w > 0.5;
+    light.ambient = texel2.rgb;
+    light.cutOff = texel2.w;
+    light.diffuse = texel3.rgb;
+    light.outerCutOff = texel3.w;
+    light.specular = texel4.rgb;
+    light.direction = texel5.xyz;
+    return light;
+}
+
+// calculates the color when using a directional light.
+vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir)
+{
+    vec3 lightDir = normalize(-light.direction);
+    // diffuse shading
+    float diff = max(dot(normal, lightDir), 0.0);
+    // specular shading
+    vec3 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
+    // combine results
+    vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoords));
+    vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoords));
+    vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoords));
+    return (ambient + diffuse + specular);
+}
+
+// calculates the color when using a point or a spot light.
+vec3 CalcLight(Light light, vec3 normal, vec3 fragPos, vec3 viewDir)
+{
+    vec3 lightDir = normalize(light.position - fragPos);
+    // diffuse shading
+    float diff = max(dot(normal, lightDir), 0.0);
+    // specular shading
+    vec3 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
+    // attenuation
+    float distance = length(light.position - fragPos);
+    float attenuation = CalcAttenuation(light.constant, light.linear, light.quadratic, light.radius, distance);
+    // spotlight intensity
+    float intensity = 1.0;
+    if (light.spot)
+    {
+        float theta = dot(lightDir, normalize(-light.direction));
+        float epsilon = light.cutOff - light.outerCutOff;
+        intensity = clamp((theta - light.outerCutOff) / epsilon, 0.0, 1.0);
+    }
+    // combine results
+    vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoords));
+    vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoords));
+    vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoords));
+    ambient *= attenuation * intensity;
+    diffuse *= attenuation * intensity;
+    specular *= attenuation * intensity;
+    return (ambient + diffuse + specular);
+}
+
+// calculates how much of the light reaches the given distance, see Attenuation in light.rs
+float CalcAttenuation(float constant, float linear, float quadratic, float radius, float distance)
+{
+    if (radius > 0.0)
+    {
+        // inverse square falloff, faded to zero at the radius
+        float window = clamp(1.0 - pow(distance / radius, 4.0), 0.0, 1.0);
+        return window * window / (distance * distance + 1.0);
+    }
+    return 1.0 / (constant + linear * distance + quadratic * (distance * distance));
+}
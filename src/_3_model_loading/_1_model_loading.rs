@@ -1,9 +1,13 @@
 use glfw::{Action, Context, Key};
 use nalgebra_glm as glm;
 use crate::shader::Shader;
-use crate::camera::Camera;
+use crate::camera::{Camera, CameraController, OrbitCamera, OrbitMode, OrientationMode};
 use crate::camera::CameraMovement;
+use crate::camera_path::CameraPathDriver;
+use crate::gl_debug;
+use crate::gl_state::{self, RenderState};
 use crate::model::Model;
+use crate::profiler::{self, Profiler};
 
 // settings
 const SCR_WIDTH: u32 = 800;
@@ -17,6 +21,8 @@ pub fn main_3_1() {
     glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
     #[cfg(target_os = "macos")]
     glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+    // GL errors are reported in debug builds, see gl_debug
+    gl_debug::request_debug_context(&mut glfw);
 
     // glfw window creation
     // --------------------
@@ -33,10 +39,11 @@ pub fn main_3_1() {
     // gl: load all OpenGL function pointers
     // ---------------------------------------
     gl::load_with(|s| glfw.get_proc_address_raw(s));
+    gl_debug::init();
 
     // configure global opengl state
     // -----------------------------
-    unsafe { gl::Enable(gl::DEPTH_TEST) };
+    gl_state::with(|gl| gl.set_render_state(&RenderState { depth_test: true, ..RenderState::default() }));
 
     // build and compile shaders
     // -------------------------
@@ -55,17 +62,39 @@ pub fn main_3_1() {
     let our_model = Model::new("resources/objects/backpack/backpack.obj", false);
 
     // draw in wireframe
-    //unsafe { gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE); }
-
-    // camera
-    let mut camera = Camera {
-        position: glm::vec3(0.0, 0.0, 3.0),
-        ..Camera::default()
-    };
+    //let _wireframe = gl_state::with_state(|state| state.polygon_mode = gl::LINE);
+
+    // cameras, press TAB to switch between them and hold the right mouse button to pan the orbit cameras.
+    // The flight camera uses a quaternion orientation and can be rolled with Q and E.
+    let mut cameras: [Box<dyn CameraController>; 4] = [
+        Box::new(Camera {
+            position: glm::vec3(0.0, 0.0, 3.0),
+            ..Camera::default()
+        }),
+        Box::new(Camera {
+            position: glm::vec3(0.0, 0.0, 3.0),
+            orientation_mode: OrientationMode::Quaternion,
+            ..Camera::default()
+        }),
+        Box::new(OrbitCamera::default()),
+        Box::new(OrbitCamera {
+            mode: OrbitMode::Arcball,
+            ..OrbitCamera::default()
+        }),
+    ];
+    let mut active_camera = 0;
     let mut last_x = SCR_WIDTH as f32 / 2.0;
     let mut last_y = SCR_HEIGHT as f32 / 2.0;
     let mut first_mouse = true;
 
+    // camera path recording/playback, see CameraPathDriver
+    let mut camera_path = CameraPathDriver::from_args();
+
+    // frame timings with --profile, see Profiler
+    let profiler = Profiler::from_args();
+    let textures: Vec<u32> = our_model.textures_loaded.iter().map(|texture| texture.id).collect();
+    profiler.set_texture_memory(profiler::texture_memory(&textures));
+
     // timing
     let mut delta_time; // time between current frame and last frame
     let mut last_frame = 0.0;
@@ -78,6 +107,11 @@ pub fn main_3_1() {
         let current_frame = glfw.get_time() as f32;
         delta_time = current_frame - last_frame;
         last_frame = current_frame;
+        profiler.begin_frame();
+
+        if let Some(camera_path) = &mut camera_path {
+            camera_path.update(current_frame, cameras[active_camera].as_mut());
+        }
 
         unsafe {
             // render
@@ -89,7 +123,8 @@ pub fn main_3_1() {
             our_shader.use_program();
 
             // view/projection transformations
-            let projection = glm::perspective(SCR_WIDTH as f32 / SCR_HEIGHT as f32, camera.zoom.to_radians(), 0.1, 100.0);
+            let camera = &cameras[active_camera];
+            let projection = camera.get_projection_matrix();
             let view = camera.get_view_matrix();
             our_shader.set_mat4("projection", &projection);
             our_shader.set_mat4("view", &view);
@@ -98,10 +133,15 @@ pub fn main_3_1() {
             let mut model: glm::Mat4 = glm::identity();
             model = glm::translate(&model, &glm::vec3(0.0, 0.0, 0.0)); // translate it down so it's at the center of the scene
             model = glm::scale(&model, &glm::vec3(1.0, 1.0, 1.0)); // it's a bit too big for our scene, so scale it down
-            our_shader.set_mat4("model", &model);
-            our_model.draw(&our_shader);
+            // meshes outside of the view are skipped, draw_culled sets the model matrix of each mesh itself
+            let _pass = profiler.pass("model");
+            our_model.draw_culled(&our_shader, &camera.get_frustum(), &model);
         }
 
+        // the timings of the passes, with --profile-overlay
+        let (width, height) = window.get_framebuffer_size();
+        profiler.draw_overlay(width, height);
+
         // glfw: swap buffers and poll IO events (keys pressed/released, mouse moved etc.)
         // -------------------------------------------------------------------------------
         window.swap_buffers();
@@ -114,17 +154,26 @@ pub fn main_3_1() {
                 glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
                     window.set_should_close(true);
                 }
+                glfw::WindowEvent::Key(Key::Tab, _, Action::Press, _) => {
+                    active_camera = (active_camera + 1) % cameras.len();
+                }
                 glfw::WindowEvent::Key(Key::W, _, Action::Press | Action::Repeat, _) => {
-                    camera.process_keyboard(CameraMovement::Forward, delta_time);
+                    cameras[active_camera].process_keyboard(CameraMovement::Forward, delta_time);
                 }
                 glfw::WindowEvent::Key(Key::S, _, Action::Press | Action::Repeat, _) => {
-                    camera.process_keyboard(CameraMovement::Backward, delta_time);
+                    cameras[active_camera].process_keyboard(CameraMovement::Backward, delta_time);
                 }
                 glfw::WindowEvent::Key(Key::A, _, Action::Press | Action::Repeat, _) => {
-                    camera.process_keyboard(CameraMovement::Left, delta_time);
+                    cameras[active_camera].process_keyboard(CameraMovement::Left, delta_time);
                 }
                 glfw::WindowEvent::Key(Key::D, _, Action::Press | Action::Repeat, _) => {
-                    camera.process_keyboard(CameraMovement::Right, delta_time);
+                    cameras[active_camera].process_keyboard(CameraMovement::Right, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::Q, _, Action::Press | Action::Repeat, _) => {
+                    cameras[active_camera].process_keyboard(CameraMovement::RollLeft, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::E, _, Action::Press | Action::Repeat, _) => {
+                    cameras[active_camera].process_keyboard(CameraMovement::RollRight, delta_time);
                 }
                 glfw::WindowEvent::CursorPos(x, y) => {
                     let (x, y) = (x as f32, y as f32);
@@ -141,12 +190,20 @@ pub fn main_3_1() {
                     last_x = x;
                     last_y = y;
 
-                    camera.process_mouse_movement(offset_x, offset_y, true);
+                    if window.get_mouse_button(glfw::MouseButtonRight) == Action::Press {
+                        cameras[active_camera].process_mouse_pan(offset_x, offset_y);
+                    } else {
+                        cameras[active_camera].process_mouse_movement(offset_x, offset_y, true);
+                    }
                 }
                 glfw::WindowEvent::Scroll(_offset_x, offset_y) => {
-                    camera.process_mouse_scroll(offset_y as f32);
+                    cameras[active_camera].process_mouse_scroll(offset_y as f32);
                 }
                 glfw::WindowEvent::FramebufferSize(width, height) => {
+                    for camera in cameras.iter_mut() {
+                        camera.process_framebuffer_size(width, height);
+                    }
+
                     // make sure the viewport matches the new window dimensions; note that width and
                     // height will be significantly larger than specified on retina displays.
                     unsafe {
@@ -0,0 +1,222 @@
+use glfw::{Action, Context, Key};
+use nalgebra_glm as glm;
+use crate::shader::Shader;
+use crate::camera::{Camera, Projection};
+use crate::camera::CameraMovement;
+use crate::camera_path::CameraPathDriver;
+use crate::gl_state::{self, RenderState};
+use crate::model::Model;
+
+// settings
+const SCR_WIDTH: u32 = 800;
+const SCR_HEIGHT: u32 = 600;
+
+pub fn main_3_2() {
+    // glfw: initialize and configure
+    // ------------------------------
+    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+    glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+    #[cfg(target_os = "macos")]
+    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+
+    // glfw window creation
+    // --------------------
+    let (mut window, events) = glfw
+        .create_window(SCR_WIDTH, SCR_HEIGHT, "LearnOpenGL", glfw::WindowMode::Windowed)
+        .expect("Failed to create GLFW window");
+    window.make_current();
+    window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_scroll_polling(true);
+    window.set_cursor_mode(glfw::CursorMode::Disabled);
+
+    // gl: load all OpenGL function pointers
+    // ---------------------------------------
+    gl::load_with(|s| glfw.get_proc_address_raw(s));
+
+    // configure global opengl state
+    // -----------------------------
+    gl_state::with(|gl| gl.set_render_state(&RenderState { depth_test: true, ..RenderState::default() }));
+
+    // build and compile shaders
+    // -------------------------
+    let our_shader = match Shader::new("src/_3_model_loading/shaders/1.model_loading.vert",
+                                       "src/_3_model_loading/shaders/1.model_loading.frag")
+    {
+        Ok(shader) => shader,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(-1);
+        }
+    };
+    let instance_shader = match Shader::new("src/_3_model_loading/shaders/2.model_instancing.vert",
+                                            "src/_3_model_loading/shaders/1.model_loading.frag")
+    {
+        Ok(shader) => shader,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(-1);
+        }
+    };
+
+    // load models
+    // -----------
+    let our_model = Model::new("resources/objects/backpack/backpack.obj", false);
+
+    // generate a large list of semi-random model transformation matrices, the backpacks form a ring around the
+    // one in the center like the asteroids around a planet
+    // ------------------------------------------------------------------------------------------------------
+    const AMOUNT: usize = 10000;
+    let radius = 50.0;
+    let offset = 10.0;
+    // a small xorshift generator, returns values in [0, 1)
+    let mut seed = (glfw.get_time() * 1000.0) as u32 | 1;
+    let mut random = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        (seed % 10000) as f32 / 10000.0
+    };
+    let mut model_matrices: Vec<glm::Mat4> = Vec::with_capacity(AMOUNT);
+    for i in 0..AMOUNT {
+        // 1. translation: displace along circle with 'radius' in range [-offset, offset]
+        let angle = i as f32 / AMOUNT as f32 * 360.0;
+        let x = angle.to_radians().sin() * radius + (random() * 2.0 - 1.0) * offset;
+        let y = (random() * 2.0 - 1.0) * offset * 0.4; // keep height of asteroid field smaller compared to width of x and z
+        let z = angle.to_radians().cos() * radius + (random() * 2.0 - 1.0) * offset;
+        let mut model = glm::translate(&glm::identity(), &glm::vec3(x, y, z));
+
+        // 2. scale: scale between 0.05 and 0.25
+        let scale = 0.05 + random() * 0.2;
+        model = glm::scale(&model, &glm::vec3(scale, scale, scale));
+
+        // 3. rotation: add random rotation around a (semi)randomly picked rotation axis vector
+        let rotation = random() * 360.0;
+        model = glm::rotate(&model, rotation.to_radians(), &glm::vec3(0.4, 0.6, 0.8));
+
+        // 4. now add to list of matrices
+        model_matrices.push(model);
+    }
+
+    // camera
+    let mut camera = Camera {
+        position: glm::vec3(0.0, 5.0, 70.0),
+        movement_speed: 10.0,
+        projection: Projection {
+            far: 1000.0,
+            ..Projection::default()
+        },
+        ..Camera::default()
+    };
+    let mut last_x = SCR_WIDTH as f32 / 2.0;
+    let mut last_y = SCR_HEIGHT as f32 / 2.0;
+    let mut first_mouse = true;
+
+    // camera path recording/playback, see CameraPathDriver
+    let mut camera_path = CameraPathDriver::from_args();
+
+    // timing
+    let mut delta_time; // time between current frame and last frame
+    let mut last_frame = 0.0;
+
+    // render loop
+    // -----------
+    while !window.should_close() {
+        // per-frame time logic
+        // --------------------
+        let current_frame = glfw.get_time() as f32;
+        delta_time = current_frame - last_frame;
+        last_frame = current_frame;
+
+        if let Some(camera_path) = &mut camera_path {
+            camera_path.update(current_frame, &mut camera);
+        }
+
+        unsafe {
+            // render
+            // ------
+            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+
+        // configure transformation matrices
+        let projection = camera.get_projection_matrix();
+        let view = camera.get_view_matrix();
+        our_shader.use_program();
+        our_shader.set_mat4("projection", &projection);
+        our_shader.set_mat4("view", &view);
+        instance_shader.use_program();
+        instance_shader.set_mat4("projection", &projection);
+        instance_shader.set_mat4("view", &view);
+
+        // draw the center backpack
+        our_shader.use_program();
+        let mut model: glm::Mat4 = glm::identity();
+        model = glm::scale(&model, &glm::vec3(4.0, 4.0, 4.0));
+        our_shader.set_mat4("model", &model);
+        our_model.draw(&our_shader);
+
+        // draw the ring with a single instanced draw call per mesh
+        instance_shader.use_program();
+        our_model.draw_instanced(&instance_shader, &model_matrices);
+
+        // glfw: swap buffers and poll IO events (keys pressed/released, mouse moved etc.)
+        // -------------------------------------------------------------------------------
+        window.swap_buffers();
+        glfw.poll_events();
+
+        // events
+        // ------
+        for (_, event) in glfw::flush_messages(&events) {
+            match event {
+                glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
+                    window.set_should_close(true);
+                }
+                glfw::WindowEvent::Key(Key::W, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::Forward, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::S, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::Backward, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::A, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::Left, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::D, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::Right, delta_time);
+                }
+                glfw::WindowEvent::CursorPos(x, y) => {
+                    let (x, y) = (x as f32, y as f32);
+
+                    if first_mouse {
+                        last_x = x;
+                        last_y = y;
+                        first_mouse = false;
+                    }
+
+                    let offset_x = x - last_x;
+                    let offset_y = last_y - y; // reversed since y-coordinates go from bottom to top
+
+                    last_x = x;
+                    last_y = y;
+
+                    camera.process_mouse_movement(offset_x, offset_y, true);
+                }
+                glfw::WindowEvent::Scroll(_offset_x, offset_y) => {
+                    camera.process_mouse_scroll(offset_y as f32);
+                }
+                glfw::WindowEvent::FramebufferSize(width, height) => {
+                    camera.process_framebuffer_size(width, height);
+
+                    // make sure the viewport matches the new window dimensions; note that width and
+                    // height will be significantly larger than specified on retina displays.
+                    unsafe {
+                        gl::Viewport(0, 0, width, height);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
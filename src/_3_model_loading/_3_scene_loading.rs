@@ -0,0 +1,321 @@
+use glfw::{Action, Context, Key};
+use nalgebra_glm as glm;
+use crate::shader::Shader;
+use crate::camera::{Camera, CameraController, OrbitCamera, OrbitMode, OrientationMode, ProjectionMode};
+use crate::camera::CameraMovement;
+use crate::camera_path::CameraPathDriver;
+use crate::gl_debug;
+use crate::gl_state::{self, RenderState};
+use crate::asset_loader::LoadProgress;
+use crate::light::LightsBlock;
+use crate::lod::{LodSettings, LodView};
+use crate::scene::Scene;
+use crate::uniform_buffer::{CameraMatrices, UniformBuffer};
+
+// settings
+const SCR_WIDTH: u32 = 800;
+const SCR_HEIGHT: u32 = 600;
+
+// the scene shown when no --scene <file> option is given
+const DEFAULT_SCENE: &str = "resources/scenes/multiple_lights.ron";
+
+// uniform buffer binding points
+const MATRICES_BINDING: u32 = 0;
+const LIGHTS_BINDING: u32 = 1;
+
+pub fn main_3_3() {
+    // the scene file, see resources/scenes
+    let args: Vec<String> = std::env::args().collect();
+    let scene_path = args.iter().position(|arg| arg == "--scene")
+        .and_then(|i| args.get(i + 1))
+        .map_or(DEFAULT_SCENE, |path| path.as_str());
+
+    // glfw: initialize and configure
+    // ------------------------------
+    let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+    glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+    #[cfg(target_os = "macos")]
+    glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+    // GL errors are reported in debug builds, see gl_debug
+    gl_debug::request_debug_context(&mut glfw);
+
+    // glfw window creation
+    // --------------------
+    let (mut window, events) = glfw
+        .create_window(SCR_WIDTH, SCR_HEIGHT, "LearnOpenGL", glfw::WindowMode::Windowed)
+        .expect("Failed to create GLFW window");
+    window.make_current();
+    window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_scroll_polling(true);
+    window.set_cursor_mode(glfw::CursorMode::Disabled);
+
+    // gl: load all OpenGL function pointers
+    // ---------------------------------------
+    gl::load_with(|s| glfw.get_proc_address_raw(s));
+    gl_debug::init();
+
+    // configure global opengl state
+    // -----------------------------
+    gl_state::with(|gl| gl.set_render_state(&RenderState { depth_test: true, ..RenderState::default() }));
+
+    // build and compile shaders
+    // -------------------------
+    let scene_shader = match Shader::new("src/_3_model_loading/shaders/3.scene.vert",
+                                         "src/_3_model_loading/shaders/3.scene.frag")
+    {
+        Ok(shader) => shader,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(-1);
+        }
+    };
+
+    let skybox_shader = match Shader::new("src/_3_model_loading/shaders/3.skybox.vert",
+                                          "src/_3_model_loading/shaders/3.skybox.frag")
+    {
+        Ok(shader) => shader,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(-1);
+        }
+    };
+
+    // load the scene: models, primitives, lights and skybox
+    // -----------------------------------------------------
+    // the models keep loading in the background, a loading bar shows how far they are
+    let mut scene = match Scene::load(scene_path) {
+        Ok(scene) => scene,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(-1);
+        }
+    };
+
+    // the camera matrices and the lights reach the shader through uniform buffers
+    let matrices_ubo = UniformBuffer::<CameraMatrices>::new(MATRICES_BINDING);
+    matrices_ubo.bind_to_shaders(&[&scene_shader]);
+    let lights_ubo = UniformBuffer::<LightsBlock>::new(LIGHTS_BINDING);
+    lights_ubo.bind_to_shaders(&[&scene_shader]);
+
+    // cameras, the fly camera starts where the scene says. TAB switches to an orbit camera around the point in front
+    // of it and back (hold the right mouse button to pan), M between orbit and arcball rotation and P between a
+    // perspective and an orthographic projection. R lets the fly camera rotate freely and roll with Q and E, hold L
+    // to level it again. F turns the orbit camera into a third-person camera: it follows the fly camera, which the
+    // movement keys then keep moving.
+    let mut camera = Camera::default();
+    camera.set_pose(&scene.camera);
+    let mut orbit = OrbitCamera::default();
+    let mut orbiting = false;
+    let mut following = false;
+    let mut last_x = SCR_WIDTH as f32 / 2.0;
+    let mut last_y = SCR_HEIGHT as f32 / 2.0;
+    let mut first_mouse = true;
+
+    // camera path recording/playback, see CameraPathDriver
+    let mut camera_path = CameraPathDriver::from_args();
+
+    // timing
+    let mut delta_time; // time between current frame and last frame
+    let mut last_frame = 0.0;
+
+    // render loop
+    // -----------
+    while !window.should_close() {
+        // per-frame time logic
+        // --------------------
+        let current_frame = glfw.get_time() as f32;
+        delta_time = current_frame - last_frame;
+        last_frame = current_frame;
+
+        if let Some(camera_path) = &mut camera_path {
+            camera_path.update(current_frame, active_camera(&mut camera, &mut orbit, orbiting));
+        }
+        if orbiting && following {
+            orbit.follow(&camera.position);
+        }
+        let active: &dyn CameraController = if orbiting { &orbit } else { &camera };
+
+        // upload the models that finished loading
+        scene.update();
+
+        unsafe {
+            // render
+            // ------
+            gl::ClearColor(scene.background.x, scene.background.y, scene.background.z, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+
+        // view/projection transformations
+        let projection = active.get_projection_matrix();
+        let view = active.get_view_matrix();
+        matrices_ubo.update(&CameraMatrices { projection, view, view_pos: active.get_position().into() });
+
+        // the flashlights follow the camera
+        scene.follow_camera(active);
+        scene.lights.upload(&lights_ubo);
+
+        // render the scene, objects outside of the view are skipped and the models are drawn with the level of
+        // detail that fits their size on screen
+        let (_, framebuffer_height) = window.get_framebuffer_size();
+        let lod = LodView::new(active, framebuffer_height as f32, current_frame, LodSettings::default());
+        scene_shader.use_program();
+        scene.draw(&scene_shader, &active.get_frustum(), Some(&lod));
+
+        // draw the skybox last, it only covers what is still empty
+        if let Some(skybox) = &scene.skybox {
+            skybox.draw(&skybox_shader, &projection, &view);
+        }
+
+        // the loading bar goes on top of everything
+        let progress = scene.progress();
+        if !progress.is_done() {
+            let (width, height) = window.get_framebuffer_size();
+            draw_loading_bar(&progress, width, height);
+        }
+
+        // glfw: swap buffers and poll IO events (keys pressed/released, mouse moved etc.)
+        // -------------------------------------------------------------------------------
+        window.swap_buffers();
+        glfw.poll_events();
+
+        // events
+        // ------
+        for (_, event) in glfw::flush_messages(&events) {
+            match event {
+                glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
+                    window.set_should_close(true);
+                }
+                glfw::WindowEvent::Key(Key::Tab, _, Action::Press, _) => {
+                    // the camera switched to takes over the view
+                    if orbiting {
+                        camera.set_pose(&orbit.get_pose());
+                    } else {
+                        orbit.set_pose(&camera.get_pose());
+                    }
+                    orbiting = !orbiting;
+                }
+                glfw::WindowEvent::Key(Key::M, _, Action::Press, _) => {
+                    orbit.set_mode(if orbit.mode == OrbitMode::Orbit { OrbitMode::Arcball } else { OrbitMode::Orbit });
+                }
+                glfw::WindowEvent::Key(Key::F, _, Action::Press, _) => {
+                    following = !following;
+                }
+                glfw::WindowEvent::Key(Key::R, _, Action::Press, _) => {
+                    camera.set_orientation_mode(match camera.orientation_mode {
+                        OrientationMode::Euler => OrientationMode::Quaternion,
+                        OrientationMode::Quaternion => OrientationMode::Euler,
+                    });
+                }
+                glfw::WindowEvent::Key(Key::P, _, Action::Press, _) => {
+                    let mode = match camera.projection.mode {
+                        ProjectionMode::Perspective => ProjectionMode::Orthographic,
+                        ProjectionMode::Orthographic => ProjectionMode::Perspective,
+                    };
+                    camera.projection.mode = mode;
+                    orbit.projection.mode = mode;
+                }
+                glfw::WindowEvent::Key(Key::L, _, Action::Press | Action::Repeat, _) => {
+                    // the roll is the last rotation of the orientation, turn back by it
+                    let level = camera.orientation
+                        * glm::quat_angle_axis(-camera.roll.to_radians(), &glm::vec3(0.0, 0.0, -1.0));
+                    camera.slerp_orientation(&level, (10.0 * delta_time).min(1.0));
+                }
+                glfw::WindowEvent::Key(Key::W, _, Action::Press | Action::Repeat, _) => {
+                    active_camera(&mut camera, &mut orbit, orbiting && !following).process_keyboard(CameraMovement::Forward, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::S, _, Action::Press | Action::Repeat, _) => {
+                    active_camera(&mut camera, &mut orbit, orbiting && !following).process_keyboard(CameraMovement::Backward, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::A, _, Action::Press | Action::Repeat, _) => {
+                    active_camera(&mut camera, &mut orbit, orbiting && !following).process_keyboard(CameraMovement::Left, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::D, _, Action::Press | Action::Repeat, _) => {
+                    active_camera(&mut camera, &mut orbit, orbiting && !following).process_keyboard(CameraMovement::Right, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::Q, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::RollLeft, delta_time);
+                }
+                glfw::WindowEvent::Key(Key::E, _, Action::Press | Action::Repeat, _) => {
+                    camera.process_keyboard(CameraMovement::RollRight, delta_time);
+                }
+                glfw::WindowEvent::CursorPos(x, y) => {
+                    let (x, y) = (x as f32, y as f32);
+
+                    if first_mouse {
+                        last_x = x;
+                        last_y = y;
+                        first_mouse = false;
+                    }
+
+                    let offset_x = x - last_x;
+                    let offset_y = last_y - y; // reversed since y-coordinates go from bottom to top
+
+                    last_x = x;
+                    last_y = y;
+
+                    let active = active_camera(&mut camera, &mut orbit, orbiting);
+                    if window.get_mouse_button(glfw::MouseButtonRight) == Action::Press {
+                        active.process_mouse_pan(offset_x, offset_y);
+                    } else {
+                        active.process_mouse_movement(offset_x, offset_y, true);
+                    }
+                }
+                glfw::WindowEvent::Scroll(_offset_x, offset_y) => {
+                    active_camera(&mut camera, &mut orbit, orbiting).process_mouse_scroll(offset_y as f32);
+                }
+                glfw::WindowEvent::FramebufferSize(width, height) => {
+                    camera.process_framebuffer_size(width, height);
+                    orbit.process_framebuffer_size(width, height);
+
+                    // make sure the viewport matches the new window dimensions; note that width and
+                    // height will be significantly larger than specified on retina displays.
+                    unsafe {
+                        gl::Viewport(0, 0, width, height);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // optional: de-allocate all resources once they've outlived their purpose:
+    // ------------------------------------------------------------------------
+    if let Some(skybox) = &scene.skybox {
+        skybox.delete();
+    }
+    unsafe {
+        gl::DeleteBuffers(1, &matrices_ubo.id);
+        gl::DeleteBuffers(1, &lights_ubo.id);
+    }
+}
+
+// the camera that gets the input
+fn active_camera<'a>(camera: &'a mut Camera, orbit: &'a mut OrbitCamera, orbiting: bool) -> &'a mut dyn CameraController {
+    if orbiting {
+        orbit
+    } else {
+        camera
+    }
+}
+
+// draws a bar at the bottom of the window that fills up while the models load. It only clears two rectangles of the
+// window, so it needs no shader.
+fn draw_loading_bar(progress: &LoadProgress, width: i32, height: i32) {
+    let (x, y) = (width / 8, height / 16);
+    let (bar_width, bar_height) = (width - 2 * x, (height / 40).max(4));
+    let filled = (bar_width as f32 * progress.fraction()) as i32;
+
+    unsafe {
+        gl::Enable(gl::SCISSOR_TEST);
+        gl::Scissor(x, y, bar_width, bar_height);
+        gl::ClearColor(0.2, 0.2, 0.2, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::Scissor(x, y, filled, bar_height);
+        gl::ClearColor(0.9, 0.9, 0.9, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::Disable(gl::SCISSOR_TEST);
+    }
+}
@@ -1,3 +1,12 @@
+// the first tutorials load the backpack, which needs a loader for OBJ files
+#[cfg(any(feature = "assimp", feature = "obj"))]
 mod _1_model_loading;
+#[cfg(any(feature = "assimp", feature = "obj"))]
+mod _2_model_instancing;
+mod _3_scene_loading;
 
-pub use _1_model_loading::*;
\ No newline at end of file
+#[cfg(any(feature = "assimp", feature = "obj"))]
+pub use _1_model_loading::*;
+#[cfg(any(feature = "assimp", feature = "obj"))]
+pub use _2_model_instancing::*;
+pub use _3_scene_loading::*;
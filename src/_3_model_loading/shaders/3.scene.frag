@@ -0,0 +1,192 @@
+#version 330 core
+out vec4 FragColor;
+
+struct DirLight {
+    vec3 direction;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+};
+
+struct PointLight {
+    vec3 position;
+
+    // a radius above 0 selects the windowed inverse square attenuation, otherwise the polynomial one is used
+    float constant;
+    float linear;
+    float quadratic;
+    float radius;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+};
+
+struct SpotLight {
+    vec3 position;
+    float cutOff;
+    vec3 direction;
+    float outerCutOff;
+
+    // a radius above 0 selects the windowed inverse square attenuation, otherwise the polynomial one is used
+    float constant;
+    float linear;
+    float quadratic;
+    float radius;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+};
+
+// these have to match the constants in light.rs
+#define MAX_DIR_LIGHTS 4
+#define MAX_POINT_LIGHTS 128
+#define MAX_SPOT_LIGHTS 16
+
+in vec3 FragPos;
+in vec3 Normal;
+in vec2 TexCoords;
+
+// the camera and the lights come from uniform buffers shared with the other shaders, only the first nr*Lights entries
+// of each array are used, so the number of lights can change without editing the shader
+layout (std140) uniform Matrices
+{
+    mat4 projection;
+    mat4 view;
+    vec3 viewPos;
+};
+
+layout (std140) uniform Lights
+{
+    int nrDirLights;
+    int nrPointLights;
+    int nrSpotLights;
+    DirLight dirLights[MAX_DIR_LIGHTS];
+    PointLight pointLights[MAX_POINT_LIGHTS];
+    SpotLight spotLights[MAX_SPOT_LIGHTS];
+};
+
+// the textures of the mesh, see Mesh::draw
+uniform sampler2D texture_diffuse1;
+uniform sampler2D texture_specular1;
+uniform float shininess;
+
+// the cross-fade between two levels of detail, see Model::draw_with_lod: the fading in level keeps the fragments where
+// the dither threshold is below lodFade, the fading out one gets lodFade - 1.0 and keeps the others. At 1.0 every
+// fragment is kept.
+uniform float lodFade = 1.0;
+
+// function prototypes
+vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir);
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
+vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
+float CalcAttenuation(float constant, float linear, float quadratic, float radius, float distance);
+
+void main()
+{
+    // interleaved gradient noise, a threshold that looks random but changes smoothly over the screen
+    float threshold = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    if (lodFade >= 0.0 ? threshold >= lodFade : threshold < lodFade + 1.0)
+        discard;
+
+    // properties
+    vec3 norm = normalize(Normal);
+    vec3 viewDir = normalize(viewPos - FragPos);
+
+    // == =====================================================
+    // Our lighting is set up in 3 phases: directional, point and spot lights
+    // For each phase, a calculate function is defined that calculates the corresponding color
+    // per lamp. In the main() function we take all the calculated colors and sum them up for
+    // this fragment's final color.
+    // == =====================================================
+    vec3 result = vec3(0.0);
+    // phase 1: directional lighting
+    for(int i = 0; i < nrDirLights; i++)
+    result += CalcDirLight(dirLights[i], norm, viewDir);
+    // phase 2: point lights
+    for(int i = 0; i < nrPointLights; i++)
+    result += CalcPointLight(pointLights[i], norm, FragPos, viewDir);
+    // phase 3: spot lights
+    for(int i = 0; i < nrSpotLights; i++)
+    result += CalcSpotLight(spotLights[i], norm, FragPos, viewDir);
+
+    FragColor = vec4(result, 1.0);
+}
+
+// calculates the color when using a directional light.
+vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir)
+{
+    vec3 lightDir = normalize(-light.direction);
+    // diffuse shading
+    float diff = max(dot(normal, lightDir), 0.0);
+    // specular shading
+    vec3 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
+    // combine results
+    vec3 ambient = light.ambient * vec3(texture(texture_diffuse1, TexCoords));
+    vec3 diffuse = light.diffuse * diff * vec3(texture(texture_diffuse1, TexCoords));
+    vec3 specular = light.specular * spec * vec3(texture(texture_specular1, TexCoords));
+    return (ambient + diffuse + specular);
+}
+
+// calculates the color when using a point light.
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
+{
+    vec3 lightDir = normalize(light.position - fragPos);
+    // diffuse shading
+    float diff = max(dot(normal, lightDir), 0.0);
+    // specular shading
+    vec3 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
+    // attenuation
+    float distance = length(light.position - fragPos);
+    float attenuation = CalcAttenuation(light.constant, light.linear, light.quadratic, light.radius, distance);
+    // combine results
+    vec3 ambient = light.ambient * vec3(texture(texture_diffuse1, TexCoords));
+    vec3 diffuse = light.diffuse * diff * vec3(texture(texture_diffuse1, TexCoords));
+    vec3 specular = light.specular * spec * vec3(texture(texture_specular1, TexCoords));
+    ambient *= attenuation;
+    diffuse *= attenuation;
+    specular *= attenuation;
+    return (ambient + diffuse + specular);
+}
+
+// calculates the color when using a spot light.
+vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
+{
+    vec3 lightDir = normalize(light.position - fragPos);
+    // diffuse shading
+    float diff = max(dot(normal, lightDir), 0.0);
+    // specular shading
+    vec3 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
+    // attenuation
+    float distance = length(light.position - fragPos);
+    float attenuation = CalcAttenuation(light.constant, light.linear, light.quadratic, light.radius, distance);
+    // spotlight intensity
+    float theta = dot(lightDir, normalize(-light.direction));
+    float epsilon = light.cutOff - light.outerCutOff;
+    float intensity = clamp((theta - light.outerCutOff) / epsilon, 0.0, 1.0);
+    // combine results
+    vec3 ambient = light.ambient * vec3(texture(texture_diffuse1, TexCoords));
+    vec3 diffuse = light.diffuse * diff * vec3(texture(texture_diffuse1, TexCoords));
+    vec3 specular = light.specular * spec * vec3(texture(texture_specular1, TexCoords));
+    ambient *= attenuation * intensity;
+    diffuse *= attenuation * intensity;
+    specular *= attenuation * intensity;
+    return (ambient + diffuse + specular);
+}
+
+// calculates how much of the light reaches the given distance, see Attenuation in light.rs
+float CalcAttenuation(float constant, float linear, float quadratic, float radius, float distance)
+{
+    if (radius > 0.0)
+    {
+        // inverse square falloff, faded to zero at the radius
+        float window = clamp(1.0 - pow(distance / radius, 4.0), 0.0, 1.0);
+        return window * window / (distance * distance + 1.0);
+    }
+    return 1.0 / (constant + linear * distance + quadratic * (distance * distance));
+}
@@ -0,0 +1,260 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use image::{DynamicImage, Rgb, RgbImage};
+use nalgebra_glm as glm;
+use crate::gl_debug;
+use crate::mesh::{MeshData, Texture};
+use crate::mesh_ops;
+use crate::model::{texture_from_image, Material, Model, ModelData, ModelNode, TextureReference};
+use crate::primitives::Primitive;
+
+// Loads models on worker threads, so the window keeps drawing while a big model is parsed. A worker reads the model
+// data (see ModelData::load), the GL thread uploads it in update, and then the workers decode the textures of the
+// model, which replace the placeholder textures it was uploaded with. Until a model is uploaded, a placeholder cube
+// is drawn in its place.
+//
+// update uploads at most uploads_per_frame finished jobs, so a frame never waits for more than a few uploads.
+
+// the path of the placeholder texture, it is not a file
+const PLACEHOLDER: &str = "placeholder";
+
+// the most a level of detail may deviate from the full mesh, relative to the size of the mesh
+const LOD_MAX_ERROR: f32 = 0.05;
+
+// refers to a model of the asset loader
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ModelHandle(usize);
+
+// how many jobs (models and textures) are finished, the total grows when a model is uploaded and its textures are
+// queued
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LoadProgress {
+    pub finished: usize,
+    pub total: usize,
+}
+
+impl LoadProgress {
+    // between 0 and 1, for a loading bar
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 { 1.0 } else { self.finished as f32 / self.total as f32 }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.finished == self.total
+    }
+}
+
+enum Job {
+    // lods is the number of levels of detail to generate for every mesh
+    Model { handle: ModelHandle, path: String, lods: usize },
+    // a texture of a model, the file path is the path of the reference within the model directory
+    Texture { handle: ModelHandle, reference: TextureReference, file: String },
+}
+
+enum Finished {
+    Model { handle: ModelHandle, data: Result<ModelData, String> },
+    Texture { handle: ModelHandle, path: String, image: Result<DynamicImage, String> },
+}
+
+pub struct AssetLoader {
+    // the number of finished jobs update uploads at most
+    pub uploads_per_frame: usize,
+
+    jobs: Option<mpsc::Sender<Job>>,
+    finished: mpsc::Receiver<Finished>,
+    workers: Vec<thread::JoinHandle<()>>,
+
+    // None until the model is uploaded
+    models: Vec<Option<Model>>,
+    gamma: Vec<bool>,
+    progress: LoadProgress,
+
+    placeholder_texture: Texture,
+    placeholder_model: Model,
+}
+
+impl AssetLoader {
+    // starts a worker for every core, up to 4
+    pub fn new() -> AssetLoader {
+        let threads = thread::available_parallelism().map_or(2, |threads| threads.get().min(4));
+        AssetLoader::with_threads(threads)
+    }
+
+    pub fn with_threads(threads: usize) -> AssetLoader {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (finished_sender, finished_receiver) = mpsc::channel();
+
+        // the workers take turns waiting for the next job
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..threads.max(1)).map(|_| {
+            let jobs = Arc::clone(&job_receiver);
+            let finished = finished_sender.clone();
+            thread::spawn(move || loop {
+                let job = match jobs.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    // the loader was dropped
+                    Err(_) => break,
+                };
+                if finished.send(run(job)).is_err() {
+                    break;
+                }
+            })
+        }).collect();
+
+        // a gray checkerboard
+        let checkers = RgbImage::from_fn(8, 8, |x, y| if (x + y) % 2 == 0 { Rgb([96, 96, 96]) } else { Rgb([160, 160, 160]) });
+        let placeholder_texture = Texture {
+            id: texture_from_image(DynamicImage::ImageRgb8(checkers)),
+            type_name: "texture_diffuse".to_string(),
+            path: PLACEHOLDER.to_string(),
+        };
+
+        let (vertices, indices) = Primitive::Cube.geometry();
+        let cube = ModelData {
+            meshes: vec![MeshData::new(vertices, indices)],
+            materials: vec![Material {
+                textures: vec![TextureReference { type_name: placeholder_texture.type_name.clone(),
+                    path: PLACEHOLDER.to_string(), embedded: None }],
+                ..Material::default()
+            }],
+            nodes: vec![ModelNode { name: PLACEHOLDER.to_string(), parent: None, transform: glm::identity(),
+                meshes: vec![0], skin: None }],
+            ..ModelData::default()
+        };
+        gl_debug::label(gl::TEXTURE, placeholder_texture.id, PLACEHOLDER);
+        let placeholder_model = Model::upload_with_textures(&cube, false, vec![placeholder_texture.clone()]);
+
+        AssetLoader {
+            uploads_per_frame: 4,
+            jobs: Some(job_sender),
+            finished: finished_receiver,
+            workers,
+            models: Vec::new(),
+            gamma: Vec::new(),
+            progress: LoadProgress::default(),
+            placeholder_texture,
+            placeholder_model,
+        }
+    }
+
+    // starts loading a model, it can be drawn right away (as a placeholder). The workers also generate up to lods
+    // levels of detail for every mesh, see mesh_ops::generate_lods.
+    pub fn load_model(&mut self, path: &str, gamma: bool, lods: usize) -> ModelHandle {
+        let handle = ModelHandle(self.models.len());
+        self.models.push(None);
+        self.gamma.push(gamma);
+        self.queue(Job::Model { handle, path: path.to_string(), lods });
+        handle
+    }
+
+    // the model, or the placeholder while it is still loading
+    pub fn model(&self, handle: ModelHandle) -> &Model {
+        self.models[handle.0].as_ref().unwrap_or(&self.placeholder_model)
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+
+    // uploads what the workers finished since the last call, up to uploads_per_frame. Call it once per frame on the
+    // GL thread.
+    pub fn update(&mut self) {
+        for _ in 0..self.uploads_per_frame {
+            match self.finished.try_recv() {
+                Ok(finished) => self.upload(finished),
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn upload(&mut self, finished: Finished) {
+        self.progress.finished += 1;
+
+        match finished {
+            Finished::Model { handle, data } => {
+                let gamma = self.gamma[handle.0];
+                let data = data.unwrap_or_else(|e| {
+                    // like Model::new, a model that fails to load is empty
+                    eprintln!("{}", e);
+                    ModelData::default()
+                });
+
+                // every texture starts as the placeholder
+                let mut references: Vec<&TextureReference> = Vec::new();
+                for reference in data.materials.iter().flat_map(|material| &material.textures) {
+                    if !references.iter().any(|other| other.path == reference.path) {
+                        references.push(reference);
+                    }
+                }
+                let placeholders = references.iter().map(|reference| Texture {
+                    id: self.placeholder_texture.id,
+                    type_name: reference.type_name.clone(),
+                    path: reference.path.clone(),
+                }).collect();
+                self.models[handle.0] = Some(Model::upload_with_textures(&data, gamma, placeholders));
+
+                for reference in references {
+                    let file = format!("{}/{}", data.directory, reference.path);
+                    self.queue(Job::Texture { handle, reference: reference.clone(), file });
+                }
+            }
+            Finished::Texture { handle, path, image } => {
+                let image = match image {
+                    Ok(image) => image,
+                    // the placeholder stays
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                };
+                if let Some(model) = &mut self.models[handle.0] {
+                    let id = texture_from_image(image);
+                    gl_debug::label(gl::TEXTURE, id, &path);
+                    model.replace_texture(&path, id);
+                }
+            }
+        }
+    }
+
+    fn queue(&mut self, job: Job) {
+        self.progress.total += 1;
+        if let Some(jobs) = &self.jobs {
+            jobs.send(job).expect("the asset loader workers stopped");
+        }
+    }
+}
+
+impl Drop for AssetLoader {
+    // closes the job queue, so the workers stop once they are done with their current job
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+// does the part of a job that doesn't need the GL context
+fn run(job: Job) -> Finished {
+    match job {
+        Job::Model { handle, path, lods } => {
+            let data = ModelData::load(&path).map(|mut data| {
+                if lods > 0 {
+                    for mesh in &mut data.meshes {
+                        mesh_ops::generate_lods(mesh, lods, LOD_MAX_ERROR);
+                    }
+                }
+                data
+            });
+            Finished::Model { handle, data }
+        }
+        Job::Texture { handle, reference, file } => {
+            let image = match &reference.embedded {
+                Some(bytes) => image::load_from_memory(bytes),
+                None => image::open(&file),
+            };
+            let image = image.map_err(|e| format!("ERROR::ASSET_LOADER::TEXTURE_FAILED: {}: {}", reference.path, e));
+            Finished::Texture { handle, path: reference.path, image }
+        }
+    }
+}
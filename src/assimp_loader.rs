@@ -0,0 +1,128 @@
+use russimp::node::Node;
+use russimp::scene::{PostProcess, Scene};
+use russimp::texture::TextureType;
+use nalgebra_glm as glm;
+use crate::mesh::{MeshData, Vertex};
+use crate::model::{Material, ModelData, ModelNode, TextureReference};
+
+// The assimp loader (enabled by the assimp feature, which is on by default), it reads every format assimp supports.
+
+// reads a model file with supported ASSIMP extensions
+pub fn import(path: &str) -> Result<ModelData, String> {
+    // read file via ASSIMP
+    let scene = Scene::from_file(path,
+                                 vec![
+                                     PostProcess::Triangulate,
+                                     PostProcess::GenerateSmoothNormals,
+                                     //PostProcess::FlipUVs,
+                                     PostProcess::CalculateTangentSpace],
+    ).map_err(|e| format!("ERROR::ASSIMP:: {}", e))?;
+
+    let mut data = ModelData {
+        // retrieve the directory path of the filepath
+        directory: path.rfind('/').map_or(".", |i| &path[..i]).to_owned(),
+        ..ModelData::default()
+    };
+
+    data.meshes = scene.meshes.iter().map(process_mesh).collect();
+    data.materials = scene.materials.iter().map(process_material).collect();
+
+    // process ASSIMP's root node recursively
+    if let Some(root) = &scene.root {
+        process_node(root, None, &mut data);
+    }
+
+    Ok(data)
+}
+
+// adds a node and then its children, so the parents always come first
+fn process_node(node: &Node, parent: Option<usize>, data: &mut ModelData)
+{
+    let m = &node.transformation;
+    data.nodes.push(ModelNode {
+        name: node.name.clone(),
+        parent,
+        transform: glm::mat4(
+            m.a1, m.a2, m.a3, m.a4,
+            m.b1, m.b2, m.b3, m.b4,
+            m.c1, m.c2, m.c3, m.c4,
+            m.d1, m.d2, m.d3, m.d4,
+        ),
+        // the node object only contains indices to index the actual objects in the scene.
+        // the scene contains all the data, node is just to keep stuff organized (like relations between nodes).
+        meshes: node.meshes.iter().map(|&i| i as usize).collect(),
+        skin: None,
+    });
+
+    let index = data.nodes.len() - 1;
+    for child in node.children.borrow().iter() {
+        process_node(child, Some(index), data);
+    }
+}
+
+fn process_mesh(mesh: &russimp::mesh::Mesh) -> MeshData
+{
+    // walk through each of the mesh's vertices
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(mesh.vertices.len());
+
+    for i in 0..mesh.vertices.len() {
+        let mut vertex = Vertex {
+            // positions
+            position: glm::vec3(mesh.vertices[i].x, mesh.vertices[i].y, mesh.vertices[i].z),
+            // normals
+            normal: glm::vec3(mesh.normals[i].x, mesh.normals[i].y, mesh.normals[i].z),
+            ..Default::default()
+        };
+
+        // texture coordinates
+        if let Some(texture_coord) = &mesh.texture_coords[0] {
+            vertex.tex_coords = glm::vec2(texture_coord[i].x, texture_coord[i].y);
+        }
+        // tangent and bitangent, only calculated for meshes with texture coordinates
+        if let (Some(tangent), Some(bitangent)) = (mesh.tangents.get(i), mesh.bitangents.get(i)) {
+            vertex.tangent = glm::vec3(tangent.x, tangent.y, tangent.z);
+            vertex.bitangent = glm::vec3(bitangent.x, bitangent.y, bitangent.z);
+        }
+
+        vertices.push(vertex);
+    }
+
+    // now wak through each of the mesh's faces (a face is a mesh its triangle) and retrieve the corresponding vertex indices.
+    let mut indices: Vec<u32> = Vec::with_capacity(mesh.faces.len() * 3);
+    for face in mesh.faces.iter() {
+        // retrieve all indices of the face and store them in the indices vector
+        indices.extend(&face.0);
+    }
+
+    MeshData { vertices, indices, material: mesh.material_index as usize, joints: Vec::new(), weights: Vec::new(),
+        lods: Vec::new() }
+}
+
+fn process_material(material: &russimp::material::Material) -> Material
+{
+    // we assume a convention for sampler names in the shaders. Each diffuse texture should be named
+    // as 'texture_diffuseN' where N is a sequential number ranging from 1 to MAX_SAMPLER_NUMBER.
+    // Same applies to other texture as the following list summarizes:
+    // diffuse: texture_diffuseN
+    // specular: texture_specularN
+    // normal: texture_normalN
+    let textures = material.textures.iter().map(|(texture_type, texture)| {
+        let type_name = match texture_type {
+            // 1. diffuse maps
+            TextureType::Diffuse => "texture_diffuse",
+            // 2. specular maps
+            TextureType::Specular => "texture_specular",
+            // 3. normal maps
+            TextureType::Normals => "texture_normal",
+            // 4. height maps
+            TextureType::Height => "texture_height",
+            // 5. ambient maps
+            TextureType::Ambient => "texture_ambient",
+            // Unknown
+            _ => "texture_unknown",
+        };
+        TextureReference { type_name: type_name.to_string(), path: texture[0].path.clone(), embedded: None }
+    }).collect();
+
+    Material { textures, ..Material::default() }
+}
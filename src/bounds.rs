@@ -0,0 +1,300 @@
+use nalgebra_glm as glm;
+
+// An axis aligned bounding box. An empty box has its minimum above its maximum, so extending it with the first point
+// makes it contain exactly that point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Default for Aabb {
+    fn default() -> Aabb {
+        Aabb {
+            min: glm::vec3(f32::MAX, f32::MAX, f32::MAX),
+            max: glm::vec3(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+}
+
+impl Aabb {
+    // returns the smallest box containing all the points
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a glm::Vec3>) -> Aabb {
+        let mut aabb = Aabb::default();
+        for point in points {
+            aabb.extend(point);
+        }
+        aabb
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    // half the size of the box along each axis
+    pub fn extents(&self) -> glm::Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    // grows the box so it contains the point
+    pub fn extend(&mut self, point: &glm::Vec3) {
+        self.min = glm::min2(&self.min, point);
+        self.max = glm::max2(&self.max, point);
+    }
+
+    // grows the box so it contains the other box
+    pub fn merge(&mut self, other: &Aabb) {
+        if !other.is_empty() {
+            self.extend(&other.min);
+            self.extend(&other.max);
+        }
+    }
+
+    // returns the axis aligned box containing this box after the transformation. The center is transformed as a point
+    // and each new extent is the sum of the old extents weighted by the absolute values of the rotation/scale part.
+    pub fn transform(&self, matrix: &glm::Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let center = (matrix * self.center().push(1.0)).xyz();
+        let extents = self.extents();
+        let mut new_extents = glm::Vec3::zeros();
+        for i in 0..3 {
+            for j in 0..3 {
+                new_extents[i] += matrix[(i, j)].abs() * extents[j];
+            }
+        }
+
+        Aabb {
+            min: center - new_extents,
+            max: center + new_extents,
+        }
+    }
+}
+
+// A sphere enclosing a set of points
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: glm::Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // returns a sphere around the center of the bounding box of the points, not the smallest possible sphere but
+    // usually close to it and cheap to calculate
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a glm::Vec3> + Clone) -> BoundingSphere {
+        let aabb = Aabb::from_points(points.clone());
+        if aabb.is_empty() {
+            return BoundingSphere::default();
+        }
+
+        let center = aabb.center();
+        let radius = points.into_iter().map(|point| glm::distance(point, &center)).fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    // returns a sphere containing this sphere after the transformation, a non-uniform scale grows the radius by the
+    // largest scale factor
+    pub fn transform(&self, matrix: &glm::Mat4) -> BoundingSphere {
+        let scale = (0..3).map(|i| matrix.fixed_view::<3, 1>(0, i).norm()).fold(0.0, f32::max);
+        BoundingSphere {
+            center: (matrix * self.center.push(1.0)).xyz(),
+            radius: self.radius * scale,
+        }
+    }
+
+    // returns true if the sphere and the box overlap, by comparing the radius with the distance to the closest point
+    // of the box
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+
+        let closest = glm::clamp_vec(&self.center, &aabb.min, &aabb.max);
+        glm::distance2(&closest, &self.center) <= self.radius * self.radius
+    }
+}
+
+// A plane in the form dot(normal, point) + distance = 0, points on the side the normal points to are inside
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: glm::Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    // builds a plane from the coefficients (a, b, c, d) and normalizes it, so signed_distance returns real distances
+    fn from_coefficients(coefficients: &glm::Vec4) -> Plane {
+        let normal = coefficients.xyz();
+        let length = normal.norm();
+        if length <= f32::EPSILON {
+            // the far plane of an infinite projection, every point is inside
+            return Plane { normal: glm::Vec3::zeros(), distance: f32::MAX };
+        }
+        Plane { normal: normal / length, distance: coefficients.w / length }
+    }
+
+    pub fn signed_distance(&self, point: &glm::Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+// The six planes enclosing the visible volume of a camera, used to skip drawing objects that can't be seen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    // left, right, bottom, top, near and far plane, all pointing inwards
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // extracts the planes from a projection * view matrix (Gribb/Hartmann), the planes are in world space then.
+    // The near plane is taken from the [-1, 1] depth range of OpenGL, for [0, 1] projections it lies a bit behind the
+    // real one, which only makes culling slightly more conservative.
+    pub fn from_matrix(projection_view: &glm::Mat4) -> Frustum {
+        let row = |i: usize| -> glm::Vec4 { projection_view.row(i).transpose() };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        Frustum {
+            planes: [
+                Plane::from_coefficients(&(w + x)),
+                Plane::from_coefficients(&(w - x)),
+                Plane::from_coefficients(&(w + y)),
+                Plane::from_coefficients(&(w - y)),
+                Plane::from_coefficients(&(w + z)),
+                Plane::from_coefficients(&(w - z)),
+            ],
+        }
+    }
+
+    // returns false if the box is completely outside of one of the planes. Boxes near the corners of the frustum may
+    // be reported as intersecting although they are outside, which is fine for culling.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+
+        self.planes.iter().all(|plane| {
+            // the corner of the box furthest along the plane normal
+            let corner = glm::vec3(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.signed_distance(&corner) >= 0.0
+        })
+    }
+
+    // returns false if the sphere is completely outside of one of the planes
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(&sphere.center) >= -sphere.radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec3(actual: &glm::Vec3, expected: &glm::Vec3) {
+        assert!((actual - expected).norm() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+
+    // a camera at (0, 0, 5) looking at the origin with a field of view of 90°, so at a distance d in front of it the
+    // frustum reaches from -d to d along x and y, from 1 (z = 4) to 10 (z = -5) units away
+    fn frustum() -> Frustum {
+        let projection = glm::perspective(1.0, 90f32.to_radians(), 1.0, 10.0);
+        let view = glm::look_at(&glm::vec3(0.0, 0.0, 5.0), &glm::Vec3::zeros(), &glm::vec3(0.0, 1.0, 0.0));
+        Frustum::from_matrix(&(projection * view))
+    }
+
+    fn cube(center: glm::Vec3, half_size: f32) -> Aabb {
+        let half_size = glm::vec3(half_size, half_size, half_size);
+        Aabb { min: center - half_size, max: center + half_size }
+    }
+
+    // a center outside of each plane, in the order of Frustum::planes
+    fn outside() -> [glm::Vec3; 6] {
+        [
+            glm::vec3(-10.0, 0.0, 0.0),
+            glm::vec3(10.0, 0.0, 0.0),
+            glm::vec3(0.0, -10.0, 0.0),
+            glm::vec3(0.0, 10.0, 0.0),
+            glm::vec3(0.0, 0.0, 4.8),
+            glm::vec3(0.0, 0.0, -8.0),
+        ]
+    }
+
+    #[test]
+    fn planes_are_normalized_and_point_inwards() {
+        let frustum = frustum();
+        for plane in &frustum.planes {
+            assert!((plane.normal.norm() - 1.0).abs() < 1e-4);
+            assert!(plane.signed_distance(&glm::Vec3::zeros()) > 0.0);
+        }
+        // the near and far plane are 1 unit in front of and 5 units behind the origin
+        assert!((frustum.planes[4].signed_distance(&glm::Vec3::zeros()) - 4.0).abs() < 1e-3);
+        assert!((frustum.planes[5].signed_distance(&glm::Vec3::zeros()) - 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn boxes_inside_outside_and_straddling() {
+        let frustum = frustum();
+        assert!(frustum.intersects_aabb(&cube(glm::Vec3::zeros(), 0.5)));
+        for (i, center) in outside().iter().enumerate() {
+            assert!(frustum.planes[i].signed_distance(center) < 0.0, "plane {}", i);
+            assert!(!frustum.intersects_aabb(&cube(*center, 0.1)), "plane {}", i);
+        }
+        // across the right plane (x = 5 at z = 0), the near plane and the far plane
+        assert!(frustum.intersects_aabb(&cube(glm::vec3(5.0, 0.0, 0.0), 1.0)));
+        assert!(frustum.intersects_aabb(&cube(glm::vec3(0.0, 0.0, 4.5), 1.0)));
+        assert!(frustum.intersects_aabb(&cube(glm::vec3(0.0, 0.0, -5.0), 1.0)));
+        assert!(!frustum.intersects_aabb(&Aabb::default()));
+    }
+
+    #[test]
+    fn spheres_inside_outside_and_straddling() {
+        let frustum = frustum();
+        assert!(frustum.intersects_sphere(&BoundingSphere { center: glm::Vec3::zeros(), radius: 0.5 }));
+        for (i, center) in outside().iter().enumerate() {
+            assert!(!frustum.intersects_sphere(&BoundingSphere { center: *center, radius: 0.1 }), "plane {}", i);
+        }
+        assert!(frustum.intersects_sphere(&BoundingSphere { center: glm::vec3(5.5, 0.0, 0.0), radius: 1.0 }));
+        assert!(frustum.intersects_sphere(&BoundingSphere { center: glm::vec3(0.0, -5.5, 0.0), radius: 1.0 }));
+        assert!(frustum.intersects_sphere(&BoundingSphere { center: glm::vec3(0.0, 0.0, -5.5), radius: 1.0 }));
+    }
+
+    #[test]
+    fn transform_of_a_rotated_box() {
+        // a 2x2x2 cube turned by 45° around z and moved, its corners reach sqrt(2) out along x and y
+        let matrix = glm::rotate_z(&glm::translation(&glm::vec3(1.0, 2.0, 3.0)), 45f32.to_radians());
+        let aabb = cube(glm::Vec3::zeros(), 1.0).transform(&matrix);
+        let half = 2f32.sqrt();
+        assert_vec3(&aabb.min, &glm::vec3(1.0 - half, 2.0 - half, 2.0));
+        assert_vec3(&aabb.max, &glm::vec3(1.0 + half, 2.0 + half, 4.0));
+
+        // it contains every transformed corner
+        let corners = [-1.0, 1.0].iter().flat_map(|&x| [-1.0, 1.0].iter().flat_map(move |&y| [
+            glm::vec3(x, y, -1.0f32), glm::vec3(x, y, 1.0),
+        ]));
+        for corner in corners {
+            let corner = (matrix * corner.push(1.0)).xyz();
+            assert!(corner.iter().zip(aabb.min.iter()).all(|(c, min)| *c >= min - 1e-4));
+            assert!(corner.iter().zip(aabb.max.iter()).all(|(c, max)| *c <= max + 1e-4));
+        }
+
+        assert!(Aabb::default().transform(&matrix).is_empty());
+    }
+
+    #[test]
+    fn sphere_transform_uses_the_largest_scale() {
+        let sphere = BoundingSphere { center: glm::vec3(1.0, 0.0, 0.0), radius: 1.0 };
+        let matrix = glm::scale(&glm::translation(&glm::vec3(0.0, 1.0, 0.0)), &glm::vec3(2.0, 3.0, 1.0));
+        let transformed = sphere.transform(&matrix);
+        assert_vec3(&transformed.center, &glm::vec3(2.0, 1.0, 0.0));
+        assert!((transformed.radius - 3.0).abs() < 1e-5);
+    }
+}
@@ -1,22 +1,169 @@
 use nalgebra_glm as glm;
+use crate::bounds::Frustum;
 
 // Defines several possible options for camera movement. Used as abstraction to stay away from window-system specific input methods
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum CameraMovement {
     Forward,
     Backward,
     Left,
     Right,
+    RollLeft,
+    RollRight,
+}
+
+// Defines how the Camera stores its rotation
+#[derive(PartialEq, Clone, Copy)]
+pub enum OrientationMode {
+    // yaw and pitch with the pitch clamped and no roll, the classic FPS camera
+    Euler,
+    // a quaternion that rotates freely around the camera's own axes, supports roll and has no gimbal lock
+    Quaternion,
 }
 
 use self::CameraMovement::*;
 
+// Defines the possible projection types of a camera
+#[derive(PartialEq, Clone, Copy)]
+pub enum ProjectionMode {
+    Perspective,
+    Orthographic,
+}
+
 // Default camera values
 const YAW: f32 = -90.0;
 const PITCH: f32 = 0.0;
 const SPEED: f32 = 2.5;
 const SENSITIVITY: f32 = 0.1;
 const ZOOM: f32 = 45.0;
+const ROLL: f32 = 0.0;
+const ROLL_SPEED: f32 = 90.0;
+
+// Default projection values
+const ASPECT_RATIO: f32 = 800.0 / 600.0;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 100.0;
+const ORTHO_HEIGHT: f32 = 10.0;
+
+// Default orbit camera values
+const DISTANCE: f32 = 3.0;
+const MIN_DISTANCE: f32 = 0.1;
+const MAX_DISTANCE: f32 = 100.0;
+const ORBIT_YAW: f32 = 90.0;
+const PAN_SENSITIVITY: f32 = 0.002;
+const ZOOM_SPEED: f32 = 0.1;
+
+// The position, view direction and roll (as Euler Angles in degrees) and zoom of a camera, independent of the camera
+// type
+#[derive(Clone, Copy)]
+pub struct CameraPose {
+    pub position: glm::Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub zoom: f32,
+}
+
+// The viewport and clipping planes of a camera, used to calculate its projection matrix
+pub struct Projection {
+    pub mode: ProjectionMode,
+    pub aspect_ratio: f32,
+    pub near: f32,
+    pub far: f32,
+    // height of the view volume in orthographic mode, the width follows from the aspect ratio
+    pub ortho_height: f32,
+    // maps the near plane to depth 1.0 and the far plane to 0.0, which spreads the floating point depth precision
+    // much more evenly. Needs a [0, 1] depth range (glClipControl), glClearDepth(0.0) and glDepthFunc(GL_GREATER).
+    pub reversed_z: bool,
+    // moves the far plane to infinity for perspective projections, the far value is ignored then
+    pub infinite_far: bool,
+}
+
+impl Default for Projection {
+    fn default() -> Projection {
+        Projection {
+            mode: ProjectionMode::Perspective,
+            aspect_ratio: ASPECT_RATIO,
+            near: NEAR,
+            far: FAR,
+            ortho_height: ORTHO_HEIGHT,
+            reversed_z: false,
+            infinite_far: false,
+        }
+    }
+}
+
+impl Projection {
+    // returns the projection matrix, the vertical field of view (in degrees) is only used in perspective mode
+    pub fn get_matrix(&self, fovy: f32) -> glm::Mat4 {
+        match self.mode {
+            ProjectionMode::Perspective => {
+                let fovy = fovy.to_radians();
+                match (self.reversed_z, self.infinite_far) {
+                    (false, false) => glm::perspective(self.aspect_ratio, fovy, self.near, self.far),
+                    (false, true) => glm::infinite_perspective_rh_no(self.aspect_ratio, fovy, self.near),
+                    (true, false) => glm::reversed_perspective_rh_zo(self.aspect_ratio, fovy, self.near, self.far),
+                    (true, true) => glm::reversed_infinite_perspective_rh_zo(self.aspect_ratio, fovy, self.near),
+                }
+            }
+            ProjectionMode::Orthographic => {
+                let top = self.ortho_height / 2.0;
+                let right = top * self.aspect_ratio;
+                if self.reversed_z {
+                    // swapping the planes of a [0, 1] projection maps near to 1.0 and far to 0.0
+                    glm::ortho_rh_zo(-right, right, -top, top, self.far, self.near)
+                } else {
+                    glm::ortho(-right, right, -top, top, self.near, self.far)
+                }
+            }
+        }
+    }
+
+    // keeps the aspect ratio in sync with the framebuffer, ignoring the zero size of a minimized window
+    pub fn set_viewport_size(&mut self, width: i32, height: i32) {
+        if width > 0 && height > 0 {
+            self.aspect_ratio = width as f32 / height as f32;
+        }
+    }
+}
+
+// The operations every camera type supports, so a tutorial can switch between camera types at runtime
+pub trait CameraController {
+    // returns the view matrix
+    fn get_view_matrix(&self) -> glm::Mat4;
+
+    // returns the projection matrix for the current viewport
+    fn get_projection_matrix(&self) -> glm::Mat4;
+
+    // returns the world space planes of the visible volume
+    fn get_frustum(&self) -> Frustum {
+        Frustum::from_matrix(&(self.get_projection_matrix() * self.get_view_matrix()))
+    }
+
+    // returns the position of the camera in world space
+    fn get_position(&self) -> glm::Vec3;
+
+    // processes input received from any keyboard-like input system
+    fn process_keyboard(&mut self, direction: CameraMovement, delta_time: f32);
+
+    // processes input received from a mouse input system. Expects the offset value in both the x and y direction.
+    fn process_mouse_movement(&mut self, offset_x: f32, offset_y: f32, constrain_pitch: bool);
+
+    // processes input received from a mouse scroll-wheel event. Only requires input on the vertical wheel-axis
+    fn process_mouse_scroll(&mut self, offset_y: f32);
+
+    // processes a mouse drag that should move the camera parallel to the view plane, ignored by default
+    fn process_mouse_pan(&mut self, _offset_x: f32, _offset_y: f32) {}
+
+    // processes a framebuffer resize event so the projection keeps the correct aspect ratio
+    fn process_framebuffer_size(&mut self, width: i32, height: i32);
+
+    // returns where the camera is and where it is looking at, used to record camera paths
+    fn get_pose(&self) -> CameraPose;
+
+    // moves the camera to the given pose, used to play back camera paths
+    fn set_pose(&mut self, pose: &CameraPose);
+}
 
 // An abstract camera class that processes input and calculates the corresponding Euler Angles, Vectors and Matrices for use in OpenGL
 pub struct Camera {
@@ -30,11 +177,20 @@ pub struct Camera {
     // euler Angles
     pub yaw: f32,
     pub pitch: f32,
+    pub roll: f32,
+
+    // quaternion orientation, the rotation from camera space (looking down -Z) to world space
+    pub orientation: glm::Quat,
+    pub orientation_mode: OrientationMode,
 
     // camera options
     pub movement_speed: f32,
     pub mouse_sensitivity: f32,
+    pub roll_speed: f32,
     pub zoom: f32,
+
+    // projection options
+    pub projection: Projection,
 }
 
 impl Default for Camera {
@@ -47,10 +203,16 @@ impl Default for Camera {
             world_up: glm::vec3(0.0, 1.0, 0.0),
             yaw: YAW,
             pitch: PITCH,
+            roll: ROLL,
+            orientation: glm::quat_identity(),
+            orientation_mode: OrientationMode::Euler,
             movement_speed: SPEED,
             mouse_sensitivity: SENSITIVITY,
+            roll_speed: ROLL_SPEED,
             zoom: ZOOM,
+            projection: Projection::default(),
         };
+        camera.orientation = camera.orientation_from_euler_angles();
         camera.update_camera_vectors();
         camera
     }
@@ -62,6 +224,11 @@ impl Camera {
         glm::look_at(&self.position, &(self.position + self.front), &self.up)
     }
 
+    // returns the projection matrix, using the zoom as vertical field of view in perspective mode
+    pub fn get_projection_matrix(&self) -> glm::Mat4 {
+        self.projection.get_matrix(self.zoom)
+    }
+
     // processes input received from any keyboard-like input system. Accepts input parameter in the form of camera defined ENUM (to abstract it from windowing systems)
     pub fn process_keyboard(&mut self, direction: CameraMovement, delta_time: f32) {
         let velocity = self.movement_speed * delta_time;
@@ -77,6 +244,12 @@ impl Camera {
         if direction == Right {
             self.position += self.right * velocity;
         }
+        if direction == RollLeft {
+            self.process_roll(-self.roll_speed * delta_time);
+        }
+        if direction == RollRight {
+            self.process_roll(self.roll_speed * delta_time);
+        }
     }
 
     // processes input received from a mouse input system. Expects the offset value in both the x and y direction.
@@ -84,20 +257,24 @@ impl Camera {
         offset_x *= self.mouse_sensitivity;
         offset_y *= self.mouse_sensitivity;
 
+        if self.orientation_mode == OrientationMode::Quaternion {
+            // yaw around the camera's own up axis and pitch around its own right axis, so there is nothing to clamp
+            let yaw = glm::quat_angle_axis(-offset_x.to_radians(), &glm::vec3(0.0, 1.0, 0.0));
+            let pitch = glm::quat_angle_axis(offset_y.to_radians(), &glm::vec3(1.0, 0.0, 0.0));
+            self.set_orientation(&(self.orientation * yaw * pitch));
+            return;
+        }
+
         self.yaw += offset_x;
         self.pitch += offset_y;
 
         // make sure that when pitch is out of bounds, screen doesn't get flipped
         if constrain_pitch {
-            if self.pitch > 89.0 {
-                self.pitch = 89.0;
-            }
-            if self.pitch < -89.0 {
-                self.pitch = -89.0;
-            }
+            self.pitch = self.pitch.clamp(-89.0, 89.0);
         }
 
         // update Front, Right and Up Vectors using the updated Euler angles
+        self.orientation = self.orientation_from_euler_angles();
         self.update_camera_vectors();
     }
 
@@ -105,16 +282,103 @@ impl Camera {
     pub fn process_mouse_scroll(&mut self, offset_y: f32) {
         self.zoom -= offset_y;
 
-        if self.zoom <= 1.0 {
-            self.zoom = 1.0;
+        self.zoom = self.zoom.clamp(1.0, 45.0);
+    }
+
+    // processes a framebuffer resize event, the new size determines the aspect ratio of the projection
+    pub fn process_framebuffer_size(&mut self, width: i32, height: i32) {
+        self.projection.set_viewport_size(width, height);
+    }
+
+    // rolls the camera around its front vector, positive angles (in degrees) bank to the right. Only has an effect in Quaternion mode.
+    pub fn process_roll(&mut self, angle: f32) {
+        if self.orientation_mode == OrientationMode::Quaternion {
+            let roll = glm::quat_angle_axis(angle.to_radians(), &glm::vec3(0.0, 0.0, -1.0));
+            self.set_orientation(&(self.orientation * roll));
         }
-        if self.zoom >= 45.0 {
-            self.zoom = 45.0;
+    }
+
+    // switches between Euler and Quaternion mode, keeping the current view direction
+    pub fn set_orientation_mode(&mut self, mode: OrientationMode) {
+        self.orientation_mode = mode;
+        if mode == OrientationMode::Euler {
+            // an FPS camera has neither roll nor a pitch beyond straight up/down
+            self.roll = 0.0;
+            self.pitch = self.pitch.clamp(-89.0, 89.0);
         }
+        self.orientation = self.orientation_from_euler_angles();
+        self.update_camera_vectors();
+    }
+
+    // sets the orientation and updates yaw, pitch and roll to match. In Euler mode the roll is dropped.
+    pub fn set_orientation(&mut self, orientation: &glm::Quat) {
+        self.orientation = glm::quat_normalize(orientation);
+        let (yaw, pitch, roll) = self.euler_angles_from_orientation();
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.roll = roll;
+
+        if self.orientation_mode == OrientationMode::Euler {
+            self.roll = 0.0;
+            self.orientation = self.orientation_from_euler_angles();
+        }
+
+        self.update_camera_vectors();
+    }
+
+    // smoothly rotates towards the target orientation, t = 0.0 keeps the current orientation and t = 1.0 reaches the target
+    pub fn slerp_orientation(&mut self, target: &glm::Quat, t: f32) {
+        let orientation = glm::quat_slerp(&self.orientation, target, t);
+        self.set_orientation(&orientation);
+    }
+
+    // re-calculates the orientation after yaw, pitch or roll have been changed directly
+    pub fn sync_orientation(&mut self) {
+        self.orientation = self.orientation_from_euler_angles();
+        self.update_camera_vectors();
+    }
+
+    // builds the quaternion matching the Euler Angles: yaw around the world up vector, then pitch around the camera's
+    // right vector and finally roll around its front vector. The default yaw of -90 degrees maps to the identity.
+    pub fn orientation_from_euler_angles(&self) -> glm::Quat {
+        let yaw = glm::quat_angle_axis(-(self.yaw + 90.0).to_radians(), &self.world_up);
+        let pitch = glm::quat_angle_axis(self.pitch.to_radians(), &glm::vec3(1.0, 0.0, 0.0));
+        let roll = glm::quat_angle_axis(self.roll.to_radians(), &glm::vec3(0.0, 0.0, -1.0));
+        glm::quat_normalize(&(yaw * pitch * roll))
+    }
+
+    // extracts yaw, pitch and roll (in degrees) from the orientation, the inverse of orientation_from_euler_angles
+    pub fn euler_angles_from_orientation(&self) -> (f32, f32, f32) {
+        let front = glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 0.0, -1.0));
+        let up = glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 1.0, 0.0));
+
+        let yaw = front.z.atan2(front.x).to_degrees();
+        let pitch = front.y.clamp(-1.0, 1.0).asin().to_degrees();
+
+        // the roll is the angle between the actual up vector and the one an unrolled camera would have
+        let right = front.cross(&self.world_up);
+        let roll = if right.norm() > f32::EPSILON {
+            let right = right.normalize();
+            let unrolled_up = right.cross(&front);
+            up.dot(&right).atan2(up.dot(&unrolled_up)).to_degrees()
+        } else {
+            // looking straight up or down, the roll can't be told apart from the yaw
+            0.0
+        };
+
+        (yaw, pitch, roll)
     }
 
     // calculates the front vector from the Camera's (updated) Euler Angles
     fn update_camera_vectors(&mut self) {
+        if self.orientation_mode == OrientationMode::Quaternion {
+            // the camera vectors are simply the rotated camera space axes
+            self.front = glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 0.0, -1.0)).normalize();
+            self.right = glm::quat_rotate_vec3(&self.orientation, &glm::vec3(1.0, 0.0, 0.0)).normalize();
+            self.up = glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 1.0, 0.0)).normalize();
+            return;
+        }
+
         // calculate the new Front vector
         let front = glm::vec3(
             self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
@@ -126,4 +390,391 @@ impl Camera {
         self.right = self.front.cross(&self.world_up).normalize(); // normalize the vectors, because their length gets closer to 0 the more you look up or down which results in slower movement.
         self.up = self.right.cross(&self.front).normalize();
     }
-}
\ No newline at end of file
+}
+impl CameraController for Camera {
+    fn get_view_matrix(&self) -> glm::Mat4 {
+        Camera::get_view_matrix(self)
+    }
+
+    fn get_projection_matrix(&self) -> glm::Mat4 {
+        Camera::get_projection_matrix(self)
+    }
+
+    fn get_position(&self) -> glm::Vec3 {
+        self.position
+    }
+
+    fn process_keyboard(&mut self, direction: CameraMovement, delta_time: f32) {
+        Camera::process_keyboard(self, direction, delta_time);
+    }
+
+    fn process_mouse_movement(&mut self, offset_x: f32, offset_y: f32, constrain_pitch: bool) {
+        Camera::process_mouse_movement(self, offset_x, offset_y, constrain_pitch);
+    }
+
+    fn process_mouse_scroll(&mut self, offset_y: f32) {
+        Camera::process_mouse_scroll(self, offset_y);
+    }
+
+    fn process_framebuffer_size(&mut self, width: i32, height: i32) {
+        Camera::process_framebuffer_size(self, width, height);
+    }
+
+    fn get_pose(&self) -> CameraPose {
+        CameraPose {
+            position: self.position,
+            yaw: self.yaw,
+            pitch: self.pitch,
+            roll: self.roll,
+            zoom: self.zoom,
+        }
+    }
+
+    // the roll only applies in Quaternion mode, like process_roll
+    fn set_pose(&mut self, pose: &CameraPose) {
+        self.position = pose.position;
+        self.yaw = pose.yaw;
+        self.pitch = pose.pitch;
+        self.roll = if self.orientation_mode == OrientationMode::Quaternion { pose.roll } else { 0.0 };
+        self.zoom = pose.zoom;
+        self.sync_orientation();
+    }
+}
+
+// Defines how an OrbitCamera reacts to mouse movement
+#[derive(PartialEq, Clone, Copy)]
+pub enum OrbitMode {
+    // yaw and pitch around the target, keeping the world up vector up (turntable style)
+    Orbit,
+    // free rotation around the target as if dragging a virtual trackball, the camera may end up upside down
+    Arcball,
+}
+
+// A camera that always looks at a target point, useful to inspect a model from all sides. The camera rotates around
+// the target, zooms by changing its distance to the target and pans by moving the target parallel to the view plane.
+// Moving the target every frame (see follow) turns it into a third-person camera.
+pub struct OrbitCamera {
+    // orbit Attributes
+    pub target: glm::Vec3,
+    pub distance: f32,
+    pub world_up: glm::Vec3,
+    pub mode: OrbitMode,
+
+    // euler Angles of the camera position around the target, used in Orbit mode
+    pub yaw: f32,
+    pub pitch: f32,
+
+    // rotation of the camera around the target, used in Arcball mode
+    pub orientation: glm::Quat,
+
+    // camera options
+    pub movement_speed: f32,
+    pub mouse_sensitivity: f32,
+    pub pan_sensitivity: f32,
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub zoom: f32,
+
+    // projection options
+    pub projection: Projection,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> OrbitCamera {
+        OrbitCamera {
+            target: glm::vec3(0.0, 0.0, 0.0),
+            distance: DISTANCE,
+            world_up: glm::vec3(0.0, 1.0, 0.0),
+            mode: OrbitMode::Orbit,
+            yaw: ORBIT_YAW,
+            pitch: PITCH,
+            orientation: glm::quat_identity(),
+            movement_speed: SPEED,
+            mouse_sensitivity: SENSITIVITY,
+            pan_sensitivity: PAN_SENSITIVITY,
+            zoom_speed: ZOOM_SPEED,
+            min_distance: MIN_DISTANCE,
+            max_distance: MAX_DISTANCE,
+            zoom: ZOOM,
+            projection: Projection::default(),
+        }
+    }
+}
+
+impl OrbitCamera {
+    // switches between Orbit and Arcball mode, keeping the current camera position
+    pub fn set_mode(&mut self, mode: OrbitMode) {
+        if self.mode == mode {
+            return;
+        }
+
+        match mode {
+            OrbitMode::Orbit => {
+                // recover yaw and pitch from the direction pointing from the target to the camera
+                let direction = glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 0.0, 1.0));
+                self.yaw = direction.z.atan2(direction.x).to_degrees();
+                self.pitch = direction.y.clamp(-1.0, 1.0).asin().to_degrees();
+            }
+            OrbitMode::Arcball => self.orientation = self.orientation_from_orbit_angles(),
+        }
+
+        self.mode = mode;
+    }
+
+    // moves the point the camera is looking at, call it every frame with the position of an object to get a third-person camera
+    pub fn follow(&mut self, target: &glm::Vec3) {
+        self.target = *target;
+    }
+
+    // returns the right, up and (target to camera) direction vectors
+    fn camera_vectors(&self) -> (glm::Vec3, glm::Vec3, glm::Vec3) {
+        match self.mode {
+            OrbitMode::Orbit => self.orbit_vectors(),
+            OrbitMode::Arcball => (
+                glm::quat_rotate_vec3(&self.orientation, &glm::vec3(1.0, 0.0, 0.0)),
+                glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 1.0, 0.0)),
+                glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 0.0, 1.0)),
+            ),
+        }
+    }
+
+    // builds the arcball orientation that matches the Euler Angles of Orbit mode
+    fn orientation_from_orbit_angles(&self) -> glm::Quat {
+        let (right, up, direction) = self.orbit_vectors();
+        glm::mat3_to_quat(&glm::mat3(
+            right.x, up.x, direction.x,
+            right.y, up.y, direction.y,
+            right.z, up.z, direction.z,
+        ))
+    }
+
+    // calculates the camera vectors from the Euler Angles, the same way Camera does
+    fn orbit_vectors(&self) -> (glm::Vec3, glm::Vec3, glm::Vec3) {
+        let direction = glm::vec3(
+            self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
+            self.pitch.to_radians().sin(),
+            self.yaw.to_radians().sin() * self.pitch.to_radians().cos(),
+        ).normalize();
+        let right = (-direction).cross(&self.world_up).normalize();
+        let up = right.cross(&(-direction)).normalize();
+        (right, up, direction)
+    }
+}
+
+impl CameraController for OrbitCamera {
+    fn get_view_matrix(&self) -> glm::Mat4 {
+        let (_, up, _) = self.camera_vectors();
+        glm::look_at(&self.get_position(), &self.target, &up)
+    }
+
+    fn get_projection_matrix(&self) -> glm::Mat4 {
+        self.projection.get_matrix(self.zoom)
+    }
+
+    fn get_position(&self) -> glm::Vec3 {
+        let (_, _, direction) = self.camera_vectors();
+        self.target + direction * self.distance
+    }
+
+    // moves towards/away from the target and orbits left/right around it
+    fn process_keyboard(&mut self, direction: CameraMovement, delta_time: f32) {
+        let velocity = self.movement_speed * delta_time;
+        match direction {
+            Forward => self.distance = (self.distance - velocity).max(self.min_distance),
+            Backward => self.distance = (self.distance + velocity).min(self.max_distance),
+            RollLeft | RollRight => {}
+            Left | Right => {
+                // a full turn takes as long as walking the circumference of the orbit
+                let mut angle = (velocity / self.distance).to_degrees();
+                if direction == Left {
+                    angle = -angle;
+                }
+                match self.mode {
+                    OrbitMode::Orbit => self.yaw += angle,
+                    OrbitMode::Arcball => {
+                        let (_, up, _) = self.camera_vectors();
+                        self.orientation = glm::quat_normalize(
+                            &(glm::quat_angle_axis(angle.to_radians(), &up) * self.orientation));
+                    }
+                }
+            }
+        }
+    }
+
+    fn process_mouse_movement(&mut self, mut offset_x: f32, mut offset_y: f32, constrain_pitch: bool) {
+        offset_x *= self.mouse_sensitivity;
+        offset_y *= self.mouse_sensitivity;
+
+        match self.mode {
+            OrbitMode::Orbit => {
+                // dragging to the right rotates the scene to the right, so the camera moves to the left
+                self.yaw -= offset_x;
+                self.pitch -= offset_y;
+
+                // make sure that when pitch is out of bounds, screen doesn't get flipped
+                if constrain_pitch {
+                    self.pitch = self.pitch.clamp(-89.0, 89.0);
+                }
+            }
+            OrbitMode::Arcball => {
+                // the scene rotates around the axis perpendicular to the drag direction, which means the camera
+                // rotates around the same (camera space) axis in the opposite direction
+                let angle = (offset_x * offset_x + offset_y * offset_y).sqrt();
+                if angle > 0.0 {
+                    let axis = glm::vec3(-offset_y, offset_x, 0.0).normalize();
+                    self.orientation = glm::quat_normalize(
+                        &(self.orientation * glm::quat_angle_axis(-angle.to_radians(), &axis)));
+                }
+            }
+        }
+    }
+
+    // zooms by moving the camera towards/away from the target, the step is relative to the distance
+    fn process_mouse_scroll(&mut self, offset_y: f32) {
+        self.distance *= 1.0 - offset_y * self.zoom_speed;
+        self.distance = self.distance.clamp(self.min_distance, self.max_distance);
+    }
+
+    // moves the target parallel to the view plane, scaled by the distance so the scene follows the cursor
+    fn process_mouse_pan(&mut self, offset_x: f32, offset_y: f32) {
+        let (right, up, _) = self.camera_vectors();
+        let scale = self.pan_sensitivity * self.distance;
+        self.target -= right * offset_x * scale + up * offset_y * scale;
+    }
+
+    fn process_framebuffer_size(&mut self, width: i32, height: i32) {
+        self.projection.set_viewport_size(width, height);
+    }
+
+    // the camera looks in the opposite direction of the one pointing from the target to the camera
+    fn get_pose(&self) -> CameraPose {
+        let (_, _, direction) = self.camera_vectors();
+        let front = -direction;
+        CameraPose {
+            position: self.get_position(),
+            yaw: front.z.atan2(front.x).to_degrees(),
+            pitch: front.y.clamp(-1.0, 1.0).asin().to_degrees(),
+            // like set_pose the orbit camera only keeps the view direction, not how far an arcball is tilted
+            roll: 0.0,
+            zoom: self.zoom,
+        }
+    }
+
+    // keeps the distance and moves the target in front of the camera, the roll is ignored
+    fn set_pose(&mut self, pose: &CameraPose) {
+        self.yaw = pose.yaw + 180.0;
+        self.pitch = -pose.pitch;
+        self.zoom = pose.zoom;
+
+        let (_, _, direction) = self.orbit_vectors();
+        self.target = pose.position - direction * self.distance;
+        if self.mode == OrbitMode::Arcball {
+            self.orientation = self.orientation_from_orbit_angles();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    // whether two angles in degrees point the same way
+    fn same_angle(a: f32, b: f32) -> bool {
+        close((a - b + 180.0).rem_euclid(360.0), 180.0)
+    }
+
+    fn quaternion_camera(yaw: f32, pitch: f32, roll: f32) -> Camera {
+        let mut camera = Camera { orientation_mode: OrientationMode::Quaternion, yaw, pitch, roll, ..Camera::default() };
+        camera.sync_orientation();
+        camera
+    }
+
+    // the depth of a point at the distance in front of the camera, after the perspective divide
+    fn depth(projection: &Projection, distance: f32) -> f32 {
+        let clip = projection.get_matrix(45.0) * glm::vec4(0.0, 0.0, -distance, 1.0);
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn the_default_yaw_is_the_identity() {
+        let camera = Camera::default();
+        assert!(glm::quat_equal_eps(&camera.orientation, &glm::quat_identity(), 1e-6).iter().all(|&equal| equal));
+        assert!((camera.front - glm::vec3(0.0, 0.0, -1.0)).norm() < 1e-6);
+    }
+
+    #[test]
+    fn euler_angles_survive_the_round_trip() {
+        for (yaw, pitch, roll) in [(-90.0, 0.0, 0.0), (0.0, 30.0, 0.0), (135.0, -45.0, 20.0), (-170.0, 80.0, -100.0),
+                                   (180.0, -10.0, 179.0)] {
+            let camera = quaternion_camera(yaw, pitch, roll);
+            let (yaw2, pitch2, roll2) = camera.euler_angles_from_orientation();
+            assert!(same_angle(yaw2, yaw), "{} {}", yaw, yaw2);
+            assert!(close(pitch2, pitch), "{} {}", pitch, pitch2);
+            assert!(same_angle(roll2, roll), "{} {}", roll, roll2);
+
+            // and the Euler mode camera looks the same way
+            let mut euler = Camera { yaw, pitch, ..Camera::default() };
+            euler.sync_orientation();
+            assert!((euler.front - camera.front).norm() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn positive_roll_banks_to_the_right() {
+        let mut camera = quaternion_camera(-90.0, 0.0, 0.0);
+        camera.process_roll(30.0);
+        assert!(close(camera.roll, 30.0), "{}", camera.roll);
+        // the up vector tilts towards the right one
+        assert!(close(camera.up.x, 0.5) && close(camera.up.y, 0.75_f32.sqrt()), "{:?}", camera.up);
+        assert!((camera.front - glm::vec3(0.0, 0.0, -1.0)).norm() < 1e-5);
+
+        // Euler mode has no roll
+        let mut camera = Camera::default();
+        camera.process_roll(30.0);
+        assert_eq!(camera.roll, 0.0);
+        assert!((camera.up - glm::vec3(0.0, 1.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn looking_straight_up_has_no_roll() {
+        // the gimbal lock case: yaw and roll turn around the same axis, all of it ends up in the yaw
+        let camera = quaternion_camera(30.0, 90.0, 40.0);
+        let (yaw, pitch, roll) = camera.euler_angles_from_orientation();
+        assert!(close(pitch, 90.0) && roll == 0.0 && yaw.is_finite(), "{} {} {}", yaw, pitch, roll);
+        let rebuilt = quaternion_camera(yaw, pitch, roll);
+        assert!((rebuilt.front - camera.front).norm() < 1e-3);
+
+        // the quaternion camera can pitch past it, where the Euler mode camera stops
+        let mut camera = quaternion_camera(-90.0, 80.0, 0.0);
+        camera.process_mouse_movement(0.0, 200.0, true);
+        assert!(camera.front.z > 0.0 && camera.up.y < 0.0, "{:?} {:?}", camera.front, camera.up);
+    }
+
+    #[test]
+    fn reversed_z_maps_near_to_one_and_far_to_zero() {
+        let mut projection = Projection { near: 0.1, far: 100.0, ..Projection::default() };
+        assert!(close(depth(&projection, 0.1), -1.0) && close(depth(&projection, 100.0), 1.0));
+
+        projection.reversed_z = true;
+        assert!(close(depth(&projection, 0.1), 1.0) && close(depth(&projection, 100.0), 0.0));
+        assert!(depth(&projection, 1.0) > depth(&projection, 2.0));
+
+        // the infinite far plane is reached at infinity
+        projection.infinite_far = true;
+        assert!(close(depth(&projection, 0.1), 1.0));
+        assert!(depth(&projection, 1e6) > 0.0 && depth(&projection, 1e6) < 1e-6);
+
+        projection.reversed_z = false;
+        assert!(close(depth(&projection, 0.1), -1.0));
+        assert!(depth(&projection, 1e6) < 1.0 && close(depth(&projection, 1e6), 1.0));
+
+        projection.mode = ProjectionMode::Orthographic;
+        projection.reversed_z = true;
+        assert!(close(depth(&projection, 0.1), 1.0) && close(depth(&projection, 100.0), 0.0));
+    }
+}
@@ -0,0 +1,421 @@
+use std::fs;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use crate::camera::{CameraController, CameraPose};
+
+// the minimum time between two recorded keyframes, the spline fills in the frames in between
+const RECORD_INTERVAL: f32 = 0.1;
+
+// Defines how a camera path moves between its keyframes
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum Interpolation {
+    // a smooth curve that passes through every keyframe
+    CatmullRom,
+    // cubic Bézier segments between the keyframes, with control points that keep the speed steady across keyframes
+    // that were recorded at uneven intervals, which suits slow fly-throughs
+    Bezier,
+}
+
+// A recorded camera pose at a point in time (in seconds since the recording started). The angles are in degrees, the
+// roll can be left out for cameras that don't roll.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    #[serde(default)]
+    pub roll: f32,
+    pub zoom: f32,
+}
+
+impl CameraKeyframe {
+    fn new(time: f32, pose: &CameraPose) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            position: [pose.position.x, pose.position.y, pose.position.z],
+            yaw: pose.yaw,
+            pitch: pose.pitch,
+            roll: pose.roll,
+            zoom: pose.zoom,
+        }
+    }
+
+    fn pose(&self) -> CameraPose {
+        CameraPose {
+            position: glm::make_vec3(&self.position),
+            yaw: self.yaw,
+            pitch: self.pitch,
+            roll: self.roll,
+            zoom: self.zoom,
+        }
+    }
+
+    // the animated values as one vector, so the interpolation can treat them all the same way
+    fn values(&self) -> [f32; 7] {
+        [self.position[0], self.position[1], self.position[2], self.yaw, self.pitch, self.roll, self.zoom]
+    }
+
+    // turns the yaw and the roll by whole turns to within 180 degrees of the previous keyframe's, so the camera takes
+    // the short way from 179 to -179 degrees instead of spinning around the other way
+    fn unwrap_angles(&mut self, previous: &CameraKeyframe) {
+        let unwrap = |angle: f32, previous: f32| previous + (angle - previous + 180.0).rem_euclid(360.0) - 180.0;
+        self.yaw = unwrap(self.yaw, previous.yaw);
+        self.roll = unwrap(self.roll, previous.roll);
+    }
+}
+
+// A list of keyframes that can be saved to and loaded from a RON file
+#[derive(Serialize, Deserialize)]
+pub struct CameraPath {
+    pub interpolation: Interpolation,
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+impl Default for CameraPath {
+    fn default() -> CameraPath {
+        CameraPath {
+            interpolation: Interpolation::CatmullRom,
+            keyframes: Vec::new(),
+        }
+    }
+}
+
+impl CameraPath {
+    // reads a camera path from a RON file
+    pub fn load(path: &str) -> Result<CameraPath, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => return Err(format!("ERROR::CAMERA_PATH::FILE_NOT_SUCCESFULLY_READ: {}", error)),
+        };
+        let mut camera_path: CameraPath = match ron::from_str(&text) {
+            Ok(camera_path) => camera_path,
+            Err(error) => return Err(format!("ERROR::CAMERA_PATH::PARSE_ERROR: {}", error)),
+        };
+        if camera_path.keyframes.is_empty() {
+            return Err(format!("ERROR::CAMERA_PATH::NO_KEYFRAMES: {}", path));
+        }
+        for i in 1..camera_path.keyframes.len() {
+            let previous = camera_path.keyframes[i - 1];
+            camera_path.keyframes[i].unwrap_angles(&previous);
+        }
+        Ok(camera_path)
+    }
+
+    // writes the camera path to a RON file
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(text) => text,
+            Err(error) => return Err(format!("ERROR::CAMERA_PATH::SERIALIZE_ERROR: {}", error)),
+        };
+        match fs::write(path, text) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(format!("ERROR::CAMERA_PATH::FILE_NOT_SUCCESFULLY_WRITTEN: {}", error)),
+        }
+    }
+
+    // appends the current pose of the camera, keyframes are expected to be recorded in chronological order
+    pub fn record(&mut self, time: f32, camera: &dyn CameraController) {
+        let mut keyframe = CameraKeyframe::new(time, &camera.get_pose());
+        if let Some(previous) = self.keyframes.last() {
+            keyframe.unwrap_angles(previous);
+        }
+        self.keyframes.push(keyframe);
+    }
+
+    // the time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    // returns the interpolated camera pose at the given time, clamped to the start and the end of the path
+    pub fn sample(&self, time: f32) -> Option<CameraPose> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if self.keyframes.len() == 1 || time <= first.time {
+            return Some(first.pose());
+        }
+        if time >= last.time {
+            return Some(last.pose());
+        }
+
+        let values = match self.interpolation {
+            Interpolation::CatmullRom => self.sample_catmull_rom(time),
+            Interpolation::Bezier => self.sample_bezier(time),
+        };
+
+        Some(CameraPose {
+            position: glm::vec3(values[0], values[1], values[2]),
+            yaw: values[3],
+            pitch: values[4],
+            roll: values[5],
+            zoom: values[6],
+        })
+    }
+
+    // moves the camera to its pose at the given time
+    pub fn apply(&self, time: f32, camera: &mut dyn CameraController) {
+        if let Some(pose) = self.sample(time) {
+            camera.set_pose(&pose);
+        }
+    }
+
+    // the keyframes of the segment p1 -> p2 that contains the time and how far into it the time is (0 to 1)
+    fn segment(&self, time: f32) -> (usize, f32) {
+        let last = self.keyframes.len() - 1;
+        let i = self.keyframes.iter().rposition(|keyframe| keyframe.time <= time).unwrap_or(0).min(last - 1);
+        let duration = self.keyframes[i + 1].time - self.keyframes[i].time;
+        let t = if duration > 0.0 { (time - self.keyframes[i].time) / duration } else { 0.0 };
+        (i, t)
+    }
+
+    fn sample_catmull_rom(&self, time: f32) -> [f32; 7] {
+        // the outer points are repeated at both ends of the path
+        let (i, t) = self.segment(time);
+        let last = self.keyframes.len() - 1;
+        let p0 = self.keyframes[i.saturating_sub(1)].values();
+        let p1 = self.keyframes[i].values();
+        let p2 = self.keyframes[i + 1].values();
+        let p3 = self.keyframes[(i + 2).min(last)].values();
+
+        let t2 = t * t;
+        let t3 = t2 * t;
+
+        let mut values = [0.0; 7];
+        for (j, value) in values.iter_mut().enumerate() {
+            *value = 0.5 * ((2.0 * p1[j])
+                + (-p0[j] + p2[j]) * t
+                + (2.0 * p0[j] - 5.0 * p1[j] + 4.0 * p2[j] - p3[j]) * t2
+                + (-p0[j] + 3.0 * p1[j] - 3.0 * p2[j] + p3[j]) * t3);
+        }
+        values
+    }
+
+    // the rate of change per second at a keyframe, from its neighbours (or the one neighbour at the ends)
+    fn velocity(&self, i: usize) -> [f32; 7] {
+        let before = &self.keyframes[i.saturating_sub(1)];
+        let after = &self.keyframes[(i + 1).min(self.keyframes.len() - 1)];
+        let duration = after.time - before.time;
+        let (before, after) = (before.values(), after.values());
+        let mut velocity = [0.0; 7];
+        if duration > 0.0 {
+            for (j, value) in velocity.iter_mut().enumerate() {
+                *value = (after[j] - before[j]) / duration;
+            }
+        }
+        velocity
+    }
+
+    fn sample_bezier(&self, time: f32) -> [f32; 7] {
+        // the segment starts at p1 and ends at p2, the inner control points are a third of the segment's duration
+        // along the velocity at its ends, so the camera keeps its speed when it passes a keyframe
+        let (i, t) = self.segment(time);
+        let duration = self.keyframes[i + 1].time - self.keyframes[i].time;
+        let p1 = self.keyframes[i].values();
+        let p2 = self.keyframes[i + 1].values();
+        let (v1, v2) = (self.velocity(i), self.velocity(i + 1));
+
+        let s = 1.0 - t;
+        let mut values = [0.0; 7];
+        for (j, value) in values.iter_mut().enumerate() {
+            let c1 = p1[j] + v1[j] * duration / 3.0;
+            let c2 = p2[j] - v2[j] * duration / 3.0;
+            *value = s * s * s * p1[j] + 3.0 * s * s * t * c1 + 3.0 * s * t * t * c2 + t * t * t * p2[j];
+        }
+        values
+    }
+}
+
+// Records or plays back a camera path as requested on the command line:
+//   --record-path <file>  records the camera while the tutorial runs and saves it when the tutorial ends
+//   --play-path <file>    moves the camera along the path, restarting when the end is reached
+pub enum CameraPathDriver {
+    Record { file: String, path: CameraPath, start: Option<f32> },
+    Play { path: CameraPath, start: Option<f32> },
+}
+
+impl CameraPathDriver {
+    // returns a driver if one of the options was given, a path that can't be loaded is reported and ignored
+    pub fn from_args() -> Option<CameraPathDriver> {
+        let args: Vec<String> = std::env::args().collect();
+        let value = |option: &str| {
+            args.iter().position(|arg| arg == option).and_then(|i| args.get(i + 1)).cloned()
+        };
+
+        if let Some(file) = value("--play-path") {
+            return match CameraPath::load(&file) {
+                Ok(path) => Some(CameraPathDriver::Play { path, start: None }),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    None
+                }
+            };
+        }
+
+        value("--record-path").map(|file| CameraPathDriver::Record { file, path: CameraPath::default(), start: None })
+    }
+
+    // records the camera or moves it along the path, call it once per frame with the current time
+    pub fn update(&mut self, current_time: f32, camera: &mut dyn CameraController) {
+        match self {
+            CameraPathDriver::Record { path, start, .. } => {
+                let time = current_time - *start.get_or_insert(current_time);
+                if path.keyframes.is_empty() || time - path.duration() >= RECORD_INTERVAL {
+                    path.record(time, camera);
+                }
+            }
+            CameraPathDriver::Play { path, start } => {
+                let mut time = current_time - *start.get_or_insert(current_time);
+                if path.duration() > 0.0 {
+                    time %= path.duration();
+                }
+                path.apply(time, camera);
+            }
+        }
+    }
+}
+
+impl Drop for CameraPathDriver {
+    // a recording is saved once the tutorial has finished
+    fn drop(&mut self) {
+        if let CameraPathDriver::Record { file, path, .. } = self {
+            match path.save(file) {
+                Ok(_) => println!("Camera path saved to {}", file),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Camera, OrientationMode};
+
+    fn keyframe(time: f32, x: f32, yaw: f32) -> CameraKeyframe {
+        CameraKeyframe { time, position: [x, 2.0 * x, -x], yaw, pitch: 0.0, roll: 0.0, zoom: 45.0 }
+    }
+
+    // keyframes at uneven intervals
+    fn path(interpolation: Interpolation) -> CameraPath {
+        CameraPath {
+            interpolation,
+            keyframes: vec![keyframe(0.0, 0.0, -90.0), keyframe(1.0, 1.0, -45.0), keyframe(1.5, 3.0, 0.0),
+                            keyframe(4.0, 2.0, 90.0)],
+        }
+    }
+
+    fn assert_pose(pose: &CameraPose, keyframe: &CameraKeyframe) {
+        assert!((pose.position - glm::make_vec3(&keyframe.position)).norm() < 1e-4, "{:?}", pose.position);
+        assert!((pose.yaw - keyframe.yaw).abs() < 1e-3);
+        assert!((pose.pitch - keyframe.pitch).abs() < 1e-3);
+        assert!((pose.roll - keyframe.roll).abs() < 1e-3);
+        assert!((pose.zoom - keyframe.zoom).abs() < 1e-3);
+    }
+
+    #[test]
+    fn passes_through_the_keyframes() {
+        for interpolation in [Interpolation::CatmullRom, Interpolation::Bezier] {
+            let path = path(interpolation);
+            for keyframe in &path.keyframes {
+                assert_pose(&path.sample(keyframe.time).unwrap(), keyframe);
+            }
+        }
+    }
+
+    #[test]
+    fn clamps_at_the_ends() {
+        for interpolation in [Interpolation::CatmullRom, Interpolation::Bezier] {
+            let path = path(interpolation);
+            assert_pose(&path.sample(-1.0).unwrap(), &path.keyframes[0]);
+            assert_pose(&path.sample(10.0).unwrap(), &path.keyframes[3]);
+        }
+    }
+
+    #[test]
+    fn moves_between_the_keyframes() {
+        for interpolation in [Interpolation::CatmullRom, Interpolation::Bezier] {
+            let path = path(interpolation);
+            let yaw = path.sample(0.5).unwrap().yaw;
+            assert!(yaw > -90.0 && yaw < -45.0, "{}", yaw);
+        }
+    }
+
+    #[test]
+    fn bezier_keeps_a_steady_speed() {
+        // on keyframes spaced evenly along a line the speed is constant, even though the intervals differ
+        let path = CameraPath {
+            interpolation: Interpolation::Bezier,
+            keyframes: vec![keyframe(0.0, 0.0, 0.0), keyframe(1.0, 1.0, 0.0), keyframe(3.0, 3.0, 0.0)],
+        };
+        for time in [0.25, 0.5, 1.5, 2.0, 2.5] {
+            assert!((path.sample(time).unwrap().position.x - time).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn empty_and_single_keyframe_paths() {
+        let mut path = path(Interpolation::Bezier);
+        path.keyframes.truncate(1);
+        assert_pose(&path.sample(2.0).unwrap(), &path.keyframes[0]);
+        path.keyframes.clear();
+        assert!(path.sample(0.0).is_none());
+    }
+
+    #[test]
+    fn turns_the_short_way_across_180_degrees() {
+        // the yaw of a camera is in (-180, 180], turning a little past 180 jumps to the other end
+        let mut camera = Camera { orientation_mode: OrientationMode::Quaternion, ..Camera::default() };
+        let mut path = CameraPath::default();
+        for (time, yaw, roll) in [(0.0, 170.0, 175.0), (1.0, 179.0, -179.0), (2.0, -179.0, -170.0), (3.0, -170.0, 179.0)] {
+            camera.set_pose(&CameraPose { position: glm::zero(), yaw, pitch: 0.0, roll, zoom: 45.0 });
+            path.record(time, &camera);
+        }
+
+        for interpolation in [Interpolation::CatmullRom, Interpolation::Bezier] {
+            path.interpolation = interpolation;
+            let pose = path.sample(1.5).unwrap();
+            assert!((pose.yaw - 180.0).abs() < 1.0, "{}", pose.yaw);
+            for time in [0.5, 1.5, 2.5] {
+                let (before, after) = (path.sample(time - 0.25).unwrap(), path.sample(time + 0.25).unwrap());
+                assert!((after.yaw - before.yaw).abs() < 10.0, "{} {}", before.yaw, after.yaw);
+                assert!((after.roll - before.roll).abs() < 10.0, "{} {}", before.roll, after.roll);
+            }
+        }
+    }
+
+    #[test]
+    fn plays_back_the_roll() {
+        let mut camera = Camera { orientation_mode: OrientationMode::Quaternion, ..Camera::default() };
+        camera.process_roll(30.0);
+        let mut path = CameraPath::default();
+        path.record(0.0, &camera);
+
+        let mut played = Camera { orientation_mode: OrientationMode::Quaternion, ..Camera::default() };
+        path.apply(0.0, &mut played);
+        assert!((played.roll - 30.0).abs() < 1e-3, "{}", played.roll);
+        assert!((played.up - camera.up).norm() < 1e-4);
+
+        // a fly camera in Euler mode stays level
+        let mut level = Camera::default();
+        path.apply(0.0, &mut level);
+        assert_eq!(level.roll, 0.0);
+    }
+
+    #[test]
+    fn loads_files_with_and_without_roll() {
+        let file = std::env::temp_dir().join(format!("camera_path_{}.ron", std::process::id()));
+        let file = file.to_str().unwrap();
+        std::fs::write(file, "(interpolation: Bezier, keyframes: [(time: 0.0, position: (0.0, 0.0, 0.0), yaw: 179.0, \
+            pitch: 0.0, zoom: 45.0), (time: 1.0, position: (0.0, 0.0, 0.0), yaw: -179.0, pitch: 0.0, roll: 10.0, \
+            zoom: 45.0)])").unwrap();
+        let path = CameraPath::load(file);
+        std::fs::remove_file(file).unwrap();
+
+        let path = path.unwrap();
+        assert_eq!(path.keyframes[0].roll, 0.0);
+        assert_eq!(path.keyframes[1].roll, 10.0);
+        // the yaw is unwrapped when the path is loaded
+        assert_eq!(path.keyframes[1].yaw, 181.0);
+    }
+}
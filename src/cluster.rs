@@ -0,0 +1,465 @@
+use std::mem;
+use gl::types::*;
+use nalgebra_glm as glm;
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::light::{Attenuation, LightSet};
+use crate::shader::Shader;
+
+// every light takes this many RGBA32F texels in the light buffer:
+//   position.xyz, attenuation radius
+//   constant, linear, quadratic, 1 for a spot light
+//   ambient.rgb, cut-off
+//   diffuse.rgb, outer cut-off
+//   specular.rgb, 0
+//   direction.xyz, 0
+const TEXELS_PER_LIGHT: usize = 6;
+
+// the most lights a cluster gets by default, which bounds the cost of the shader's light loop where many lights overlap
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 64;
+
+// The view frustum split into tiles_x * tiles_y tiles on the screen and slices along the depth, each of these clusters
+// gets the list of lights that reach into it. The slices get exponentially thicker with the distance, like the
+// precision of the depth buffer, so the clusters keep roughly the same shape.
+//
+// The grid only works with perspective projections and is built for a projection matrix, so it has to be rebuilt when
+// the projection changes (after a resize for example).
+pub struct ClusterGrid {
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    pub slices: u32,
+    // the depth range covered by the slices, lights outside of it are ignored
+    pub near: f32,
+    pub far: f32,
+    // when more lights reach into a cluster, the ones closest to its center are kept
+    pub max_lights_per_cluster: usize,
+    // the projection scale along x and y, ndc = scale * view / depth
+    scale: glm::Vec2,
+    // view space bounds of every cluster, indexed by cluster_index
+    bounds: Vec<Aabb>,
+}
+
+impl ClusterGrid {
+    pub fn new(tiles_x: u32, tiles_y: u32, slices: u32, near: f32, far: f32, projection: &glm::Mat4) -> ClusterGrid {
+        let mut grid = ClusterGrid {
+            tiles_x,
+            tiles_y,
+            slices,
+            near,
+            far,
+            max_lights_per_cluster: MAX_LIGHTS_PER_CLUSTER,
+            scale: glm::vec2(projection[(0, 0)], projection[(1, 1)]),
+            bounds: Vec::with_capacity((tiles_x * tiles_y * slices) as usize),
+        };
+
+        for slice in 0..slices {
+            let depths = [grid.slice_depth(slice), grid.slice_depth(slice + 1)];
+            for y in 0..tiles_y {
+                let ndc_y = [tile_to_ndc(y, tiles_y), tile_to_ndc(y + 1, tiles_y)];
+                for x in 0..tiles_x {
+                    let ndc_x = [tile_to_ndc(x, tiles_x), tile_to_ndc(x + 1, tiles_x)];
+
+                    // the corners of the cluster are on the rays through the corners of the tile
+                    let mut corners = Vec::with_capacity(8);
+                    for depth in depths {
+                        for ndc_y in ndc_y {
+                            for ndc_x in ndc_x {
+                                corners.push(glm::vec3(ndc_x * depth / grid.scale.x, ndc_y * depth / grid.scale.y, -depth));
+                            }
+                        }
+                    }
+                    grid.bounds.push(Aabb::from_points(&corners));
+                }
+            }
+        }
+
+        grid
+    }
+
+    pub fn cluster_count(&self) -> usize {
+        (self.tiles_x * self.tiles_y * self.slices) as usize
+    }
+
+    // clusters are stored row by row and slice by slice, with x changing fastest
+    pub fn cluster_index(&self, x: u32, y: u32, slice: u32) -> usize {
+        (x + self.tiles_x * (y + self.tiles_y * slice)) as usize
+    }
+
+    // the view space depth at which the slice starts
+    pub fn slice_depth(&self, slice: u32) -> f32 {
+        self.near * (self.far / self.near).powf(slice as f32 / self.slices as f32)
+    }
+
+    // the scale and the bias to calculate the slice of a depth with floor(log(depth) * scale + bias), which is how the
+    // shader finds its cluster
+    pub fn slice_scale_bias(&self) -> (f32, f32) {
+        let scale = self.slices as f32 / (self.far / self.near).ln();
+        (scale, -self.near.ln() * scale)
+    }
+
+    // returns the slice that contains the view space depth, clamped to the grid
+    pub fn slice(&self, depth: f32) -> u32 {
+        let (scale, bias) = self.slice_scale_bias();
+        ((depth.max(self.near).ln() * scale + bias).floor().max(0.0) as u32).min(self.slices - 1)
+    }
+
+    // finds the clusters every light reaches into. The lights are spheres in world space, the view matrix moves them
+    // into the view space of the grid. The result lists the indices into the lights slice, per cluster.
+    pub fn assign_lights(&self, view: &glm::Mat4, lights: &[BoundingSphere]) -> ClusterAssignment {
+        // (cluster, squared distance to its center, light) triples, sorted by cluster and distance afterwards
+        let mut pairs: Vec<(u32, f32, u32)> = Vec::new();
+
+        for (i, light) in lights.iter().enumerate() {
+            let center = (view * light.center.push(1.0)).xyz();
+            let depth = -center.z;
+            let radius = light.radius;
+            if depth + radius < self.near || depth - radius > self.far {
+                continue;
+            }
+
+            // the depth range of the light, only the part in front of the near plane can be seen
+            let min_depth = (depth - radius).max(self.near);
+            let max_depth = (depth + radius).min(self.far);
+
+            // a conservative screen rectangle of the light: the projection of the view space box around the sphere,
+            // the extremes lie on the front or the back side of the box
+            let project = |low: f32, high: f32, scale: f32| -> (f32, f32) {
+                let min = (low / min_depth).min(low / max_depth) * scale;
+                let max = (high / min_depth).max(high / max_depth) * scale;
+                (min, max)
+            };
+            let (min_x, max_x) = project(center.x - radius, center.x + radius, self.scale.x);
+            let (min_y, max_y) = project(center.y - radius, center.y + radius, self.scale.y);
+            if max_x < -1.0 || min_x > 1.0 || max_y < -1.0 || min_y > 1.0 {
+                continue;
+            }
+
+            let view_light = BoundingSphere { center, radius };
+            for slice in self.slice(min_depth)..=self.slice(max_depth) {
+                for y in ndc_to_tile(min_y, self.tiles_y)..=ndc_to_tile(max_y, self.tiles_y) {
+                    for x in ndc_to_tile(min_x, self.tiles_x)..=ndc_to_tile(max_x, self.tiles_x) {
+                        let cluster = self.cluster_index(x, y, slice);
+                        let bounds = &self.bounds[cluster];
+                        if view_light.intersects_aabb(bounds) {
+                            pairs.push((cluster as u32, glm::distance2(&center, &bounds.center()), i as u32));
+                        }
+                    }
+                }
+            }
+        }
+
+        // the closest lights of every cluster come first, equally close ones stay in their original order
+        pairs.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+
+        let mut clusters = vec![[0, 0]; self.cluster_count()];
+        let mut light_indices = Vec::with_capacity(pairs.len());
+        for (cluster, _, light) in pairs {
+            let entry = &mut clusters[cluster as usize];
+            if entry[1] as usize == self.max_lights_per_cluster {
+                continue;
+            }
+            if entry[1] == 0 {
+                entry[0] = light_indices.len() as u32;
+            }
+            entry[1] += 1;
+            light_indices.push(light);
+        }
+
+        ClusterAssignment { clusters, light_indices }
+    }
+}
+
+// the ndc coordinate where the tile starts
+fn tile_to_ndc(tile: u32, tiles: u32) -> f32 {
+    tile as f32 / tiles as f32 * 2.0 - 1.0
+}
+
+// the tile containing the ndc coordinate, clamped to the grid
+fn ndc_to_tile(ndc: f32, tiles: u32) -> u32 {
+    (((ndc + 1.0) * 0.5 * tiles as f32).floor().max(0.0) as u32).min(tiles - 1)
+}
+
+// The lights of every cluster: the light indices of a cluster are stored one after another in light_indices
+pub struct ClusterAssignment {
+    // offset into light_indices and number of lights, per cluster
+    pub clusters: Vec<[u32; 2]>,
+    pub light_indices: Vec<u32>,
+}
+
+impl ClusterAssignment {
+    // the indices of the lights that reach into the cluster. The shaders look them up themselves, the tests use this.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn lights(&self, cluster: usize) -> &[u32] {
+        let [offset, count] = self.clusters[cluster];
+        &self.light_indices[offset as usize..(offset + count) as usize]
+    }
+}
+
+// A buffer the shaders read through a samplerBuffer, the texture buffers of OpenGL 3.1 can hold far more data than a
+// uniform block
+struct TextureBuffer {
+    buffer: GLuint,
+    texture: GLuint,
+}
+
+impl TextureBuffer {
+    fn new(format: GLenum) -> TextureBuffer {
+        let (mut buffer, mut texture) = (0, 0);
+        unsafe {
+            gl::GenBuffers(1, &mut buffer);
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_BUFFER, texture);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, format, buffer);
+            gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+        }
+        TextureBuffer { buffer, texture }
+    }
+
+    // replaces the whole content, the old storage is orphaned so the GPU can keep reading it while we write
+    fn update<T>(&self, data: &[T]) {
+        unsafe {
+            gl::BindBuffer(gl::TEXTURE_BUFFER, self.buffer);
+            gl::BufferData(gl::TEXTURE_BUFFER, mem::size_of_val(data) as GLsizeiptr, data.as_ptr() as *const GLvoid, gl::STREAM_DRAW);
+            gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+        }
+    }
+
+    fn bind(&self, shader: &Shader, name: &str, unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.texture);
+        }
+        shader.set_int(name, unit as GLint);
+    }
+
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+    }
+}
+
+// Clustered forward lighting: the point and spot lights are assigned to the clusters of a ClusterGrid on the CPU (our
+// OpenGL 3.3 context has no compute shaders), and the lighting shader only loops over the lights of the cluster the
+// fragment is in. The lights, the cluster table and the light indices reach the shader through texture buffers:
+//
+// uniform samplerBuffer clusterLights;       // TEXELS_PER_LIGHT texels per light, point lights before spot lights
+// uniform usamplerBuffer clusterTable;       // offset and count per cluster
+// uniform usamplerBuffer clusterLightIndices;
+pub struct ClusteredLights {
+    pub grid: ClusterGrid,
+    lights: TextureBuffer,
+    clusters: TextureBuffer,
+    light_indices: TextureBuffer,
+    // the number of lights of the last update that reach into at least one cluster
+    pub visible_lights: usize,
+}
+
+impl ClusteredLights {
+    pub fn new(grid: ClusterGrid) -> ClusteredLights {
+        ClusteredLights {
+            grid,
+            lights: TextureBuffer::new(gl::RGBA32F),
+            clusters: TextureBuffer::new(gl::RG32UI),
+            light_indices: TextureBuffer::new(gl::R32UI),
+            visible_lights: 0,
+        }
+    }
+
+    // assigns the point and spot lights of the set to the clusters and uploads everything, call it once per frame
+    // after the lights or the camera moved. Directional lights reach everything and are not clustered.
+    pub fn update(&mut self, view: &glm::Mat4, lights: &LightSet) {
+        let light_count = lights.point_lights.len() + lights.spot_lights.len();
+        let mut texels: Vec<[f32; 4]> = Vec::with_capacity(light_count * TEXELS_PER_LIGHT);
+        let mut spheres: Vec<BoundingSphere> = Vec::with_capacity(light_count);
+
+        let mut push_light = |position: &glm::Vec3, direction: &glm::Vec3, attenuation: &Attenuation, colors: [&glm::Vec3; 3],
+                              cut_offs: Option<(f32, f32)>, radius: f32| {
+            let (constant, linear, quadratic, attenuation_radius) = attenuation.shader_values();
+            let (cut_off, outer_cut_off) = cut_offs.unwrap_or((0.0, 0.0));
+            let [ambient, diffuse, specular] = colors;
+            texels.push([position.x, position.y, position.z, attenuation_radius]);
+            texels.push([constant, linear, quadratic, if cut_offs.is_some() { 1.0 } else { 0.0 }]);
+            texels.push([ambient.x, ambient.y, ambient.z, cut_off]);
+            texels.push([diffuse.x, diffuse.y, diffuse.z, outer_cut_off]);
+            texels.push([specular.x, specular.y, specular.z, 0.0]);
+            texels.push([direction.x, direction.y, direction.z, 0.0]);
+            spheres.push(BoundingSphere { center: *position, radius });
+        };
+
+        for light in &lights.point_lights {
+            push_light(&light.position, &glm::Vec3::zeros(), &light.attenuation, [&light.ambient, &light.diffuse, &light.specular],
+                       None, light.effective_radius());
+        }
+        for light in &lights.spot_lights {
+            push_light(&light.position, &light.direction, &light.attenuation, [&light.ambient, &light.diffuse, &light.specular],
+                       Some((light.cut_off, light.outer_cut_off)), light.effective_radius());
+        }
+
+        let assignment = self.grid.assign_lights(view, &spheres);
+        let mut visible = vec![false; light_count];
+        for &index in &assignment.light_indices {
+            visible[index as usize] = true;
+        }
+        self.visible_lights = visible.iter().filter(|&&visible| visible).count();
+
+        self.lights.update(&texels);
+        self.clusters.update(&assignment.clusters);
+        self.light_indices.update(&assignment.light_indices);
+    }
+
+    // binds the buffers to three texture units starting at first_unit and sets the uniforms the shader needs to find
+    // the cluster of a fragment. The screen size is the size of the framebuffer in pixels.
+    pub fn bind(&self, shader: &Shader, first_unit: u32, screen_width: f32, screen_height: f32) {
+        self.lights.bind(shader, "clusterLights", first_unit);
+        self.clusters.bind(shader, "clusterTable", first_unit + 1);
+        self.light_indices.bind(shader, "clusterLightIndices", first_unit + 2);
+
+        let (scale, bias) = self.grid.slice_scale_bias();
+        shader.set_vec3("clusterCounts", self.grid.tiles_x as f32, self.grid.tiles_y as f32, self.grid.slices as f32);
+        shader.set_vec2("clusterDepthScaleBias", scale, bias);
+        shader.set_vec2("screenSize", screen_width, screen_height);
+    }
+
+    pub fn delete(&self) {
+        self.lights.delete();
+        self.clusters.delete();
+        self.light_indices.delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4x4 tiles and 8 slices from 0.1 to 100, for a projection with a field of view of 90°, so a view space point
+    // (x, y, -depth) lands at the ndc coordinates (x, y) / depth
+    fn grid() -> ClusterGrid {
+        let projection = glm::perspective(1.0, 90f32.to_radians(), 0.1, 100.0);
+        ClusterGrid::new(4, 4, 8, 0.1, 100.0, &projection)
+    }
+
+    // the cluster that contains a view space point, if the point is inside the grid
+    fn cluster_of(grid: &ClusterGrid, point: &glm::Vec3) -> Option<usize> {
+        let depth = -point.z;
+        let (ndc_x, ndc_y) = (point.x / depth, point.y / depth);
+        if depth < grid.near || depth > grid.far || ndc_x.abs() >= 1.0 || ndc_y.abs() >= 1.0 {
+            return None;
+        }
+        Some(grid.cluster_index(ndc_to_tile(ndc_x, grid.tiles_x), ndc_to_tile(ndc_y, grid.tiles_y), grid.slice(depth)))
+    }
+
+    fn clusters_of(assignment: &ClusterAssignment, light: u32) -> Vec<usize> {
+        (0..assignment.clusters.len()).filter(|&cluster| assignment.lights(cluster).contains(&light)).collect()
+    }
+
+    #[test]
+    fn slices_grow_exponentially_from_near_to_far() {
+        let grid = grid();
+        assert!((grid.slice_depth(0) - 0.1).abs() < 1e-6);
+        assert!((grid.slice_depth(8) - 100.0).abs() < 1e-3);
+        // every slice is the same factor thicker than the one before
+        let ratio = 1000f32.powf(1.0 / 8.0);
+        for slice in 0..8 {
+            assert!((grid.slice_depth(slice + 1) / grid.slice_depth(slice) - ratio).abs() < 1e-4);
+            let middle = (grid.slice_depth(slice) * grid.slice_depth(slice + 1)).sqrt();
+            assert_eq!(grid.slice(middle), slice);
+        }
+        // depths outside of the range are clamped
+        assert_eq!(grid.slice(0.01), 0);
+        assert_eq!(grid.slice(1000.0), 7);
+    }
+
+    #[test]
+    fn a_small_light_lands_in_one_cluster() {
+        let grid = grid();
+        // in the middle of slice 3 and of the tile (2, 1), which covers ndc x from 0 to 0.5 and y from -0.5 to 0
+        let depth = (grid.slice_depth(3) * grid.slice_depth(4)).sqrt();
+        let light = BoundingSphere { center: glm::vec3(0.25 * depth, -0.25 * depth, -depth), radius: 0.01 };
+        let assignment = grid.assign_lights(&glm::Mat4::identity(), &[light]);
+        assert_eq!(clusters_of(&assignment, 0), vec![grid.cluster_index(2, 1, 3)]);
+        assert_eq!(assignment.light_indices, vec![0]);
+    }
+
+    #[test]
+    fn the_view_matrix_moves_the_lights() {
+        let grid = grid();
+        let depth = (grid.slice_depth(5) * grid.slice_depth(6)).sqrt();
+        let view = glm::translation(&glm::vec3(0.0, 0.0, -depth));
+        let light = BoundingSphere { center: glm::vec3(-0.75 * depth, 0.75 * depth, 0.0), radius: 0.01 };
+        let assignment = grid.assign_lights(&view, &[light]);
+        assert_eq!(clusters_of(&assignment, 0), vec![grid.cluster_index(0, 3, 5)]);
+    }
+
+    #[test]
+    fn large_lights_reach_every_cluster_they_touch() {
+        let grid = grid();
+        let lights = [
+            BoundingSphere { center: glm::vec3(0.0, 0.0, -5.0), radius: 2.0 },
+            BoundingSphere { center: glm::vec3(3.0, -1.0, -2.0), radius: 1.5 },
+            BoundingSphere { center: glm::vec3(0.0, 0.0, 0.5), radius: 1.0 },
+            BoundingSphere { center: glm::vec3(-30.0, 20.0, -90.0), radius: 20.0 },
+        ];
+        let assignment = grid.assign_lights(&glm::Mat4::identity(), &lights);
+        for (i, light) in lights.iter().enumerate() {
+            let assigned = clusters_of(&assignment, i as u32);
+
+            // every cluster with a point of the light in it
+            let steps = 16;
+            for (x, y, z) in (0..=steps).flat_map(|x| (0..=steps).flat_map(move |y| (0..=steps).map(move |z| (x, y, z)))) {
+                let offset = glm::vec3(x as f32, y as f32, z as f32) / steps as f32 * 2.0 - glm::vec3(1.0, 1.0, 1.0);
+                if offset.norm() > 1.0 {
+                    continue;
+                }
+                if let Some(cluster) = cluster_of(&grid, &(light.center + offset * light.radius)) {
+                    assert!(assigned.contains(&cluster), "light {} misses cluster {}", i, cluster);
+                }
+            }
+
+            // and only clusters whose bounds it touches
+            assert!(assigned.len() > 1, "light {}", i);
+            assert!(assigned.iter().all(|&cluster| light.intersects_aabb(&grid.bounds[cluster])), "light {}", i);
+        }
+    }
+
+    #[test]
+    fn lights_outside_of_the_frustum_are_assigned_nowhere() {
+        let grid = grid();
+        let lights = [
+            // behind the camera, beyond the far plane, and beside, above and below the frustum
+            BoundingSphere { center: glm::vec3(0.0, 0.0, 5.0), radius: 1.0 },
+            BoundingSphere { center: glm::vec3(0.0, 0.0, -200.0), radius: 1.0 },
+            BoundingSphere { center: glm::vec3(20.0, 0.0, -10.0), radius: 1.0 },
+            BoundingSphere { center: glm::vec3(-20.0, 0.0, -10.0), radius: 1.0 },
+            BoundingSphere { center: glm::vec3(0.0, 20.0, -10.0), radius: 1.0 },
+            BoundingSphere { center: glm::vec3(0.0, -20.0, -10.0), radius: 1.0 },
+        ];
+        let assignment = grid.assign_lights(&glm::Mat4::identity(), &lights);
+        assert!(assignment.light_indices.is_empty());
+        assert!(assignment.clusters.iter().all(|&[_, count]| count == 0));
+    }
+
+    #[test]
+    fn clusters_keep_the_closest_lights_up_to_the_limit() {
+        let mut grid = grid();
+        grid.max_lights_per_cluster = 3;
+        // six lights around the middle of the cluster (2, 1, 3), further away the higher their index, in a shuffled
+        // order
+        let depth = (grid.slice_depth(3) * grid.slice_depth(4)).sqrt();
+        let middle = grid.bounds[grid.cluster_index(2, 1, 3)].center();
+        let order = [4, 1, 5, 0, 3, 2];
+        let lights: Vec<BoundingSphere> = order.iter()
+            .map(|&i| BoundingSphere { center: middle + glm::vec3(0.0, 0.0, 0.01 * i as f32), radius: 0.05 * depth })
+            .collect();
+        let assignment = grid.assign_lights(&glm::Mat4::identity(), &lights);
+
+        assert!(assignment.clusters.iter().all(|&[_, count]| count <= 3));
+        // the lights at the offsets 0, 1 and 2
+        let mut kept = assignment.lights(grid.cluster_index(2, 1, 3)).to_vec();
+        kept.sort();
+        assert_eq!(kept, vec![1, 3, 5]);
+        // the offsets of the clusters still point at their own lights
+        let total: u32 = assignment.clusters.iter().map(|&[_, count]| count).sum();
+        assert_eq!(total as usize, assignment.light_indices.len());
+    }
+}
@@ -0,0 +1,794 @@
+use std::cell::RefCell;
+#[cfg(test)]
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::rc::Rc;
+use std::{mem, ptr};
+use gl::types::*;
+use nalgebra_glm as glm;
+
+// The GL functions the rendering code (Shader, GpuMesh, Model and the state tracker in gl_state) calls, as a trait, so
+// that code can run without a GPU. ContextGl calls the functions of the current GL context, RecordingGl only writes
+// down the calls (it only exists in test builds), which a test can then look at:
+//
+//     let recording = RecordingGl::default();
+//     gl_api::install(Rc::new(recording.clone()));
+//     gl_state::with(|gl| *gl = GlState::new());
+//     let mesh = GpuMesh::upload(&data, textures);
+//     mesh.draw(&shader);
+//     assert!(recording.calls().contains(&GlCall::DrawElements { mode: gl::TRIANGLES, count: 36, ... }));
+//
+// The methods take slices and strings instead of pointers, so the trait is safe to call.
+
+pub trait GlApi {
+    // shaders and programs
+    fn create_shader(&self, kind: GLenum) -> GLuint;
+    fn shader_source(&self, shader: GLuint, source: &str);
+    fn compile_shader(&self, shader: GLuint);
+    fn get_shader_iv(&self, shader: GLuint, name: GLenum) -> GLint;
+    fn get_shader_info_log(&self, shader: GLuint) -> String;
+    fn delete_shader(&self, shader: GLuint);
+    fn create_program(&self) -> GLuint;
+    fn attach_shader(&self, program: GLuint, shader: GLuint);
+    fn link_program(&self, program: GLuint);
+    fn get_program_iv(&self, program: GLuint, name: GLenum) -> GLint;
+    fn use_program(&self, program: GLuint);
+
+    // uniforms
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint;
+    fn uniform_1i(&self, location: GLint, value: GLint);
+    fn uniform_1f(&self, location: GLint, value: GLfloat);
+    fn uniform_2f(&self, location: GLint, x: GLfloat, y: GLfloat);
+    fn uniform_3f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat);
+    fn uniform_matrix_4fv(&self, location: GLint, transpose: bool, value: &[GLfloat; 16]);
+    fn get_uniform_block_index(&self, program: GLuint, name: &str) -> GLuint;
+    fn uniform_block_binding(&self, program: GLuint, index: GLuint, binding: GLuint);
+    fn get_active_uniform_block_iv(&self, program: GLuint, index: GLuint, name: GLenum) -> GLint;
+
+    // vertex arrays and buffers
+    fn gen_vertex_array(&self) -> GLuint;
+    fn bind_vertex_array(&self, vertex_array: GLuint);
+    fn gen_buffer(&self) -> GLuint;
+    fn bind_buffer(&self, target: GLenum, buffer: GLuint);
+    // allocates size bytes, filled with data if there is some
+    fn buffer_data(&self, target: GLenum, size: usize, data: Option<&[u8]>, usage: GLenum);
+    // replaces bytes from the offset on, only the instance matrices of models are uploaded that way
+    #[cfg_attr(not(any(feature = "assimp", feature = "obj")), allow(dead_code))]
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]);
+    fn enable_vertex_attrib_array(&self, index: GLuint);
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, component_type: GLenum, normalized: bool,
+                             stride: usize, offset: usize);
+    fn vertex_attrib_divisor(&self, index: GLuint, divisor: GLuint);
+
+    // textures
+    fn gen_texture(&self) -> GLuint;
+    fn active_texture(&self, unit: GLenum);
+    fn bind_texture(&self, target: GLenum, texture: GLuint);
+    #[allow(clippy::too_many_arguments)]
+    fn tex_image_2d(&self, target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei,
+                    format: GLenum, component_type: GLenum, data: Option<&[u8]>);
+    fn generate_mipmap(&self, target: GLenum);
+    fn tex_parameter_i(&self, target: GLenum, name: GLenum, value: GLint);
+
+    // drawing, offset is in bytes into the element array buffer
+    fn draw_elements(&self, mode: GLenum, count: usize, index_type: GLenum, offset: usize);
+    // the only caller is Model::draw_instanced, which needs a model loader
+    #[cfg_attr(not(any(feature = "assimp", feature = "obj")), allow(dead_code))]
+    fn draw_elements_instanced(&self, mode: GLenum, count: usize, index_type: GLenum, offset: usize, instances: usize);
+
+    // render state
+    fn enable(&self, capability: GLenum);
+    fn disable(&self, capability: GLenum);
+    fn depth_func(&self, func: GLenum);
+    fn depth_mask(&self, write: bool);
+    fn blend_func(&self, source: GLenum, destination: GLenum);
+    fn cull_face(&self, mode: GLenum);
+    fn stencil_func(&self, func: GLenum, reference: GLint, mask: GLuint);
+    fn stencil_op(&self, stencil_fail: GLenum, depth_fail: GLenum, pass: GLenum);
+    fn stencil_mask(&self, mask: GLuint);
+    fn polygon_mode(&self, face: GLenum, mode: GLenum);
+
+    // debugging, see gl_debug
+    fn get_error(&self) -> GLenum;
+    fn object_label(&self, identifier: GLenum, name: GLuint, label: &str);
+}
+
+thread_local! {
+    static CURRENT: RefCell<Rc<dyn GlApi>> = RefCell::new(Rc::new(ContextGl));
+}
+
+// runs f with the GL functions of this thread, the ones of the GL context unless install was called
+pub fn with<R>(f: impl FnOnce(&dyn GlApi) -> R) -> R {
+    // not borrowed while f runs, so f can call with again
+    let api = CURRENT.with(|current| current.borrow().clone());
+    f(&*api)
+}
+
+// replaces the GL functions of this thread. The state tracker still caches what the old ones did, so reset it too:
+// gl_state::with(|gl| *gl = GlState::new()).
+#[cfg(test)]
+pub fn install(api: Rc<dyn GlApi>) {
+    CURRENT.with(|current| *current.borrow_mut() = api);
+}
+
+/// Plain data that can be read and written as bytes, see as_bytes and as_bytes_mut.
+///
+/// # Safety
+/// The type may have no padding bytes and no pointers, and every bit pattern has to be a valid value of it. That holds
+/// for numbers, arrays of them and repr(C) structs of them without gaps between the fields.
+pub unsafe trait Pod {}
+
+unsafe impl Pod for u8 {}
+unsafe impl Pod for u16 {}
+unsafe impl Pod for u32 {}
+unsafe impl Pod for f32 {}
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+// 16 floats in a column-major array
+unsafe impl Pod for glm::Mat4 {}
+
+// the memory of a slice of plain data, for buffer_data
+pub fn as_bytes<T: Pod>(values: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values)) }
+}
+
+// the same for filling a slice from bytes
+pub fn as_bytes_mut<T: Pod>(values: &mut [T]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, mem::size_of_val(values)) }
+}
+
+// the GL functions of the current context
+pub struct ContextGl;
+
+impl GlApi for ContextGl {
+    fn create_shader(&self, kind: GLenum) -> GLuint {
+        unsafe { gl::CreateShader(kind) }
+    }
+
+    fn shader_source(&self, shader: GLuint, source: &str) {
+        let source = CString::new(source).unwrap();
+        unsafe { gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null()) };
+    }
+
+    fn compile_shader(&self, shader: GLuint) {
+        unsafe { gl::CompileShader(shader) };
+    }
+
+    fn get_shader_iv(&self, shader: GLuint, name: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe { gl::GetShaderiv(shader, name, &mut value) };
+        value
+    }
+
+    fn get_shader_info_log(&self, shader: GLuint) -> String {
+        let len = self.get_shader_iv(shader, gl::INFO_LOG_LENGTH);
+        let mut info_log: Vec<u8> = vec![0; len as usize + 1];
+        unsafe { gl::GetShaderInfoLog(shader, len, ptr::null_mut(), info_log.as_mut_ptr() as *mut GLchar) };
+        String::from_utf8_lossy(&info_log).to_string()
+    }
+
+    fn delete_shader(&self, shader: GLuint) {
+        unsafe { gl::DeleteShader(shader) };
+    }
+
+    fn create_program(&self) -> GLuint {
+        unsafe { gl::CreateProgram() }
+    }
+
+    fn attach_shader(&self, program: GLuint, shader: GLuint) {
+        unsafe { gl::AttachShader(program, shader) };
+    }
+
+    fn link_program(&self, program: GLuint) {
+        unsafe { gl::LinkProgram(program) };
+    }
+
+    fn get_program_iv(&self, program: GLuint, name: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe { gl::GetProgramiv(program, name, &mut value) };
+        value
+    }
+
+    fn use_program(&self, program: GLuint) {
+        unsafe { gl::UseProgram(program) };
+    }
+
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        let name = CString::new(name).unwrap();
+        unsafe { gl::GetUniformLocation(program, name.as_ptr()) }
+    }
+
+    fn uniform_1i(&self, location: GLint, value: GLint) {
+        unsafe { gl::Uniform1i(location, value) };
+    }
+
+    fn uniform_1f(&self, location: GLint, value: GLfloat) {
+        unsafe { gl::Uniform1f(location, value) };
+    }
+
+    fn uniform_2f(&self, location: GLint, x: GLfloat, y: GLfloat) {
+        unsafe { gl::Uniform2f(location, x, y) };
+    }
+
+    fn uniform_3f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat) {
+        unsafe { gl::Uniform3f(location, x, y, z) };
+    }
+
+    fn uniform_matrix_4fv(&self, location: GLint, transpose: bool, value: &[GLfloat; 16]) {
+        unsafe { gl::UniformMatrix4fv(location, 1, transpose as GLboolean, value.as_ptr()) };
+    }
+
+    fn get_uniform_block_index(&self, program: GLuint, name: &str) -> GLuint {
+        let name = CString::new(name).unwrap();
+        unsafe { gl::GetUniformBlockIndex(program, name.as_ptr()) }
+    }
+
+    fn uniform_block_binding(&self, program: GLuint, index: GLuint, binding: GLuint) {
+        unsafe { gl::UniformBlockBinding(program, index, binding) };
+    }
+
+    fn get_active_uniform_block_iv(&self, program: GLuint, index: GLuint, name: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe { gl::GetActiveUniformBlockiv(program, index, name, &mut value) };
+        value
+    }
+
+    fn gen_vertex_array(&self) -> GLuint {
+        let mut vertex_array = 0;
+        unsafe { gl::GenVertexArrays(1, &mut vertex_array) };
+        vertex_array
+    }
+
+    fn bind_vertex_array(&self, vertex_array: GLuint) {
+        unsafe { gl::BindVertexArray(vertex_array) };
+    }
+
+    fn gen_buffer(&self) -> GLuint {
+        let mut buffer = 0;
+        unsafe { gl::GenBuffers(1, &mut buffer) };
+        buffer
+    }
+
+    fn bind_buffer(&self, target: GLenum, buffer: GLuint) {
+        unsafe { gl::BindBuffer(target, buffer) };
+    }
+
+    fn buffer_data(&self, target: GLenum, size: usize, data: Option<&[u8]>, usage: GLenum) {
+        let data = data.map_or(ptr::null(), |data| data.as_ptr() as *const GLvoid);
+        unsafe { gl::BufferData(target, size as GLsizeiptr, data, usage) };
+    }
+
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]) {
+        unsafe { gl::BufferSubData(target, offset as GLintptr, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid) };
+    }
+
+    fn enable_vertex_attrib_array(&self, index: GLuint) {
+        unsafe { gl::EnableVertexAttribArray(index) };
+    }
+
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, component_type: GLenum, normalized: bool,
+                             stride: usize, offset: usize) {
+        unsafe {
+            gl::VertexAttribPointer(index, size, component_type, normalized as GLboolean, stride as GLsizei,
+                                    offset as *const GLvoid)
+        };
+    }
+
+    fn vertex_attrib_divisor(&self, index: GLuint, divisor: GLuint) {
+        unsafe { gl::VertexAttribDivisor(index, divisor) };
+    }
+
+    fn gen_texture(&self) -> GLuint {
+        let mut texture = 0;
+        unsafe { gl::GenTextures(1, &mut texture) };
+        texture
+    }
+
+    fn active_texture(&self, unit: GLenum) {
+        unsafe { gl::ActiveTexture(unit) };
+    }
+
+    fn bind_texture(&self, target: GLenum, texture: GLuint) {
+        unsafe { gl::BindTexture(target, texture) };
+    }
+
+    fn tex_image_2d(&self, target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei,
+                    format: GLenum, component_type: GLenum, data: Option<&[u8]>) {
+        let data = data.map_or(ptr::null(), |data| data.as_ptr() as *const GLvoid);
+        unsafe { gl::TexImage2D(target, level, internal_format, width, height, 0, format, component_type, data) };
+    }
+
+    fn generate_mipmap(&self, target: GLenum) {
+        unsafe { gl::GenerateMipmap(target) };
+    }
+
+    fn tex_parameter_i(&self, target: GLenum, name: GLenum, value: GLint) {
+        unsafe { gl::TexParameteri(target, name, value) };
+    }
+
+    fn draw_elements(&self, mode: GLenum, count: usize, index_type: GLenum, offset: usize) {
+        unsafe { gl::DrawElements(mode, count as GLsizei, index_type, offset as *const GLvoid) };
+    }
+
+    fn draw_elements_instanced(&self, mode: GLenum, count: usize, index_type: GLenum, offset: usize, instances: usize) {
+        unsafe {
+            gl::DrawElementsInstanced(mode, count as GLsizei, index_type, offset as *const GLvoid, instances as GLsizei)
+        };
+    }
+
+    fn enable(&self, capability: GLenum) {
+        unsafe { gl::Enable(capability) };
+    }
+
+    fn disable(&self, capability: GLenum) {
+        unsafe { gl::Disable(capability) };
+    }
+
+    fn depth_func(&self, func: GLenum) {
+        unsafe { gl::DepthFunc(func) };
+    }
+
+    fn depth_mask(&self, write: bool) {
+        unsafe { gl::DepthMask(write as GLboolean) };
+    }
+
+    fn blend_func(&self, source: GLenum, destination: GLenum) {
+        unsafe { gl::BlendFunc(source, destination) };
+    }
+
+    fn cull_face(&self, mode: GLenum) {
+        unsafe { gl::CullFace(mode) };
+    }
+
+    fn stencil_func(&self, func: GLenum, reference: GLint, mask: GLuint) {
+        unsafe { gl::StencilFunc(func, reference, mask) };
+    }
+
+    fn stencil_op(&self, stencil_fail: GLenum, depth_fail: GLenum, pass: GLenum) {
+        unsafe { gl::StencilOp(stencil_fail, depth_fail, pass) };
+    }
+
+    fn stencil_mask(&self, mask: GLuint) {
+        unsafe { gl::StencilMask(mask) };
+    }
+
+    fn polygon_mode(&self, face: GLenum, mode: GLenum) {
+        unsafe { gl::PolygonMode(face, mode) };
+    }
+
+    fn get_error(&self) -> GLenum {
+        unsafe { gl::GetError() }
+    }
+
+    fn object_label(&self, identifier: GLenum, name: GLuint, label: &str) {
+        // glObjectLabel needs GL 4.3 or KHR_debug
+        if gl::ObjectLabel::is_loaded() {
+            unsafe { gl::ObjectLabel(identifier, name, label.len() as GLsizei, label.as_ptr() as *const GLchar) };
+        }
+    }
+}
+
+// a call RecordingGl wrote down. The queries (get_*) aren't recorded, except for the uniform locations, whose names
+// are kept, see RecordingGl::uniform_name.
+#[cfg(test)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum GlCall {
+    CreateShader { kind: GLenum, shader: GLuint },
+    ShaderSource { shader: GLuint, source: String },
+    CompileShader { shader: GLuint },
+    DeleteShader { shader: GLuint },
+    CreateProgram { program: GLuint },
+    AttachShader { program: GLuint, shader: GLuint },
+    LinkProgram { program: GLuint },
+    UseProgram { program: GLuint },
+    Uniform1i { location: GLint, value: GLint },
+    Uniform1f { location: GLint, value: GLfloat },
+    Uniform2f { location: GLint, value: [GLfloat; 2] },
+    Uniform3f { location: GLint, value: [GLfloat; 3] },
+    UniformMatrix4fv { location: GLint, transpose: bool, value: [GLfloat; 16] },
+    UniformBlockBinding { program: GLuint, index: GLuint, binding: GLuint },
+    GenVertexArray { vertex_array: GLuint },
+    BindVertexArray { vertex_array: GLuint },
+    GenBuffer { buffer: GLuint },
+    BindBuffer { target: GLenum, buffer: GLuint },
+    // the data itself is left out, only whether there was some
+    BufferData { target: GLenum, size: usize, has_data: bool, usage: GLenum },
+    #[cfg_attr(not(any(feature = "assimp", feature = "obj")), allow(dead_code))]
+    BufferSubData { target: GLenum, offset: usize, size: usize },
+    EnableVertexAttribArray { index: GLuint },
+    VertexAttribPointer { index: GLuint, size: GLint, component_type: GLenum, normalized: bool, stride: usize, offset: usize },
+    VertexAttribDivisor { index: GLuint, divisor: GLuint },
+    GenTexture { texture: GLuint },
+    ActiveTexture { unit: GLenum },
+    BindTexture { target: GLenum, texture: GLuint },
+    TexImage2D { target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei, format: GLenum,
+                 component_type: GLenum, has_data: bool },
+    GenerateMipmap { target: GLenum },
+    TexParameteri { target: GLenum, name: GLenum, value: GLint },
+    DrawElements { mode: GLenum, count: usize, index_type: GLenum, offset: usize },
+    #[cfg_attr(not(any(feature = "assimp", feature = "obj")), allow(dead_code))]
+    DrawElementsInstanced { mode: GLenum, count: usize, index_type: GLenum, offset: usize, instances: usize },
+    Enable { capability: GLenum },
+    Disable { capability: GLenum },
+    DepthFunc { func: GLenum },
+    DepthMask { write: bool },
+    BlendFunc { source: GLenum, destination: GLenum },
+    CullFace { mode: GLenum },
+    StencilFunc { func: GLenum, reference: GLint, mask: GLuint },
+    StencilOp { stencil_fail: GLenum, depth_fail: GLenum, pass: GLenum },
+    StencilMask { mask: GLuint },
+    PolygonMode { face: GLenum, mode: GLenum },
+    ObjectLabel { identifier: GLenum, name: GLuint, label: String },
+}
+
+// GL functions that only record the calls. New objects get consecutive names starting at 1, shaders compile and link
+// without errors, every uniform exists and no uniform block does. The clones share the recording.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingGl {
+    recording: Rc<RefCell<Recording>>,
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct Recording {
+    calls: Vec<GlCall>,
+    last_name: GLuint,
+    // the location of a uniform is its index here, plus one
+    uniforms: Vec<(GLuint, String)>,
+    uniform_locations: HashMap<(GLuint, String), GLint>,
+}
+
+#[cfg(test)]
+impl RecordingGl {
+    // the calls so far
+    pub fn calls(&self) -> Vec<GlCall> {
+        self.recording.borrow().calls.clone()
+    }
+
+    // forgets the calls so far
+    pub fn clear(&self) {
+        self.recording.borrow_mut().calls.clear();
+    }
+
+    // the program and name of the uniform get_uniform_location returned the location for
+    pub fn uniform_name(&self, location: GLint) -> Option<(GLuint, String)> {
+        let recording = self.recording.borrow();
+        usize::try_from(location - 1).ok().and_then(|index| recording.uniforms.get(index).cloned())
+    }
+
+    // the value the int uniform (or sampler) of the program was last set to
+    pub fn uniform_int(&self, program: GLuint, name: &str) -> Option<GLint> {
+        let recording = self.recording.borrow();
+        let location = *recording.uniform_locations.get(&(program, name.to_string()))?;
+        // uniforms are set on the program in use
+        let mut current = 0;
+        let mut value = None;
+        for call in &recording.calls {
+            match *call {
+                GlCall::UseProgram { program } => current = program,
+                GlCall::Uniform1i { location: l, value: v } if l == location && current == program => value = Some(v),
+                _ => {}
+            }
+        }
+        value
+    }
+
+    fn record(&self, call: GlCall) {
+        self.recording.borrow_mut().calls.push(call);
+    }
+
+    fn new_name(&self) -> GLuint {
+        let mut recording = self.recording.borrow_mut();
+        recording.last_name += 1;
+        recording.last_name
+    }
+}
+
+#[cfg(test)]
+impl GlApi for RecordingGl {
+    fn create_shader(&self, kind: GLenum) -> GLuint {
+        let shader = self.new_name();
+        self.record(GlCall::CreateShader { kind, shader });
+        shader
+    }
+
+    fn shader_source(&self, shader: GLuint, source: &str) {
+        self.record(GlCall::ShaderSource { shader, source: source.to_string() });
+    }
+
+    fn compile_shader(&self, shader: GLuint) {
+        self.record(GlCall::CompileShader { shader });
+    }
+
+    fn get_shader_iv(&self, _shader: GLuint, name: GLenum) -> GLint {
+        if name == gl::COMPILE_STATUS { gl::TRUE as GLint } else { 0 }
+    }
+
+    fn get_shader_info_log(&self, _shader: GLuint) -> String {
+        String::new()
+    }
+
+    fn delete_shader(&self, shader: GLuint) {
+        self.record(GlCall::DeleteShader { shader });
+    }
+
+    fn create_program(&self) -> GLuint {
+        let program = self.new_name();
+        self.record(GlCall::CreateProgram { program });
+        program
+    }
+
+    fn attach_shader(&self, program: GLuint, shader: GLuint) {
+        self.record(GlCall::AttachShader { program, shader });
+    }
+
+    fn link_program(&self, program: GLuint) {
+        self.record(GlCall::LinkProgram { program });
+    }
+
+    fn get_program_iv(&self, _program: GLuint, name: GLenum) -> GLint {
+        if name == gl::LINK_STATUS { gl::TRUE as GLint } else { 0 }
+    }
+
+    fn use_program(&self, program: GLuint) {
+        self.record(GlCall::UseProgram { program });
+    }
+
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        let mut recording = self.recording.borrow_mut();
+        let key = (program, name.to_string());
+        if let Some(&location) = recording.uniform_locations.get(&key) {
+            return location;
+        }
+        recording.uniforms.push(key.clone());
+        let location = recording.uniforms.len() as GLint;
+        recording.uniform_locations.insert(key, location);
+        location
+    }
+
+    fn uniform_1i(&self, location: GLint, value: GLint) {
+        self.record(GlCall::Uniform1i { location, value });
+    }
+
+    fn uniform_1f(&self, location: GLint, value: GLfloat) {
+        self.record(GlCall::Uniform1f { location, value });
+    }
+
+    fn uniform_2f(&self, location: GLint, x: GLfloat, y: GLfloat) {
+        self.record(GlCall::Uniform2f { location, value: [x, y] });
+    }
+
+    fn uniform_3f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat) {
+        self.record(GlCall::Uniform3f { location, value: [x, y, z] });
+    }
+
+    fn uniform_matrix_4fv(&self, location: GLint, transpose: bool, value: &[GLfloat; 16]) {
+        self.record(GlCall::UniformMatrix4fv { location, transpose, value: *value });
+    }
+
+    fn get_uniform_block_index(&self, _program: GLuint, _name: &str) -> GLuint {
+        gl::INVALID_INDEX
+    }
+
+    fn uniform_block_binding(&self, program: GLuint, index: GLuint, binding: GLuint) {
+        self.record(GlCall::UniformBlockBinding { program, index, binding });
+    }
+
+    fn get_active_uniform_block_iv(&self, _program: GLuint, _index: GLuint, _name: GLenum) -> GLint {
+        0
+    }
+
+    fn gen_vertex_array(&self) -> GLuint {
+        let vertex_array = self.new_name();
+        self.record(GlCall::GenVertexArray { vertex_array });
+        vertex_array
+    }
+
+    fn bind_vertex_array(&self, vertex_array: GLuint) {
+        self.record(GlCall::BindVertexArray { vertex_array });
+    }
+
+    fn gen_buffer(&self) -> GLuint {
+        let buffer = self.new_name();
+        self.record(GlCall::GenBuffer { buffer });
+        buffer
+    }
+
+    fn bind_buffer(&self, target: GLenum, buffer: GLuint) {
+        self.record(GlCall::BindBuffer { target, buffer });
+    }
+
+    fn buffer_data(&self, target: GLenum, size: usize, data: Option<&[u8]>, usage: GLenum) {
+        self.record(GlCall::BufferData { target, size, has_data: data.is_some(), usage });
+    }
+
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]) {
+        self.record(GlCall::BufferSubData { target, offset, size: data.len() });
+    }
+
+    fn enable_vertex_attrib_array(&self, index: GLuint) {
+        self.record(GlCall::EnableVertexAttribArray { index });
+    }
+
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, component_type: GLenum, normalized: bool,
+                             stride: usize, offset: usize) {
+        self.record(GlCall::VertexAttribPointer { index, size, component_type, normalized, stride, offset });
+    }
+
+    fn vertex_attrib_divisor(&self, index: GLuint, divisor: GLuint) {
+        self.record(GlCall::VertexAttribDivisor { index, divisor });
+    }
+
+    fn gen_texture(&self) -> GLuint {
+        let texture = self.new_name();
+        self.record(GlCall::GenTexture { texture });
+        texture
+    }
+
+    fn active_texture(&self, unit: GLenum) {
+        self.record(GlCall::ActiveTexture { unit });
+    }
+
+    fn bind_texture(&self, target: GLenum, texture: GLuint) {
+        self.record(GlCall::BindTexture { target, texture });
+    }
+
+    fn tex_image_2d(&self, target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei,
+                    format: GLenum, component_type: GLenum, data: Option<&[u8]>) {
+        self.record(GlCall::TexImage2D { target, level, internal_format, width, height, format, component_type,
+            has_data: data.is_some() });
+    }
+
+    fn generate_mipmap(&self, target: GLenum) {
+        self.record(GlCall::GenerateMipmap { target });
+    }
+
+    fn tex_parameter_i(&self, target: GLenum, name: GLenum, value: GLint) {
+        self.record(GlCall::TexParameteri { target, name, value });
+    }
+
+    fn draw_elements(&self, mode: GLenum, count: usize, index_type: GLenum, offset: usize) {
+        self.record(GlCall::DrawElements { mode, count, index_type, offset });
+    }
+
+    fn draw_elements_instanced(&self, mode: GLenum, count: usize, index_type: GLenum, offset: usize, instances: usize) {
+        self.record(GlCall::DrawElementsInstanced { mode, count, index_type, offset, instances });
+    }
+
+    fn enable(&self, capability: GLenum) {
+        self.record(GlCall::Enable { capability });
+    }
+
+    fn disable(&self, capability: GLenum) {
+        self.record(GlCall::Disable { capability });
+    }
+
+    fn depth_func(&self, func: GLenum) {
+        self.record(GlCall::DepthFunc { func });
+    }
+
+    fn depth_mask(&self, write: bool) {
+        self.record(GlCall::DepthMask { write });
+    }
+
+    fn blend_func(&self, source: GLenum, destination: GLenum) {
+        self.record(GlCall::BlendFunc { source, destination });
+    }
+
+    fn cull_face(&self, mode: GLenum) {
+        self.record(GlCall::CullFace { mode });
+    }
+
+    fn stencil_func(&self, func: GLenum, reference: GLint, mask: GLuint) {
+        self.record(GlCall::StencilFunc { func, reference, mask });
+    }
+
+    fn stencil_op(&self, stencil_fail: GLenum, depth_fail: GLenum, pass: GLenum) {
+        self.record(GlCall::StencilOp { stencil_fail, depth_fail, pass });
+    }
+
+    fn stencil_mask(&self, mask: GLuint) {
+        self.record(GlCall::StencilMask { mask });
+    }
+
+    fn polygon_mode(&self, face: GLenum, mode: GLenum) {
+        self.record(GlCall::PolygonMode { face, mode });
+    }
+
+    fn get_error(&self) -> GLenum {
+        gl::NO_ERROR
+    }
+
+    fn object_label(&self, identifier: GLenum, name: GLuint, label: &str) {
+        self.record(GlCall::ObjectLabel { identifier, name, label: label.to_string() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_state::{self, GlState};
+    use crate::mesh::{GpuMesh, MeshData, Texture};
+    use crate::primitives::Primitive;
+    use crate::shader::Shader;
+
+    const VERTEX_PATH: &str = "src/_3_model_loading/shaders/1.model_loading.vert";
+    const FRAGMENT_PATH: &str = "src/_3_model_loading/shaders/1.model_loading.frag";
+
+    // a recording in place of the GL context, with a tracker for a new context
+    fn record() -> RecordingGl {
+        let recording = RecordingGl::default();
+        install(Rc::new(recording.clone()));
+        gl_state::with(|gl| *gl = GlState::new());
+        recording
+    }
+
+    fn texture(id: GLuint, type_name: &str) -> Texture {
+        Texture { id, type_name: type_name.to_string(), path: format!("{}.png", id) }
+    }
+
+    #[test]
+    fn shader_compiles_and_links() {
+        let recording = record();
+        let shader = Shader::new(VERTEX_PATH, FRAGMENT_PATH).unwrap();
+
+        let vertex_source = std::fs::read_to_string(VERTEX_PATH).unwrap();
+        let fragment_source = std::fs::read_to_string(FRAGMENT_PATH).unwrap();
+        assert_eq!(shader.id, 3);
+        assert_eq!(recording.calls(), vec![
+            GlCall::CreateShader { kind: gl::VERTEX_SHADER, shader: 1 },
+            GlCall::ShaderSource { shader: 1, source: vertex_source },
+            GlCall::CompileShader { shader: 1 },
+            GlCall::CreateShader { kind: gl::FRAGMENT_SHADER, shader: 2 },
+            GlCall::ShaderSource { shader: 2, source: fragment_source },
+            GlCall::CompileShader { shader: 2 },
+            GlCall::CreateProgram { program: 3 },
+            GlCall::AttachShader { program: 3, shader: 1 },
+            GlCall::AttachShader { program: 3, shader: 2 },
+            GlCall::LinkProgram { program: 3 },
+            GlCall::ObjectLabel { identifier: gl::PROGRAM, name: 3, label: format!("{} + {}", VERTEX_PATH, FRAGMENT_PATH) },
+            GlCall::DeleteShader { shader: 1 },
+            GlCall::DeleteShader { shader: 2 },
+        ]);
+
+        // the uniforms are set on the program
+        recording.clear();
+        shader.use_program();
+        shader.set_int("texture_diffuse1", 4);
+        assert_eq!(recording.calls()[0], GlCall::UseProgram { program: 3 });
+        assert_eq!(recording.uniform_int(3, "texture_diffuse1"), Some(4));
+    }
+
+    #[test]
+    fn mesh_draw_binds_the_textures_to_their_samplers() {
+        let recording = record();
+        let shader = Shader::new(VERTEX_PATH, FRAGMENT_PATH).unwrap();
+        let (vertices, indices) = Primitive::Cube.geometry();
+        let data = MeshData::new(vertices, indices);
+        let first = GpuMesh::upload(&data, vec![texture(42, "texture_diffuse"), texture(43, "texture_specular")]);
+        let second = GpuMesh::upload(&data, vec![texture(44, "texture_diffuse")]);
+
+        shader.use_program();
+        first.draw(&shader);
+        assert_eq!(recording.uniform_int(shader.id, "texture_diffuse1"), Some(0));
+        assert_eq!(recording.uniform_int(shader.id, "texture_specular1"), Some(1));
+
+        // the second mesh binds its texture to the first unit again, which is no longer active
+        let start = recording.calls().len();
+        second.draw(&shader);
+        let calls = recording.calls().split_off(start);
+        assert!(calls.iter().any(|call| match *call {
+            GlCall::Uniform1i { location, value } =>
+                recording.uniform_name(location) == Some((shader.id, "texture_diffuse1".to_string())) && value == 0,
+            _ => false,
+        }));
+        let bind = calls.iter().position(|call| *call == GlCall::ActiveTexture { unit: gl::TEXTURE0 }).unwrap();
+        assert_eq!(calls[bind + 1], GlCall::BindTexture { target: gl::TEXTURE_2D, texture: 44 });
+
+        // the whole cube in one draw call, after its vertex array is bound
+        let draw = calls.iter().position(|call| matches!(call, GlCall::DrawElements { .. })).unwrap();
+        assert_eq!(calls[draw], GlCall::DrawElements { mode: gl::TRIANGLES, count: data.indices.len(), index_type: gl::UNSIGNED_INT, offset: 0 });
+        assert!(calls[..draw].iter().any(|call| matches!(call, GlCall::BindVertexArray { .. })));
+        assert_eq!(calls.iter().filter(|call| matches!(call, GlCall::DrawElements { .. })).count(), 1);
+    }
+}
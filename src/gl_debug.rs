@@ -0,0 +1,118 @@
+use std::ffi::{c_void, CStr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use gl::types::*;
+use crate::gl_api;
+
+// Makes GL errors visible in debug builds. With a debug context (see request_debug_context) and glDebugMessageCallback
+// (GL 4.3 or KHR_debug) the driver reports errors and warnings itself, right in the call that caused them. Where that
+// isn't available, like on macOS which stops at GL 4.1, the calls wrapped in gl_check! are followed by glGetError.
+// Release builds skip both.
+//
+// Objects get names with label, so debuggers like RenderDoc show "container.jpg" instead of "Texture 3".
+
+// whether the driver reports the errors, then gl_check! has nothing to do
+static CALLBACK_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// asks for a debug context in debug builds, call it before creating the window
+pub fn request_debug_context(glfw: &mut glfw::Glfw) {
+    if cfg!(debug_assertions) {
+        glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(true));
+    }
+}
+
+// installs the debug message callback in debug builds if the driver has one, call it after loading the GL functions
+pub fn init() {
+    if !cfg!(debug_assertions) || !gl::DebugMessageCallback::is_loaded() {
+        return;
+    }
+
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT);
+        // report in the call that caused the message, so a breakpoint in the callback shows the culprit
+        gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        gl::DebugMessageCallback(Some(debug_message), std::ptr::null());
+        // notifications like "buffer will use video memory" are only noise
+        gl::DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, gl::DEBUG_SEVERITY_NOTIFICATION, 0, std::ptr::null(),
+                                gl::FALSE);
+    }
+    CALLBACK_INSTALLED.store(true, Ordering::Relaxed);
+}
+
+// names a GL object for debuggers, identifier is the kind of object like gl::TEXTURE or gl::BUFFER. The object has to
+// be bound once before it can be labelled.
+pub fn label(identifier: GLenum, name: GLuint, label: &str) {
+    gl_api::with(|gl| gl.object_label(identifier, name, label));
+}
+
+// reports the errors glGetError has collected, unless the debug callback reported them already. Used by gl_check!.
+pub fn check_errors(call: &str, file: &str, line: u32) {
+    if !cfg!(debug_assertions) || CALLBACK_INSTALLED.load(Ordering::Relaxed) {
+        return;
+    }
+    // every error flag is returned once, a lost context could keep returning errors, so stop at some point
+    for _ in 0..16 {
+        let error = gl_api::with(|gl| gl.get_error());
+        if error == gl::NO_ERROR {
+            break;
+        }
+        eprintln!("ERROR::GL::{}: {} ({}:{})", error_name(error), call, file, line);
+    }
+}
+
+// runs a GL call, followed by check_errors in debug builds without the debug callback
+macro_rules! gl_check {
+    ($call:expr) => {{
+        let result = $call;
+        $crate::gl_debug::check_errors(stringify!($call), file!(), line!());
+        result
+    }};
+}
+pub(crate) use gl_check;
+
+extern "system" fn debug_message(source: GLenum, message_type: GLenum, id: GLuint, severity: GLenum,
+                                 length: GLsizei, message: *const GLchar, _user_param: *mut c_void) {
+    let message = unsafe {
+        if length >= 0 {
+            String::from_utf8_lossy(std::slice::from_raw_parts(message as *const u8, length as usize))
+        } else {
+            CStr::from_ptr(message).to_string_lossy()
+        }
+    };
+    let source = match source {
+        gl::DEBUG_SOURCE_API => "API",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "WINDOW_SYSTEM",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "SHADER_COMPILER",
+        gl::DEBUG_SOURCE_THIRD_PARTY => "THIRD_PARTY",
+        gl::DEBUG_SOURCE_APPLICATION => "APPLICATION",
+        _ => "OTHER",
+    };
+    let message_type = match message_type {
+        gl::DEBUG_TYPE_ERROR => "ERROR",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "DEPRECATED_BEHAVIOR",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "UNDEFINED_BEHAVIOR",
+        gl::DEBUG_TYPE_PORTABILITY => "PORTABILITY",
+        gl::DEBUG_TYPE_PERFORMANCE => "PERFORMANCE",
+        gl::DEBUG_TYPE_MARKER => "MARKER",
+        _ => "OTHER",
+    };
+    let severity = match severity {
+        gl::DEBUG_SEVERITY_HIGH => "HIGH",
+        gl::DEBUG_SEVERITY_MEDIUM => "MEDIUM",
+        gl::DEBUG_SEVERITY_LOW => "LOW",
+        _ => "NOTIFICATION",
+    };
+    eprintln!("GL::{}::{}::{} ({}): {}", message_type, source, severity, id, message.trim_end());
+}
+
+fn error_name(error: GLenum) -> String {
+    match error {
+        gl::INVALID_ENUM => "INVALID_ENUM".to_string(),
+        gl::INVALID_VALUE => "INVALID_VALUE".to_string(),
+        gl::INVALID_OPERATION => "INVALID_OPERATION".to_string(),
+        gl::INVALID_FRAMEBUFFER_OPERATION => "INVALID_FRAMEBUFFER_OPERATION".to_string(),
+        gl::OUT_OF_MEMORY => "OUT_OF_MEMORY".to_string(),
+        gl::STACK_UNDERFLOW => "STACK_UNDERFLOW".to_string(),
+        gl::STACK_OVERFLOW => "STACK_OVERFLOW".to_string(),
+        _ => format!("0x{:04X}", error),
+    }
+}
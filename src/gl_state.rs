@@ -0,0 +1,430 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use gl::types::*;
+use crate::gl_api;
+
+// A cache of the GL state: the bound program, vertex array, buffers and textures, and the render state (depth, blend,
+// cull, stencil and polygon mode). A call that wouldn't change anything is skipped, which matters when every mesh of a
+// model binds the same textures and program, and render state changes go through with_state, which puts the previous
+// state back, so a pass that draws wireframe or without depth test doesn't leak that into the next one.
+//
+// The cache only knows about what goes through it. Code that changes the same state with the gl functions directly
+// (or deletes a bound object, whose name GL may hand out again) has to call invalidate afterwards.
+//
+// GL state belongs to the context, which is current on one thread, so there is one GlState per thread, see with. The
+// calls go through gl_api, so a test can install a gl_api::RecordingGl and see which calls were skipped.
+
+// the fixed-function state of a draw, the default is the state of a new context
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RenderState {
+    pub depth_test: bool,
+    pub depth_func: GLenum,
+    pub depth_write: bool,
+    // the source and destination factors, None disables blending
+    pub blend: Option<(GLenum, GLenum)>,
+    // the faces to cull, None disables culling
+    pub cull_face: Option<GLenum>,
+    // None disables the stencil test
+    pub stencil: Option<StencilState>,
+    pub stencil_write_mask: GLuint,
+    // gl::FILL, gl::LINE or gl::POINT, for the front and back faces
+    pub polygon_mode: GLenum,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StencilState {
+    pub func: GLenum,
+    pub reference: GLint,
+    pub mask: GLuint,
+    pub stencil_fail: GLenum,
+    pub depth_fail: GLenum,
+    pub pass: GLenum,
+}
+
+impl Default for RenderState {
+    fn default() -> RenderState {
+        RenderState {
+            depth_test: false,
+            depth_func: gl::LESS,
+            depth_write: true,
+            blend: None,
+            cull_face: None,
+            stencil: None,
+            stencil_write_mask: GLuint::MAX,
+            polygon_mode: gl::FILL,
+        }
+    }
+}
+
+pub struct GlState {
+    // None when unknown, then the next call goes through
+    program: Option<GLuint>,
+    vertex_array: Option<GLuint>,
+    buffers: HashMap<GLenum, GLuint>,
+    // the active unit, counted from 0 like the sampler uniforms
+    active_unit: Option<GLuint>,
+    // the texture bound to each target of each unit
+    textures: HashMap<(GLuint, GLenum), GLuint>,
+    render: RenderState,
+    render_known: bool,
+}
+
+thread_local! {
+    static CURRENT: RefCell<GlState> = RefCell::new(GlState::new());
+}
+
+// runs f with the state tracker of the GL context of this thread
+pub fn with<R>(f: impl FnOnce(&mut GlState) -> R) -> R {
+    CURRENT.with(|state| f(&mut state.borrow_mut()))
+}
+
+impl GlState {
+    // a tracker for a new context, where everything has its default value
+    pub fn new() -> GlState {
+        GlState {
+            program: Some(0),
+            vertex_array: Some(0),
+            buffers: HashMap::new(),
+            active_unit: Some(0),
+            textures: HashMap::new(),
+            render: RenderState::default(),
+            render_known: true,
+        }
+    }
+
+    // forgets everything, so the next calls go through
+    pub fn invalidate(&mut self) {
+        self.program = None;
+        self.vertex_array = None;
+        self.buffers.clear();
+        self.active_unit = None;
+        self.textures.clear();
+        self.render_known = false;
+    }
+
+    pub fn use_program(&mut self, program: GLuint) {
+        if self.program != Some(program) {
+            gl_api::with(|gl| gl.use_program(program));
+            self.program = Some(program);
+        }
+    }
+
+    pub fn bind_vertex_array(&mut self, vertex_array: GLuint) {
+        if self.vertex_array != Some(vertex_array) {
+            gl_api::with(|gl| gl.bind_vertex_array(vertex_array));
+            self.vertex_array = Some(vertex_array);
+            // the element array buffer is part of the vertex array
+            self.buffers.remove(&gl::ELEMENT_ARRAY_BUFFER);
+        }
+    }
+
+    pub fn bind_buffer(&mut self, target: GLenum, buffer: GLuint) {
+        if self.buffers.get(&target) != Some(&buffer) {
+            gl_api::with(|gl| gl.bind_buffer(target, buffer));
+            self.buffers.insert(target, buffer);
+        }
+    }
+
+    // binds the texture to a unit (0 is gl::TEXTURE0), which becomes the active unit if the texture isn't already
+    // bound there
+    pub fn bind_texture(&mut self, unit: GLuint, target: GLenum, texture: GLuint) {
+        if self.textures.get(&(unit, target)) != Some(&texture) {
+            self.active_texture(unit);
+            gl_api::with(|gl| gl.bind_texture(target, texture));
+            self.textures.insert((unit, target), texture);
+        }
+    }
+
+    pub fn active_texture(&mut self, unit: GLuint) {
+        if self.active_unit != Some(unit) {
+            gl_api::with(|gl| gl.active_texture(gl::TEXTURE0 + unit));
+            self.active_unit = Some(unit);
+        }
+    }
+
+    // the render state, after invalidate the real one is unknown and this is the state of a new context
+    pub fn render_state(&self) -> RenderState {
+        if self.render_known { self.render } else { RenderState::default() }
+    }
+
+    // changes the render state, only the calls for the parts that differ are made
+    pub fn set_render_state(&mut self, state: &RenderState) {
+        let (old, known) = (self.render, self.render_known);
+
+        gl_api::with(|gl| {
+            let set_capability = |capability, enabled| {
+                if enabled { gl.enable(capability) } else { gl.disable(capability) }
+            };
+
+            if !known || old.depth_test != state.depth_test {
+                set_capability(gl::DEPTH_TEST, state.depth_test);
+            }
+            if !known || old.depth_func != state.depth_func {
+                gl.depth_func(state.depth_func);
+            }
+            if !known || old.depth_write != state.depth_write {
+                gl.depth_mask(state.depth_write);
+            }
+
+            if !known || old.blend.is_some() != state.blend.is_some() {
+                set_capability(gl::BLEND, state.blend.is_some());
+            }
+            if let Some((source, destination)) = state.blend {
+                if !known || old.blend != state.blend {
+                    gl.blend_func(source, destination);
+                }
+            }
+
+            if !known || old.cull_face.is_some() != state.cull_face.is_some() {
+                set_capability(gl::CULL_FACE, state.cull_face.is_some());
+            }
+            if let Some(mode) = state.cull_face {
+                if !known || old.cull_face != state.cull_face {
+                    gl.cull_face(mode);
+                }
+            }
+
+            if !known || old.stencil.is_some() != state.stencil.is_some() {
+                set_capability(gl::STENCIL_TEST, state.stencil.is_some());
+            }
+            if let Some(stencil) = state.stencil {
+                let old_stencil = old.stencil.filter(|_| known);
+                if old_stencil.map(|old| (old.func, old.reference, old.mask)) != Some((stencil.func, stencil.reference, stencil.mask)) {
+                    gl.stencil_func(stencil.func, stencil.reference, stencil.mask);
+                }
+                if old_stencil.map(|old| (old.stencil_fail, old.depth_fail, old.pass)) != Some((stencil.stencil_fail, stencil.depth_fail, stencil.pass)) {
+                    gl.stencil_op(stencil.stencil_fail, stencil.depth_fail, stencil.pass);
+                }
+            }
+            if !known || old.stencil_write_mask != state.stencil_write_mask {
+                gl.stencil_mask(state.stencil_write_mask);
+            }
+
+            if !known || old.polygon_mode != state.polygon_mode {
+                gl.polygon_mode(gl::FRONT_AND_BACK, state.polygon_mode);
+            }
+        });
+
+        self.render = *state;
+        self.render_known = true;
+    }
+}
+
+// changes the render state of this thread's context until the returned guard is dropped, which puts the previous
+// state back. After invalidate the previous state is unknown, so the guard restores the state of a new context.
+pub fn with_state(change: impl FnOnce(&mut RenderState)) -> StateGuard {
+    with(|gl| {
+        let previous = gl.render_state();
+        let mut state = previous;
+        change(&mut state);
+        gl.set_render_state(&state);
+        StateGuard { previous }
+    })
+}
+
+pub struct StateGuard {
+    previous: RenderState,
+}
+
+impl Drop for StateGuard {
+    fn drop(&mut self) {
+        with(|gl| gl.set_render_state(&self.previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::gl_api::{self, GlCall, RecordingGl};
+
+    // a recording in place of the GL context, with a tracker for a new context
+    fn record() -> RecordingGl {
+        let recording = RecordingGl::default();
+        gl_api::install(Rc::new(recording.clone()));
+        with(|gl| *gl = GlState::new());
+        recording
+    }
+
+    // a state that differs from the default in every part
+    fn changed_state() -> RenderState {
+        RenderState {
+            depth_test: true,
+            depth_func: gl::LEQUAL,
+            depth_write: false,
+            blend: Some((gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)),
+            cull_face: Some(gl::BACK),
+            stencil: Some(StencilState {
+                func: gl::NOTEQUAL,
+                reference: 1,
+                mask: 0xFF,
+                stencil_fail: gl::KEEP,
+                depth_fail: gl::KEEP,
+                pass: gl::REPLACE,
+            }),
+            stencil_write_mask: 0x00,
+            polygon_mode: gl::LINE,
+        }
+    }
+
+    // the calls that set every part of the default state
+    fn default_state_calls() -> Vec<GlCall> {
+        vec![
+            GlCall::Disable { capability: gl::DEPTH_TEST },
+            GlCall::DepthFunc { func: gl::LESS },
+            GlCall::DepthMask { write: true },
+            GlCall::Disable { capability: gl::BLEND },
+            GlCall::Disable { capability: gl::CULL_FACE },
+            GlCall::Disable { capability: gl::STENCIL_TEST },
+            GlCall::StencilMask { mask: GLuint::MAX },
+            GlCall::PolygonMode { face: gl::FRONT_AND_BACK, mode: gl::FILL },
+        ]
+    }
+
+    #[test]
+    fn binding_the_same_object_again_is_skipped() {
+        let recording = record();
+        with(|gl| {
+            for _ in 0..2 {
+                gl.use_program(3);
+                gl.bind_vertex_array(4);
+                gl.bind_buffer(gl::ARRAY_BUFFER, 5);
+                gl.bind_texture(0, gl::TEXTURE_2D, 6);
+                gl.bind_texture(1, gl::TEXTURE_2D, 7);
+            }
+        });
+        assert_eq!(recording.calls(), vec![
+            GlCall::UseProgram { program: 3 },
+            GlCall::BindVertexArray { vertex_array: 4 },
+            GlCall::BindBuffer { target: gl::ARRAY_BUFFER, buffer: 5 },
+            // unit 0 is already active in a new context
+            GlCall::BindTexture { target: gl::TEXTURE_2D, texture: 6 },
+            GlCall::ActiveTexture { unit: gl::TEXTURE1 },
+            GlCall::BindTexture { target: gl::TEXTURE_2D, texture: 7 },
+        ]);
+
+        // a different texture on a unit that isn't active
+        recording.clear();
+        with(|gl| gl.bind_texture(0, gl::TEXTURE_2D, 8));
+        assert_eq!(recording.calls(), vec![
+            GlCall::ActiveTexture { unit: gl::TEXTURE0 },
+            GlCall::BindTexture { target: gl::TEXTURE_2D, texture: 8 },
+        ]);
+    }
+
+    #[test]
+    fn binding_a_vertex_array_forgets_the_element_array_buffer() {
+        let recording = record();
+        with(|gl| {
+            gl.bind_vertex_array(1);
+            gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 2);
+            gl.bind_buffer(gl::ARRAY_BUFFER, 3);
+        });
+        recording.clear();
+
+        // the same vertex array keeps its element array buffer
+        with(|gl| {
+            gl.bind_vertex_array(1);
+            gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 2);
+        });
+        assert_eq!(recording.calls(), vec![]);
+
+        // another one has its own, but the array buffer isn't part of it
+        with(|gl| {
+            gl.bind_vertex_array(4);
+            gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 2);
+            gl.bind_buffer(gl::ARRAY_BUFFER, 3);
+        });
+        assert_eq!(recording.calls(), vec![
+            GlCall::BindVertexArray { vertex_array: 4 },
+            GlCall::BindBuffer { target: gl::ELEMENT_ARRAY_BUFFER, buffer: 2 },
+        ]);
+    }
+
+    #[test]
+    fn the_guard_puts_the_previous_state_back() {
+        let recording = record();
+        with(|gl| gl.set_render_state(&RenderState { depth_test: true, ..RenderState::default() }));
+        recording.clear();
+
+        let guard = with_state(|state| *state = changed_state());
+        // the depth test was on already
+        assert_eq!(recording.calls(), vec![
+            GlCall::DepthFunc { func: gl::LEQUAL },
+            GlCall::DepthMask { write: false },
+            GlCall::Enable { capability: gl::BLEND },
+            GlCall::BlendFunc { source: gl::SRC_ALPHA, destination: gl::ONE_MINUS_SRC_ALPHA },
+            GlCall::Enable { capability: gl::CULL_FACE },
+            GlCall::CullFace { mode: gl::BACK },
+            GlCall::Enable { capability: gl::STENCIL_TEST },
+            GlCall::StencilFunc { func: gl::NOTEQUAL, reference: 1, mask: 0xFF },
+            GlCall::StencilOp { stencil_fail: gl::KEEP, depth_fail: gl::KEEP, pass: gl::REPLACE },
+            GlCall::StencilMask { mask: 0x00 },
+            GlCall::PolygonMode { face: gl::FRONT_AND_BACK, mode: gl::LINE },
+        ]);
+        assert_eq!(with(|gl| gl.render_state()), changed_state());
+
+        recording.clear();
+        drop(guard);
+        assert_eq!(recording.calls(), vec![
+            GlCall::DepthFunc { func: gl::LESS },
+            GlCall::DepthMask { write: true },
+            GlCall::Disable { capability: gl::BLEND },
+            GlCall::Disable { capability: gl::CULL_FACE },
+            GlCall::Disable { capability: gl::STENCIL_TEST },
+            GlCall::StencilMask { mask: GLuint::MAX },
+            GlCall::PolygonMode { face: gl::FRONT_AND_BACK, mode: gl::FILL },
+        ]);
+        assert_eq!(with(|gl| gl.render_state()), RenderState { depth_test: true, ..RenderState::default() });
+    }
+
+    #[test]
+    fn invalidate_lets_every_call_through() {
+        let recording = record();
+        let bind_everything = || with(|gl| {
+            gl.use_program(3);
+            gl.bind_vertex_array(4);
+            gl.bind_buffer(gl::ARRAY_BUFFER, 5);
+            gl.bind_texture(0, gl::TEXTURE_2D, 6);
+            gl.set_render_state(&RenderState::default());
+        });
+        bind_everything();
+        recording.clear();
+
+        with(|gl| gl.invalidate());
+        bind_everything();
+        let mut expected = vec![
+            GlCall::UseProgram { program: 3 },
+            GlCall::BindVertexArray { vertex_array: 4 },
+            GlCall::BindBuffer { target: gl::ARRAY_BUFFER, buffer: 5 },
+            GlCall::ActiveTexture { unit: gl::TEXTURE0 },
+            GlCall::BindTexture { target: gl::TEXTURE_2D, texture: 6 },
+        ];
+        expected.extend(default_state_calls());
+        assert_eq!(recording.calls(), expected);
+
+        // and only once
+        recording.clear();
+        bind_everything();
+        assert_eq!(recording.calls(), vec![]);
+    }
+
+    #[test]
+    fn the_guard_restores_the_defaults_after_invalidate() {
+        let recording = record();
+        with(|gl| gl.set_render_state(&changed_state()));
+        with(|gl| gl.invalidate());
+        recording.clear();
+
+        // the state from before invalidate is not what the guard goes back to
+        let guard = with_state(|state| state.depth_test = true);
+        let mut expected = default_state_calls();
+        expected[0] = GlCall::Enable { capability: gl::DEPTH_TEST };
+        assert_eq!(recording.calls(), expected);
+
+        recording.clear();
+        drop(guard);
+        assert_eq!(recording.calls(), vec![GlCall::Disable { capability: gl::DEPTH_TEST }]);
+        assert_eq!(with(|gl| gl.render_state()), RenderState::default());
+    }
+}
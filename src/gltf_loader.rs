@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use gltf::mesh::Mode;
+use nalgebra_glm as glm;
+use crate::mesh::{MeshData, Vertex};
+use crate::mesh_ops;
+use crate::model::{Material, ModelData, ModelNode, Skin, TextureReference};
+
+// A pure-Rust glTF 2.0 loader (enabled by the gltf feature) producing the same ModelData as assimp. It reads .gltf files
+// with external or embedded (base64) buffers and binary .glb files, the metallic-roughness materials with the
+// KHR_texture_transform extension, the node tree of the default scene and skins.
+//
+// The materials name their textures like the assimp loader does, plus the textures assimp doesn't know about:
+// base color: texture_diffuseN
+// metallic (blue) and roughness (green): texture_metallic_roughnessN
+// normal: texture_normalN
+// occlusion: texture_occlusionN
+// emissive: texture_emissiveN
+//
+// All textures are sampled with the first set of texture coordinates. The shaders of the tutorials don't transform
+// texture coordinates either, so the transformation of the base color texture is applied to the vertices instead.
+
+pub fn is_gltf(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.ends_with(".gltf") || path.ends_with(".glb")
+}
+
+pub fn import(path: &str) -> Result<ModelData, String> {
+    let gltf = gltf::Gltf::open(path).map_err(|e| format!("ERROR::GLTF::LOAD_FAILED: {path}: {e}"))?;
+    let mut data = ModelData {
+        directory: path.rfind('/').map_or(".", |i| &path[..i]).to_owned(),
+        ..ModelData::default()
+    };
+
+    let buffers = gltf.buffers().map(|buffer| {
+        let bytes = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone().ok_or("the binary chunk is missing")?,
+            gltf::buffer::Source::Uri(uri) => read_uri(uri, &data.directory)?,
+        };
+        if bytes.len() < buffer.length() {
+            return Err(format!("buffer {} is shorter than its {} bytes", buffer.index(), buffer.length()));
+        }
+        Ok(bytes)
+    }).collect::<Result<Vec<_>, String>>().map_err(|e| format!("ERROR::GLTF::LOAD_FAILED: {path}: {e}"))?;
+
+    // images stored in buffers or data URIs are kept encoded and decoded together with the texture files
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let images = gltf.images().map(|image| match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let bytes = &buffers[view.buffer().index()][view.offset()..view.offset() + view.length()];
+            Ok((format!("{file_name}#image{}", image.index()), Some(bytes.into())))
+        }
+        gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:") => {
+            Ok((format!("{file_name}#image{}", image.index()), Some(read_uri(uri, "")?.into())))
+        }
+        gltf::image::Source::Uri { uri, .. } => Ok((percent_decode(uri), None)),
+    }).collect::<Result<Vec<(String, Option<Arc<[u8]>>)>, String>>()
+        .map_err(|e| format!("ERROR::GLTF::LOAD_FAILED: {path}: {e}"))?;
+
+    data.materials = gltf.materials().map(|material| process_material(&material, &images)).collect();
+    // primitives without a material use the default material, which comes after the others
+    let default_material = data.materials.len();
+
+    // every primitive becomes a mesh, so a glTF mesh is a list of meshes
+    let mut meshes = Vec::new();
+    for mesh in gltf.meshes() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            let material = primitive.material();
+            let texture_transform = material.pbr_metallic_roughness().base_color_texture()
+                .and_then(|info| info.texture_transform())
+                .map(|transform| texture_matrix(transform.offset(), transform.rotation(), transform.scale()));
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            if let Some(mut mesh_data) = process_primitive(&primitive, &reader, texture_transform)
+                .map_err(|e| format!("ERROR::GLTF::LOAD_FAILED: {path}: mesh {}: {e}", mesh.index()))? {
+                mesh_data.material = material.index().unwrap_or(default_material);
+                primitives.push(data.meshes.len());
+                data.meshes.push(mesh_data);
+            }
+        }
+        meshes.push(primitives);
+    }
+    if data.meshes.iter().any(|mesh| mesh.material == default_material) {
+        data.materials.push(Material { name: "default".to_string(), ..Material::default() });
+    }
+
+    // the nodes of the default scene (or all nodes without a parent if there are no scenes), parents first
+    let roots: Vec<gltf::Node> = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let children: Vec<usize> = gltf.nodes().flat_map(|node| node.children()).map(|child| child.index()).collect();
+            gltf.nodes().filter(|node| !children.contains(&node.index())).collect()
+        }
+    };
+    let mut node_indices = HashMap::new();
+    for root in roots {
+        process_node(&root, None, &meshes, &mut data, &mut node_indices);
+    }
+
+    for skin in gltf.skins() {
+        let joints = skin.joints()
+            .map(|joint| node_indices.get(&joint.index()).copied()
+                .ok_or_else(|| format!("ERROR::GLTF::LOAD_FAILED: {path}: joint {} is not in the scene", joint.index())))
+            .collect::<Result<Vec<_>, String>>()?;
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        // without inverse bind matrices the joints are in model space already
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(|m| glm::Mat4::from_fn(|row, column| m[column][row])).collect(),
+            None => vec![glm::identity(); joints.len()],
+        };
+        data.skins.push(Skin { name: skin.name().unwrap_or_default().to_string(), joints, inverse_bind_matrices });
+    }
+
+    Ok(data)
+}
+
+fn process_node(node: &gltf::Node, parent: Option<usize>, meshes: &[Vec<usize>], data: &mut ModelData,
+                node_indices: &mut HashMap<usize, usize>)
+{
+    let m = node.transform().matrix();
+    data.nodes.push(ModelNode {
+        name: node.name().unwrap_or_default().to_string(),
+        parent,
+        // glTF matrices are column major like glm's
+        transform: glm::Mat4::from_fn(|row, column| m[column][row]),
+        meshes: node.mesh().map_or(Vec::new(), |mesh| meshes[mesh.index()].clone()),
+        skin: node.skin().map(|skin| skin.index()),
+    });
+
+    let index = data.nodes.len() - 1;
+    node_indices.insert(node.index(), index);
+    for child in node.children() {
+        process_node(&child, Some(index), meshes, data, node_indices);
+    }
+}
+
+fn process_material(material: &gltf::Material, images: &[(String, Option<Arc<[u8]>>)]) -> Material
+{
+    let pbr = material.pbr_metallic_roughness();
+    let mut textures = Vec::new();
+    let mut add_texture = |type_name: &str, texture: Option<gltf::Texture>| {
+        if let Some(texture) = texture {
+            let (path, embedded) = &images[texture.source().index()];
+            textures.push(TextureReference { type_name: type_name.to_string(), path: path.clone(), embedded: embedded.clone() });
+        }
+    };
+    add_texture("texture_diffuse", pbr.base_color_texture().map(|info| info.texture()));
+    add_texture("texture_metallic_roughness", pbr.metallic_roughness_texture().map(|info| info.texture()));
+    add_texture("texture_normal", material.normal_texture().map(|info| info.texture()));
+    add_texture("texture_occlusion", material.occlusion_texture().map(|info| info.texture()));
+    add_texture("texture_emissive", material.emissive_texture().map(|info| info.texture()));
+
+    Material {
+        name: material.name().unwrap_or_default().to_string(),
+        textures,
+        base_color: glm::make_vec4(&pbr.base_color_factor()),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: glm::make_vec3(&material.emissive_factor()),
+        ..Material::default()
+    }
+}
+
+// reads the vertices and triangles of a primitive, points and lines are skipped. The texture coordinates are
+// transformed before the tangents are calculated from them.
+fn process_primitive<'a, 's, F>(primitive: &gltf::Primitive, reader: &gltf::mesh::Reader<'a, 's, F>,
+                                texture_transform: Option<glm::Mat3>) -> Result<Option<MeshData>, String>
+    where F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>
+{
+    let positions = match reader.read_positions() {
+        Some(positions) => positions,
+        None => return Ok(None),
+    };
+    let mut vertices: Vec<Vertex> = positions.map(|position| Vertex {
+        position: glm::make_vec3(&position),
+        ..Vertex::default()
+    }).collect();
+    let count = vertices.len();
+
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..count as u32).collect(),
+    };
+    if indices.iter().any(|&index| index as usize >= count) {
+        return Err(format!("indices past the {count} vertices"));
+    }
+    let mut indices = match primitive.mode() {
+        Mode::Triangles => indices,
+        Mode::TriangleStrip => (2..indices.len()).flat_map(|i| {
+            // every other triangle is flipped to keep the winding
+            if i % 2 == 0 { [indices[i - 2], indices[i - 1], indices[i]] } else { [indices[i - 1], indices[i - 2], indices[i]] }
+        }).collect(),
+        Mode::TriangleFan => (2..indices.len()).flat_map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+        _ => return Ok(None),
+    };
+    indices.truncate(indices.len() / 3 * 3);
+
+    if let Some(tex_coords) = reader.read_tex_coords(0) {
+        let matrix = texture_transform.unwrap_or_else(glm::identity);
+        for (vertex, tex_coords) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+            vertex.tex_coords = (matrix * glm::vec3(tex_coords[0], tex_coords[1], 1.0)).xy();
+        }
+    }
+
+    let (mut joints, mut weights) = (Vec::new(), Vec::new());
+    if let (Some(joint_reader), Some(weight_reader)) = (reader.read_joints(0), reader.read_weights(0)) {
+        joints = joint_reader.into_u16().collect();
+        weights = weight_reader.into_f32().collect();
+        if joints.len() != count || weights.len() != count {
+            return Err("joints or weights don't match the vertices".to_string());
+        }
+    }
+
+    let has_normals = match reader.read_normals() {
+        Some(normals) => {
+            for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                vertex.normal = glm::make_vec3(&normal);
+            }
+            true
+        }
+        None => false,
+    };
+    let mut mesh = MeshData { vertices, indices, material: 0, joints, weights, lods: Vec::new() };
+    // without normals the mesh has to be flat shaded
+    if !has_normals {
+        mesh_ops::flat_normals(&mut mesh);
+    }
+
+    match reader.read_tangents() {
+        // the w component tells whether the bitangent points the other way
+        Some(tangents) if has_normals => {
+            for (vertex, tangent) in mesh.vertices.iter_mut().zip(tangents) {
+                vertex.tangent = glm::vec3(tangent[0], tangent[1], tangent[2]);
+                vertex.bitangent = vertex.normal.cross(&vertex.tangent) * tangent[3];
+            }
+        }
+        // glTF asks for MikkTSpace tangents when there are none, generate_tangents only approximates them
+        _ => mesh_ops::generate_tangents(&mut mesh),
+    }
+
+    Ok(Some(mesh))
+}
+
+// the matrix of KHR_texture_transform, it scales first, then rotates and offsets the texture coordinates
+fn texture_matrix(offset: [f32; 2], rotation: f32, scale: [f32; 2]) -> glm::Mat3 {
+    let (sin, cos) = rotation.sin_cos();
+    glm::mat3(
+        cos * scale[0], sin * scale[1], offset[0],
+        -sin * scale[0], cos * scale[1], offset[1],
+        0.0, 0.0, 1.0,
+    )
+}
+
+// reads a data URI or a file relative to the directory
+fn read_uri(uri: &str, directory: &str) -> Result<Vec<u8>, String> {
+    match uri.strip_prefix("data:") {
+        Some(data) => match data.split_once(";base64,") {
+            Some((_, encoded)) => decode_base64(encoded),
+            None => Err(format!("unsupported data URI {}", &uri[..uri.len().min(40)])),
+        },
+        None => {
+            let path = format!("{directory}/{}", percent_decode(uri));
+            fs::read(&path).map_err(|e| format!("{path}: {e}"))
+        }
+    }
+}
+
+// standard or URL-safe base64, the padding at the end is optional
+fn decode_base64(encoded: &str) -> Result<Vec<u8>, String> {
+    let data = encoded.trim_end_matches('=');
+    // a single character left over holds only 6 bits, not a whole byte
+    if encoded.len() - data.len() > 2 || data.len() % 4 == 1 {
+        return Err(format!("invalid base64 length {}", encoded.len()));
+    }
+
+    let mut bytes = Vec::with_capacity(data.len() / 4 * 3 + 2);
+    let (mut bits, mut bit_count) = (0u32, 0);
+    for c in data.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(format!("invalid base64 character {:?}", c as char)),
+        };
+        bits = bits << 6 | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+// URIs escape spaces and other special characters in file names as %XX
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRECTORY: &str = "resources/objects/gltf";
+
+    fn import_sample(name: &str) -> ModelData {
+        import(&format!("{DIRECTORY}/{name}")).unwrap()
+    }
+
+    fn assert_mat4(actual: &glm::Mat4, expected: &glm::Mat4) {
+        assert!((actual - expected).abs().max() < 1e-5, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn triangle_with_an_embedded_buffer() {
+        let data = import_sample("triangle.gltf");
+        assert_eq!(data.directory, DIRECTORY);
+        assert_eq!(data.meshes.len(), 1);
+        let mesh = &data.meshes[0];
+        // no indices, so the vertices are used in order, and no normals, so they are calculated
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        let positions: Vec<glm::Vec3> = mesh.vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, vec![glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)]);
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == glm::vec3(0.0, 0.0, 1.0)));
+
+        // without a material the primitive gets the default one
+        assert_eq!(data.materials.len(), 1);
+        assert_eq!(data.materials[0].name, "default");
+        assert!(data.materials[0].textures.is_empty());
+        assert_eq!(data.nodes.len(), 1);
+        assert_eq!(data.nodes[0].meshes, vec![0]);
+    }
+
+    #[test]
+    fn textured_quad_with_a_texture_transform() {
+        let data = import_sample("textured_quad.gltf");
+        assert_eq!(data.meshes.len(), 1);
+        let mesh = &data.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 3, 0]);
+
+        let material = &data.materials[mesh.material];
+        assert_eq!(material.name, "container");
+        assert_eq!((material.metallic, material.roughness), (0.0, 0.5));
+        assert_eq!(material.textures.len(), 1);
+        assert_eq!(material.textures[0].type_name, "texture_diffuse");
+        assert_eq!(material.textures[0].path, "../../textures/container2.png");
+        assert!(material.textures[0].embedded.is_none());
+
+        // scaled by 2, turned by 90° and moved by 0.5 along u: (0, 0) -> (0.5, 0) and (1, 0) -> (0.5, -2)
+        let tex_coords: Vec<glm::Vec2> = mesh.vertices.iter().map(|vertex| vertex.tex_coords).collect();
+        assert!((tex_coords[3] - glm::vec2(0.5, 0.0)).norm() < 1e-5, "{:?}", tex_coords);
+        assert!((tex_coords[2] - glm::vec2(0.5, -2.0)).norm() < 1e-5, "{:?}", tex_coords);
+
+        // translation, rotation (90° around y) and scale of the node
+        let expected = glm::scale(&glm::rotate_y(&glm::translation(&glm::vec3(0.0, 1.0, 0.0)), 90f32.to_radians()),
+                                  &glm::vec3(2.0, 2.0, 2.0));
+        assert_mat4(&data.nodes[0].transform, &expected);
+    }
+
+    #[test]
+    fn skinned_strip_from_a_binary_file() {
+        let data = import_sample("skinned_strip.glb");
+        assert_eq!(data.meshes.len(), 1);
+        let mesh = &data.meshes[0];
+        assert_eq!((mesh.vertices.len(), mesh.indices.len()), (6, 12));
+        assert_eq!(mesh.joints.len(), 6);
+        assert_eq!(mesh.weights[2], [0.5, 0.5, 0.0, 0.0]);
+
+        // the image is stored in the binary chunk
+        let texture = &data.materials[mesh.material].textures[0];
+        assert_eq!(texture.path, "skinned_strip.glb#image0");
+        let image = image::load_from_memory(texture.embedded.as_ref().unwrap()).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+
+        // the nodes come parents first
+        let nodes: Vec<(&str, Option<usize>)> = data.nodes.iter().map(|node| (node.name.as_str(), node.parent)).collect();
+        assert_eq!(nodes, vec![("armature", None), ("root bone", Some(0)), ("tip bone", Some(1)), ("strip", Some(0))]);
+        assert_eq!(data.nodes[3].skin, Some(0));
+        // the tip bone sits 1 above the root bone and is bent by 30° around z
+        let transforms = data.node_transforms();
+        assert_mat4(&transforms[1], &glm::identity());
+        assert_mat4(&transforms[2], &glm::rotate_z(&glm::translation(&glm::vec3(0.0, 1.0, 0.0)), 30f32.to_radians()));
+
+        // the joints are the two bones, the inverse bind matrices undo where they were in the bind pose
+        assert_eq!(data.skins.len(), 1);
+        let skin = &data.skins[0];
+        assert_eq!(skin.joints, vec![1, 2]);
+        assert_eq!(skin.inverse_bind_matrices.len(), 2);
+        assert_mat4(&skin.inverse_bind_matrices[0], &glm::identity());
+        assert_mat4(&skin.inverse_bind_matrices[1], &glm::translation(&glm::vec3(0.0, -1.0, 0.0)));
+    }
+
+    #[test]
+    fn image_in_a_data_uri() {
+        // the triangle with a material whose base color texture is a 1x1 PNG in a data URI
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] }],
+            "materials": [{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }],
+            "textures": [{ "source": 0 }],
+            "images": [{ "uri": "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAIAAACQd1PeAAAADElEQVR4nGMQUDAAAACkAGE0Zn1yAAAAAElFTkSuQmCC" }],
+            "buffers": [{ "byteLength": 36, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA" }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+            "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                            "min": [0, 0, 0], "max": [1, 1, 0] }]
+        }"#;
+        let path = std::env::temp_dir().join(format!("learn-opengl-rs-{}-data_uri.gltf", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, gltf).unwrap();
+        let data = import(path);
+        fs::remove_file(path).unwrap();
+        let data = data.unwrap();
+
+        let texture = &data.materials[0].textures[0];
+        assert_eq!(texture.type_name, "texture_diffuse");
+        assert!(texture.path.ends_with("data_uri.gltf#image0"), "{}", texture.path);
+        let image = image::load_from_memory(texture.embedded.as_ref().unwrap()).unwrap().to_rgb8();
+        assert_eq!(image.into_raw(), vec![0x10, 0x20, 0x30]);
+        assert_eq!(data.meshes[0].vertices.len(), 3);
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        // with and without padding
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TWE").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+        assert_eq!(decode_base64("TQ").unwrap(), b"M");
+        assert_eq!(decode_base64("TWFuTWE=").unwrap(), b"ManMa");
+        // the standard and the URL-safe alphabet
+        assert_eq!(decode_base64("+/+/").unwrap(), vec![0xfb, 0xff, 0xbf]);
+        assert_eq!(decode_base64("-_-_").unwrap(), vec![0xfb, 0xff, 0xbf]);
+
+        assert!(decode_base64("TW!u").is_err());
+        assert!(decode_base64("TW u").is_err());
+        assert!(decode_base64("T").is_err());
+        assert!(decode_base64("TWFuT").is_err());
+        assert!(decode_base64("T===").is_err());
+        assert!(decode_base64("TW=u").is_err());
+    }
+
+    #[test]
+    fn data_uris() {
+        assert_eq!(read_uri("data:application/octet-stream;base64,TWFu", "").unwrap(), b"Man");
+        assert!(read_uri("data:text/plain,Man", "").is_err());
+        assert!(read_uri("missing%20file.bin", DIRECTORY).unwrap_err().contains("missing file.bin"));
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("container2.png"), "container2.png");
+        assert_eq!(percent_decode("my%20texture.png"), "my texture.png");
+        assert_eq!(percent_decode("%E2%82%AC.png"), "€.png");
+        assert_eq!(percent_decode("%2fa%2Fb"), "/a/b");
+        // anything that isn't an escape stays as it is
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        // bytes that aren't UTF-8 become replacement characters
+        assert_eq!(percent_decode("%ff.png"), "\u{fffd}.png");
+    }
+}
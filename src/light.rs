@@ -0,0 +1,489 @@
+use std::mem;
+use nalgebra_glm as glm;
+use memoffset::offset_of;
+use crate::shader::Shader;
+use crate::uniform_buffer::{check_offset, Std140, Std140Vec3, UniformBuffer};
+
+// the maximum number of lights of each type, these have to match the defines in the shaders that declare the Lights
+// block. A whole block takes 12048 bytes, below the 16KB every OpenGL implementation supports.
+pub const MAX_DIR_LIGHTS: usize = 4;
+pub const MAX_POINT_LIGHTS: usize = 128;
+pub const MAX_SPOT_LIGHTS: usize = 16;
+
+// the attenuation table of the Ogre3D wiki, (range, constant, linear, quadratic): a light with these coefficients
+// covers about the given distance
+const ATTENUATION_TABLE: [(f32, f32, f32, f32); 12] = [
+    (7.0, 1.0, 0.7, 1.8),
+    (13.0, 1.0, 0.35, 0.44),
+    (20.0, 1.0, 0.22, 0.20),
+    (32.0, 1.0, 0.14, 0.07),
+    (50.0, 1.0, 0.09, 0.032),
+    (65.0, 1.0, 0.07, 0.017),
+    (100.0, 1.0, 0.045, 0.0075),
+    (160.0, 1.0, 0.027, 0.0028),
+    (200.0, 1.0, 0.022, 0.0019),
+    (325.0, 1.0, 0.014, 0.0007),
+    (600.0, 1.0, 0.007, 0.0002),
+    (3250.0, 1.0, 0.0014, 0.000007),
+];
+
+// the light is considered out of reach when it contributes less than this to a color channel (5/256, dark enough to
+// go unnoticed with 8 bit colors)
+const MIN_LIGHT_CONTRIBUTION: f32 = 5.0 / 256.0;
+
+// Defines how the light of a point or spot light fades with the distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Attenuation {
+    // 1 / (constant + linear * distance + quadratic * distance²), the formula of the tutorials
+    Polynomial { constant: f32, linear: f32, quadratic: f32 },
+    // the physically correct 1 / distance², smoothly faded to zero at the radius by the window function
+    // (1 - (distance / radius)^4)² so the light really ends there (see Karis, Real Shading in Unreal Engine 4)
+    InverseSquare { radius: f32 },
+}
+
+impl Default for Attenuation {
+    fn default() -> Attenuation {
+        Attenuation::from_range(50.0)
+    }
+}
+
+impl Attenuation {
+    // returns the polynomial coefficients for a light that should reach about the given distance, interpolated from
+    // the table for ranges from 7 to 3250 and clamped outside of them
+    pub fn from_range(distance: f32) -> Attenuation {
+        let i = ATTENUATION_TABLE.iter().position(|row| row.0 >= distance).unwrap_or(ATTENUATION_TABLE.len() - 1);
+        let (range1, constant1, linear1, quadratic1) = ATTENUATION_TABLE[i.saturating_sub(1)];
+        let (range2, constant2, linear2, quadratic2) = ATTENUATION_TABLE[i];
+
+        let t = if range2 > range1 { ((distance - range1) / (range2 - range1)).clamp(0.0, 1.0) } else { 0.0 };
+        Attenuation::Polynomial {
+            constant: constant1 + (constant2 - constant1) * t,
+            linear: linear1 + (linear2 - linear1) * t,
+            quadratic: quadratic1 + (quadratic2 - quadratic1) * t,
+        }
+    }
+
+    // the factor the light is multiplied with at the distance, the same calculation as CalcAttenuation in the shaders.
+    // Only the tests need it on the CPU.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn factor(&self, distance: f32) -> f32 {
+        match *self {
+            Attenuation::Polynomial { constant, linear, quadratic } => {
+                1.0 / (constant + linear * distance + quadratic * distance * distance)
+            }
+            Attenuation::InverseSquare { radius } => {
+                let window = (1.0 - (distance / radius).powi(4)).clamp(0.0, 1.0);
+                window * window / (distance * distance + 1.0)
+            }
+        }
+    }
+
+    // returns the distance at which a light of the given brightness (its brightest color channel) becomes too dark to
+    // be noticed, so the light can be skipped for everything further away. A polynomial attenuation without linear
+    // and quadratic term never fades, which gives an infinite radius.
+    pub fn radius(&self, brightness: f32) -> f32 {
+        match *self {
+            Attenuation::Polynomial { constant, linear, quadratic } => {
+                // solve brightness / (constant + linear * d + quadratic * d²) = MIN_LIGHT_CONTRIBUTION for d
+                let c = constant - brightness / MIN_LIGHT_CONTRIBUTION;
+                if c >= 0.0 {
+                    // too dark to be noticed even next to the light
+                    0.0
+                } else if quadratic > 0.0 {
+                    (-linear + (linear * linear - 4.0 * quadratic * c).sqrt()) / (2.0 * quadratic)
+                } else if linear > 0.0 {
+                    -c / linear
+                } else {
+                    f32::INFINITY
+                }
+            }
+            Attenuation::InverseSquare { radius } => radius,
+        }
+    }
+
+    // the values of the attenuation members of the shader light structs: constant, linear, quadratic and radius. A
+    // radius of 0 selects the polynomial attenuation.
+    pub fn shader_values(&self) -> (f32, f32, f32, f32) {
+        match *self {
+            Attenuation::Polynomial { constant, linear, quadratic } => (constant, linear, quadratic, 0.0),
+            Attenuation::InverseSquare { radius } => (1.0, 0.0, 0.0, radius),
+        }
+    }
+}
+
+// A light infinitely far away that lights everything from the same direction, like the sun
+#[derive(Clone, Copy, Debug)]
+pub struct DirLight {
+    pub direction: glm::Vec3,
+
+    pub ambient: glm::Vec3,
+    pub diffuse: glm::Vec3,
+    pub specular: glm::Vec3,
+}
+
+impl Default for DirLight {
+    fn default() -> DirLight {
+        DirLight {
+            direction: glm::vec3(-0.2, -1.0, -0.3),
+            ambient: glm::vec3(0.05, 0.05, 0.05),
+            diffuse: glm::vec3(0.4, 0.4, 0.4),
+            specular: glm::vec3(0.5, 0.5, 0.5),
+        }
+    }
+}
+
+// A light at a position that shines in all directions and fades with the distance
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: glm::Vec3,
+
+    pub attenuation: Attenuation,
+
+    pub ambient: glm::Vec3,
+    pub diffuse: glm::Vec3,
+    pub specular: glm::Vec3,
+}
+
+impl Default for PointLight {
+    // the attenuation covers a distance of about 50 units
+    fn default() -> PointLight {
+        PointLight {
+            position: glm::vec3(0.0, 0.0, 0.0),
+            attenuation: Attenuation::default(),
+            ambient: glm::vec3(0.05, 0.05, 0.05),
+            diffuse: glm::vec3(0.8, 0.8, 0.8),
+            specular: glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// A point light that only shines inside a cone, like a flashlight. The cut-offs are the cosines of the inner and the
+// outer angle of the cone, between them the light fades out.
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: glm::Vec3,
+    pub direction: glm::Vec3,
+    pub cut_off: f32,
+    pub outer_cut_off: f32,
+
+    pub attenuation: Attenuation,
+
+    pub ambient: glm::Vec3,
+    pub diffuse: glm::Vec3,
+    pub specular: glm::Vec3,
+}
+
+impl Default for SpotLight {
+    fn default() -> SpotLight {
+        SpotLight {
+            position: glm::vec3(0.0, 0.0, 0.0),
+            direction: glm::vec3(0.0, 0.0, -1.0),
+            cut_off: 12.5_f32.to_radians().cos(),
+            outer_cut_off: 15.0_f32.to_radians().cos(),
+            attenuation: Attenuation::default(),
+            ambient: glm::vec3(0.0, 0.0, 0.0),
+            diffuse: glm::vec3(1.0, 1.0, 1.0),
+            specular: glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// The lights of a scene, any number of each type. Only the first MAX_*_LIGHTS of each type reach the shader.
+#[derive(Clone, Default)]
+pub struct LightSet {
+    pub dir_lights: Vec<DirLight>,
+    pub point_lights: Vec<PointLight>,
+    pub spot_lights: Vec<SpotLight>,
+}
+
+impl LightSet {
+    // copies the lights into the uniform buffer, all shaders bound to it see the new lights
+    pub fn upload(&self, buffer: &UniformBuffer<LightsBlock>) {
+        let mut block = LightsBlock::default();
+
+        for (light, gpu_light) in self.dir_lights.iter().zip(block.dir_lights.iter_mut()) {
+            *gpu_light = light.std140();
+            block.nr_dir_lights += 1;
+        }
+        for (light, gpu_light) in self.point_lights.iter().zip(block.point_lights.iter_mut()) {
+            *gpu_light = light.std140();
+            block.nr_point_lights += 1;
+        }
+        for (light, gpu_light) in self.spot_lights.iter().zip(block.spot_lights.iter_mut()) {
+            *gpu_light = light.std140();
+            block.nr_spot_lights += 1;
+        }
+
+        buffer.update(&block);
+    }
+}
+
+// The lights as the shaders see them:
+//
+// layout (std140) uniform Lights
+// {
+//     int nrDirLights;
+//     int nrPointLights;
+//     int nrSpotLights;
+//     DirLight dirLights[MAX_DIR_LIGHTS];
+//     PointLight pointLights[MAX_POINT_LIGHTS];
+//     SpotLight spotLights[MAX_SPOT_LIGHTS];
+// };
+//
+// with the members of SpotLight ordered so the floats fill the gaps behind the vec3s, and the radius of the
+// attenuation placed in the padding of PointLight and SpotLight
+#[repr(C)]
+pub struct LightsBlock {
+    nr_dir_lights: i32,
+    nr_point_lights: i32,
+    nr_spot_lights: i32,
+    dir_lights: [Std140DirLight; MAX_DIR_LIGHTS],
+    point_lights: [Std140PointLight; MAX_POINT_LIGHTS],
+    spot_lights: [Std140SpotLight; MAX_SPOT_LIGHTS],
+}
+
+impl Default for LightsBlock {
+    fn default() -> LightsBlock {
+        LightsBlock {
+            nr_dir_lights: 0,
+            nr_point_lights: 0,
+            nr_spot_lights: 0,
+            dir_lights: [Std140DirLight::default(); MAX_DIR_LIGHTS],
+            point_lights: [Std140PointLight::default(); MAX_POINT_LIGHTS],
+            spot_lights: [Std140SpotLight::default(); MAX_SPOT_LIGHTS],
+        }
+    }
+}
+
+unsafe impl Std140 for LightsBlock {
+    const BLOCK_NAME: &'static str = "Lights";
+
+    fn assert_layout() {
+        let block = Self::BLOCK_NAME;
+        check_offset(block, "nrPointLights", offset_of!(LightsBlock, nr_point_lights), 4);
+        check_offset(block, "nrSpotLights", offset_of!(LightsBlock, nr_spot_lights), 8);
+        check_offset(block, "dirLights", offset_of!(LightsBlock, dir_lights), 16);
+        check_offset(block, "pointLights", offset_of!(LightsBlock, point_lights), 16 + 64 * MAX_DIR_LIGHTS);
+        check_offset(block, "spotLights", offset_of!(LightsBlock, spot_lights), 16 + 64 * MAX_DIR_LIGHTS + 80 * MAX_POINT_LIGHTS);
+
+        check_offset(block, "dirLights[0].ambient", offset_of!(Std140DirLight, ambient), 16);
+        check_offset(block, "dirLights[0].diffuse", offset_of!(Std140DirLight, diffuse), 32);
+        check_offset(block, "dirLights[0].specular", offset_of!(Std140DirLight, specular), 48);
+        check_offset(block, "dirLights[1]", mem::size_of::<Std140DirLight>(), 64);
+
+        check_offset(block, "pointLights[0].constant", offset_of!(Std140PointLight, constant), 12);
+        check_offset(block, "pointLights[0].linear", offset_of!(Std140PointLight, linear), 16);
+        check_offset(block, "pointLights[0].quadratic", offset_of!(Std140PointLight, quadratic), 20);
+        check_offset(block, "pointLights[0].radius", offset_of!(Std140PointLight, radius), 24);
+        check_offset(block, "pointLights[0].ambient", offset_of!(Std140PointLight, ambient), 32);
+        check_offset(block, "pointLights[0].diffuse", offset_of!(Std140PointLight, diffuse), 48);
+        check_offset(block, "pointLights[0].specular", offset_of!(Std140PointLight, specular), 64);
+        check_offset(block, "pointLights[1]", mem::size_of::<Std140PointLight>(), 80);
+
+        check_offset(block, "spotLights[0].cutOff", offset_of!(Std140SpotLight, cut_off), 12);
+        check_offset(block, "spotLights[0].direction", offset_of!(Std140SpotLight, direction), 16);
+        check_offset(block, "spotLights[0].outerCutOff", offset_of!(Std140SpotLight, outer_cut_off), 28);
+        check_offset(block, "spotLights[0].constant", offset_of!(Std140SpotLight, constant), 32);
+        check_offset(block, "spotLights[0].linear", offset_of!(Std140SpotLight, linear), 36);
+        check_offset(block, "spotLights[0].quadratic", offset_of!(Std140SpotLight, quadratic), 40);
+        check_offset(block, "spotLights[0].radius", offset_of!(Std140SpotLight, radius), 44);
+        check_offset(block, "spotLights[0].ambient", offset_of!(Std140SpotLight, ambient), 48);
+        check_offset(block, "spotLights[0].diffuse", offset_of!(Std140SpotLight, diffuse), 64);
+        check_offset(block, "spotLights[0].specular", offset_of!(Std140SpotLight, specular), 80);
+        check_offset(block, "spotLights[1]", mem::size_of::<Std140SpotLight>(), 96);
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Std140DirLight {
+    direction: Std140Vec3,
+    ambient: Std140Vec3,
+    diffuse: Std140Vec3,
+    specular: Std140Vec3,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Std140PointLight {
+    position: glm::Vec3,
+    constant: f32,
+    linear: f32,
+    quadratic: f32,
+    radius: f32,
+    ambient: Std140Vec3,
+    diffuse: Std140Vec3,
+    specular: Std140Vec3,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Std140SpotLight {
+    position: glm::Vec3,
+    cut_off: f32,
+    direction: glm::Vec3,
+    outer_cut_off: f32,
+    constant: f32,
+    linear: f32,
+    quadratic: f32,
+    radius: f32,
+    ambient: Std140Vec3,
+    diffuse: Std140Vec3,
+    specular: Std140Vec3,
+}
+
+impl DirLight {
+    // sets the light as a plain DirLight struct uniform of the shader, for shaders that don't use the Lights block
+    pub fn set_uniforms(&self, shader: &Shader, name: &str) {
+        shader.set_vec3v(&format!("{}.direction", name), &self.direction);
+        shader.set_vec3v(&format!("{}.ambient", name), &self.ambient);
+        shader.set_vec3v(&format!("{}.diffuse", name), &self.diffuse);
+        shader.set_vec3v(&format!("{}.specular", name), &self.specular);
+    }
+
+    fn std140(&self) -> Std140DirLight {
+        Std140DirLight {
+            direction: self.direction.into(),
+            ambient: self.ambient.into(),
+            diffuse: self.diffuse.into(),
+            specular: self.specular.into(),
+        }
+    }
+}
+
+impl PointLight {
+    // returns a white light whose attenuation reaches about the distance, see Attenuation::from_range
+    pub fn with_range(distance: f32) -> PointLight {
+        PointLight {
+            attenuation: Attenuation::from_range(distance),
+            ..PointLight::default()
+        }
+    }
+
+    // the distance beyond which the light can be ignored
+    pub fn effective_radius(&self) -> f32 {
+        self.attenuation.radius(glm::comp_max(&(self.ambient + self.diffuse)).max(glm::comp_max(&self.specular)))
+    }
+
+    fn std140(&self) -> Std140PointLight {
+        let (constant, linear, quadratic, radius) = self.attenuation.shader_values();
+        Std140PointLight {
+            position: self.position,
+            constant,
+            linear,
+            quadratic,
+            radius,
+            ambient: self.ambient.into(),
+            diffuse: self.diffuse.into(),
+            specular: self.specular.into(),
+        }
+    }
+}
+
+impl SpotLight {
+    // the distance beyond which the light can be ignored, the cone is not taken into account
+    pub fn effective_radius(&self) -> f32 {
+        self.attenuation.radius(glm::comp_max(&(self.ambient + self.diffuse)).max(glm::comp_max(&self.specular)))
+    }
+
+    fn std140(&self) -> Std140SpotLight {
+        let (constant, linear, quadratic, radius) = self.attenuation.shader_values();
+        Std140SpotLight {
+            position: self.position,
+            cut_off: self.cut_off,
+            direction: self.direction,
+            outer_cut_off: self.outer_cut_off,
+            constant,
+            linear,
+            quadratic,
+            radius,
+            ambient: self.ambient.into(),
+            diffuse: self.diffuse.into(),
+            specular: self.specular.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coefficients(attenuation: Attenuation) -> [f32; 3] {
+        match attenuation {
+            Attenuation::Polynomial { constant, linear, quadratic } => [constant, linear, quadratic],
+            Attenuation::InverseSquare { .. } => panic!("not polynomial"),
+        }
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() <= 1e-6 * expected.max(1.0), "{:?} {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn ranges_of_the_table_give_its_rows() {
+        for (range, constant, linear, quadratic) in ATTENUATION_TABLE {
+            assert_close(coefficients(Attenuation::from_range(range)), [constant, linear, quadratic]);
+        }
+    }
+
+    #[test]
+    fn ranges_between_the_rows_are_interpolated() {
+        // a quarter of the way from 32 to 50
+        assert_close(coefficients(Attenuation::from_range(36.5)), [1.0, 0.14 - 0.25 * 0.05, 0.07 - 0.25 * 0.038]);
+        // the coefficients shrink as the range grows
+        let mut previous = coefficients(Attenuation::from_range(7.0));
+        for range in (8..3250).step_by(7) {
+            let current = coefficients(Attenuation::from_range(range as f32));
+            assert!(current[1] <= previous[1] && current[2] <= previous[2], "{}", range);
+            previous = current;
+        }
+    }
+
+    #[test]
+    fn ranges_outside_of_the_table_are_clamped() {
+        assert_close(coefficients(Attenuation::from_range(1.0)), [1.0, 0.7, 1.8]);
+        assert_close(coefficients(Attenuation::from_range(0.0)), [1.0, 0.7, 1.8]);
+        assert_close(coefficients(Attenuation::from_range(10000.0)), [1.0, 0.0014, 0.000007]);
+    }
+
+    #[test]
+    fn the_light_is_too_dark_beyond_the_radius() {
+        for brightness in [0.1, 1.0, 5.0] {
+            for range in [7.0, 20.0, 100.0, 3250.0] {
+                let attenuation = Attenuation::from_range(range);
+                let radius = attenuation.radius(brightness);
+                // the radius is where the light drops to the cut-off
+                let at_radius = brightness * attenuation.factor(radius);
+                assert!((at_radius - MIN_LIGHT_CONTRIBUTION).abs() < 1e-5, "{} {} {}", brightness, range, at_radius);
+                assert!(brightness * attenuation.factor(radius * 1.01) < MIN_LIGHT_CONTRIBUTION);
+            }
+            // a light of full brightness reaches most of the range it was made for
+            for range in [7.0, 50.0, 3250.0] {
+                let radius = Attenuation::from_range(range).radius(1.0);
+                assert!(radius > 0.6 * range && radius < range, "{} {}", range, radius);
+            }
+
+            let attenuation = Attenuation::InverseSquare { radius: 4.0 };
+            assert_eq!(attenuation.radius(brightness), 4.0);
+            assert_eq!(attenuation.factor(4.0), 0.0);
+            assert!(attenuation.factor(3.9) > 0.0);
+        }
+    }
+
+    #[test]
+    fn lights_too_dark_or_without_falloff() {
+        assert_eq!(Attenuation::from_range(50.0).radius(MIN_LIGHT_CONTRIBUTION * 0.5), 0.0);
+        let constant = Attenuation::Polynomial { constant: 1.0, linear: 0.0, quadratic: 0.0 };
+        assert_eq!(constant.radius(1.0), f32::INFINITY);
+        let linear = Attenuation::Polynomial { constant: 1.0, linear: 0.5, quadratic: 0.0 };
+        assert!((linear.factor(linear.radius(1.0)) - MIN_LIGHT_CONTRIBUTION).abs() < 1e-6);
+    }
+
+    #[test]
+    fn shader_values_select_the_falloff() {
+        assert_eq!(Attenuation::from_range(50.0).shader_values(), (1.0, 0.09, 0.032, 0.0));
+        assert_eq!(Attenuation::InverseSquare { radius: 2.5 }.shader_values(), (1.0, 0.0, 0.0, 2.5));
+    }
+}
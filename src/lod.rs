@@ -0,0 +1,206 @@
+use nalgebra_glm as glm;
+use crate::bounds::BoundingSphere;
+use crate::camera::CameraController;
+
+// Level of detail selection. Every level of a mesh has an error relative to the size of the mesh (the full mesh has
+// none, see mesh_ops::simplify), so with the size of the mesh on screen the error becomes a number of pixels. The
+// coarsest level that stays below pixel_error is drawn.
+//
+// To keep a mesh from flickering between two levels at the threshold, the current level is kept until its error is
+// clearly too big, and a coarser level only takes over once its error is clearly small enough (the hysteresis). The
+// switch itself can be hidden by a dithered cross-fade: for a moment both levels are drawn, each discarding the
+// fragments the other one draws, see lodFade in the shaders.
+
+#[derive(Clone, Copy)]
+pub struct LodSettings {
+    // the error in pixels a level may have on screen
+    pub pixel_error: f32,
+    // a coarser level takes over below pixel_error * (1 - hysteresis), the current one is kept up to
+    // pixel_error * (1 + hysteresis)
+    pub hysteresis: f32,
+    // how long the cross-fade between two levels takes in seconds, None switches at once
+    pub fade_duration: Option<f32>,
+}
+
+impl Default for LodSettings {
+    fn default() -> LodSettings {
+        LodSettings {
+            pixel_error: 1.0,
+            hysteresis: 0.25,
+            fade_duration: Some(0.3),
+        }
+    }
+}
+
+// what the level of detail selection needs to know about the camera, for one frame
+pub struct LodView {
+    pub settings: LodSettings,
+    position: glm::Vec3,
+    // pixels per world unit, at a distance of one in perspective mode
+    scale: f32,
+    orthographic: bool,
+    // the time of the frame in seconds, for the cross-fade
+    time: f32,
+}
+
+impl LodView {
+    pub fn new(camera: &dyn CameraController, viewport_height: f32, time: f32, settings: LodSettings) -> LodView {
+        // the projection maps the view volume to a clip space two units high, scaling y by 1 / tan(fovy / 2) in
+        // perspective mode (where w is the distance) and by 2 / height in orthographic mode (where w is 1)
+        let projection = camera.get_projection_matrix();
+        LodView {
+            settings,
+            position: camera.get_position(),
+            scale: projection[(1, 1)] * viewport_height / 2.0,
+            orthographic: projection[(3, 3)] != 0.0,
+            time,
+        }
+    }
+
+    // the size of the sphere on screen in pixels, infinite when the camera is inside of it
+    pub fn projected_size(&self, sphere: &BoundingSphere) -> f32 {
+        let size = 2.0 * sphere.radius * self.scale;
+        if self.orthographic {
+            return size;
+        }
+        let distance = glm::distance(&self.position, &sphere.center) - sphere.radius;
+        if distance > 0.0 { size / distance } else { f32::INFINITY }
+    }
+}
+
+// the level of detail a mesh is drawn with, and the one it is fading from
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LodState {
+    pub lod: usize,
+    pub previous: usize,
+    // when the level changed
+    changed_at: f32,
+}
+
+impl LodState {
+    // picks the level for this frame, from the errors of the levels (the first one is the full mesh) and the size of
+    // the mesh on screen in pixels
+    pub fn update(&mut self, errors: &[f32], size: f32, view: &LodView) {
+        let settings = &view.settings;
+        let fits = |lod: usize, factor: f32| errors[lod] * size <= settings.pixel_error * factor;
+
+        let mut lod = self.lod.min(errors.len() - 1);
+        if !fits(lod, 1.0 + settings.hysteresis) {
+            while lod > 0 && !fits(lod, 1.0) {
+                lod -= 1;
+            }
+        } else {
+            while lod + 1 < errors.len() && fits(lod + 1, 1.0 - settings.hysteresis) {
+                lod += 1;
+            }
+        }
+
+        if lod != self.lod {
+            self.previous = self.lod;
+            self.lod = lod;
+            self.changed_at = view.time;
+        }
+    }
+
+    // how far the cross-fade from the previous level is, between 0 and 1, or None when it is over
+    pub fn fade(&self, view: &LodView) -> Option<f32> {
+        let duration = view.settings.fade_duration?;
+        let progress = (view.time - self.changed_at) / duration;
+        if self.previous != self.lod && progress < 1.0 { Some(progress.max(0.0)) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Camera, ProjectionMode};
+
+    // the full mesh, a level that reaches the pixel error at a size of 100 pixels and one at 25 pixels
+    const ERRORS: [f32; 3] = [0.0, 0.01, 0.04];
+
+    fn view(time: f32, fade_duration: Option<f32>) -> LodView {
+        let camera = Camera::default();
+        LodView::new(&camera, 600.0, time, LodSettings { fade_duration, ..LodSettings::default() })
+    }
+
+    fn at_level(lod: usize) -> LodState {
+        LodState { lod, previous: lod, changed_at: 0.0 }
+    }
+
+    #[test]
+    fn no_flicker_around_the_threshold() {
+        // the size goes back and forth around the point where level 1 reaches the pixel error, whichever level was
+        // drawn stays
+        let view = view(0.0, None);
+        for lod in [0, 1] {
+            let mut state = at_level(lod);
+            for size in [95.0, 105.0, 90.0, 110.0, 100.0, 80.0, 120.0] {
+                state.update(&ERRORS, size, &view);
+                assert_eq!(state.lod, lod, "{}", size);
+            }
+        }
+    }
+
+    #[test]
+    fn switches_past_the_hysteresis() {
+        let view = view(0.0, None);
+        // a quarter below the threshold the coarser level takes over
+        let mut state = at_level(0);
+        state.update(&ERRORS, 70.0, &view);
+        assert_eq!(state.lod, 1);
+        // and a quarter above it the finer one comes back
+        state.update(&ERRORS, 130.0, &view);
+        assert_eq!(state.lod, 0);
+        // a big change skips levels
+        state.update(&ERRORS, 10.0, &view);
+        assert_eq!((state.lod, state.previous), (2, 0));
+        state.update(&ERRORS, 1000.0, &view);
+        assert_eq!((state.lod, state.previous), (0, 2));
+
+        // a level past the ones the mesh has falls back to the coarsest
+        let mut state = at_level(5);
+        state.update(&ERRORS, 10.0, &view);
+        assert_eq!(state.lod, 2);
+    }
+
+    #[test]
+    fn fades_from_the_previous_level() {
+        let mut state = at_level(0);
+        assert_eq!(state.fade(&view(0.0, Some(0.3))), None);
+
+        state.update(&ERRORS, 10.0, &view(1.0, Some(0.3)));
+        assert_eq!(state.fade(&view(1.0, Some(0.3))), Some(0.0));
+        let halfway = state.fade(&view(1.15, Some(0.3))).unwrap();
+        assert!((halfway - 0.5).abs() < 1e-4, "{}", halfway);
+        assert_eq!(state.fade(&view(1.31, Some(0.3))), None);
+        // the level stays, the fade doesn't start again
+        state.update(&ERRORS, 10.0, &view(2.0, Some(0.3)));
+        assert_eq!(state.fade(&view(2.0, Some(0.3))), None);
+
+        // without a fade the switch happens at once
+        let mut state = at_level(0);
+        state.update(&ERRORS, 10.0, &view(1.0, None));
+        assert_eq!(state.fade(&view(1.0, None)), None);
+    }
+
+    #[test]
+    fn projected_size_in_perspective_and_orthographic_mode() {
+        let sphere = |distance: f32| BoundingSphere { center: glm::vec3(0.0, 0.0, -distance - 1.0), radius: 1.0 };
+
+        // in perspective mode the size halves when the distance to the sphere doubles, at a distance of one unit a
+        // sphere of one unit covers the viewport height divided by tan(fovy / 2)
+        let view = view(0.0, None);
+        let size = view.projected_size(&sphere(1.0));
+        assert!((size - 600.0 / f32::to_radians(22.5).tan()).abs() < 0.1, "{}", size);
+        assert!((view.projected_size(&sphere(2.0)) - size / 2.0).abs() < 0.1);
+        assert_eq!(view.projected_size(&BoundingSphere { center: glm::zero(), radius: 1.0 }), f32::INFINITY);
+
+        // in orthographic mode it only depends on the view volume height, 10 units fill 600 pixels
+        let mut camera = Camera::default();
+        camera.projection.mode = ProjectionMode::Orthographic;
+        let view = LodView::new(&camera, 600.0, 0.0, LodSettings::default());
+        for distance in [1.0, 2.0, 50.0] {
+            assert!((view.projected_size(&sphere(distance)) - 120.0).abs() < 1e-3);
+        }
+    }
+}
@@ -1,7 +1,30 @@
+#[cfg(feature = "assimp")]
+mod assimp_loader;
+mod asset_loader;
+mod bounds;
 mod camera;
+mod camera_path;
+mod cluster;
+mod gl_api;
+mod gl_debug;
+mod gl_state;
+#[cfg(feature = "gltf")]
+mod gltf_loader;
+mod light;
+mod lod;
 mod mesh;
+mod mesh_ops;
 mod model;
+mod model_cache;
+#[cfg(feature = "obj")]
+mod obj_loader;
+mod primitives;
+mod profiler;
+mod scene;
 mod shader;
+mod soft_raster;
+mod soft_tutorials;
+mod uniform_buffer;
 
 mod _1_getting_started;
 mod _2_lighting;
@@ -11,13 +34,14 @@ use _1_getting_started::*;
 use _2_lighting::*;
 use _3_model_loading::*;
 
-const MAIN_PROGRAM: &str = "3.1";
+const MAIN_PROGRAM: &str = "3.3";
 
 fn main() {
     let mut main = MAIN_PROGRAM;
     let args: Vec<String> = std::env::args().collect();
 
-    if args.len() > 1 {
+    // options (starting with "--") are read by the modules that use them
+    if args.len() > 1 && !args[1].starts_with("--") {
         main = args[1].as_str();
     }
 
@@ -74,10 +98,30 @@ fn main() {
         "2.5.3" => main_2_5_3(),
         "2.5.4" => main_2_5_4(),
         "2.6" => main_2_6(),
+        "2.7" => main_2_7(),
 
         // Chapter 3 - Model Loading
+        #[cfg(any(feature = "assimp", feature = "obj"))]
         "3.1" => main_3_1(),
+        #[cfg(any(feature = "assimp", feature = "obj"))]
+        "3.2" => main_3_2(),
+        #[cfg(not(any(feature = "assimp", feature = "obj")))]
+        "3.1" | "3.2" => {
+            eprintln!("{main} loads the backpack model, build it with the assimp or the obj feature, like: cargo run --no-default-features --features obj {main}");
+            std::process::exit(2);
+        }
+        "3.3" => main_3_3(),
 
-        _ => {}
+        // Tools
+        "convert" => model_cache::convert(&args[2..]),
+        "soft" => soft_tutorials::render(&args[2..]),
+
+        _ => {
+            eprintln!("unknown tutorial {main}");
+            eprintln!("usage: learn-opengl-rs [<tutorial like 1.3.6, {MAIN_PROGRAM} by default>] [<options>]");
+            eprintln!("       learn-opengl-rs convert <model file> [<cache file>]");
+            eprintln!("       learn-opengl-rs soft <tutorial|all> [<image file or directory>]");
+            std::process::exit(2);
+        }
     }
 }
@@ -1,10 +1,17 @@
+use std::cell::Cell;
 use std::mem;
 use gl::types::*;
 use nalgebra_glm as glm;
 use memoffset::offset_of;
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::gl_api::{self, as_bytes, Pod};
+use crate::gl_debug::{self, gl_check};
+use crate::gl_state;
+use crate::profiler;
 use crate::shader;
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct Vertex {
     // position
     pub position: glm::Vec3,
@@ -12,14 +19,23 @@ pub struct Vertex {
     pub normal: glm::Vec3,
     // texCoords
     pub tex_coords: glm::Vec2,
+    // tangent
+    pub tangent: glm::Vec3,
+    // bitangent
+    pub bitangent: glm::Vec3,
 }
 
+// 14 floats without gaps between them
+unsafe impl Pod for Vertex {}
+
 impl Default for Vertex {
     fn default() -> Self {
         Vertex {
             position: glm::zero(),
             normal: glm::zero(),
             tex_coords: glm::zero(),
+            tangent: glm::zero(),
+            bitangent: glm::zero(),
         }
     }
 }
@@ -31,31 +47,135 @@ pub struct Texture {
     pub path: String,
 }
 
-pub struct Mesh {
-    // mesh Data
+// the vertices and indices of a mesh, as the loaders produce them. It holds no GL objects, so it can be loaded and
+// processed without a GL context (e.g. on another thread), GpuMesh::upload then makes it drawable.
+#[derive(Clone, Default)]
+pub struct MeshData {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    // index into the materials of the model data
+    pub material: usize,
+    // the four joints (indices into the joints of the skin) and weights of every vertex, empty unless the mesh is
+    // skinned
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+    // simpler versions of the mesh from fine to coarse, see mesh_ops::generate_lods
+    pub lods: Vec<MeshLod>,
+}
+
+// a level of detail of a mesh, a triangle list over the same vertices
+#[derive(Clone)]
+pub struct MeshLod {
+    pub indices: Vec<u32>,
+    // how far it deviates from the full mesh, relative to the size of the mesh
+    pub error: f32,
+}
+
+impl MeshData {
+    // a mesh without material and skin
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> MeshData {
+        MeshData { vertices, indices, ..MeshData::default() }
+    }
+}
+
+// a mesh uploaded to the GPU, the vertices and indices stay with the MeshData it was uploaded from
+pub struct GpuMesh {
     pub textures: Vec<Texture>,
     pub vao: u32,
+    // the ranges of the index buffer of the levels of detail, the first one is the full mesh
+    pub lods: Vec<GpuLod>,
+
+    // bounding volumes in mesh space
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
 
     // render data
     vbo: u32,
     ebo: u32,
+    // per-instance model matrices for draw_instanced, the capacity is counted in matrices
+    instance_vbo: u32,
+    instance_capacity: Cell<usize>,
 }
 
-impl Mesh {
-    // constructor
-    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>) -> Mesh {
-        let mut mesh = Mesh { vertices, indices, textures, vao: 0, vbo: 0, ebo: 0 };
+#[derive(Clone, Copy)]
+pub struct GpuLod {
+    pub first_index: usize,
+    pub index_count: usize,
+    pub error: f32,
+}
+
+impl GpuMesh {
+    // creates the buffers of a mesh, drawn with the given textures
+    pub fn upload(data: &MeshData, textures: Vec<Texture>) -> GpuMesh {
+        let positions = data.vertices.iter().map(|vertex| &vertex.position);
+        let aabb = Aabb::from_points(positions.clone());
+        let bounding_sphere = BoundingSphere::from_points(positions);
+
+        // the levels of detail follow the full mesh in the index buffer
+        let mut lods = vec![GpuLod { first_index: 0, index_count: data.indices.len(), error: 0.0 }];
+        let mut indices = data.indices.clone();
+        for lod in &data.lods {
+            lods.push(GpuLod { first_index: indices.len(), index_count: lod.indices.len(), error: lod.error });
+            indices.extend_from_slice(&lod.indices);
+        }
+
+        let mut mesh = GpuMesh { textures, vao: 0, lods, aabb, bounding_sphere, vbo: 0, ebo: 0, instance_vbo: 0,
+            instance_capacity: Cell::new(0) };
 
         // now that we have all the required data, set the vertex buffers and its attribute pointers.
-        mesh.setup_mesh();
+        mesh.setup_mesh(&data.vertices, &indices);
 
         mesh
     }
 
+    // names the vertex array and the buffers of the mesh for debuggers, see gl_debug::label
+    pub fn label(&self, name: &str)
+    {
+        gl_debug::label(gl::VERTEX_ARRAY, self.vao, name);
+        gl_debug::label(gl::BUFFER, self.vbo, &format!("{} vertices", name));
+        gl_debug::label(gl::BUFFER, self.ebo, &format!("{} indices", name));
+    }
+
     // render the mesh
     pub fn draw(&self, shader: &shader::Shader)
+    {
+        self.draw_lod(shader, 0);
+    }
+
+    // render a level of detail of the mesh, 0 is the full mesh and levels past the last one draw the coarsest
+    pub fn draw_lod(&self, shader: &shader::Shader, lod: usize)
+    {
+        let lod = self.lods[lod.min(self.lods.len() - 1)];
+        self.bind_textures(shader);
+
+        // draw mesh, the vertex array stays bound, the next mesh binds its own
+        gl_state::with(|gl| gl.bind_vertex_array(self.vao));
+        gl_api::with(|gl| {
+            gl_check!(gl.draw_elements(gl::TRIANGLES, lod.index_count, gl::UNSIGNED_INT, lod.first_index * mem::size_of::<u32>()))
+        });
+        profiler::record_draw(lod.index_count / 3);
+    }
+
+    // render the mesh once for every model matrix. The matrices are available to the vertex shader as a mat4
+    // attribute at location 3 (which occupies the locations 3 to 6).
+    #[cfg(any(feature = "assimp", feature = "obj"))]
+    pub fn draw_instanced(&self, shader: &shader::Shader, models: &[glm::Mat4])
+    {
+        if models.is_empty() {
+            return;
+        }
+
+        self.update_instances(models);
+        self.bind_textures(shader);
+
+        // draw mesh
+        gl_state::with(|gl| gl.bind_vertex_array(self.vao));
+        gl_api::with(|gl| gl.draw_elements_instanced(gl::TRIANGLES, self.lods[0].index_count, gl::UNSIGNED_INT, 0, models.len()));
+        profiler::record_draw(self.lods[0].index_count / 3 * models.len());
+    }
+
+    // binds the textures to consecutive texture units and points the samplers to them
+    fn bind_textures(&self, shader: &shader::Shader)
     {
         // bind appropriate textures
         let mut diffuse_count = 0;
@@ -63,88 +183,117 @@ impl Mesh {
         let mut normal_count = 0;
         let mut height_count = 0;
 
-        unsafe {
-            for (i, texture) in self.textures.iter().enumerate()
-            {
-                gl::ActiveTexture(gl::TEXTURE0 + i as GLenum); // active proper texture unit before binding
-                // retrieve texture number (the N in diffuse_textureN)
-                let name = &texture.type_name;
-                let number = match name.as_str() {
-                    "texture_diffuse" => {
-                        diffuse_count += 1;
-                        diffuse_count
-                    }
-                    "texture_specular" => {
-                        specular_count += 1;
-                        specular_count
-                    }
-                    "texture_normal" => {
-                        normal_count += 1;
-                        normal_count
-                    }
-                    "texture_height" => {
-                        height_count += 1;
-                        height_count
-                    }
-                    _ => 0,
-                };
-
-                // now set the sampler to the correct texture unit
-                shader.set_int(&format!("{name}{number}"), i as GLint);
-                // and finally bind the texture
-                gl::BindTexture(gl::TEXTURE_2D, texture.id);
-            }
-
-            // draw mesh
-            gl::BindVertexArray(self.vao);
-            gl::DrawElements(gl::TRIANGLES, self.indices.len() as GLsizei, gl::UNSIGNED_INT, std::ptr::null());
-            gl::BindVertexArray(0);
+        for (i, texture) in self.textures.iter().enumerate()
+        {
+            // retrieve texture number (the N in diffuse_textureN)
+            let name = &texture.type_name;
+            let number = match name.as_str() {
+                "texture_diffuse" => {
+                    diffuse_count += 1;
+                    diffuse_count
+                }
+                "texture_specular" => {
+                    specular_count += 1;
+                    specular_count
+                }
+                "texture_normal" => {
+                    normal_count += 1;
+                    normal_count
+                }
+                "texture_height" => {
+                    height_count += 1;
+                    height_count
+                }
+                _ => 0,
+            };
 
-            // always good practice to set everything back to defaults once configured.
-            gl::ActiveTexture(gl::TEXTURE0);
+            // now set the sampler to the correct texture unit
+            shader.set_int(&format!("{name}{number}"), i as GLint);
+            // and finally bind the texture to the proper texture unit
+            gl_state::with(|gl| gl.bind_texture(i as GLuint, gl::TEXTURE_2D, texture.id));
         }
     }
 
+    // uploads the instance matrices, the buffer only grows when there are more instances than ever before. Otherwise
+    // the old storage is orphaned first, so the driver doesn't have to wait for draws still using it.
+    #[cfg(any(feature = "assimp", feature = "obj"))]
+    fn update_instances(&self, models: &[glm::Mat4])
+    {
+        let data = as_bytes(models);
+
+        gl_state::with(|gl| gl.bind_buffer(gl::ARRAY_BUFFER, self.instance_vbo));
+        gl_api::with(|gl| {
+            if models.len() > self.instance_capacity.get() {
+                gl.buffer_data(gl::ARRAY_BUFFER, data.len(), Some(data), gl::DYNAMIC_DRAW);
+                self.instance_capacity.set(models.len());
+            } else {
+                let capacity = self.instance_capacity.get() * mem::size_of::<glm::Mat4>();
+                gl.buffer_data(gl::ARRAY_BUFFER, capacity, None, gl::DYNAMIC_DRAW);
+                gl.buffer_sub_data(gl::ARRAY_BUFFER, 0, data);
+            }
+        });
+    }
+
     // initializes all the buffer objects/arrays
-    fn setup_mesh(&mut self)
+    fn setup_mesh(&mut self, vertices: &[Vertex], indices: &[u32])
     {
-        unsafe {
-            // create buffers/arrays
-            gl::GenVertexArrays(1, &mut self.vao);
-            gl::GenBuffers(1, &mut self.vbo);
-            gl::GenBuffers(1, &mut self.ebo);
+        // create buffers/arrays
+        gl_api::with(|gl| {
+            self.vao = gl.gen_vertex_array();
+            self.vbo = gl.gen_buffer();
+            self.ebo = gl.gen_buffer();
+        });
 
-            gl::BindVertexArray(self.vao);
+        gl_state::with(|gl| {
+            gl.bind_vertex_array(self.vao);
             // load data into vertex buffers
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl.bind_buffer(gl::ARRAY_BUFFER, self.vbo);
+        });
+        gl_api::with(|gl| {
             // A great thing about structs is that their memory layout is sequential for all its items.
             // The effect is that we can simply pass a pointer to the struct and it translates perfectly to a gl::m::vec3/2 array which
             // again translates to 3/2 floats which translates to a byte array.
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (self.vertices.len() * mem::size_of::<Vertex>()) as GLsizeiptr,
-                self.vertices.as_ptr() as *const GLvoid,
-                gl::STATIC_DRAW);
-
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                (self.indices.len() * mem::size_of::<u32>()) as GLsizeiptr,
-                self.indices.as_ptr() as *const GLvoid,
-                gl::STATIC_DRAW);
+            gl_check!(gl.buffer_data(gl::ARRAY_BUFFER, mem::size_of_val(vertices), Some(as_bytes(vertices)), gl::STATIC_DRAW));
+        });
+
+        gl_state::with(|gl| gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo));
+        gl_api::with(|gl| {
+            gl_check!(gl.buffer_data(gl::ELEMENT_ARRAY_BUFFER, mem::size_of_val(indices), Some(as_bytes(indices)), gl::STATIC_DRAW));
 
             // set the vertex attribute pointers
             // vertex Positions
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, offset_of!(Vertex, position) as *const GLvoid);
+            gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer(0, 3, gl::FLOAT, false, mem::size_of::<Vertex>(), offset_of!(Vertex, position));
             // vertex normals
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, offset_of!(Vertex, normal) as *const GLvoid);
+            gl.enable_vertex_attrib_array(1);
+            gl.vertex_attrib_pointer(1, 3, gl::FLOAT, false, mem::size_of::<Vertex>(), offset_of!(Vertex, normal));
             // vertex texture coords
-            gl::EnableVertexAttribArray(2);
-            gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, offset_of!(Vertex, tex_coords) as *const GLvoid);
+            gl.enable_vertex_attrib_array(2);
+            gl.vertex_attrib_pointer(2, 2, gl::FLOAT, false, mem::size_of::<Vertex>(), offset_of!(Vertex, tex_coords));
+            // vertex tangent and bitangent, after the locations 3 to 6 of the instance matrix
+            gl.enable_vertex_attrib_array(7);
+            gl.vertex_attrib_pointer(7, 3, gl::FLOAT, false, mem::size_of::<Vertex>(), offset_of!(Vertex, tangent));
+            gl.enable_vertex_attrib_array(8);
+            gl.vertex_attrib_pointer(8, 3, gl::FLOAT, false, mem::size_of::<Vertex>(), offset_of!(Vertex, bitangent));
 
-            gl::BindVertexArray(0);
-        }
+            // instance model matrices, a mat4 attribute takes 4 consecutive locations of one vec4 column each.
+            // It starts with a single identity matrix, so plain draws never read outside of the buffer.
+            self.instance_vbo = gl.gen_buffer();
+        });
+        gl_state::with(|gl| gl.bind_buffer(gl::ARRAY_BUFFER, self.instance_vbo));
+        gl_api::with(|gl| {
+            let identity: glm::Mat4 = glm::identity();
+            gl.buffer_data(gl::ARRAY_BUFFER, mem::size_of::<glm::Mat4>(), Some(as_bytes(identity.as_slice())), gl::DYNAMIC_DRAW);
+            self.instance_capacity.set(1);
+
+            for i in 0..4 {
+                gl.enable_vertex_attrib_array(3 + i);
+                gl.vertex_attrib_pointer(3 + i, 4, gl::FLOAT, false, mem::size_of::<glm::Mat4>(), i as usize * mem::size_of::<glm::Vec4>());
+                // advance once per instance instead of once per vertex
+                gl.vertex_attrib_divisor(3 + i, 1);
+            }
+        });
+
+        gl_state::with(|gl| gl.bind_vertex_array(0));
     }
 }
\ No newline at end of file
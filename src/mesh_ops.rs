@@ -0,0 +1,815 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use nalgebra_glm as glm;
+use crate::mesh::{MeshData, MeshLod, Vertex};
+
+// Mesh processing on the CPU, for what assimp's post-processing steps would otherwise do: normals, tangents, welding,
+// vertex cache optimization, simplification and levels of detail. The operations that change the number of vertices
+// take the whole MeshData, so the joints and weights of skinned meshes stay in step with the vertices.
+
+// gives every triangle its own vertices with the normal of the triangle, for flat shading. The levels of detail are
+// dropped, generate them afterwards.
+#[cfg_attr(not(feature = "gltf"), allow(dead_code))]
+pub fn flat_normals(mesh: &mut MeshData) {
+    mesh.lods.clear();
+    remap_vertices(mesh, &mesh.indices.clone());
+    mesh.indices = (0..mesh.vertices.len() as u32).collect();
+
+    for triangle in mesh.vertices.chunks_mut(3) {
+        let normal = triangle_normal(&triangle[0].position, &triangle[1].position, &triangle[2].position)
+            .unwrap_or_else(|| glm::vec3(0.0, 0.0, 1.0));
+        triangle.iter_mut().for_each(|vertex| vertex.normal = normal);
+    }
+}
+
+// sets the normal of every vertex to the average of the triangles around its position, weighted by the angle of each
+// triangle at the vertex. Vertices at the same position (split by texture seams) get the same normal, unless they
+// are in different smoothing groups (one number per vertex, like the s groups of OBJ files), which makes hard edges.
+#[cfg_attr(not(feature = "obj"), allow(dead_code))]
+pub fn smooth_normals(vertices: &mut [Vertex], indices: &[u32], smoothing_groups: Option<&[u64]>) {
+    let groups = match smoothing_groups {
+        Some(smoothing_groups) => {
+            let mut first: HashMap<([u32; 3], u64), usize> = HashMap::new();
+            vertices.iter().zip(smoothing_groups).enumerate()
+                .map(|(i, (vertex, &group))| *first.entry((vertex.position.map(f32::to_bits).into(), group)).or_insert(i))
+                .collect()
+        }
+        None => position_groups(vertices),
+    };
+    let mut normals = vec![glm::Vec3::zeros(); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let positions = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+        let normal = match triangle_normal(&positions[0], &positions[1], &positions[2]) {
+            Some(normal) => normal,
+            None => continue,
+        };
+        for corner in 0..3 {
+            let angle = corner_angle(&positions, corner);
+            normals[groups[triangle[corner] as usize]] += normal * angle;
+        }
+    }
+
+    for (vertex, &group) in vertices.iter_mut().zip(&groups) {
+        vertex.normal = normals[group].try_normalize(f32::EPSILON).unwrap_or_else(|| glm::vec3(0.0, 0.0, 1.0));
+    }
+}
+
+// calculates the tangents and bitangents from the texture coordinates: the tangents of the triangles around a vertex
+// are weighted by their angle at the vertex and made orthogonal to its normal. Where mirrored texture coordinates
+// meet, the vertex is split, so each side gets a bitangent that points the right way. Triangles without area in
+// texture space add nothing. This is not MikkTSpace (which normal maps are usually baked with), it doesn't group the
+// triangles by tangent space, so normal maps baked with it can show faint seams.
+#[cfg_attr(not(any(feature = "obj", feature = "gltf")), allow(dead_code))]
+pub fn generate_tangents(mesh: &mut MeshData) {
+    // the sum of the tangents around every vertex, for triangles with texture coordinates that are not mirrored and
+    // for those that are
+    let mut tangents = vec![[glm::Vec3::zeros(); 2]; mesh.vertices.len()];
+    let mut mirrored = vec![false; mesh.indices.len() / 3];
+
+    for (t, triangle) in mesh.indices.chunks_exact(3).enumerate() {
+        let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+        let (edge1, edge2) = (b.position - a.position, c.position - a.position);
+        let (delta1, delta2) = (b.tex_coords - a.tex_coords, c.tex_coords - a.tex_coords);
+        let determinant = delta1.x * delta2.y - delta2.x * delta1.y;
+        let tangent = match ((edge1 * delta2.y - edge2 * delta1.y) * determinant.signum()).try_normalize(f32::EPSILON) {
+            Some(tangent) if determinant.abs() > f32::EPSILON => tangent,
+            _ => continue,
+        };
+        mirrored[t] = determinant < 0.0;
+
+        let positions = [a.position, b.position, c.position];
+        for corner in 0..3 {
+            tangents[triangle[corner] as usize][mirrored[t] as usize] += tangent * corner_angle(&positions, corner);
+        }
+    }
+
+    // split the vertices that both kinds of triangles use, the mirrored ones get the copy
+    let mut copies: HashMap<u32, u32> = HashMap::new();
+    let mut sources: Vec<u32> = (0..mesh.vertices.len() as u32).collect();
+    for (t, triangle) in mesh.indices.chunks_exact_mut(3).enumerate() {
+        if !mirrored[t] {
+            continue;
+        }
+        for index in triangle {
+            if tangents[*index as usize][0] == glm::Vec3::zeros() {
+                continue;
+            }
+            *index = *copies.entry(*index).or_insert_with(|| {
+                sources.push(*index);
+                tangents.push([glm::Vec3::zeros(), tangents[*index as usize][1]]);
+                sources.len() as u32 - 1
+            });
+        }
+    }
+    if !copies.is_empty() {
+        remap_vertices(mesh, &sources);
+    }
+
+    for (vertex, [tangent, mirrored_tangent]) in mesh.vertices.iter_mut().zip(tangents) {
+        let (tangent, sign) = if tangent != glm::Vec3::zeros() { (tangent, 1.0) } else { (mirrored_tangent, -1.0) };
+        let tangent = tangent - vertex.normal * vertex.normal.dot(&tangent);
+        if let Some(tangent) = tangent.try_normalize(f32::EPSILON) {
+            vertex.tangent = tangent;
+            vertex.bitangent = vertex.normal.cross(&tangent) * sign;
+        }
+    }
+}
+
+// merges the vertices that differ by no more than the tolerance in any attribute (skinned vertices also need the
+// same joints), then removes the vertices no triangle uses and the triangles that collapsed
+pub fn weld(mesh: &mut MeshData, tolerance: f32) {
+    let skinned = !mesh.joints.is_empty();
+    let close = |a: usize, b: usize| {
+        let (u, v) = (&mesh.vertices[a], &mesh.vertices[b]);
+        glm::distance(&u.position, &v.position) <= tolerance
+            && glm::distance(&u.normal, &v.normal) <= tolerance
+            && glm::distance(&u.tex_coords, &v.tex_coords) <= tolerance
+            && glm::distance(&u.tangent, &v.tangent) <= tolerance
+            && glm::distance(&u.bitangent, &v.bitangent) <= tolerance
+            && (!skinned || (mesh.joints[a] == mesh.joints[b]
+                && mesh.weights[a].iter().zip(&mesh.weights[b]).all(|(x, y)| (x - y).abs() <= tolerance)))
+    };
+
+    // the vertices that are kept, in a grid of cells as big as the tolerance, so only the vertices in the cells
+    // around a vertex have to be compared with it
+    let cell_size = tolerance.max(1e-6);
+    let cell = |position: &glm::Vec3| [0, 1, 2].map(|i| (position[i] / cell_size).floor() as i64);
+    let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut kept: Vec<u32> = Vec::new();
+    let mut remap: Vec<u32> = Vec::with_capacity(mesh.vertices.len());
+
+    for vertex in 0..mesh.vertices.len() {
+        let [x, y, z] = cell(&mesh.vertices[vertex].position);
+        let mut found = None;
+        'search: for neighbor in (-1..=1).flat_map(|i| (-1..=1).flat_map(move |j| (-1..=1).map(move |k| [x + i, y + j, z + k]))) {
+            for &other in grid.get(&neighbor).into_iter().flatten() {
+                if close(kept[other] as usize, vertex) {
+                    found = Some(other);
+                    break 'search;
+                }
+            }
+        }
+        let index = found.unwrap_or_else(|| {
+            grid.entry([x, y, z]).or_default().push(kept.len());
+            kept.push(vertex as u32);
+            kept.len() - 1
+        });
+        remap.push(index as u32);
+    }
+
+    let remap_triangles = |indices: &[u32]| -> Vec<u32> {
+        indices.chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|i| remap[triangle[i] as usize]))
+            .filter(|[a, b, c]| a != b && b != c && c != a)
+            .flatten()
+            .collect()
+    };
+    mesh.indices = remap_triangles(&mesh.indices);
+    for lod in &mut mesh.lods {
+        lod.indices = remap_triangles(&lod.indices);
+    }
+    remap_vertices(mesh, &kept);
+    optimize_vertex_fetch(mesh);
+}
+
+// reorders the triangles so vertices are used again while they are still in the post-transform cache of the GPU,
+// with Tom Forsyth's "Linear-Speed Vertex Cache Optimisation"
+pub fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize) {
+    const CACHE_SIZE: usize = 32;
+    let triangle_count = indices.len() / 3;
+
+    // the score of a vertex is higher the more recently it was used and the fewer triangles still need it
+    let score = |cache_position: Option<usize>, remaining: usize| -> f32 {
+        if remaining == 0 {
+            return -1.0;
+        }
+        let cache_score = match cache_position {
+            // the vertices of the last triangle get a fixed score, so it doesn't matter which one comes first
+            Some(position) if position < 3 => 0.75,
+            Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+            None => 0.0,
+        };
+        cache_score + 2.0 / (remaining as f32).sqrt()
+    };
+
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for (t, triangle) in indices.chunks_exact(3).enumerate() {
+        for &index in triangle {
+            vertex_triangles[index as usize].push(t);
+        }
+    }
+    let mut remaining: Vec<usize> = vertex_triangles.iter().map(Vec::len).collect();
+    let mut vertex_scores: Vec<f32> = remaining.iter().map(|&count| score(None, count)).collect();
+    let triangle_score = |triangle: &[u32], vertex_scores: &[f32]| triangle.iter().map(|&i| vertex_scores[i as usize]).sum::<f32>();
+    let mut triangle_scores: Vec<f32> = indices.chunks_exact(3).map(|triangle| triangle_score(triangle, &vertex_scores)).collect();
+
+    let mut emitted = vec![false; triangle_count];
+    let mut order: Vec<u32> = Vec::with_capacity(indices.len());
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut best: Option<usize> = None;
+    let mut next_unemitted = 0;
+
+    for _ in 0..triangle_count {
+        // without a candidate from the cache (at the start and when an island of triangles is done), continue with
+        // the next triangle in the original order
+        let t = match best {
+            Some(t) => t,
+            None => {
+                while emitted[next_unemitted] {
+                    next_unemitted += 1;
+                }
+                next_unemitted
+            }
+        };
+        emitted[t] = true;
+        let triangle = [indices[3 * t], indices[3 * t + 1], indices[3 * t + 2]];
+        order.extend_from_slice(&triangle);
+
+        // the triangle's vertices move to the front of the cache, and the ones falling out lose their cache score
+        for &index in &triangle {
+            remaining[index as usize] -= 1;
+            vertex_triangles[index as usize].retain(|&other| other != t);
+            cache.retain(|&cached| cached != index);
+        }
+        cache.splice(0..0, triangle);
+
+        for (position, &index) in cache.iter().enumerate() {
+            let position = if position < CACHE_SIZE { Some(position) } else { None };
+            vertex_scores[index as usize] = score(position, remaining[index as usize]);
+        }
+        best = None;
+        let mut best_score = f32::MIN;
+        for &index in &cache {
+            for &other in &vertex_triangles[index as usize] {
+                triangle_scores[other] = triangle_score(&indices[3 * other..3 * other + 3], &vertex_scores);
+                if triangle_scores[other] > best_score {
+                    best_score = triangle_scores[other];
+                    best = Some(other);
+                }
+            }
+        }
+        cache.truncate(CACHE_SIZE);
+    }
+
+    indices[..order.len()].copy_from_slice(&order);
+}
+
+// reorders the vertices in the order the triangles first use them, so the vertex fetches move through memory in
+// order, and drops the vertices no triangle uses
+pub fn optimize_vertex_fetch(mesh: &mut MeshData) {
+    let mut remap = vec![u32::MAX; mesh.vertices.len()];
+    let mut order: Vec<u32> = Vec::with_capacity(mesh.vertices.len());
+    for index in &mut mesh.indices {
+        if remap[*index as usize] == u32::MAX {
+            remap[*index as usize] = order.len() as u32;
+            order.push(*index);
+        }
+        *index = remap[*index as usize];
+    }
+    // the levels of detail only use vertices of the full mesh
+    for index in mesh.lods.iter_mut().flat_map(|lod| &mut lod.indices) {
+        *index = remap[*index as usize];
+    }
+    remap_vertices(mesh, &order);
+}
+
+// the average number of vertices that miss a FIFO post-transform cache of the given size per triangle (ACMR), between
+// 0.5 for a perfect grid and 3 when no vertex is ever reused from the cache
+#[cfg(test)]
+pub fn average_cache_miss_ratio(indices: &[u32], cache_size: usize) -> f32 {
+    let mut cache: std::collections::VecDeque<u32> = std::collections::VecDeque::with_capacity(cache_size);
+    let mut misses = 0;
+    for &index in indices {
+        if !cache.contains(&index) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.pop_front();
+            }
+            cache.push_back(index);
+        }
+    }
+    misses as f32 / (indices.len() / 3).max(1) as f32
+}
+
+// reduces the triangles to about target_index_count indices by collapsing edges, cheapest first by the quadric error
+// metric of Garland and Heckbert, but never further than max_error (relative to the size of the mesh). A vertex
+// collapses into a neighbor, so the result uses the same vertices and can share their buffer. Vertices on borders
+// and texture seams stay where they are, so the outline and the texture mapping survive.
+//
+// returns the indices and the largest error it allowed, relative to the size of the mesh
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target_index_count: usize, max_error: f32) -> (Vec<u32>, f32) {
+    let mut triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+    let mut triangle_alive = vec![true; triangles.len()];
+    let mut alive_count = triangles.len();
+
+    let positions: Vec<glm::DVec3> = vertices.iter().map(|vertex| glm::convert(vertex.position)).collect();
+    let extent = Bounds::of(&positions).diagonal().max(f64::EPSILON);
+    let max_cost = (max_error as f64 * extent).powi(2);
+
+    // the triangles around every vertex
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &index in triangle {
+            vertex_triangles[index as usize].push(t);
+        }
+    }
+
+    // vertices that share their position with another one are on a seam, and vertices on an edge with only one
+    // triangle are on a border
+    let groups = position_groups(vertices);
+    let mut group_sizes = vec![0; vertices.len()];
+    groups.iter().for_each(|&group| group_sizes[group] += 1);
+    let mut edge_counts: HashMap<(usize, usize), usize> = HashMap::new();
+    for triangle in &triangles {
+        for i in 0..3 {
+            let (a, b) = (groups[triangle[i] as usize], groups[triangle[(i + 1) % 3] as usize]);
+            *edge_counts.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+    let mut locked: Vec<bool> = groups.iter().map(|&group| group_sizes[group] > 1).collect();
+    for triangle in &triangles {
+        for i in 0..3 {
+            let (a, b) = (triangle[i] as usize, triangle[(i + 1) % 3] as usize);
+            if edge_counts[&(groups[a].min(groups[b]), groups[a].max(groups[b]))] == 1 {
+                locked[a] = true;
+                locked[b] = true;
+            }
+        }
+    }
+
+    // the sum of the squared distances to the planes of the triangles around each vertex
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    for triangle in &triangles {
+        let [a, b, c] = triangle.map(|i| positions[i as usize]);
+        if let Some(normal) = (b - a).cross(&(c - a)).try_normalize(f64::EPSILON) {
+            let plane = Quadric::plane(&normal, -normal.dot(&a));
+            for &index in triangle {
+                quadrics[index as usize].add(&plane);
+            }
+        }
+    }
+
+    // candidate collapses, an outdated one has an older version of its vertex than the current
+    let mut versions = vec![0u32; vertices.len()];
+    let mut collapsed = vec![false; vertices.len()];
+    let mut candidates = BinaryHeap::new();
+    let push_candidates = |vertex: usize, candidates: &mut BinaryHeap<Collapse>, triangles: &[[u32; 3]],
+                           vertex_triangles: &[Vec<usize>], quadrics: &[Quadric], versions: &[u32]| {
+        if locked[vertex] {
+            return;
+        }
+        for &t in &vertex_triangles[vertex] {
+            for &target in &triangles[t] {
+                let target = target as usize;
+                if target != vertex {
+                    let mut quadric = quadrics[vertex].clone();
+                    quadric.add(&quadrics[target]);
+                    let cost = quadric.error(&positions[target]);
+                    candidates.push(Collapse { cost, vertex, target, version: versions[vertex] });
+                }
+            }
+        }
+    };
+    for vertex in 0..vertices.len() {
+        push_candidates(vertex, &mut candidates, &triangles, &vertex_triangles, &quadrics, &versions);
+    }
+
+    let mut error: f64 = 0.0;
+    while alive_count * 3 > target_index_count {
+        let Collapse { cost, vertex, target, version } = match candidates.pop() {
+            Some(collapse) => collapse,
+            None => break,
+        };
+        if collapsed[vertex] || collapsed[target] || version != versions[vertex] {
+            continue;
+        }
+        if cost > max_cost {
+            break;
+        }
+
+        // the neighbors both vertices have may only be the third vertices of the triangles on the edge, otherwise
+        // the collapse would fold the surface onto itself
+        let target_neighbors = neighbors(target, &triangles, &vertex_triangles);
+        let shared = neighbors(vertex, &triangles, &vertex_triangles).iter().filter(|n| target_neighbors.contains(n)).count();
+        let on_edge = vertex_triangles[vertex].iter().filter(|&&t| triangles[t].contains(&(target as u32))).count();
+        if shared != on_edge {
+            continue;
+        }
+
+        // no triangle may flip over or become a sliver
+        let flips = vertex_triangles[vertex].iter().filter(|&&t| !triangles[t].contains(&(target as u32))).any(|&t| {
+            let corners = triangles[t].map(|i| positions[i as usize]);
+            let moved = triangles[t].map(|i| if i as usize == vertex { positions[target] } else { positions[i as usize] });
+            let before = (corners[1] - corners[0]).cross(&(corners[2] - corners[0]));
+            let after = (moved[1] - moved[0]).cross(&(moved[2] - moved[0]));
+            after.dot(&before) <= 0.0 || after.norm() < 1e-12 * extent * extent
+        });
+        if flips {
+            continue;
+        }
+
+        // the triangles on the edge disappear, the others move to the target
+        for t in std::mem::take(&mut vertex_triangles[vertex]) {
+            if triangles[t].contains(&(target as u32)) {
+                triangle_alive[t] = false;
+                alive_count -= 1;
+                for &other in &triangles[t] {
+                    vertex_triangles[other as usize].retain(|&u| u != t);
+                }
+            } else {
+                triangles[t].iter_mut().filter(|i| **i as usize == vertex).for_each(|i| *i = target as u32);
+                vertex_triangles[target].push(t);
+            }
+        }
+        collapsed[vertex] = true;
+        let quadric = quadrics[vertex].clone();
+        quadrics[target].add(&quadric);
+        error = error.max(cost);
+
+        // the collapses around the target have new costs
+        let mut around = neighbors(target, &triangles, &vertex_triangles);
+        around.push(target as u32);
+        for &v in &around {
+            versions[v as usize] += 1;
+        }
+        for &v in &around {
+            push_candidates(v as usize, &mut candidates, &triangles, &vertex_triangles, &quadrics, &versions);
+        }
+    }
+
+    let indices = triangles.iter().zip(&triangle_alive).filter(|(_, &alive)| alive).flat_map(|(t, _)| *t).collect();
+    (indices, (error.sqrt() / extent) as f32)
+}
+
+// replaces the levels of detail of the mesh with up to count simplified versions, each with about half the triangles
+// of the one before. It stops early when simplify can't take away at least a quarter of the triangles without going
+// past max_error (relative to the size of the mesh), or when the mesh gets too small to bother.
+pub fn generate_lods(mesh: &mut MeshData, count: usize, max_error: f32) {
+    const MIN_INDICES: usize = 3 * 16;
+
+    mesh.lods.clear();
+    let mut previous = mesh.indices.len();
+    while mesh.lods.len() < count && previous >= 2 * MIN_INDICES {
+        // every level starts from the full mesh, so the errors don't add up
+        let target = (previous / 2 / 3) * 3;
+        let (mut indices, error) = simplify(&mesh.vertices, &mesh.indices, target, max_error);
+        if indices.len() * 4 > previous * 3 {
+            break;
+        }
+        optimize_vertex_cache(&mut indices, mesh.vertices.len());
+        previous = indices.len();
+        mesh.lods.push(MeshLod { indices, error });
+    }
+}
+
+// the vertices that share a triangle with a vertex
+fn neighbors(vertex: usize, triangles: &[[u32; 3]], vertex_triangles: &[Vec<usize>]) -> Vec<u32> {
+    let mut neighbors: Vec<u32> = vertex_triangles[vertex].iter()
+        .flat_map(|&t| triangles[t])
+        .filter(|&n| n as usize != vertex)
+        .collect();
+    neighbors.sort_unstable();
+    neighbors.dedup();
+    neighbors
+}
+
+// a symmetric 4x4 matrix that sums the squared distances to planes, stored as its upper triangle
+#[derive(Clone, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(normal: &glm::DVec3, d: f64) -> Quadric {
+        let (a, b, c) = (normal.x, normal.y, normal.z);
+        Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d])
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        self.0.iter_mut().zip(&other.0).for_each(|(x, y)| *x += y);
+    }
+
+    fn error(&self, p: &glm::DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        (q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]).max(0.0)
+    }
+}
+
+// moving a vertex onto a neighbor, ordered so the binary heap pops the cheapest first
+struct Collapse {
+    cost: f64,
+    vertex: usize,
+    target: usize,
+    version: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Bounds {
+    min: glm::DVec3,
+    max: glm::DVec3,
+}
+
+impl Bounds {
+    fn of(positions: &[glm::DVec3]) -> Bounds {
+        let mut bounds = Bounds { min: glm::DVec3::repeat(f64::MAX), max: glm::DVec3::repeat(f64::MIN) };
+        for position in positions {
+            bounds.min = glm::min2(&bounds.min, position);
+            bounds.max = glm::max2(&bounds.max, position);
+        }
+        bounds
+    }
+
+    fn diagonal(&self) -> f64 {
+        if self.min.x > self.max.x { 0.0 } else { glm::distance(&self.min, &self.max) }
+    }
+}
+
+// replaces the vertices (and joints and weights) with the ones at the given indices
+fn remap_vertices(mesh: &mut MeshData, sources: &[u32]) {
+    mesh.vertices = sources.iter().map(|&i| mesh.vertices[i as usize].clone()).collect();
+    if !mesh.joints.is_empty() {
+        mesh.joints = sources.iter().map(|&i| mesh.joints[i as usize]).collect();
+        mesh.weights = sources.iter().map(|&i| mesh.weights[i as usize]).collect();
+    }
+}
+
+// for every vertex the index of the first vertex at the same position
+fn position_groups(vertices: &[Vertex]) -> Vec<usize> {
+    let mut first: HashMap<[u32; 3], usize> = HashMap::new();
+    vertices.iter().enumerate()
+        .map(|(i, vertex)| *first.entry([0, 1, 2].map(|c| vertex.position[c].to_bits())).or_insert(i))
+        .collect()
+}
+
+fn triangle_normal(a: &glm::Vec3, b: &glm::Vec3, c: &glm::Vec3) -> Option<glm::Vec3> {
+    (b - a).cross(&(c - a)).try_normalize(f32::EPSILON)
+}
+
+// the angle of a triangle at one of its corners
+fn corner_angle(positions: &[glm::Vec3; 3], corner: usize) -> f32 {
+    let p = positions[corner];
+    let (u, v) = (positions[(corner + 1) % 3] - p, positions[(corner + 2) % 3] - p);
+    match (u.try_normalize(f32::EPSILON), v.try_normalize(f32::EPSILON)) {
+        (Some(u), Some(v)) => u.dot(&v).clamp(-1.0, 1.0).acos(),
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::Primitive;
+
+    fn vertex(position: [f32; 3], tex_coords: [f32; 2]) -> Vertex {
+        Vertex {
+            position: glm::make_vec3(&position),
+            normal: glm::vec3(0.0, 0.0, 1.0),
+            tex_coords: glm::make_vec2(&tex_coords),
+            ..Vertex::default()
+        }
+    }
+
+    fn close(a: &glm::Vec3, b: &glm::Vec3) -> bool {
+        glm::distance(a, b) < 1e-4
+    }
+
+    // the cube of the primitives, without normals and texture coordinates, so its 24 vertices only differ by face
+    fn bare_cube() -> MeshData {
+        let (vertices, indices) = Primitive::Cube.geometry();
+        let vertices = vertices.iter().map(|vertex| Vertex { position: vertex.position, ..Vertex::default() }).collect();
+        MeshData::new(vertices, indices)
+    }
+
+    // a grid of n x n quads in the xy plane, with the triangles shuffled
+    fn shuffled_grid(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = (0..=n).flat_map(|y| (0..=n).map(move |x| vertex([x as f32, y as f32, 0.0], [0.0, 0.0]))).collect();
+        let mut triangles: Vec<[u32; 3]> = Vec::new();
+        for y in 0..n {
+            for x in 0..n {
+                let corner = y * (n + 1) + x;
+                triangles.push([corner, corner + 1, corner + n + 2]);
+                triangles.push([corner, corner + n + 2, corner + n + 1]);
+            }
+        }
+        let mut seed = 12345u64;
+        for i in (1..triangles.len()).rev() {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            triangles.swap(i, (seed >> 33) as usize % (i + 1));
+        }
+        (vertices, triangles.into_iter().flatten().collect())
+    }
+
+    // the icosphere without texture coordinates, welded so it has no seams
+    fn closed_sphere() -> MeshData {
+        let (vertices, indices) = Primitive::Icosphere { subdivisions: 3 }.geometry();
+        let vertices = vertices.iter().map(|vertex| Vertex { position: vertex.position, ..Vertex::default() }).collect();
+        let mut mesh = MeshData::new(vertices, indices);
+        weld(&mut mesh, 1e-5);
+        mesh
+    }
+
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn flat_normals_face_out_of_the_cube() {
+        let mut cube = bare_cube();
+        cube.joints = vec![[1, 0, 0, 0]; 24];
+        cube.weights = vec![[1.0, 0.0, 0.0, 0.0]; 24];
+        flat_normals(&mut cube);
+
+        assert_eq!(cube.vertices.len(), 36);
+        assert_eq!(cube.joints.len(), 36);
+        assert_eq!(cube.indices, (0..36).collect::<Vec<u32>>());
+        for triangle in cube.vertices.chunks_exact(3) {
+            let center = (triangle[0].position + triangle[1].position + triangle[2].position) / 3.0;
+            // the center of a triangle is on its face, so the axis it is furthest along is the normal
+            let axis = center.iamax();
+            let mut expected = glm::Vec3::zeros();
+            expected[axis] = center[axis].signum();
+            assert!(triangle.iter().all(|vertex| close(&vertex.normal, &expected)), "{:?}", triangle[0].normal);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_are_shared_across_seams() {
+        // the faces of the cube have their own vertices, the corners get the average of the three faces
+        let mut cube = bare_cube();
+        smooth_normals(&mut cube.vertices, &cube.indices, None);
+        for vertex in &cube.vertices {
+            assert!(close(&vertex.normal, &vertex.position.normalize()), "{:?}", vertex.normal);
+        }
+
+        // with every face in a smoothing group of its own, the edges stay hard
+        let groups: Vec<u64> = (0..cube.vertices.len() as u64).map(|i| i / 4).collect();
+        smooth_normals(&mut cube.vertices, &cube.indices, Some(&groups));
+        for face in cube.vertices.chunks(4) {
+            let normal = triangle_normal(&face[0].position, &face[1].position, &face[2].position).unwrap();
+            assert!(face.iter().all(|vertex| close(&vertex.normal, &normal)), "{:?}", face[0].normal);
+        }
+
+        // a flat quad keeps the normal of its plane
+        let mut quad = vec![vertex([0.0, 0.0, 0.0], [0.0, 0.0]), vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+                            vertex([1.0, 1.0, 0.0], [1.0, 1.0]), vertex([0.0, 1.0, 0.0], [0.0, 1.0])];
+        quad.iter_mut().for_each(|vertex| vertex.normal = glm::zero());
+        smooth_normals(&mut quad, &[0, 1, 2, 0, 2, 3], None);
+        assert!(quad.iter().all(|vertex| close(&vertex.normal, &glm::vec3(0.0, 0.0, 1.0))));
+    }
+
+    #[test]
+    fn tangents_follow_the_texture_coordinates() {
+        let mut quad = MeshData::new(vec![vertex([0.0, 0.0, 0.0], [0.0, 0.0]), vertex([2.0, 0.0, 0.0], [1.0, 0.0]),
+                                          vertex([2.0, 2.0, 0.0], [1.0, 1.0]), vertex([0.0, 2.0, 0.0], [0.0, 1.0])],
+                                     vec![0, 1, 2, 0, 2, 3]);
+        generate_tangents(&mut quad);
+        assert_eq!(quad.vertices.len(), 4);
+        for vertex in &quad.vertices {
+            assert!(close(&vertex.tangent, &glm::vec3(1.0, 0.0, 0.0)), "{:?}", vertex.tangent);
+            assert!(close(&vertex.bitangent, &glm::vec3(0.0, 1.0, 0.0)), "{:?}", vertex.bitangent);
+        }
+    }
+
+    #[test]
+    fn mirrored_texture_coordinates_flip_the_bitangent_sign() {
+        // two quads side by side, the texture is mirrored at x = 0, where they share their vertices
+        let mut quads = MeshData::new(vec![
+            vertex([-1.0, 0.0, 0.0], [1.0, 0.0]), vertex([0.0, 0.0, 0.0], [0.0, 0.0]), vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([-1.0, 1.0, 0.0], [1.0, 1.0]), vertex([0.0, 1.0, 0.0], [0.0, 1.0]), vertex([1.0, 1.0, 0.0], [1.0, 1.0]),
+        ], vec![0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4]);
+        generate_tangents(&mut quads);
+
+        // the two vertices on the mirror line are split
+        assert_eq!(quads.vertices.len(), 8);
+        for triangle in quads.indices.chunks_exact(3) {
+            let corners = [0, 1, 2].map(|i| &quads.vertices[triangle[i] as usize]);
+            let left = corners.iter().map(|vertex| vertex.position.x).sum::<f32>() < 0.0;
+            for vertex in corners {
+                // u grows to the left on the left side, v grows up on both
+                let tangent = glm::vec3(if left { -1.0 } else { 1.0 }, 0.0, 0.0);
+                assert!(close(&vertex.tangent, &tangent), "{:?}", vertex.tangent);
+                assert!(close(&vertex.bitangent, &glm::vec3(0.0, 1.0, 0.0)), "{:?}", vertex.bitangent);
+                let sign = vertex.normal.cross(&vertex.tangent).dot(&vertex.bitangent);
+                assert_eq!(sign < 0.0, left);
+            }
+        }
+    }
+
+    #[test]
+    fn weld_merges_the_corners_of_a_cube() {
+        let mut cube = bare_cube();
+        weld(&mut cube, 1e-5);
+        assert_eq!(cube.vertices.len(), 8);
+        assert_eq!(cube.indices.len(), 36);
+
+        // the faces of the cube with normals stay apart
+        let (vertices, indices) = Primitive::Cube.geometry();
+        let mut cube = MeshData::new(vertices, indices);
+        weld(&mut cube, 1e-5);
+        assert_eq!(cube.vertices.len(), 24);
+    }
+
+    #[test]
+    fn weld_drops_the_triangles_that_collapse() {
+        // the second triangle is the first one moved by less than the tolerance, the third one collapses to an edge
+        let mut mesh = MeshData::new(vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]), vertex([1.0, 0.0, 0.0], [1.0, 0.0]), vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+            vertex([1e-4, 0.0, 0.0], [0.0, 0.0]), vertex([1.0, 1e-4, 0.0], [1.0, 0.0]), vertex([0.0, 1.0, 1e-4], [0.0, 1.0]),
+            vertex([0.0, 1.0 + 1e-4, 0.0], [0.0, 1.0]),
+        ], vec![0, 1, 2, 3, 4, 5, 0, 2, 6]);
+        weld(&mut mesh, 1e-3);
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn optimize_vertex_cache_reuses_more_vertices() {
+        let (vertices, mut indices) = shuffled_grid(24);
+        let original = indices.clone();
+        let before = average_cache_miss_ratio(&indices, 16);
+        optimize_vertex_cache(&mut indices, vertices.len());
+        let after = average_cache_miss_ratio(&indices, 16);
+
+        assert!(after < 0.8 && after < before / 2.0, "{before} -> {after}");
+        assert_eq!(sorted_triangles(&indices), sorted_triangles(&original));
+    }
+
+    #[test]
+    fn optimize_vertex_fetch_orders_the_vertices_by_first_use() {
+        let (vertices, indices) = shuffled_grid(4);
+        let mut grid = MeshData::new(vertices.clone(), indices.clone());
+        optimize_vertex_fetch(&mut grid);
+
+        let mut next = 0;
+        for &index in &grid.indices {
+            assert!(index <= next);
+            next = next.max(index + 1);
+        }
+        assert_eq!(next as usize, grid.vertices.len());
+        for (&old, &new) in indices.iter().zip(&grid.indices) {
+            assert_eq!(vertices[old as usize].position, grid.vertices[new as usize].position);
+        }
+    }
+
+    #[test]
+    fn simplify_stays_within_the_error() {
+        let sphere = closed_sphere();
+        for max_error in [0.01, 0.05] {
+            let (indices, error) = simplify(&sphere.vertices, &sphere.indices, sphere.indices.len() / 4, max_error);
+            assert!(indices.len() < sphere.indices.len(), "{max_error}");
+            assert!(error <= max_error, "{error} > {max_error}");
+
+            // the remaining vertices are on the sphere (radius 0.5), so the triangles may only cut a little into it
+            let diagonal = 3f32.sqrt();
+            for triangle in indices.chunks_exact(3) {
+                let center = triangle.iter().map(|&i| sphere.vertices[i as usize].position).sum::<glm::Vec3>() / 3.0;
+                assert!(0.5 - center.norm() <= 2.0 * max_error * diagonal, "{}", center.norm());
+            }
+        }
+
+        // the inside of a flat grid collapses for free, its border stays
+        let (vertices, indices) = shuffled_grid(8);
+        let (simplified, error) = simplify(&vertices, &indices, 0, 0.0);
+        assert!(simplified.len() < indices.len() / 4, "{}", simplified.len() / 3);
+        assert_eq!(error, 0.0);
+    }
+
+    #[test]
+    fn lods_halve_the_triangles() {
+        let mut sphere = closed_sphere();
+        generate_lods(&mut sphere, 3, 0.1);
+
+        assert_eq!(sphere.lods.len(), 3);
+        let mut previous = sphere.indices.len();
+        for lod in &sphere.lods {
+            assert!(lod.indices.len() * 4 <= previous * 3, "{} of {}", lod.indices.len(), previous);
+            assert!(lod.error <= 0.1);
+            assert!(lod.indices.iter().all(|&i| (i as usize) < sphere.vertices.len()));
+            previous = lod.indices.len();
+        }
+    }
+}
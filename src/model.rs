@@ -1,34 +1,267 @@
-use image::DynamicImage;
-use russimp::scene::{PostProcess, Scene};
-use crate::mesh::{Mesh, Texture, Vertex};
+use std::cell::Cell;
+use std::sync::Arc;
+use image::{DynamicImage, Rgb, RgbImage};
+#[cfg(feature = "assimp")]
+use crate::assimp_loader;
+use crate::bounds::{Aabb, BoundingSphere, Frustum};
+#[cfg(feature = "gltf")]
+use crate::gltf_loader;
+use crate::gl_api;
+use crate::gl_debug::{self, gl_check};
+use crate::gl_state;
+use crate::lod::{LodState, LodView};
+use crate::mesh::{GpuMesh, MeshData, Texture};
+use crate::model_cache;
+#[cfg(feature = "obj")]
+use crate::obj_loader;
 use nalgebra_glm as glm;
-use russimp::texture::TextureType;
 use gl::types::*;
 use crate::shader::Shader;
 
+// What a culled draw call actually did
+#[derive(Default, Clone, Debug)]
+pub struct DrawStats {
+    pub meshes_drawn: usize,
+    pub meshes_culled: usize,
+    // the level of detail each mesh was drawn with, None for the culled ones
+    pub mesh_lods: Vec<Option<usize>>,
+}
+
+impl DrawStats {
+    // adds the stats of another draw call
+    pub fn add(&mut self, other: DrawStats) {
+        self.meshes_drawn += other.meshes_drawn;
+        self.meshes_culled += other.meshes_culled;
+        self.mesh_lods.extend(other.mesh_lods);
+    }
+}
+
+// a texture of a material, the path is relative to the directory of the model. Images stored inside the model file
+// come with their encoded bytes, their path only has to be unique within the model.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TextureReference {
+    pub type_name: String,
+    pub path: String,
+    pub embedded: Option<Arc<[u8]>>,
+}
+
+// the textures of a material and its constant properties. The properties follow the PBR metallic-roughness model of
+// glTF, plus the specular color and shininess of the Phong model used by OBJ files. The shaders of the tutorials only
+// use the textures.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Material {
+    pub name: String,
+    pub textures: Vec<TextureReference>,
+    // multiplied with the base color (diffuse) texture
+    pub base_color: glm::Vec4,
+    // multiplied with the blue and green channels of the metallic-roughness texture
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: glm::Vec3,
+    pub specular: glm::Vec3,
+    pub shininess: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: String::new(),
+            textures: Vec::new(),
+            base_color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: glm::zero(),
+            specular: glm::zero(),
+            shininess: 32.0,
+        }
+    }
+}
+
+// a node of the model's node tree, the nodes are stored parents first
+#[derive(Clone, Debug)]
+pub struct ModelNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    // the transformation relative to the parent node
+    pub transform: glm::Mat4,
+    // indices into the meshes of the model data
+    pub meshes: Vec<usize>,
+    // index into the skins of the model data, for the meshes of this node
+    pub skin: Option<usize>,
+}
+
+// the joints a skinned mesh is bound to. The meshes are drawn in their bind pose, the skin is kept for animation.
+#[derive(Clone, Debug)]
+pub struct Skin {
+    pub name: String,
+    // indices into the nodes of the model data
+    pub joints: Vec<usize>,
+    // from model space to the space of each joint in the bind pose
+    pub inverse_bind_matrices: Vec<glm::Mat4>,
+}
+
+// everything a model file contains that the Model needs, without any GL objects. It comes from one of the loaders (see
+// assimp_loader, gltf_loader and obj_loader) or from a model cache (see model_cache), so it can be read without a GL context.
+#[derive(Default)]
+pub struct ModelData {
+    // the directory the texture paths are relative to
+    pub directory: String,
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<Material>,
+    pub nodes: Vec<ModelNode>,
+    pub skins: Vec<Skin>,
+}
+
+impl ModelData {
+    // reads a model file, or the model cache next to it if that is up to date. Paths ending in the cache extension
+    // are read as a model cache directly.
+    pub fn load(path: &str) -> Result<ModelData, String> {
+        if model_cache::is_cache(path) {
+            return model_cache::read(path);
+        }
+
+        let cache = model_cache::cache_path(path);
+        if model_cache::is_up_to_date(path, &cache) {
+            match model_cache::read(&cache) {
+                Ok(data) => return Ok(data),
+                // an unreadable cache (e.g. from an older version) is not fatal, the model file is still there
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        ModelData::import(path)
+    }
+
+    // reads a model file with the loaders that are enabled, glTF and OBJ files are read by their own loaders and
+    // everything else by assimp
+    pub fn import(path: &str) -> Result<ModelData, String> {
+        #[cfg(feature = "gltf")]
+        {
+            if gltf_loader::is_gltf(path) {
+                return gltf_loader::import(path);
+            }
+        }
+        #[cfg(feature = "obj")]
+        {
+            if obj_loader::is_obj(path) {
+                return obj_loader::import(path);
+            }
+        }
+
+        #[cfg(feature = "assimp")]
+        {
+            assimp_loader::import(path)
+        }
+        #[cfg(not(feature = "assimp"))]
+        {
+            Err(format!("ERROR::MODEL::NO_LOADER: {path}: enable the assimp feature to load this file"))
+        }
+    }
+
+    // returns the accumulated transformation of every node, from node space to model space
+    pub fn node_transforms(&self) -> Vec<glm::Mat4> {
+        let mut transforms: Vec<glm::Mat4> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let transform = match node.parent {
+                Some(parent) => transforms[parent] * node.transform,
+                None => node.transform,
+            };
+            transforms.push(transform);
+        }
+        transforms
+    }
+}
+
 #[derive(Default)]
 pub struct Model {
     // model data
     // stores all the textures loaded so far, optimization to make sure textures aren't loaded more than once.
     pub textures_loaded: Vec<Texture>,
-    pub meshes: Vec<Mesh>,
+    pub meshes: Vec<GpuMesh>,
+    // the accumulated node transformation of each mesh, from mesh space to model space
+    pub mesh_transforms: Vec<glm::Mat4>,
     pub directory: String,
     pub gamma_correction: bool,
+
+    // bounding volumes of all meshes in model space
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+
+    // the level of detail of each mesh, kept from frame to frame by draw_with_lod
+    lod_states: Vec<Cell<LodState>>,
 }
 
 impl Model {
     // constructor, expects a filepath to a 3D model.
+    #[cfg(any(feature = "assimp", feature = "obj"))]
     pub fn new(path: &str, gamma: bool) -> Model
+    {
+        match ModelData::load(path) {
+            Ok(data) => Model::upload(&data, gamma),
+            Err(e) => {
+                eprintln!("{}", e);
+                Model { gamma_correction: gamma, ..Model::default() }
+            }
+        }
+    }
+
+    // creates the meshes and textures of loaded model data (see ModelData::load), which needs a GL context unlike the
+    // loading itself
+    #[cfg(any(feature = "assimp", feature = "obj"))]
+    pub fn upload(data: &ModelData, gamma: bool) -> Model
+    {
+        Model::upload_with_textures(data, gamma, Vec::new())
+    }
+
+    // like upload, but the textures given are used instead of loading the ones with the same path. The asset loader
+    // uses this to start with placeholders while it decodes the images on other threads, see replace_texture.
+    pub fn upload_with_textures(data: &ModelData, gamma: bool, textures: Vec<Texture>) -> Model
     {
         let mut model = Model {
+            textures_loaded: textures,
+            directory: data.directory.clone(),
             gamma_correction: gamma,
             ..Model::default()
         };
-        model.load_model(path);
+
+        // a mesh gets uploaded once for every node that uses it
+        for (node, transform) in data.nodes.iter().zip(data.node_transforms()) {
+            for &mesh_index in &node.meshes {
+                let mesh = &data.meshes[mesh_index];
+                let textures = match data.materials.get(mesh.material) {
+                    Some(material) => material.textures.iter()
+                        .map(|texture| model.load_material_texture(texture))
+                        .collect(),
+                    None => Vec::new(),
+                };
+
+                let gpu_mesh = GpuMesh::upload(mesh, textures);
+                gpu_mesh.label(&format!("{}/{} mesh {}", data.directory, node.name, mesh_index));
+                model.meshes.push(gpu_mesh);
+                model.mesh_transforms.push(transform);
+                model.lod_states.push(Cell::new(LodState::default()));
+            }
+        }
+
+        // gather the bounding volumes of all meshes in model space, the sphere is centered in the box and encloses
+        // the spheres of all meshes
+        for (mesh, mesh_transform) in model.meshes.iter().zip(&model.mesh_transforms) {
+            model.aabb.merge(&mesh.aabb.transform(mesh_transform));
+        }
+        let center = model.aabb.center();
+        let radius = model.meshes.iter().zip(&model.mesh_transforms)
+            .map(|(mesh, mesh_transform)| {
+                let sphere = mesh.bounding_sphere.transform(mesh_transform);
+                glm::distance(&center, &sphere.center) + sphere.radius
+            })
+            .fold(0.0, f32::max);
+        model.bounding_sphere = BoundingSphere { center, radius };
+
         model
     }
 
     // draws the model, and thus all its meshes
+    #[cfg(any(feature = "assimp", feature = "obj"))]
     pub fn draw(&self, shader: &Shader)
     {
         for mesh in &self.meshes {
@@ -36,97 +269,108 @@ impl Model {
         }
     }
 
-    // loads a model with supported ASSIMP extensions from file and stores the resulting meshes in the meshes vector.
-    fn load_model(&mut self, path: &str)
+    // draws the model once for every model matrix, see GpuMesh::draw_instanced
+    #[cfg(any(feature = "assimp", feature = "obj"))]
+    pub fn draw_instanced(&self, shader: &Shader, models: &[glm::Mat4])
     {
-        // read file via ASSIMP
-        let scene = match Scene::from_file(path,
-                                           vec![
-                                               PostProcess::Triangulate,
-                                               PostProcess::GenerateSmoothNormals,
-                                               //PostProcess::FlipUVs,
-                                               PostProcess::CalculateTangentSpace],
-        ) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("ERROR::ASSIMP:: {}", e);
-                return;
-            }
-        };
+        for mesh in &self.meshes {
+            mesh.draw_instanced(shader, models);
+        }
+    }
 
-        // retrieve the directory path of the filepath
-        self.directory = path[..path.rfind('/').unwrap()].to_owned();
+    // draws only the meshes that intersect the frustum. Unlike draw, this places each mesh with its node
+    // transformation, so it sets the "model" uniform itself, using the given model matrix for the whole model.
+    #[cfg(any(feature = "assimp", feature = "obj"))]
+    pub fn draw_culled(&self, shader: &Shader, frustum: &Frustum, model: &glm::Mat4) -> DrawStats
+    {
+        self.draw_with_lod(shader, frustum, model, None)
+    }
 
-        for mesh in scene.meshes.iter() {
-            // walk through each of the mesh's vertices
-            let mut vertices: Vec<Vertex> = Vec::with_capacity(mesh.vertices.len());
+    // like draw_culled, but with a view every mesh is drawn with the level of detail that fits its size on screen
+    // (see lod.rs). During a cross-fade the mesh is drawn twice, the "lodFade" uniform tells the shader which
+    // fragments to keep.
+    pub fn draw_with_lod(&self, shader: &Shader, frustum: &Frustum, model: &glm::Mat4, view: Option<&LodView>) -> DrawStats
+    {
+        let mut stats = DrawStats::default();
 
-            for i in 0..mesh.vertices.len() {
-                let mut vertex = Vertex {
-                    // positions
-                    position: glm::vec3(mesh.vertices[i].x, mesh.vertices[i].y, mesh.vertices[i].z),
-                    // normals
-                    normal: glm::vec3(mesh.normals[i].x, mesh.normals[i].y, mesh.normals[i].z),
-                    ..Default::default()
-                };
+        // skip the meshes one by one only if the model as a whole is visible
+        if !frustum.intersects_sphere(&self.bounding_sphere.transform(model)) {
+            stats.meshes_culled = self.meshes.len();
+            stats.mesh_lods = vec![None; self.meshes.len()];
+            return stats;
+        }
+
+        for ((mesh, mesh_transform), state) in self.meshes.iter().zip(&self.mesh_transforms).zip(&self.lod_states) {
+            let transform = model * mesh_transform;
+            let sphere = mesh.bounding_sphere.transform(&transform);
+            if !frustum.intersects_sphere(&sphere) || !frustum.intersects_aabb(&mesh.aabb.transform(&transform)) {
+                stats.meshes_culled += 1;
+                stats.mesh_lods.push(None);
+                continue;
+            }
 
-                // texture coordinates
-                if let Some(texture_coord) = &mesh.texture_coords[0] {
-                    vertex.tex_coords = glm::vec2(texture_coord[i].x, texture_coord[i].y);
+            shader.set_mat4("model", &transform);
+            let view = match view {
+                Some(view) => view,
+                None => {
+                    mesh.draw(shader);
+                    stats.meshes_drawn += 1;
+                    stats.mesh_lods.push(Some(0));
+                    continue;
                 }
+            };
 
-                vertices.push(vertex);
-            }
+            let mut lod = state.get();
+            let errors: Vec<f32> = mesh.lods.iter().map(|lod| lod.error).collect();
+            lod.update(&errors, view.projected_size(&sphere), view);
+            state.set(lod);
 
-            // now wak through each of the mesh's faces (a face is a mesh its triangle) and retrieve the corresponding vertex indices.
-            let mut indices: Vec<u32> = Vec::with_capacity(mesh.faces.len() * 3);
-            for face in mesh.faces.iter() {
-                // retrieve all indices of the face and store them in the indices vector
-                indices.extend(&face.0);
+            match lod.fade(view) {
+                // the new level fades in while the previous one fades out
+                Some(fade) => {
+                    shader.set_float("lodFade", fade);
+                    mesh.draw_lod(shader, lod.lod);
+                    shader.set_float("lodFade", fade - 1.0);
+                    mesh.draw_lod(shader, lod.previous);
+                    shader.set_float("lodFade", 1.0);
+                }
+                None => mesh.draw_lod(shader, lod.lod),
             }
+            stats.meshes_drawn += 1;
+            stats.mesh_lods.push(Some(lod.lod));
+        }
 
-            // process materials
-            let material = scene.materials.get(mesh.material_index as usize).unwrap();
-
-            // we assume a convention for sampler names in the shaders. Each diffuse texture should be named
-            // as 'texture_diffuseN' where N is a sequential number ranging from 1 to MAX_SAMPLER_NUMBER.
-            // Same applies to other texture as the following list summarizes:
-            // diffuse: texture_diffuseN
-            // specular: texture_specularN
-            // normal: texture_normalN
-            let mut textures = Vec::new();
-            for (texture_type, texture) in &material.textures {
-                let type_name = match texture_type {
-                    // 1. diffuse maps
-                    TextureType::Diffuse => "texture_diffuse",
-                    // 2. specular maps
-                    TextureType::Specular => "texture_specular",
-                    // 3. normal maps
-                    TextureType::Normals => "texture_normal",
-                    // 4. height maps
-                    TextureType::Height => "texture_height",
-                    // 5. ambient maps
-                    TextureType::Ambient => "texture_ambient",
-                    // Unknown
-                    _ => "texture_unknown",
-                };
-                textures.push(self.load_material_texture(type_name, &texture[0].path));
-            }
+        stats
+    }
 
-            self.meshes.push(Mesh::new(vertices, indices, textures));
+    // makes every mesh use the texture id for the texture with the given path
+    pub fn replace_texture(&mut self, path: &str, id: GLuint)
+    {
+        let textures = self.textures_loaded.iter_mut().chain(self.meshes.iter_mut().flat_map(|mesh| &mut mesh.textures));
+        for texture in textures.filter(|texture| texture.path == path) {
+            texture.id = id;
         }
     }
 
-    fn load_material_texture(&mut self, type_name: &str, path: &str) -> Texture {
-        if let Some(texture) = self.textures_loaded.iter().find(|&x| x.path == path)
+    fn load_material_texture(&mut self, reference: &TextureReference) -> Texture {
+        if let Some(texture) = self.textures_loaded.iter().find(|&x| x.path == reference.path)
         {
             return texture.clone();
         }
 
+        let id = match &reference.embedded {
+            Some(bytes) => texture_from_memory(bytes, &reference.path, self.gamma_correction),
+            None => texture_from_file(&reference.path, &self.directory, self.gamma_correction),
+        };
+        // a texture that can't be loaded shows as magenta, the rest of the model still works
+        let id = id.unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            texture_from_image(DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([255, 0, 255]))))
+        });
         let texture = Texture {
-            id: texture_from_file(path, &self.directory, self.gamma_correction),
-            type_name: type_name.to_string(),
-            path: path.to_string(),
+            id,
+            type_name: reference.type_name.clone(),
+            path: reference.path.clone(),
         };
 
         self.textures_loaded.push(texture.clone());
@@ -134,13 +378,35 @@ impl Model {
     }
 }
 
-fn texture_from_file(path: &str, directory: &str, _gamma: bool) -> GLuint
+pub fn texture_from_file(path: &str, directory: &str, _gamma: bool) -> Result<GLuint, String>
 {
     let filename = format!("{directory}/{path}");
-    let image = image::open(filename).expect("Texture failed to load at path: {path}");
+    let image = match image::open(&filename) {
+        Ok(image) => image,
+        Err(e) => return Err(format!("ERROR::TEXTURE::FILE_NOT_SUCCESFULLY_READ: {}: {}", filename, e)),
+    };
+    let id = texture_from_image(image);
+    gl_debug::label(gl::TEXTURE, id, path);
+    Ok(id)
+}
 
-    let mut texture_id: GLuint = 0;
-    unsafe { gl::GenTextures(1, &mut texture_id) };
+// creates a texture from an encoded image (like a PNG file) in memory, the path is only used for the error message
+// and the label
+pub fn texture_from_memory(bytes: &[u8], path: &str, _gamma: bool) -> Result<GLuint, String>
+{
+    let image = match image::load_from_memory(bytes) {
+        Ok(image) => image,
+        Err(e) => return Err(format!("ERROR::TEXTURE::DATA_NOT_SUCCESFULLY_DECODED: {}: {}", path, e)),
+    };
+    let id = texture_from_image(image);
+    gl_debug::label(gl::TEXTURE, id, path);
+    Ok(id)
+}
+
+// creates a texture with mipmaps from a decoded image
+pub fn texture_from_image(image: DynamicImage) -> GLuint
+{
+    let texture_id = gl_api::with(|gl| gl.gen_texture());
 
     let format = match image {
         DynamicImage::ImageLuma8(_) => gl::RED,
@@ -151,18 +417,17 @@ fn texture_from_file(path: &str, directory: &str, _gamma: bool) -> GLuint
 
     let data = image.as_bytes();
 
-    unsafe {
-        gl::GenTextures(1, &mut texture_id);
-        gl::BindTexture(gl::TEXTURE_2D, texture_id);
-        gl::TexImage2D(gl::TEXTURE_2D, 0, format as GLint, image.width() as GLsizei, image.height() as GLsizei,
-                       0, format, gl::UNSIGNED_BYTE, data.as_ptr() as *const GLvoid);
-        gl::GenerateMipmap(gl::TEXTURE_2D);
-
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as GLint);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
-    }
+    gl_state::with(|gl| gl.bind_texture(0, gl::TEXTURE_2D, texture_id));
+    gl_api::with(|gl| {
+        gl_check!(gl.tex_image_2d(gl::TEXTURE_2D, 0, format as GLint, image.width() as GLsizei, image.height() as GLsizei,
+                                  format, gl::UNSIGNED_BYTE, Some(data)));
+        gl.generate_mipmap(gl::TEXTURE_2D);
+
+        gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
+        gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
+        gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as GLint);
+        gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+    });
 
     texture_id
 }
@@ -0,0 +1,513 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Take, Write};
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use nalgebra_glm as glm;
+use crate::gl_api::{as_bytes, as_bytes_mut};
+use crate::mesh::{MeshData, Vertex};
+use crate::mesh_ops;
+use crate::model::{Material, ModelData, ModelNode, Skin, TextureReference};
+
+// A binary cache of the ModelData of a model file, written by the convert subcommand:
+//
+//     cargo run --release convert resources/objects/backpack/backpack.obj
+//
+// writes resources/objects/backpack/backpack.obj.cache, which Model::new then reads instead of the model file as long
+// as it is newer. Loading a cache skips assimp, and the vertices and indices are read straight into the buffers that
+// get uploaded, without converting anything.
+//
+// Everything is little endian, strings and arrays start with their length as a u32:
+//
+//     magic "LOGLMODL", version u32, size of a vertex u32
+//     directory string (relative to the directory of the cache file)
+//     materials: [name string, base color 4 f32, metallic f32, roughness f32, emissive 3 f32, specular 3 f32,
+//                 shininess f32,
+//                 [texture type name string, texture path string, embedded image bytes (empty for files)]]
+//     nodes: [name string, parent u32, local transformation 16 f32 (column major), [mesh u32], skin u32]
+//     skins: [name string, [joint u32], [inverse bind matrix 16 f32]]
+//     meshes: [material u32, vertex count u32, index count u32, vertices, indices, skinned u32,
+//              (if skinned) vertex count * (joints 4 u16, weights 4 f32)]
+//
+// Missing parents and skins are stored as u32::MAX.
+//
+// The directory the texture paths are relative to is stored relative to the cache, so the cache can be read from any
+// working directory and moved together with the textures.
+//
+// The vertices and indices are stored in the memory layout of Vertex and u32 (which is little endian on every platform
+// this runs on), so the vertex size guards against stale caches after Vertex changes. Bump the version whenever the
+// rest of the format changes.
+
+pub const CACHE_EXTENSION: &str = ".cache";
+
+const MAGIC: &[u8; 8] = b"LOGLMODL";
+const VERSION: u32 = 3;
+const NONE: u32 = u32::MAX;
+
+// the cache that belongs to a model file
+pub fn cache_path(path: &str) -> String {
+    format!("{path}{CACHE_EXTENSION}")
+}
+
+pub fn is_cache(path: &str) -> bool {
+    path.ends_with(CACHE_EXTENSION)
+}
+
+// whether the cache exists and was written after the model file was last modified
+pub fn is_up_to_date(path: &str, cache: &str) -> bool {
+    let modified = |path: &str| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    match (modified(path), modified(cache)) {
+        (Some(model), Some(cache)) => cache >= model,
+        (None, Some(_)) => true,
+        _ => false,
+    }
+}
+
+pub fn write(path: &str, data: &ModelData) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("ERROR::MODEL_CACHE::WRITE_FAILED: {path}: {e}"))?;
+    let mut writer = CacheWriter { writer: BufWriter::new(file) };
+    writer.write_model(data, &relative_directory(&data.directory, path)).map_err(|e| format!("ERROR::MODEL_CACHE::WRITE_FAILED: {path}: {e}"))
+}
+
+pub fn read(path: &str) -> Result<ModelData, String> {
+    let file = File::open(path).map_err(|e| format!("ERROR::MODEL_CACHE::READ_FAILED: {path}: {e}"))?;
+    let length = file.metadata().map_err(|e| format!("ERROR::MODEL_CACHE::READ_FAILED: {path}: {e}"))?.len();
+    let mut reader = CacheReader { reader: BufReader::new(file).take(length) };
+    let mut data = reader.read_model().map_err(|e| format!("ERROR::MODEL_CACHE::READ_FAILED: {path}: {e}"))?;
+    data.directory = path_string(&normalize(&parent(path).join(&data.directory)));
+    Ok(data)
+}
+
+// the directory of a file, "" for files in the working directory
+fn parent(path: &str) -> &Path {
+    Path::new(path).parent().unwrap_or(Path::new(""))
+}
+
+// the directory as seen from the directory of the cache, e.g. "../textures" for "models/textures" and
+// "models/cache/model.cache"
+fn relative_directory(directory: &str, cache: &str) -> String {
+    let (directory, base) = (absolute(Path::new(directory)), absolute(parent(cache)));
+    let common = directory.components().zip(base.components()).take_while(|(a, b)| a == b).count();
+    let mut relative: PathBuf = base.components().skip(common).map(|_| Component::ParentDir).collect();
+    relative.extend(directory.components().skip(common));
+    path_string(&relative)
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    normalize(&std::env::current_dir().unwrap_or_default().join(path))
+}
+
+// removes "." and resolves ".." without touching the file system, symbolic links are taken as they are
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn path_string(path: &Path) -> String {
+    match path.to_string_lossy() {
+        path if path.is_empty() => ".".to_string(),
+        path => path.into_owned(),
+    }
+}
+
+// the convert subcommand, the arguments are the model file and optionally where to write the cache
+pub fn convert(args: &[String]) {
+    let (input, output) = match args {
+        [input] => (input.clone(), cache_path(input)),
+        [input, output] => (input.clone(), output.clone()),
+        _ => {
+            eprintln!("usage: convert <model file> [<cache file>]");
+            std::process::exit(2);
+        }
+    };
+
+    // always import the model file itself, an existing cache may be the one being replaced. Assimp runs without
+    // JoinIdenticalVertices and gives every triangle vertices of its own, the cache stores equal vertices only once.
+    let import = ModelData::import(&input).map(|mut data| {
+        data.meshes.iter_mut().for_each(|mesh| mesh_ops::weld(mesh, 0.0));
+        data
+    });
+    let data = match import.and_then(|data| write(&output, &data).map(|_| data)) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let vertices: usize = data.meshes.iter().map(|mesh| mesh.vertices.len()).sum();
+    let triangles: usize = data.meshes.iter().map(|mesh| mesh.indices.len() / 3).sum();
+    let size = fs::metadata(&output).map_or(0, |metadata| metadata.len());
+    println!("{input} -> {output} ({size} bytes): {} meshes, {vertices} vertices, {triangles} triangles, {} materials, {} nodes",
+             data.meshes.len(), data.materials.len(), data.nodes.len());
+}
+
+struct CacheWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CacheWriter<W> {
+    // the directory is data.directory relative to the cache file
+    fn write_model(&mut self, data: &ModelData, directory: &str) -> std::io::Result<()> {
+        self.writer.write_all(MAGIC)?;
+        self.u32(VERSION)?;
+        self.u32(mem::size_of::<Vertex>() as u32)?;
+        self.string(directory)?;
+
+        self.u32(data.materials.len() as u32)?;
+        for material in &data.materials {
+            self.string(&material.name)?;
+            self.f32s(material.base_color.as_slice())?;
+            self.f32s(&[material.metallic, material.roughness])?;
+            self.f32s(material.emissive.as_slice())?;
+            self.f32s(material.specular.as_slice())?;
+            self.f32s(&[material.shininess])?;
+            self.u32(material.textures.len() as u32)?;
+            for texture in &material.textures {
+                self.string(&texture.type_name)?;
+                self.string(&texture.path)?;
+                let embedded = texture.embedded.as_deref().unwrap_or(&[]);
+                self.u32(embedded.len() as u32)?;
+                self.writer.write_all(embedded)?;
+            }
+        }
+
+        self.u32(data.nodes.len() as u32)?;
+        for node in &data.nodes {
+            self.string(&node.name)?;
+            self.u32(node.parent.map_or(NONE, |parent| parent as u32))?;
+            self.f32s(node.transform.as_slice())?;
+            self.u32(node.meshes.len() as u32)?;
+            for &mesh in &node.meshes {
+                self.u32(mesh as u32)?;
+            }
+            self.u32(node.skin.map_or(NONE, |skin| skin as u32))?;
+        }
+
+        self.u32(data.skins.len() as u32)?;
+        for skin in &data.skins {
+            self.string(&skin.name)?;
+            self.u32(skin.joints.len() as u32)?;
+            for &joint in &skin.joints {
+                self.u32(joint as u32)?;
+            }
+            self.u32(skin.inverse_bind_matrices.len() as u32)?;
+            for matrix in &skin.inverse_bind_matrices {
+                self.f32s(matrix.as_slice())?;
+            }
+        }
+
+        self.u32(data.meshes.len() as u32)?;
+        for mesh in &data.meshes {
+            self.u32(mesh.material as u32)?;
+            self.u32(mesh.vertices.len() as u32)?;
+            self.u32(mesh.indices.len() as u32)?;
+            self.writer.write_all(as_bytes(&mesh.vertices))?;
+            self.writer.write_all(as_bytes(&mesh.indices))?;
+            let skinned = !mesh.joints.is_empty();
+            self.u32(skinned as u32)?;
+            if skinned {
+                self.writer.write_all(as_bytes(&mesh.joints))?;
+                self.writer.write_all(as_bytes(&mesh.weights))?;
+            }
+        }
+
+        self.writer.flush()
+    }
+
+    fn u32(&mut self, value: u32) -> std::io::Result<()> {
+        self.writer.write_all(&value.to_le_bytes())
+    }
+
+    fn f32s(&mut self, values: &[f32]) -> std::io::Result<()> {
+        values.iter().try_for_each(|value| self.writer.write_all(&value.to_le_bytes()))
+    }
+
+    fn string(&mut self, value: &str) -> std::io::Result<()> {
+        self.u32(value.len() as u32)?;
+        self.writer.write_all(value.as_bytes())
+    }
+}
+
+// reads at most the length of the file, which bounds the arrays it allocates, so a corrupt length can't ask for more
+// memory than the file could fill
+struct CacheReader<R: Read> {
+    reader: Take<R>,
+}
+
+impl<R: Read> CacheReader<R> {
+    fn read_model(&mut self) -> Result<ModelData, String> {
+        let mut magic = [0; 8];
+        self.reader.read_exact(&mut magic).map_err(|e| e.to_string())?;
+        if &magic != MAGIC {
+            return Err("not a model cache".to_string());
+        }
+        let version = self.u32()?;
+        let vertex_size = self.u32()?;
+        if version != VERSION || vertex_size as usize != mem::size_of::<Vertex>() {
+            return Err(format!("written by another version (version {version}, vertex size {vertex_size}), convert the model again"));
+        }
+
+        let mut data = ModelData { directory: self.string()?, ..ModelData::default() };
+
+        for _ in 0..self.u32()? {
+            let mut material = Material { name: self.string()?, ..Material::default() };
+            self.f32s(material.base_color.as_mut_slice())?;
+            material.metallic = self.f32()?;
+            material.roughness = self.f32()?;
+            self.f32s(material.emissive.as_mut_slice())?;
+            self.f32s(material.specular.as_mut_slice())?;
+            material.shininess = self.f32()?;
+            for _ in 0..self.count(1)? {
+                let type_name = self.string()?;
+                let path = self.string()?;
+                let embedded = self.bytes()?;
+                let embedded = if embedded.is_empty() { None } else { Some(embedded.into()) };
+                material.textures.push(TextureReference { type_name, path, embedded });
+            }
+            data.materials.push(material);
+        }
+
+        for _ in 0..self.u32()? {
+            let name = self.string()?;
+            let parent = match self.u32()? {
+                NONE => None,
+                parent if (parent as usize) < data.nodes.len() => Some(parent as usize),
+                parent => return Err(format!("node {name} comes before its parent {parent}")),
+            };
+            let mut transform: glm::Mat4 = glm::zero();
+            self.f32s(transform.as_mut_slice())?;
+            let meshes = (0..self.count(4)?).map(|_| self.u32().map(|mesh| mesh as usize)).collect::<Result<_, String>>()?;
+            let skin = match self.u32()? {
+                NONE => None,
+                skin => Some(skin as usize),
+            };
+            data.nodes.push(ModelNode { name, parent, transform, meshes, skin });
+        }
+
+        for _ in 0..self.u32()? {
+            let name = self.string()?;
+            let joints: Vec<usize> = (0..self.count(4)?).map(|_| self.u32().map(|joint| joint as usize)).collect::<Result<_, String>>()?;
+            let mut inverse_bind_matrices = vec![glm::Mat4::zeros(); self.count(mem::size_of::<glm::Mat4>())?];
+            for matrix in &mut inverse_bind_matrices {
+                self.f32s(matrix.as_mut_slice())?;
+            }
+            if joints.iter().any(|&joint| joint >= data.nodes.len()) {
+                return Err(format!("skin {name} has joints past the {} nodes", data.nodes.len()));
+            }
+            data.skins.push(Skin { name, joints, inverse_bind_matrices });
+        }
+
+        for _ in 0..self.u32()? {
+            let material = self.u32()? as usize;
+            let vertex_count = self.u32()? as usize;
+            let index_count = self.u32()? as usize;
+            if vertex_count * mem::size_of::<Vertex>() + index_count * mem::size_of::<u32>() > self.bytes_left() {
+                return Err(format!("mesh {} has {vertex_count} vertices and {index_count} indices, more than the rest of the file",
+                                   data.meshes.len()));
+            }
+
+            // read straight into the vectors, they already have the layout of the vertex and index buffers
+            let mut vertices = vec![Vertex::default(); vertex_count];
+            let mut indices = vec![0u32; index_count];
+            self.reader.read_exact(as_bytes_mut(&mut vertices)).map_err(|e| e.to_string())?;
+            self.reader.read_exact(as_bytes_mut(&mut indices)).map_err(|e| e.to_string())?;
+
+            let (mut joints, mut weights) = (Vec::new(), Vec::new());
+            if self.u32()? != 0 {
+                if vertex_count * (mem::size_of::<[u16; 4]>() + mem::size_of::<[f32; 4]>()) > self.bytes_left() {
+                    return Err(format!("mesh {} has more joints and weights than the rest of the file", data.meshes.len()));
+                }
+                joints = vec![[0u16; 4]; vertex_count];
+                weights = vec![[0f32; 4]; vertex_count];
+                self.reader.read_exact(as_bytes_mut(&mut joints)).map_err(|e| e.to_string())?;
+                self.reader.read_exact(as_bytes_mut(&mut weights)).map_err(|e| e.to_string())?;
+            }
+
+            if indices.iter().any(|&index| index as usize >= vertex_count) {
+                return Err(format!("mesh {} has indices past its {vertex_count} vertices", data.meshes.len()));
+            }
+            data.meshes.push(MeshData { vertices, indices, material, joints, weights, lods: Vec::new() });
+        }
+
+        if data.nodes.iter().flat_map(|node| &node.meshes).any(|&mesh| mesh >= data.meshes.len()) {
+            return Err(format!("a node uses a mesh past the {} meshes", data.meshes.len()));
+        }
+        if data.nodes.iter().filter_map(|node| node.skin).any(|skin| skin >= data.skins.len()) {
+            return Err(format!("a node uses a skin past the {} skins", data.skins.len()));
+        }
+
+        Ok(data)
+    }
+
+    fn bytes_left(&self) -> usize {
+        self.reader.limit() as usize
+    }
+
+    // reads the length of an array whose elements take at least element_size bytes each
+    fn count(&mut self, element_size: usize) -> Result<usize, String> {
+        let count = self.u32()? as usize;
+        if count * element_size > self.bytes_left() {
+            return Err(format!("an array of {count} elements is longer than the {} bytes left", self.bytes_left()));
+        }
+        Ok(count)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        self.reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn f32s(&mut self, values: &mut [f32]) -> Result<(), String> {
+        values.iter_mut().try_for_each(|value| {
+            *value = self.f32()?;
+            Ok(())
+        })
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![0; self.count(1)?];
+        self.reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        String::from_utf8(self.bytes()?).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::Primitive;
+
+    // a cube with a textured material, two nodes and a skin
+    fn model() -> ModelData {
+        let (vertices, indices) = Primitive::Cube.geometry();
+        let mut mesh = MeshData::new(vertices, indices);
+        mesh.joints = vec![[0, 1, 0, 0]; mesh.vertices.len()];
+        mesh.weights = vec![[0.25, 0.75, 0.0, 0.0]; mesh.vertices.len()];
+        ModelData {
+            directory: "resources/objects/cube".to_string(),
+            meshes: vec![mesh],
+            materials: vec![Material {
+                name: "crate".to_string(),
+                textures: vec![TextureReference { type_name: "texture_diffuse".to_string(), path: "crate.png".to_string(),
+                                                  embedded: Some(vec![1, 2, 3].into()) }],
+                ..Material::default()
+            }],
+            nodes: vec![
+                ModelNode { name: "root".to_string(), parent: None, transform: glm::identity(), meshes: vec![], skin: None },
+                ModelNode { name: "cube".to_string(), parent: Some(0), transform: glm::translation(&glm::vec3(1.0, 2.0, 3.0)),
+                            meshes: vec![0], skin: Some(0) },
+            ],
+            skins: vec![Skin { name: "skin".to_string(), joints: vec![0, 1], inverse_bind_matrices: vec![glm::identity(); 2] }],
+        }
+    }
+
+    // writes the bytes to a file of their own, reads it back and removes it
+    fn read_bytes(name: &str, bytes: &[u8]) -> Result<ModelData, String> {
+        let path = std::env::temp_dir().join(format!("learn-opengl-rs-{}-{name}{CACHE_EXTENSION}", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, bytes).unwrap();
+        let data = read(path);
+        fs::remove_file(path).unwrap();
+        data
+    }
+
+    fn written(name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("learn-opengl-rs-{}-{name}{CACHE_EXTENSION}", std::process::id()));
+        let path = path.to_str().unwrap();
+        write(path, &model()).unwrap();
+        let bytes = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn reads_what_was_written() {
+        let (expected, data) = (model(), read_bytes("round_trip", &written("round_trip")).unwrap());
+        // resolved against the cache in the temporary directory
+        assert_eq!(Path::new(&data.directory), absolute(Path::new(&expected.directory)));
+        assert_eq!(data.materials, expected.materials);
+        assert_eq!(data.nodes.len(), 2);
+        assert_eq!(data.nodes[1].parent, Some(0));
+        assert_eq!(data.nodes[1].transform, expected.nodes[1].transform);
+        assert_eq!(data.skins[0].joints, vec![0, 1]);
+
+        let (mesh, expected_mesh) = (&data.meshes[0], &expected.meshes[0]);
+        assert_eq!(mesh.indices, expected_mesh.indices);
+        assert!(mesh.vertices.iter().zip(&expected_mesh.vertices)
+            .all(|(a, b)| a.position == b.position && a.normal == b.normal && a.tex_coords == b.tex_coords));
+        assert_eq!(mesh.joints, expected_mesh.joints);
+        assert_eq!(mesh.weights, expected_mesh.weights);
+    }
+
+    #[test]
+    fn directories_are_relative_to_the_cache() {
+        assert_eq!(relative_directory("models/textures", "models/cache/model.cache"), "../textures");
+        assert_eq!(relative_directory("models", "models/model.cache"), ".");
+        assert_eq!(relative_directory("models/./backpack/", "model.cache"), "models/backpack");
+        assert_eq!(relative_directory("models/backpack/..", "other/model.cache"), "../models");
+    }
+
+    #[test]
+    fn caches_move_with_their_textures() {
+        let root = std::env::temp_dir().join(format!("learn-opengl-rs-{}-moved", std::process::id()));
+        let (from, to) = (root.join("from"), root.join("to"));
+        fs::create_dir_all(&from).unwrap();
+        fs::create_dir_all(&to).unwrap();
+
+        let data = ModelData { directory: path_string(&from.join("textures")), ..model() };
+        let cache = from.join(format!("model{CACHE_EXTENSION}"));
+        write(cache.to_str().unwrap(), &data).unwrap();
+        let moved = to.join(format!("model{CACHE_EXTENSION}"));
+        fs::rename(&cache, &moved).unwrap();
+        let read = read(moved.to_str().unwrap());
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(Path::new(&read.unwrap().directory), to.join("textures"));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let bytes = written("truncated");
+        for length in 0..bytes.len() {
+            assert!(read_bytes("truncated", &bytes[..length]).is_err(), "{} of {} bytes", length, bytes.len());
+        }
+    }
+
+    #[test]
+    fn corrupt_lengths_are_errors_before_allocating() {
+        let bytes = written("corrupt");
+        let corrupt = |offset: usize| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            read_bytes("corrupt", &bytes)
+        };
+
+        // the length of the directory, right after the magic, the version and the vertex size
+        let error = corrupt(16).err().unwrap();
+        assert!(error.contains("longer than"), "{}", error);
+
+        // the vertex and the index count of the cube: 24 and 36
+        let counts = bytes.windows(8).position(|window| window == [24, 0, 0, 0, 36, 0, 0, 0]).unwrap();
+        let error = corrupt(counts).err().unwrap();
+        assert!(error.contains("more than the rest of the file"), "{}", error);
+        let error = corrupt(counts + 4).err().unwrap();
+        assert!(error.contains("more than the rest of the file"), "{}", error);
+    }
+}
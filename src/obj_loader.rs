@@ -0,0 +1,718 @@
+use std::collections::HashMap;
+use std::fs;
+use nalgebra_glm as glm;
+use crate::mesh::{MeshData, Vertex};
+use crate::mesh_ops;
+use crate::model::{Material, ModelData, ModelNode, TextureReference};
+
+// A pure-Rust OBJ/MTL loader (enabled by the obj feature) producing the same ModelData as assimp. Every group (g or o)
+// becomes a node with a mesh for each material used in it. Polygons are triangulated, and missing normals are
+// calculated per smoothing group (s), flat where smoothing is off.
+//
+// The textures are named like assimp does for OBJ files:
+// map_Kd: texture_diffuseN
+// map_Ks: texture_specularN
+// map_Ka: texture_ambientN
+// map_Ke: texture_emissiveN
+// map_Bump, bump: texture_heightN (these usually hold normal maps, see the backpack)
+// norm: texture_normalN
+//
+// Texture options are skipped, except for the offset (-o) and scale (-s) of map_Kd, which are applied to the texture
+// coordinates of the meshes using the material, as the shaders of the tutorials don't transform them.
+
+pub fn is_obj(path: &str) -> bool {
+    path.to_lowercase().ends_with(".obj")
+}
+
+// reads an OBJ file and the MTL files it references, which are relative to the OBJ file
+pub fn import(path: &str) -> Result<ModelData, String> {
+    let directory = path.rfind('/').map_or(".", |i| &path[..i]).to_owned();
+    let obj = fs::read_to_string(path).map_err(|e| format!("ERROR::OBJ::LOAD_FAILED: {path}: {e}"))?;
+
+    let mut data = parse(&obj, |name| {
+        let mtl_path = format!("{directory}/{name}");
+        fs::read_to_string(&mtl_path).map_err(|e| format!("{mtl_path}: {e}"))
+    }).map_err(|e| format!("ERROR::OBJ::LOAD_FAILED: {path}: {e}"))?;
+
+    data.directory = directory;
+    data.nodes[0].name = path.rsplit('/').next().unwrap_or(path).to_string();
+    Ok(data)
+}
+
+// parses the text of an OBJ file, read_mtl returns the text of the MTL files it references (by the name in the OBJ
+// file). The model has a root node, the groups are its children.
+pub fn parse(obj: &str, read_mtl: impl Fn(&str) -> Result<String, String>) -> Result<ModelData, String> {
+    let mut positions: Vec<glm::Vec3> = Vec::new();
+    let mut tex_coords: Vec<glm::Vec2> = Vec::new();
+    let mut normals: Vec<glm::Vec3> = Vec::new();
+
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut builders: Vec<MeshBuilder> = Vec::new();
+    let mut builder_indices: HashMap<(String, Option<usize>), usize> = HashMap::new();
+
+    let mut group = String::from("default");
+    let mut material: Option<usize> = None;
+    let mut smoothing_group = 0;
+    let mut face_count: u64 = 0;
+
+    for (number, line) in logical_lines(obj) {
+        let error = |message: String| format!("line {number}: {message}");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let rest = line.trim_start()[keyword.len()..].trim();
+
+        match keyword {
+            "v" => positions.push(glm::make_vec3(&parse_floats::<3>(tokens, 0.0).map_err(error)?)),
+            "vt" => tex_coords.push(glm::make_vec2(&parse_floats::<2>(tokens, 0.0).map_err(error)?)),
+            "vn" => normals.push(glm::make_vec3(&parse_floats::<3>(tokens, 0.0).map_err(error)?)),
+            "g" | "o" => group = if rest.is_empty() { String::from("default") } else { rest.to_string() },
+            "s" => smoothing_group = match rest {
+                "off" | "" => 0,
+                _ => rest.parse().map_err(|_| error(format!("invalid smoothing group {rest}")))?,
+            },
+            "mtllib" => {
+                for name in rest.split_whitespace() {
+                    for mtl_material in parse_mtl(&read_mtl(name)?).map_err(|e| format!("{name}: {e}"))? {
+                        material_indices.insert(mtl_material.material.name.clone(), materials.len());
+                        materials.push(mtl_material);
+                    }
+                }
+            }
+            "usemtl" => {
+                material = Some(match material_indices.get(rest) {
+                    Some(&index) => index,
+                    // assimp uses the default material for unknown materials as well
+                    None => {
+                        eprintln!("WARNING::OBJ::UNKNOWN_MATERIAL: line {number}: {rest}");
+                        material_indices.insert(rest.to_string(), materials.len());
+                        materials.push(MtlMaterial::named(rest));
+                        materials.len() - 1
+                    }
+                });
+            }
+            "f" => {
+                let corners = tokens
+                    .map(|corner| parse_corner(corner, positions.len(), tex_coords.len(), normals.len()))
+                    .collect::<Result<Vec<_>, String>>()
+                    .map_err(error)?;
+                if corners.len() < 3 {
+                    return Err(error("a face needs at least 3 vertices".to_string()));
+                }
+
+                let builder = *builder_indices.entry((group.clone(), material)).or_insert_with(|| {
+                    builders.push(MeshBuilder::new(&group, material));
+                    builders.len() - 1
+                });
+                // vertices without normals are only shared within their smoothing group, or not at all without one
+                let sharing = if smoothing_group == 0 { u64::MAX - face_count } else { smoothing_group };
+                builders[builder].add_face(&corners, sharing, &positions, &tex_coords, &normals);
+                face_count += 1;
+            }
+            // points, lines, curves and the like don't make triangles
+            _ => {}
+        }
+    }
+
+    // faces before any usemtl get a default material
+    let default_material = materials.len();
+    if builders.iter().any(|builder| builder.material.is_none()) {
+        materials.push(MtlMaterial::named("default"));
+    }
+
+    // a root node and a node for every group, in the order they first appear
+    let mut data = ModelData::default();
+    data.nodes.push(ModelNode { name: String::new(), parent: None, transform: glm::identity(), meshes: Vec::new(), skin: None });
+    for builder in builders {
+        let material = builder.material.unwrap_or(default_material);
+        let mesh = builder.build(material, &materials[material]);
+        if mesh.indices.is_empty() {
+            continue;
+        }
+
+        let node = match data.nodes.iter().position(|node| node.parent.is_some() && node.name == builder.group) {
+            Some(node) => node,
+            None => {
+                data.nodes.push(ModelNode { name: builder.group.clone(), parent: Some(0), transform: glm::identity(),
+                    meshes: Vec::new(), skin: None });
+                data.nodes.len() - 1
+            }
+        };
+        data.nodes[node].meshes.push(data.meshes.len());
+        data.meshes.push(mesh);
+    }
+    data.materials = materials.into_iter().map(|material| material.material).collect();
+
+    Ok(data)
+}
+
+// a material with the texture coordinate offset and scale of its diffuse texture
+struct MtlMaterial {
+    material: Material,
+    offset: glm::Vec2,
+    scale: glm::Vec2,
+}
+
+impl MtlMaterial {
+    fn named(name: &str) -> MtlMaterial {
+        MtlMaterial {
+            // OBJ materials are not metallic unless they say so (Pm)
+            material: Material { name: name.to_string(), metallic: 0.0, ..Material::default() },
+            offset: glm::zero(),
+            scale: glm::vec2(1.0, 1.0),
+        }
+    }
+}
+
+// parses the text of an MTL file
+fn parse_mtl(mtl: &str) -> Result<Vec<MtlMaterial>, String> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    // the roughness is derived from the shininess unless it is given (Pr)
+    let mut roughness_given = false;
+
+    for (number, line) in logical_lines(mtl) {
+        let error = |message: String| format!("line {number}: {message}");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let rest = line.trim_start()[keyword.len()..].trim();
+
+        if keyword == "newmtl" {
+            materials.push(MtlMaterial::named(rest));
+            roughness_given = false;
+            continue;
+        }
+        let current = materials.last_mut().ok_or_else(|| error(format!("{keyword} before newmtl")))?;
+        let material = &mut current.material;
+
+        match keyword {
+            "Kd" => {
+                let color = parse_color(tokens).map_err(error)?;
+                material.base_color = glm::vec4(color.x, color.y, color.z, material.base_color.w);
+            }
+            "Ks" => material.specular = parse_color(tokens).map_err(error)?,
+            "Ke" => material.emissive = parse_color(tokens).map_err(error)?,
+            "Ns" => {
+                material.shininess = parse_floats::<1>(tokens, 0.0).map_err(error)?[0];
+                if !roughness_given {
+                    material.roughness = (2.0 / (material.shininess + 2.0)).sqrt();
+                }
+            }
+            "d" => material.base_color.w = parse_floats::<1>(tokens, 1.0).map_err(error)?[0],
+            "Tr" => material.base_color.w = 1.0 - parse_floats::<1>(tokens, 0.0).map_err(error)?[0],
+            "Pr" => {
+                material.roughness = parse_floats::<1>(tokens, 1.0).map_err(error)?[0];
+                roughness_given = true;
+            }
+            "Pm" => material.metallic = parse_floats::<1>(tokens, 0.0).map_err(error)?[0],
+            "map_Kd" | "map_Ks" | "map_Ka" | "map_Ke" | "map_Bump" | "map_bump" | "bump" | "norm" => {
+                let type_name = match keyword {
+                    "map_Kd" => "texture_diffuse",
+                    "map_Ks" => "texture_specular",
+                    "map_Ka" => "texture_ambient",
+                    "map_Ke" => "texture_emissive",
+                    "norm" => "texture_normal",
+                    _ => "texture_height",
+                };
+                let (path, offset, scale) = parse_texture(rest).map_err(error)?;
+                if keyword == "map_Kd" {
+                    current.offset = offset;
+                    current.scale = scale;
+                }
+                current.material.textures.push(TextureReference { type_name: type_name.to_string(), path, embedded: None });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+// parses the options and the file name of a texture statement, returns the file name, offset and scale
+fn parse_texture(statement: &str) -> Result<(String, glm::Vec2, glm::Vec2), String> {
+    let (mut offset, mut scale) = (glm::Vec2::zeros(), glm::vec2(1.0, 1.0));
+    let mut tokens = statement.split_whitespace().peekable();
+
+    while let Some(&option) = tokens.peek() {
+        let arguments = match option {
+            "-blendu" | "-blendv" | "-boost" | "-cc" | "-clamp" | "-texres" | "-bm" | "-imfchan" | "-type" => 1,
+            "-mm" => 2,
+            // up to three numbers, u and v are the first two
+            "-o" | "-s" | "-t" => {
+                tokens.next();
+                let mut values = Vec::new();
+                while let Some(value) = tokens.peek().and_then(|token| token.parse::<f32>().ok()) {
+                    values.push(value);
+                    tokens.next();
+                    if values.len() == 3 {
+                        break;
+                    }
+                }
+                let value = match values.as_slice() {
+                    [u] => glm::vec2(*u, *u),
+                    [u, v, ..] => glm::vec2(*u, *v),
+                    [] => return Err(format!("{option} needs a value")),
+                };
+                match option {
+                    "-o" => offset = value,
+                    "-s" => scale = value,
+                    _ => {}
+                }
+                continue;
+            }
+            _ => break,
+        };
+        tokens.next();
+        for _ in 0..arguments {
+            tokens.next().ok_or_else(|| format!("{option} needs {arguments} value(s)"))?;
+        }
+    }
+
+    let path = tokens.collect::<Vec<_>>().join(" ").replace('\\', "/");
+    if path.is_empty() {
+        return Err("a texture needs a file name".to_string());
+    }
+    Ok((path, offset, scale))
+}
+
+// the mesh of one material in one group
+struct MeshBuilder {
+    group: String,
+    material: Option<usize>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    // the position, texture coordinate and normal indices of every vertex, and with which faces it may be shared
+    vertex_indices: HashMap<(usize, Option<usize>, Option<usize>, u64), u32>,
+    // whether the normal of a vertex has to be calculated, and with which faces it may be shared
+    missing_normals: Vec<bool>,
+    smoothing_groups: Vec<u64>,
+    has_tex_coords: bool,
+}
+
+impl MeshBuilder {
+    fn new(group: &str, material: Option<usize>) -> MeshBuilder {
+        MeshBuilder {
+            group: group.to_string(),
+            material,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            vertex_indices: HashMap::new(),
+            missing_normals: Vec::new(),
+            smoothing_groups: Vec::new(),
+            has_tex_coords: false,
+        }
+    }
+
+    fn add_face(&mut self, corners: &[Corner], sharing: u64, positions: &[glm::Vec3], tex_coords: &[glm::Vec2],
+                normals: &[glm::Vec3]) {
+        let indices: Vec<u32> = corners.iter().map(|&(position, tex_coord, normal)| {
+            let sharing = if normal.is_some() { 0 } else { sharing };
+            let key = (position, tex_coord, normal, sharing);
+            *self.vertex_indices.entry(key).or_insert_with(|| {
+                self.vertices.push(Vertex {
+                    position: positions[position],
+                    normal: normal.map_or(glm::Vec3::zeros(), |normal| normals[normal]),
+                    tex_coords: tex_coord.map_or(glm::Vec2::zeros(), |tex_coord| tex_coords[tex_coord]),
+                    ..Vertex::default()
+                });
+                self.missing_normals.push(normal.is_none());
+                self.smoothing_groups.push(sharing);
+                self.has_tex_coords |= tex_coord.is_some();
+                self.vertices.len() as u32 - 1
+            })
+        }).collect();
+
+        let polygon: Vec<glm::Vec3> = corners.iter().map(|&(position, _, _)| positions[position]).collect();
+        for [a, b, c] in triangulate(&polygon) {
+            self.indices.extend_from_slice(&[indices[a], indices[b], indices[c]]);
+        }
+    }
+
+    fn build(&self, material_index: usize, material: &MtlMaterial) -> MeshData {
+        let mut vertices = self.vertices.clone();
+
+        // the normals of the triangles around each vertex that has none, within its smoothing group
+        if self.missing_normals.contains(&true) {
+            let mut smoothed = vertices.clone();
+            mesh_ops::smooth_normals(&mut smoothed, &self.indices, Some(&self.smoothing_groups));
+            for ((vertex, smoothed), &missing) in vertices.iter_mut().zip(smoothed).zip(&self.missing_normals) {
+                if missing {
+                    vertex.normal = smoothed.normal;
+                }
+            }
+        }
+
+        let mut mesh = MeshData { vertices, indices: self.indices.clone(), material: material_index, ..MeshData::default() };
+        if self.has_tex_coords {
+            for vertex in &mut mesh.vertices {
+                vertex.tex_coords = vertex.tex_coords.component_mul(&material.scale) + material.offset;
+            }
+            mesh_ops::generate_tangents(&mut mesh);
+        }
+        mesh
+    }
+}
+
+// the position, texture coordinate and normal index of a face corner
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// parses v, v/vt, v//vn or v/vt/vn, indices start at 1 and negative indices count back from the last element
+fn parse_corner(corner: &str, positions: usize, tex_coords: usize, normals: usize) -> Result<Corner, String> {
+    let index = |value: Option<&str>, count: usize, name: &str| -> Result<Option<usize>, String> {
+        let value = match value {
+            Some(value) if !value.is_empty() => value,
+            _ => return Ok(None),
+        };
+        let index: i64 = value.parse().map_err(|_| format!("invalid {name} index {value}"))?;
+        let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(format!("{name} index {index} is out of range (there are {count})"));
+        }
+        Ok(Some(resolved as usize))
+    };
+
+    let mut parts = corner.split('/');
+    let position = index(parts.next(), positions, "vertex")?.ok_or_else(|| format!("invalid face vertex {corner}"))?;
+    let tex_coord = index(parts.next(), tex_coords, "texture coordinate")?;
+    let normal = index(parts.next(), normals, "normal")?;
+    Ok((position, tex_coord, normal))
+}
+
+// splits a polygon into triangles by cutting off ears, which also works for concave polygons. The polygon is
+// projected onto the plane it (mostly) lies in, and falls back to a fan if it is too twisted to find an ear.
+fn triangulate(polygon: &[glm::Vec3]) -> Vec<[usize; 3]> {
+    let count = polygon.len();
+    if count == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // Newell's method gives the normal of a polygon, which doesn't have to be planar
+    let mut normal = glm::Vec3::zeros();
+    for i in 0..count {
+        let (a, b) = (polygon[i], polygon[(i + 1) % count]);
+        normal += glm::vec3((a.y - b.y) * (a.z + b.z), (a.z - b.z) * (a.x + b.x), (a.x - b.x) * (a.y + b.y));
+    }
+    let axis_u = if normal.x.abs() > 0.9 * normal.norm() { glm::vec3(0.0, 1.0, 0.0) } else { glm::vec3(1.0, 0.0, 0.0) };
+    let axis_u = axis_u.cross(&normal).normalize();
+    let axis_v = normal.cross(&axis_u).normalize();
+    let points: Vec<glm::Vec2> = polygon.iter().map(|p| glm::vec2(p.dot(&axis_u), p.dot(&axis_v))).collect();
+
+    let cross = |a: usize, b: usize, c: usize| {
+        let (ab, ac) = (points[b] - points[a], points[c] - points[a]);
+        ab.x * ac.y - ab.y * ac.x
+    };
+    let contains = |a: usize, b: usize, c: usize, p: usize| {
+        cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+    };
+
+    let mut remaining: Vec<usize> = (0..count).collect();
+    let mut triangles = Vec::with_capacity(count - 2);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+            // a convex corner without any other corner inside its triangle
+            cross(a, b, c) > 0.0 && remaining.iter().all(|&p| p == a || p == b || p == c || !contains(a, b, c, p))
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]]);
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
+// the lines of an OBJ or MTL file with their numbers, without comments and with continued lines (ending in a
+// backslash) joined. A comment starts with a # at the start of a token, file names may contain a # elsewhere.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (number, line) in text.lines().enumerate() {
+        let comment = line.char_indices()
+            .find(|&(i, c)| c == '#' && line[..i].chars().next_back().is_none_or(char::is_whitespace));
+        let line = comment.map_or(line, |(i, _)| &line[..i]);
+        let (line, continued) = match line.trim_end().strip_suffix('\\') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let (start, mut joined) = current.take().unwrap_or((number + 1, String::new()));
+        joined.push(' ');
+        joined.push_str(line);
+        if continued {
+            current = Some((start, joined));
+        } else {
+            lines.push((start, joined));
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+// parses up to N numbers, the missing ones get the default value
+fn parse_floats<'a, const N: usize>(tokens: impl Iterator<Item = &'a str>, default: f32) -> Result<[f32; N], String> {
+    let mut values = [default; N];
+    for (value, token) in values.iter_mut().zip(tokens) {
+        *value = token.parse().map_err(|_| format!("invalid number {token}"))?;
+    }
+    Ok(values)
+}
+
+// parses r g b, or a single value for gray
+fn parse_color<'a>(tokens: impl Iterator<Item = &'a str>) -> Result<glm::Vec3, String> {
+    let tokens: Vec<&str> = tokens.collect();
+    let [r, g, b] = parse_floats::<3>(tokens.iter().copied(), f32::NAN)?;
+    Ok(if g.is_nan() { glm::vec3(r, r, r) } else { glm::vec3(r, g, b) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_obj(obj: &str) -> Result<ModelData, String> {
+        parse(obj, |name| Err(format!("no MTL file {name}")))
+    }
+
+    fn parse_with_mtl(obj: &str, mtl_name: &str, mtl: &str) -> ModelData {
+        parse(obj, |name| if name == mtl_name { Ok(mtl.to_string()) } else { Err(format!("no MTL file {name}")) }).unwrap()
+    }
+
+    // the positions of the triangles of a mesh
+    fn triangles(mesh: &MeshData) -> Vec<[glm::Vec3; 3]> {
+        mesh.indices.chunks(3).map(|triangle| [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position)).collect()
+    }
+
+    // twice the area of a triangle in the xy plane, positive if it is counter-clockwise
+    fn area_xy(triangle: &[glm::Vec3; 3]) -> f32 {
+        (triangle[1] - triangle[0]).cross(&(triangle[2] - triangle[0])).z
+    }
+
+    #[test]
+    fn negative_and_relative_indices() {
+        let data = parse_obj("
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            f -3 -2 -1
+            v 1 1 0
+            f 2 4 -2
+        ").unwrap();
+        let triangles = triangles(&data.meshes[0]);
+        assert_eq!(triangles, vec![
+            [glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)],
+            [glm::vec3(1.0, 0.0, 0.0), glm::vec3(1.0, 1.0, 0.0), glm::vec3(0.0, 1.0, 0.0)],
+        ]);
+
+        for face in ["f 1 2 4", "f 0 1 2", "f -4 -2 -1", "f 1 2 x", "f 1 2"] {
+            let error = parse_obj(&format!("v 0 0 0\nv 1 0 0\nv 0 1 0\n{face}")).err().unwrap();
+            assert!(error.starts_with("line 4: "), "{face}: {error}");
+        }
+    }
+
+    #[test]
+    fn corners_with_normals_or_texture_coordinates() {
+        let data = parse_obj("
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 0 1
+            vn 0 0 1
+            vn 0 1 0
+            g normals
+            f 1//1 2//1 3//2
+            g tex_coords
+            f 1/1 2/2 3/3
+            g both
+            f 1/3/2 2/2/2 3/1/2
+        ").unwrap();
+        let [normals, tex_coords, both] = [0, 1, 2].map(|i| &data.meshes[i].vertices);
+
+        // the normals are taken as they are, there are no texture coordinates
+        assert_eq!(normals.iter().map(|vertex| vertex.normal).collect::<Vec<_>>(),
+                   vec![glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, 1.0, 0.0)]);
+        assert!(normals.iter().all(|vertex| vertex.tex_coords == glm::Vec2::zeros()));
+
+        // the normals are calculated, and the tangents from the texture coordinates
+        assert_eq!(tex_coords.iter().map(|vertex| vertex.tex_coords).collect::<Vec<_>>(),
+                   vec![glm::vec2(0.0, 0.0), glm::vec2(1.0, 0.0), glm::vec2(0.0, 1.0)]);
+        assert!(tex_coords.iter().all(|vertex| vertex.normal == glm::vec3(0.0, 0.0, 1.0)));
+        assert!(tex_coords.iter().all(|vertex| (vertex.tangent - glm::vec3(1.0, 0.0, 0.0)).norm() < 1e-5));
+
+        assert_eq!(both[0].tex_coords, glm::vec2(0.0, 1.0));
+        assert!(both.iter().all(|vertex| vertex.normal == glm::vec3(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn polygons_are_triangulated() {
+        let data = parse_obj("
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            g quad
+            f 1 2 3 4
+            # an L shape that starts at the inner corner, where a fan would make triangles that face backwards
+            v 2 0 0
+            v 2 1 0
+            v 1 2 0
+            v 0 2 0
+            g concave
+            f 6 3 7 8 1 5
+        ").unwrap();
+
+        let quad = triangles(&data.meshes[0]);
+        assert_eq!(quad.len(), 2);
+        assert_eq!(quad.iter().map(area_xy).sum::<f32>(), 2.0);
+
+        let concave = triangles(&data.meshes[1]);
+        assert_eq!(concave.len(), 4);
+        assert!(concave.iter().all(|triangle| area_xy(triangle) > 0.0), "{:?}", concave);
+        assert!((concave.iter().map(area_xy).sum::<f32>() - 6.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn groups_and_materials_make_meshes() {
+        let mtl = "
+            newmtl red
+            Kd 1 0 0
+            newmtl blue
+            Kd 0 0 1
+            Ns 100
+        ";
+        let data = parse_with_mtl("
+            mtllib colors.mtl
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            f 1 2 3
+            o first
+            usemtl red
+            f 1 2 3
+            usemtl blue
+            f 1 2 3
+            f 1 2 3
+            g second
+            f 1 2 3
+            o first
+            usemtl red
+            f 1 2 3
+        ", "colors.mtl", mtl);
+
+        let material_names: Vec<&str> = data.materials.iter().map(|material| material.name.as_str()).collect();
+        assert_eq!(material_names, vec!["red", "blue", "default"]);
+        assert_eq!(data.materials[0].base_color, glm::vec4(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(data.materials[1].shininess, 100.0);
+
+        // the root node and a node per group, each with a mesh per material
+        let nodes: Vec<(&str, Option<usize>, Vec<usize>)> = data.nodes.iter()
+            .map(|node| (node.name.as_str(), node.parent, node.meshes.iter().map(|&mesh| data.meshes[mesh].material).collect()))
+            .collect();
+        assert_eq!(nodes, vec![("", None, vec![]), ("default", Some(0), vec![2]), ("first", Some(0), vec![0, 1]),
+                               ("second", Some(0), vec![1])]);
+        let triangle_counts: Vec<usize> = data.meshes.iter().map(|mesh| mesh.indices.len() / 3).collect();
+        assert_eq!(triangle_counts, vec![1, 2, 2, 1]);
+    }
+
+    #[test]
+    fn smoothing_groups() {
+        // two triangles folded along the y axis
+        let roof = "
+            v 0 0 0
+            v 0 1 0
+            v 1 0 1
+            v -1 0 1
+            f 1 3 2
+            f 1 2 4
+        ";
+        let smooth = parse_obj(&format!("s 1\n{roof}")).unwrap();
+        let flat = parse_obj(&format!("s off\n{roof}")).unwrap();
+
+        // smoothed, the vertices along the fold are shared and their normal is between the faces
+        let mesh = &smooth.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        let fold: Vec<&Vertex> = mesh.vertices.iter().filter(|vertex| vertex.position.x == 0.0).collect();
+        assert_eq!(fold.len(), 2);
+        assert!(fold.iter().all(|vertex| (vertex.normal - glm::vec3(0.0, 0.0, 1.0)).norm() < 1e-5));
+
+        // flat, every face has its own vertices with the face normal
+        let mesh = &flat.meshes[0];
+        assert_eq!(mesh.vertices.len(), 6);
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            let face_normal = (b.position - a.position).cross(&(c.position - a.position)).normalize();
+            assert!([a, b, c].iter().all(|vertex| (vertex.normal - face_normal).norm() < 1e-5));
+        }
+
+        // the same without a smoothing group
+        assert_eq!(parse_obj(roof).unwrap().meshes[0].vertices.len(), 6);
+        assert!(parse_obj(&format!("s soft\n{roof}")).is_err());
+    }
+
+    #[test]
+    fn texture_options() {
+        let mtl = r"
+            newmtl textured
+            map_Kd -blendu off -o 0.5 0.25 -s 2 2 1 -mm 0 1 textures\wood floor.png
+            map_Ks -clamp on spec#1.png # the specular map
+            map_Bump -bm 0.5 normal.png
+            norm -o 1 normal.png
+        ";
+        let data = parse_with_mtl("
+            mtllib my#materials.mtl # a file name with a #
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 0 1
+            usemtl textured
+            f 1/1 2/2 3/3
+        ", "my#materials.mtl", mtl);
+
+        let textures: Vec<(&str, &str)> = data.materials[0].textures.iter()
+            .map(|texture| (texture.type_name.as_str(), texture.path.as_str()))
+            .collect();
+        assert_eq!(textures, vec![("texture_diffuse", "textures/wood floor.png"), ("texture_specular", "spec#1.png"),
+                                  ("texture_height", "normal.png"), ("texture_normal", "normal.png")]);
+
+        // the offset and scale of the diffuse texture move the texture coordinates
+        let tex_coords: Vec<glm::Vec2> = data.meshes[0].vertices.iter().map(|vertex| vertex.tex_coords).collect();
+        assert_eq!(tex_coords, vec![glm::vec2(0.5, 0.25), glm::vec2(2.5, 0.25), glm::vec2(0.5, 2.25)]);
+
+        assert_eq!(parse_texture("-s 3 tile.png").unwrap(), ("tile.png".to_string(), glm::vec2(0.0, 0.0), glm::vec2(3.0, 3.0)));
+        assert!(parse_texture("-o").is_err());
+        assert!(parse_texture("-clamp").is_err());
+        assert!(parse_texture("-clamp on").is_err());
+    }
+
+    #[test]
+    fn continued_lines() {
+        let data = parse_obj("
+            v 0 0 0 # the origin
+            v 1 \\
+              0 0
+            v 0 1 0
+            f 1 \\
+              2 \\
+              3
+        ").unwrap();
+        assert_eq!(triangles(&data.meshes[0]),
+                   vec![[glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)]]);
+
+        // errors name the line a continued line starts on
+        let error = parse_obj("v 0 0 0\nf 1 \\\n 2 \\\n 3").err().unwrap();
+        assert!(error.starts_with("line 2: "), "{}", error);
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+use nalgebra_glm as glm;
+use serde::Deserialize;
+use crate::mesh::{GpuMesh, MeshData, Texture, Vertex};
+use crate::mesh_ops;
+
+// vertices closer than this in every attribute are merged
+const WELD_TOLERANCE: f32 = 1e-5;
+
+// Procedurally generated shapes. All of them fit into a unit cube around the origin (except the torus, whose size is
+// given by its radii, and the fullscreen quad, which covers the whole screen in normalized device coordinates), have
+// outward facing normals, texture coordinates and tangents, and use counter-clockwise triangles.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Primitive {
+    // a cube with an edge length of 1, every face shows the whole texture
+    Cube,
+    // a square with an edge length of 1 in the xz plane, facing up
+    Plane,
+    // a sphere with a diameter of 1 made of segments around the y axis and rings from pole to pole
+    UvSphere { segments: u32, rings: u32 },
+    // a sphere with a diameter of 1 made of evenly sized triangles, a subdivided icosahedron
+    Icosphere { subdivisions: u32 },
+    // a cylinder along the y axis with a diameter and a height of 1, closed at both ends
+    Cylinder { segments: u32 },
+    // a cone along the y axis with a diameter and a height of 1, the tip points up
+    Cone { segments: u32 },
+    // a ring around the y axis, the major radius goes to the center of the tube and the minor radius is the radius of
+    // the tube
+    Torus { major_radius: f32, minor_radius: f32, segments: u32, sides: u32 },
+    // a square from -1 to 1 in x and y, to draw post-processing effects with an identity transformation
+    FullscreenQuad,
+}
+
+impl Primitive {
+    // returns the vertices and the triangle indices of the shape
+    pub fn geometry(&self) -> (Vec<Vertex>, Vec<u32>) {
+        let mut geometry = Geometry::default();
+        match *self {
+            Primitive::Cube => {
+                // every face is given as (normal, u axis, v axis)
+                let faces = [
+                    (glm::vec3(0.0, 0.0, 1.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
+                    (glm::vec3(0.0, 0.0, -1.0), glm::vec3(-1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
+                    (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 0.0, -1.0), glm::vec3(0.0, 1.0, 0.0)),
+                    (glm::vec3(-1.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, 1.0, 0.0)),
+                    (glm::vec3(0.0, 1.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 0.0, -1.0)),
+                    (glm::vec3(0.0, -1.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+                ];
+                for (normal, u, v) in faces {
+                    geometry.square(&(normal * 0.5), &normal, &u, &v, 1.0);
+                }
+            }
+            Primitive::Plane => {
+                geometry.square(&glm::Vec3::zeros(), &glm::vec3(0.0, 1.0, 0.0), &glm::vec3(1.0, 0.0, 0.0),
+                                &glm::vec3(0.0, 0.0, -1.0), 1.0);
+            }
+            Primitive::UvSphere { segments, rings } => {
+                // theta goes around the y axis starting at +z, phi from the south to the north pole
+                geometry.grid(segments.max(3), rings.max(2), |u, v| {
+                    let (theta, phi) = (u * TAU, v * PI);
+                    let normal = glm::vec3(phi.sin() * theta.sin(), -phi.cos(), phi.sin() * theta.cos());
+                    (normal * 0.5, normal, glm::vec3(theta.cos(), 0.0, -theta.sin()))
+                });
+            }
+            Primitive::Icosphere { subdivisions } => geometry.icosphere(subdivisions),
+            Primitive::Cylinder { segments } => {
+                let segments = segments.max(3);
+                geometry.grid(segments, 1, |u, v| {
+                    let theta = u * TAU;
+                    let normal = glm::vec3(theta.sin(), 0.0, theta.cos());
+                    (normal * 0.5 + glm::vec3(0.0, v - 0.5, 0.0), normal, glm::vec3(theta.cos(), 0.0, -theta.sin()))
+                });
+                geometry.disk(0.5, segments);
+                geometry.disk(-0.5, segments);
+            }
+            Primitive::Cone { segments } => {
+                let segments = segments.max(3);
+                // the side leans in by the radius over the height, so the normal leans up by the same ratio
+                geometry.grid(segments, 1, |u, v| {
+                    let theta = u * TAU;
+                    let direction = glm::vec3(theta.sin(), 0.0, theta.cos());
+                    let normal = (direction + glm::vec3(0.0, 0.5, 0.0)).normalize();
+                    (direction * 0.5 * (1.0 - v) + glm::vec3(0.0, v - 0.5, 0.0), normal, glm::vec3(theta.cos(), 0.0, -theta.sin()))
+                });
+                geometry.disk(-0.5, segments);
+            }
+            Primitive::Torus { major_radius, minor_radius, segments, sides } => {
+                // theta goes around the y axis, phi around the tube starting at the outside
+                geometry.grid(segments.max(3), sides.max(3), |u, v| {
+                    let (theta, phi) = (u * TAU, v * TAU);
+                    let direction = glm::vec3(theta.sin(), 0.0, theta.cos());
+                    let normal = direction * phi.cos() + glm::vec3(0.0, phi.sin(), 0.0);
+                    (direction * major_radius + normal * minor_radius, normal, glm::vec3(theta.cos(), 0.0, -theta.sin()))
+                });
+            }
+            Primitive::FullscreenQuad => {
+                geometry.square(&glm::Vec3::zeros(), &glm::vec3(0.0, 0.0, 1.0), &glm::vec3(1.0, 0.0, 0.0),
+                                &glm::vec3(0.0, 1.0, 0.0), 2.0);
+            }
+        }
+
+        // the first and the last vertex around the disks end up in the same place
+        let mut mesh = MeshData::new(geometry.vertices, geometry.indices);
+        mesh_ops::weld(&mut mesh, WELD_TOLERANCE);
+        (mesh.vertices, mesh.indices)
+    }
+
+    // creates a mesh of the shape that is drawn with the textures
+    pub fn mesh(&self, textures: Vec<Texture>) -> GpuMesh {
+        let (vertices, indices) = self.geometry();
+        GpuMesh::upload(&MeshData::new(vertices, indices), textures)
+    }
+}
+
+#[derive(Default)]
+struct Geometry {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+impl Geometry {
+    fn vertex(&mut self, position: glm::Vec3, normal: glm::Vec3, tex_coords: glm::Vec2, tangent: glm::Vec3) -> u32 {
+        self.vertices.push(Vertex {
+            position,
+            normal,
+            tex_coords,
+            tangent,
+            bitangent: normal.cross(&tangent),
+        });
+        self.vertices.len() as u32 - 1
+    }
+
+    // adds a triangle, unless it has no area (like the triangles touching the poles of a sphere)
+    fn triangle(&mut self, a: u32, b: u32, c: u32) {
+        let position = |i: u32| self.vertices[i as usize].position;
+        if (position(b) - position(a)).cross(&(position(c) - position(a))).norm() > 1e-7 {
+            self.indices.extend_from_slice(&[a, b, c]);
+        }
+    }
+
+    // adds a square around the center, spanned by the u and v axes (with u x v = normal) and showing the whole texture
+    fn square(&mut self, center: &glm::Vec3, normal: &glm::Vec3, u: &glm::Vec3, v: &glm::Vec3, size: f32) {
+        let first = self.vertices.len() as u32;
+        for (s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            let position = center + (u * (s - 0.5) + v * (t - 0.5)) * size;
+            self.vertex(position, *normal, glm::vec2(s, t), *u);
+        }
+        self.triangle(first, first + 1, first + 2);
+        self.triangle(first + 2, first + 3, first);
+    }
+
+    // adds a grid of (columns + 1) x (rows + 1) vertices for a parametric surface. The function maps texture
+    // coordinates in [0, 1] to position, normal and tangent, the tangent has to point along increasing u and the
+    // normal to the side from which u and v run counter-clockwise.
+    fn grid(&mut self, columns: u32, rows: u32, surface: impl Fn(f32, f32) -> (glm::Vec3, glm::Vec3, glm::Vec3)) {
+        let first = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (u, v) = (column as f32 / columns as f32, row as f32 / rows as f32);
+                let (position, normal, tangent) = surface(u, v);
+                self.vertex(position, normal, glm::vec2(u, v), tangent);
+            }
+        }
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = first + row * (columns + 1) + column;
+                let b = a + 1;
+                let c = b + columns + 1;
+                let d = a + columns + 1;
+                self.triangle(a, b, c);
+                self.triangle(c, d, a);
+            }
+        }
+    }
+
+    // adds a disk with a diameter of 1 at the height y, facing up for a positive height and down otherwise. The
+    // texture is mapped from above (or below), like on the faces of the cube.
+    fn disk(&mut self, y: f32, segments: u32) {
+        let up = y > 0.0;
+        let normal = glm::vec3(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+        let tangent = glm::vec3(1.0, 0.0, 0.0);
+        // the texture v axis, see the top and bottom faces of the cube
+        let v_axis = if up { -1.0 } else { 1.0 };
+
+        let center = self.vertex(glm::vec3(0.0, y, 0.0), normal, glm::vec2(0.5, 0.5), tangent);
+        for segment in 0..=segments {
+            let theta = segment as f32 / segments as f32 * TAU;
+            let (x, z) = (theta.sin() * 0.5, theta.cos() * 0.5);
+            self.vertex(glm::vec3(x, y, z), normal, glm::vec2(0.5 + x, 0.5 + z * v_axis), tangent);
+        }
+
+        for segment in 0..segments {
+            let (a, b) = (center + 1 + segment, center + 2 + segment);
+            if up {
+                self.triangle(center, a, b);
+            } else {
+                self.triangle(center, b, a);
+            }
+        }
+    }
+
+    // adds a sphere by subdividing the triangles of an icosahedron and pushing the new vertices onto the sphere
+    fn icosphere(&mut self, subdivisions: u32) {
+        // the 12 vertices of an icosahedron are the corners of three orthogonal golden rectangles
+        let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+        let mut positions: Vec<glm::Vec3> = [
+            (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+            (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+            (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+        ].iter().map(|&(x, y, z)| glm::vec3(x, y, z).normalize()).collect();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+
+        // every subdivision splits each triangle into four, the vertices in the middle of the edges are shared
+        for _ in 0..subdivisions {
+            let mut middles: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut middle = |a: u32, b: u32| -> u32 {
+                *middles.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    positions.push((positions[a as usize] + positions[b as usize]).normalize());
+                    positions.len() as u32 - 1
+                })
+            };
+            triangles = triangles.iter().flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (middle(a, b), middle(b, c), middle(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            }).collect();
+        }
+
+        // the texture is wrapped around like on the UV sphere. Triangles crossing the seam at the back get copies of
+        // their vertices with the u coordinate moved past 1, so the texture doesn't run backwards over the triangle.
+        let first = self.vertices.len() as u32;
+        let tex_coords = |position: &glm::Vec3| {
+            let u = position.x.atan2(position.z) / TAU;
+            glm::vec2(if u < 0.0 { u + 1.0 } else { u }, 0.5 + position.y.asin() / PI)
+        };
+        let tangent = |position: &glm::Vec3| {
+            let tangent = glm::vec3(position.z, 0.0, -position.x);
+            if tangent.norm() > 1e-6 { tangent.normalize() } else { glm::vec3(1.0, 0.0, 0.0) }
+        };
+        for position in &positions {
+            self.vertex(position * 0.5, *position, tex_coords(position), tangent(position));
+        }
+
+        let mut seam_copies: HashMap<u32, u32> = HashMap::new();
+        for triangle in triangles {
+            let mut triangle = triangle.map(|i| first + i);
+            let u = triangle.map(|i| self.vertices[i as usize].tex_coords.x);
+            if u.iter().cloned().fold(f32::MIN, f32::max) - u.iter().cloned().fold(f32::MAX, f32::min) > 0.5 {
+                for (i, u) in triangle.iter_mut().zip(u) {
+                    if u < 0.5 {
+                        *i = *seam_copies.entry(*i).or_insert_with(|| {
+                            let mut vertex = self.vertices[*i as usize].clone();
+                            vertex.tex_coords.x += 1.0;
+                            self.vertices.push(vertex);
+                            self.vertices.len() as u32 - 1
+                        });
+                    }
+                }
+            }
+            self.triangle(triangle[0], triangle[1], triangle[2]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHAPES: [Primitive; 8] = [
+        Primitive::Cube,
+        Primitive::Plane,
+        Primitive::UvSphere { segments: 16, rings: 8 },
+        Primitive::Icosphere { subdivisions: 2 },
+        Primitive::Cylinder { segments: 12 },
+        Primitive::Cone { segments: 12 },
+        Primitive::Torus { major_radius: 0.35, minor_radius: 0.15, segments: 16, sides: 8 },
+        Primitive::FullscreenQuad,
+    ];
+
+    #[test]
+    fn vertex_and_index_counts() {
+        // (vertices, triangles), the triangles at the poles of the UV sphere and at the tip of the cone have no area,
+        // which leaves one of the vertices at each pole and at the tip unused. The disks have a vertex in the center and one for every
+        // segment. The icosphere has 10 * 4^n + 2 vertices and the copies along the texture seam.
+        let expected = [(Some(24), 12), (Some(4), 2), (Some(17 * 9 - 2), 2 * 16 * 8 - 2 * 16), (None, 20 * 16),
+                        (Some(2 * 13 + 2 * 13), 48), (Some(2 * 13 - 1 + 13), 12 + 12), (Some(17 * 9), 2 * 16 * 8), (Some(4), 2)];
+        for (shape, (vertices, triangles)) in SHAPES.iter().zip(expected) {
+            let (shape_vertices, indices) = shape.geometry();
+            assert_eq!(indices.len() % 3, 0);
+            assert_eq!(indices.len() / 3, triangles, "{:?}", shape);
+            assert!(indices.iter().all(|&i| (i as usize) < shape_vertices.len()), "{:?}", shape);
+            match vertices {
+                Some(vertices) => assert_eq!(shape_vertices.len(), vertices, "{:?}", shape),
+                None => assert!(shape_vertices.len() >= 10 * 4 * 4 + 2),
+            }
+        }
+    }
+
+    #[test]
+    fn normals_and_tangents_have_unit_length() {
+        for shape in SHAPES {
+            for vertex in shape.geometry().0 {
+                assert!((vertex.normal.norm() - 1.0).abs() < 1e-4, "{:?}", shape);
+                assert!((vertex.tangent.norm() - 1.0).abs() < 1e-4, "{:?}", shape);
+                assert!(vertex.normal.dot(&vertex.tangent).abs() < 1e-4, "{:?}", shape);
+                assert!((vertex.bitangent - vertex.normal.cross(&vertex.tangent)).norm() < 1e-5, "{:?}", shape);
+            }
+        }
+    }
+
+    #[test]
+    fn triangles_are_counter_clockwise() {
+        for shape in SHAPES {
+            let (vertices, indices) = shape.geometry();
+            for triangle in indices.chunks(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+                let face_normal = (b.position - a.position).cross(&(c.position - a.position));
+                assert!(face_normal.norm() > 0.0, "{:?}", shape);
+                for vertex in [a, b, c] {
+                    assert!(face_normal.dot(&vertex.normal) > 0.0, "{:?} {:?}", shape, triangle);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_fit_into_the_unit_cube() {
+        for shape in SHAPES {
+            let extent = shape.geometry().0.iter().map(|vertex| vertex.position.abs().max()).fold(0.0, f32::max);
+            let expected = match shape {
+                Primitive::FullscreenQuad => 1.0,
+                _ => 0.5,
+            };
+            assert!((extent - expected).abs() < 1e-5, "{:?}", shape);
+        }
+        // the points of the spheres lie on them
+        for shape in [Primitive::UvSphere { segments: 16, rings: 8 }, Primitive::Icosphere { subdivisions: 2 }] {
+            for vertex in shape.geometry().0 {
+                assert!((vertex.position.norm() - 0.5).abs() < 1e-5);
+                assert!((vertex.normal - vertex.position * 2.0).norm() < 1e-4);
+            }
+        }
+    }
+}
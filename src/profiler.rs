@@ -0,0 +1,376 @@
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use gl::types::*;
+use crate::gl_state;
+
+// A frame profiler: passes are timed on the GPU with GL_TIME_ELAPSED queries and on the CPU, scopes only on the CPU.
+// The result of a query is only read two frames after it was issued, when the GPU is done with it for sure, so every
+// pass has a query for the even and one for the odd frames and reading never stalls the pipeline.
+//
+// It is switched on from the command line:
+//   --profile                 prints the averages of the passes every second
+//   --profile-overlay         also draws the GPU time of every pass as a bar on top of the window, the bars are in the
+//                             order of the printed passes and the white line marks 1/60 s
+//   --profile-trace <file>    writes every frame to a Chrome trace (open it in chrome://tracing or ui.perfetto.dev)
+//                             when the tutorial ends
+// Without any of them the profiler does nothing.
+//
+// Draw calls and triangles are counted by record_draw, which GpuMesh calls for its draws and the tutorials for their
+// own. GL can only time one query of a type at a time, so passes don't nest (a nested pass is timed on the CPU only).
+
+// the draws since the start, see record_draw
+static DRAW_CALLS: AtomicUsize = AtomicUsize::new(0);
+static TRIANGLES: AtomicUsize = AtomicUsize::new(0);
+
+// the width of a bar of the overlay that takes a whole frame at 60 frames per second
+const OVERLAY_FRAME_MS: f32 = 1000.0 / 60.0;
+
+// counts a draw call for the profiler
+pub fn record_draw(triangles: usize) {
+    DRAW_CALLS.fetch_add(1, Ordering::Relaxed);
+    TRIANGLES.fetch_add(triangles, Ordering::Relaxed);
+}
+
+// the memory used by the textures (all mipmap levels of a 2D texture), as the driver reports it
+pub fn texture_memory(textures: &[GLuint]) -> usize {
+    let mut bytes = 0;
+    unsafe {
+        for &texture in textures {
+            gl_state::with(|gl| gl.bind_texture(0, gl::TEXTURE_2D, texture));
+            for level in 0.. {
+                let parameter = |name: GLenum| {
+                    let mut value = 0;
+                    gl::GetTexLevelParameteriv(gl::TEXTURE_2D, level, name, &mut value);
+                    value as usize
+                };
+                let (width, height) = (parameter(gl::TEXTURE_WIDTH), parameter(gl::TEXTURE_HEIGHT));
+                if width == 0 || height == 0 {
+                    break;
+                }
+                let bits: usize = [gl::TEXTURE_RED_SIZE, gl::TEXTURE_GREEN_SIZE, gl::TEXTURE_BLUE_SIZE,
+                    gl::TEXTURE_ALPHA_SIZE, gl::TEXTURE_DEPTH_SIZE].into_iter().map(parameter).sum();
+                bytes += width * height * bits / 8;
+            }
+        }
+    }
+    bytes
+}
+
+// the sums since the last report
+#[derive(Default)]
+struct Totals {
+    frames: usize,
+    cpu_ms: f64,
+    gpu_ms: f64,
+    gpu_frames: usize,
+    draw_calls: usize,
+    triangles: usize,
+}
+
+struct Pass {
+    name: &'static str,
+    // the queries of the even and the odd frames, and when the pass started (in the trace) if the query was issued
+    queries: [GLuint; 2],
+    issued: [Option<f64>; 2],
+    totals: Totals,
+    // the last result, for the overlay
+    last_gpu_ms: f32,
+}
+
+// an event of the Chrome trace, the times are in microseconds since the profiler was created
+struct TraceEvent {
+    name: &'static str,
+    gpu: bool,
+    start: f64,
+    duration: f64,
+}
+
+struct State {
+    frame: usize,
+    frame_start: Option<Instant>,
+    frame_draws: (usize, usize),
+    frame_totals: Totals,
+    passes: Vec<Pass>,
+    active_pass: Option<usize>,
+    last_report: Instant,
+    texture_memory: usize,
+    trace: Vec<TraceEvent>,
+}
+
+pub struct Profiler {
+    enabled: bool,
+    overlay: bool,
+    // seconds between the reports, None prints nothing
+    report_interval: Option<f32>,
+    trace_file: Option<String>,
+    epoch: Instant,
+    state: RefCell<State>,
+}
+
+// a pass or a scope being timed, it ends when it is dropped
+pub struct Scope<'a> {
+    profiler: &'a Profiler,
+    name: &'static str,
+    // the index of the pass, None for a scope
+    pass: Option<usize>,
+    start: Instant,
+    draws: (usize, usize),
+}
+
+impl Profiler {
+    // a profiler set up by the command line options, see above
+    pub fn from_args() -> Profiler {
+        let args: Vec<String> = std::env::args().collect();
+        let flag = |option: &str| args.iter().any(|arg| arg == option);
+        let trace_file = args.iter().position(|arg| arg == "--profile-trace").and_then(|i| args.get(i + 1)).cloned();
+
+        let overlay = flag("--profile-overlay");
+        let report_interval = if flag("--profile") || overlay { Some(1.0) } else { None };
+        Profiler::new(report_interval, overlay, trace_file)
+    }
+
+    pub fn new(report_interval: Option<f32>, overlay: bool, trace_file: Option<String>) -> Profiler {
+        let now = Instant::now();
+        Profiler {
+            enabled: report_interval.is_some() || overlay || trace_file.is_some(),
+            overlay,
+            report_interval,
+            trace_file,
+            epoch: now,
+            state: RefCell::new(State {
+                frame: 0,
+                frame_start: None,
+                frame_draws: draw_counts(),
+                frame_totals: Totals::default(),
+                passes: Vec::new(),
+                active_pass: None,
+                last_report: now,
+                texture_memory: 0,
+                trace: Vec::new(),
+            }),
+        }
+    }
+
+    // the texture memory for the reports, see texture_memory
+    pub fn set_texture_memory(&self, bytes: usize) {
+        self.state.borrow_mut().texture_memory = bytes;
+    }
+
+    // ends the previous frame and starts the next one, call it at the start of every frame
+    pub fn begin_frame(&self) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+
+        if let Some(start) = state.frame_start {
+            let draws = draw_counts();
+            let totals = &mut state.frame_totals;
+            totals.frames += 1;
+            totals.cpu_ms += (now - start).as_secs_f64() * 1000.0;
+            totals.draw_calls += draws.0 - state.frame_draws.0;
+            totals.triangles += draws.1 - state.frame_draws.1;
+            if self.trace_file.is_some() {
+                state.trace.push(TraceEvent { name: "frame", gpu: false, start: self.micros(start),
+                    duration: (now - start).as_secs_f64() * 1e6 });
+            }
+        }
+        state.frame += 1;
+        state.frame_start = Some(now);
+        state.frame_draws = draw_counts();
+
+        // the queries of this frame were issued two frames ago, their results are ready
+        let buffer = state.frame % 2;
+        for pass in &mut state.passes {
+            let start = match pass.issued[buffer].take() {
+                Some(start) => start,
+                None => continue,
+            };
+            let mut nanoseconds: GLuint64 = 0;
+            unsafe { gl::GetQueryObjectui64v(pass.queries[buffer], gl::QUERY_RESULT, &mut nanoseconds) };
+            let milliseconds = nanoseconds as f64 / 1e6;
+            pass.totals.gpu_ms += milliseconds;
+            pass.totals.gpu_frames += 1;
+            pass.last_gpu_ms = milliseconds as f32;
+            if self.trace_file.is_some() {
+                state.trace.push(TraceEvent { name: pass.name, gpu: true, start, duration: milliseconds * 1000.0 });
+            }
+        }
+
+        if let Some(interval) = self.report_interval {
+            if (now - state.last_report).as_secs_f32() >= interval {
+                state.last_report = now;
+                print!("{}", state.report());
+            }
+        }
+    }
+
+    // times a pass on the GPU and the CPU until the returned scope is dropped, every pass should run once per frame
+    pub fn pass(&self, name: &'static str) -> Scope<'_> {
+        let mut pass = None;
+        if self.enabled {
+            let mut state = self.state.borrow_mut();
+            if state.active_pass.is_none() {
+                let index = state.pass_index(name);
+                let buffer = state.frame % 2;
+                let pass_state = &mut state.passes[index];
+                // a pass that runs twice in a frame is only timed the first time
+                if pass_state.issued[buffer].is_none() {
+                    pass_state.issued[buffer] = Some(self.micros(Instant::now()));
+                    unsafe { gl::BeginQuery(gl::TIME_ELAPSED, pass_state.queries[buffer]) };
+                    state.active_pass = Some(index);
+                    pass = Some(index);
+                }
+            }
+        }
+        Scope { profiler: self, name, pass, start: Instant::now(), draws: draw_counts() }
+    }
+
+    // times a scope on the CPU until the returned scope is dropped, scopes can nest
+    pub fn scope(&self, name: &'static str) -> Scope<'_> {
+        Scope { profiler: self, name, pass: None, start: Instant::now(), draws: draw_counts() }
+    }
+
+    fn end(&self, scope: &Scope) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+
+        if let Some(index) = scope.pass {
+            unsafe { gl::EndQuery(gl::TIME_ELAPSED) };
+            state.active_pass = None;
+
+            let draws = draw_counts();
+            let totals = &mut state.passes[index].totals;
+            totals.frames += 1;
+            totals.cpu_ms += (now - scope.start).as_secs_f64() * 1000.0;
+            totals.draw_calls += draws.0 - scope.draws.0;
+            totals.triangles += draws.1 - scope.draws.1;
+        }
+        if self.trace_file.is_some() {
+            state.trace.push(TraceEvent { name: scope.name, gpu: false, start: self.micros(scope.start),
+                duration: (now - scope.start).as_secs_f64() * 1e6 });
+        }
+    }
+
+    // draws the GPU time of the last frame of every pass as a bar in the top left corner, if the overlay is on. It
+    // only clears rectangles of the window, so it needs no shader.
+    pub fn draw_overlay(&self, width: i32, height: i32) {
+        if !self.overlay {
+            return;
+        }
+        let state = self.state.borrow();
+        let (x, bar_height) = (8, (height / 60).max(4));
+        let full_width = width / 3;
+        let colors = [[0.9, 0.4, 0.1], [0.2, 0.7, 0.9], [0.5, 0.9, 0.3], [0.9, 0.8, 0.2], [0.8, 0.3, 0.8]];
+
+        unsafe {
+            gl::Enable(gl::SCISSOR_TEST);
+            for (i, pass) in state.passes.iter().enumerate() {
+                let y = height - 8 - (i as i32 + 1) * (bar_height + 2);
+                gl::Scissor(x, y, full_width, bar_height);
+                gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+
+                let filled = (full_width as f32 * pass.last_gpu_ms / OVERLAY_FRAME_MS).min(width as f32) as i32;
+                let [r, g, b] = colors[i % colors.len()];
+                gl::Scissor(x, y, filled, bar_height);
+                gl::ClearColor(r, g, b, 1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+
+                // the end of a frame at 60 frames per second
+                gl::Scissor(x + full_width - 1, y, 2, bar_height);
+                gl::ClearColor(1.0, 1.0, 1.0, 1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+            }
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+    }
+
+    // writes the recorded events as a Chrome trace, the CPU and the GPU are two threads of the trace
+    pub fn write_trace(&self, path: &str) -> Result<(), String> {
+        let state = self.state.borrow();
+        let mut json = String::from("{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n");
+        json.push_str("{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\"args\":{\"name\":\"CPU\"}},\n");
+        json.push_str("{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":2,\"args\":{\"name\":\"GPU\"}}");
+        for event in &state.trace {
+            let _ = write!(json, ",\n{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{}}}",
+                           escape(event.name), if event.gpu { "gpu" } else { "cpu" }, event.start, event.duration,
+                           if event.gpu { 2 } else { 1 });
+        }
+        json.push_str("\n]}\n");
+        fs::write(path, json).map_err(|e| format!("ERROR::PROFILER::TRACE_NOT_WRITTEN: {}: {}", path, e))
+    }
+
+    fn micros(&self, instant: Instant) -> f64 {
+        (instant - self.epoch).as_secs_f64() * 1e6
+    }
+}
+
+impl State {
+    // the pass with the name, created the first time
+    fn pass_index(&mut self, name: &'static str) -> usize {
+        if let Some(index) = self.passes.iter().position(|pass| pass.name == name) {
+            return index;
+        }
+        let mut queries = [0; 2];
+        unsafe { gl::GenQueries(2, queries.as_mut_ptr()) };
+        self.passes.push(Pass { name, queries, issued: [None; 2], totals: Totals::default(), last_gpu_ms: 0.0 });
+        self.passes.len() - 1
+    }
+
+    // the averages per frame since the last report, which are then reset
+    fn report(&mut self) -> String {
+        let frame = std::mem::take(&mut self.frame_totals);
+        let frames = frame.frames.max(1) as f64;
+        let mut report = format!("profile: {} frames, {:.2} ms CPU, {:.0} draw calls and {:.0} triangles per frame, {:.1} MiB of textures\n",
+                                 frame.frames, frame.cpu_ms / frames, frame.draw_calls as f64 / frames,
+                                 frame.triangles as f64 / frames, self.texture_memory as f64 / (1024.0 * 1024.0));
+        for pass in &mut self.passes {
+            let totals = std::mem::take(&mut pass.totals);
+            let (frames, gpu_frames) = (totals.frames.max(1) as f64, totals.gpu_frames.max(1) as f64);
+            let _ = writeln!(report, "  {:<16} {:>7.3} ms CPU {:>7.3} ms GPU {:>6.0} draw calls {:>9.0} triangles",
+                             pass.name, totals.cpu_ms / frames, totals.gpu_ms / gpu_frames,
+                             totals.draw_calls as f64 / frames, totals.triangles as f64 / frames);
+        }
+        report
+    }
+}
+
+impl Drop for Scope<'_> {
+    fn drop(&mut self) {
+        self.profiler.end(self);
+    }
+}
+
+impl Drop for Profiler {
+    // the trace is written once the tutorial has finished
+    fn drop(&mut self) {
+        if let Some(file) = &self.trace_file {
+            match self.write_trace(file) {
+                Ok(_) => println!("Profile trace saved to {}", file),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+        for pass in &self.state.borrow().passes {
+            unsafe { gl::DeleteQueries(2, pass.queries.as_ptr()) };
+        }
+    }
+}
+
+fn draw_counts() -> (usize, usize) {
+    (DRAW_CALLS.load(Ordering::Relaxed), TRIANGLES.load(Ordering::Relaxed))
+}
+
+// the names are string literals, but a quote would still break the JSON
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
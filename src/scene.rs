@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::fs;
+use gl::types::*;
+use nalgebra_glm as glm;
+use serde::Deserialize;
+use crate::asset_loader::{AssetLoader, LoadProgress, ModelHandle};
+use crate::bounds::Frustum;
+use crate::camera::{CameraController, CameraPose};
+use crate::gl_debug::{self, gl_check};
+use crate::gl_state;
+use crate::light::{Attenuation, DirLight, LightSet, PointLight, SpotLight};
+use crate::lod::LodView;
+use crate::mesh::{GpuMesh, Texture};
+use crate::model::{texture_from_file, DrawStats};
+use crate::primitives::Primitive;
+use crate::shader::Shader;
+
+// A scene as it is written in a RON file, see resources/scenes for examples. Everything is optional, paths are
+// relative to the working directory like the paths in the tutorials.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct SceneDescription {
+    pub camera: CameraDescription,
+    // the clear color
+    pub background: Option<[f32; 3]>,
+    pub materials: HashMap<String, MaterialDescription>,
+    pub models: Vec<ModelDescription>,
+    pub primitives: Vec<PrimitiveDescription>,
+    pub lights: LightsDescription,
+    pub skybox: Option<SkyboxDescription>,
+}
+
+// the start pose of the camera
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CameraDescription {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub zoom: f32,
+}
+
+impl Default for CameraDescription {
+    fn default() -> CameraDescription {
+        CameraDescription {
+            position: [0.0, 0.0, 3.0],
+            yaw: -90.0,
+            pitch: 0.0,
+            roll: 0.0,
+            zoom: 45.0,
+        }
+    }
+}
+
+// The placement of an object: scaled first, then rotated and then moved to the position
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct Transform {
+    pub position: [f32; 3],
+    pub rotation: Rotation,
+    pub scale: [f32; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            position: [0.0, 0.0, 0.0],
+            rotation: Rotation::Euler(0.0, 0.0, 0.0),
+            scale: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum Rotation {
+    // angles in degrees around the x, y and z axis, applied in this order
+    Euler(f32, f32, f32),
+    // an angle in degrees around an axis
+    AxisAngle { axis: [f32; 3], angle: f32 },
+}
+
+impl Transform {
+    // returns the model matrix
+    pub fn matrix(&self) -> glm::Mat4 {
+        let mut model = glm::translate(&glm::identity(), &glm::make_vec3(&self.position));
+        model = match self.rotation {
+            Rotation::Euler(x, y, z) => {
+                let model = glm::rotate_z(&model, z.to_radians());
+                let model = glm::rotate_y(&model, y.to_radians());
+                glm::rotate_x(&model, x.to_radians())
+            }
+            Rotation::AxisAngle { axis, angle } => glm::rotate(&model, angle.to_radians(), &glm::make_vec3(&axis)),
+        };
+        glm::scale(&model, &glm::make_vec3(&self.scale))
+    }
+}
+
+// The textures of a primitive, a missing specular map means no specular highlights
+#[derive(Deserialize)]
+pub struct MaterialDescription {
+    pub diffuse: String,
+    #[serde(default)]
+    pub specular: Option<String>,
+    #[serde(default = "default_shininess")]
+    pub shininess: f32,
+}
+
+fn default_shininess() -> f32 {
+    32.0
+}
+
+// a model file loaded with Model
+#[derive(Deserialize)]
+pub struct ModelDescription {
+    pub path: String,
+    // the number of levels of detail to generate, see mesh_ops::generate_lods
+    #[serde(default)]
+    pub lods: usize,
+    #[serde(default)]
+    pub transform: Transform,
+}
+
+// a generated mesh with a material from the materials of the scene
+#[derive(Deserialize)]
+pub struct PrimitiveDescription {
+    pub shape: Primitive,
+    pub material: String,
+    #[serde(default)]
+    pub transform: Transform,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct LightsDescription {
+    pub directional: Vec<DirLightDescription>,
+    pub point: Vec<PointLightDescription>,
+    pub spot: Vec<SpotLightDescription>,
+}
+
+#[derive(Deserialize)]
+pub struct DirLightDescription {
+    pub direction: [f32; 3],
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+}
+
+// how the light of a point or spot light fades, see Attenuation
+#[derive(Deserialize, Clone, Copy)]
+pub enum AttenuationDescription {
+    // the distance the light should reach, see Attenuation::from_range
+    Range(f32),
+    Polynomial { constant: f32, linear: f32, quadratic: f32 },
+    InverseSquare { radius: f32 },
+}
+
+impl Default for AttenuationDescription {
+    fn default() -> AttenuationDescription {
+        AttenuationDescription::Range(50.0)
+    }
+}
+
+impl From<AttenuationDescription> for Attenuation {
+    fn from(description: AttenuationDescription) -> Attenuation {
+        match description {
+            AttenuationDescription::Range(distance) => Attenuation::from_range(distance),
+            AttenuationDescription::Polynomial { constant, linear, quadratic } => {
+                Attenuation::Polynomial { constant, linear, quadratic }
+            }
+            AttenuationDescription::InverseSquare { radius } => Attenuation::InverseSquare { radius },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PointLightDescription {
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub attenuation: AttenuationDescription,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+}
+
+// a spot light, the cut-off angles are in degrees. A light that follows the camera is a flashlight, its position and
+// direction are ignored.
+#[derive(Deserialize)]
+pub struct SpotLightDescription {
+    #[serde(default)]
+    pub position: [f32; 3],
+    #[serde(default = "default_spot_direction")]
+    pub direction: [f32; 3],
+    #[serde(default)]
+    pub follow_camera: bool,
+    pub cut_off: f32,
+    pub outer_cut_off: f32,
+    #[serde(default)]
+    pub attenuation: AttenuationDescription,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+}
+
+fn default_spot_direction() -> [f32; 3] {
+    [0.0, 0.0, -1.0]
+}
+
+// the six faces of a cube map, in the order right, left, top, bottom, front and back
+#[derive(Deserialize)]
+pub struct SkyboxDescription {
+    pub faces: [String; 6],
+}
+
+impl SceneDescription {
+    // reads a scene from a RON file
+    pub fn load(path: &str) -> Result<SceneDescription, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => return Err(format!("ERROR::SCENE::FILE_NOT_SUCCESFULLY_READ: {}: {}", path, error)),
+        };
+        SceneDescription::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<SceneDescription, String> {
+        let description: SceneDescription = match ron::from_str(text) {
+            Ok(description) => description,
+            Err(error) => return Err(format!("ERROR::SCENE::PARSE_ERROR: {}", error)),
+        };
+
+        // check the references between the parts of the scene before anything is loaded
+        for primitive in &description.primitives {
+            if !description.materials.contains_key(&primitive.material) {
+                return Err(format!("ERROR::SCENE::UNKNOWN_MATERIAL: {}", primitive.material));
+            }
+        }
+        Ok(description)
+    }
+
+    pub fn camera_pose(&self) -> CameraPose {
+        CameraPose {
+            position: glm::make_vec3(&self.camera.position),
+            yaw: self.camera.yaw,
+            pitch: self.camera.pitch,
+            roll: self.camera.roll,
+            zoom: self.camera.zoom,
+        }
+    }
+
+    // the lights of the scene, and the indices of the spot lights that follow the camera
+    pub fn light_set(&self) -> (LightSet, Vec<usize>) {
+        let color = |color: &[f32; 3]| glm::make_vec3(color);
+
+        let lights = LightSet {
+            dir_lights: self.lights.directional.iter().map(|light| DirLight {
+                direction: color(&light.direction),
+                ambient: color(&light.ambient),
+                diffuse: color(&light.diffuse),
+                specular: color(&light.specular),
+            }).collect(),
+            point_lights: self.lights.point.iter().map(|light| PointLight {
+                position: color(&light.position),
+                attenuation: light.attenuation.into(),
+                ambient: color(&light.ambient),
+                diffuse: color(&light.diffuse),
+                specular: color(&light.specular),
+            }).collect(),
+            spot_lights: self.lights.spot.iter().map(|light| SpotLight {
+                position: color(&light.position),
+                direction: color(&light.direction),
+                cut_off: light.cut_off.to_radians().cos(),
+                outer_cut_off: light.outer_cut_off.to_radians().cos(),
+                attenuation: light.attenuation.into(),
+                ambient: color(&light.ambient),
+                diffuse: color(&light.diffuse),
+                specular: color(&light.specular),
+            }).collect(),
+        };
+        let flashlights = self.lights.spot.iter().enumerate()
+            .filter(|(_, light)| light.follow_camera)
+            .map(|(i, _)| i)
+            .collect();
+
+        (lights, flashlights)
+    }
+}
+
+// A model or a primitive placed in the scene
+pub struct SceneObject<T> {
+    pub object: T,
+    pub transform: glm::Mat4,
+    pub shininess: f32,
+}
+
+// A loaded scene, ready to be drawn. The models load in the background, see update.
+pub struct Scene {
+    pub camera: CameraPose,
+    pub background: glm::Vec3,
+    pub models: Vec<SceneObject<ModelHandle>>,
+    pub primitives: Vec<SceneObject<GpuMesh>>,
+    pub lights: LightSet,
+    // indices of the spot lights that follow the camera
+    pub flashlights: Vec<usize>,
+    pub skybox: Option<Skybox>,
+    pub assets: AssetLoader,
+}
+
+impl Scene {
+    // reads the scene file and loads all textures it refers to, and starts loading the models
+    pub fn load(path: &str) -> Result<Scene, String> {
+        let description = SceneDescription::load(path)?;
+
+        // a black texture for materials without specular map
+        let no_specular = Texture {
+            id: single_color_texture([0, 0, 0]),
+            type_name: "texture_specular".to_string(),
+            path: String::new(),
+        };
+
+        let mut materials: HashMap<&str, (Vec<Texture>, f32)> = HashMap::new();
+        for (name, material) in &description.materials {
+            let load = |path: &str| texture_from_file(path, ".", false)
+                .map_err(|e| format!("ERROR::SCENE::MATERIAL_TEXTURE_NOT_LOADED: {}: {}", name, e));
+            let diffuse = Texture {
+                id: load(&material.diffuse)?,
+                type_name: "texture_diffuse".to_string(),
+                path: material.diffuse.clone(),
+            };
+            let specular = match &material.specular {
+                Some(path) => Texture {
+                    id: load(path)?,
+                    type_name: "texture_specular".to_string(),
+                    path: path.clone(),
+                },
+                None => no_specular.clone(),
+            };
+            materials.insert(name, (vec![diffuse, specular], material.shininess));
+        }
+
+        let mut assets = AssetLoader::new();
+        let models = description.models.iter().map(|model| SceneObject {
+            object: assets.load_model(&model.path, false, model.lods),
+            transform: model.transform.matrix(),
+            shininess: default_shininess(),
+        }).collect();
+
+        let primitives = description.primitives.iter().map(|primitive| {
+            let (textures, shininess) = &materials[primitive.material.as_str()];
+            let mesh = primitive.shape.mesh(textures.clone());
+            mesh.label(&format!("{:?} ({})", primitive.shape, primitive.material));
+            SceneObject {
+                object: mesh,
+                transform: primitive.transform.matrix(),
+                shininess: *shininess,
+            }
+        }).collect();
+
+        let skybox = match &description.skybox {
+            Some(skybox) => Some(Skybox::new(&skybox.faces)?),
+            None => None,
+        };
+
+        let (lights, flashlights) = description.light_set();
+        Ok(Scene {
+            camera: description.camera_pose(),
+            background: glm::make_vec3(&description.background.unwrap_or([0.1, 0.1, 0.1])),
+            models,
+            primitives,
+            lights,
+            flashlights,
+            skybox,
+            assets,
+        })
+    }
+
+    // uploads the models (and their textures) that finished loading, once per frame
+    pub fn update(&mut self) {
+        self.assets.update();
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.assets.progress()
+    }
+
+    // moves the flashlights to the camera
+    pub fn follow_camera(&mut self, camera: &dyn CameraController) {
+        let view = camera.get_view_matrix();
+        // the camera looks down the negative z axis of its view space
+        let front = -glm::vec3(view[(2, 0)], view[(2, 1)], view[(2, 2)]);
+        for &i in &self.flashlights {
+            self.lights.spot_lights[i].position = camera.get_position();
+            self.lights.spot_lights[i].direction = front;
+        }
+    }
+
+    // draws the models and primitives that intersect the frustum, the shader needs the "model", "shininess" and
+    // "lodFade" uniforms and the texture_diffuse1 and texture_specular1 samplers. With a view the models are drawn
+    // with levels of detail, see Model::draw_with_lod.
+    pub fn draw(&self, shader: &Shader, frustum: &Frustum, lod: Option<&LodView>) -> DrawStats {
+        let mut stats = DrawStats::default();
+
+        for model in &self.models {
+            shader.set_float("shininess", model.shininess);
+            stats.add(self.assets.model(model.object).draw_with_lod(shader, frustum, &model.transform, lod));
+        }
+
+        for primitive in &self.primitives {
+            if !frustum.intersects_aabb(&primitive.object.aabb.transform(&primitive.transform)) {
+                stats.meshes_culled += 1;
+                stats.mesh_lods.push(None);
+                continue;
+            }
+            shader.set_float("shininess", primitive.shininess);
+            shader.set_mat4("model", &primitive.transform);
+            primitive.object.draw(shader);
+            stats.meshes_drawn += 1;
+            stats.mesh_lods.push(Some(0));
+        }
+
+        stats
+    }
+}
+
+// a 1x1 texture of a single color
+fn single_color_texture(color: [u8; 3]) -> GLuint {
+    let mut texture_id: GLuint = 0;
+    unsafe {
+        gl::GenTextures(1, &mut texture_id);
+        gl_state::with(|gl| gl.bind_texture(0, gl::TEXTURE_2D, texture_id));
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB as GLint, 1, 1, 0, gl::RGB, gl::UNSIGNED_BYTE, color.as_ptr() as *const GLvoid);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+    }
+    texture_id
+}
+
+// A cube map drawn behind everything else, see https://learnopengl.com/Advanced-OpenGL/Cubemaps
+pub struct Skybox {
+    pub texture: GLuint,
+    vao: GLuint,
+    vbo: GLuint,
+}
+
+impl Skybox {
+    // loads the six faces, in the order right, left, top, bottom, front and back
+    pub fn new(faces: &[String; 6]) -> Result<Skybox, String> {
+        let mut texture: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+        }
+        gl_state::with(|gl| gl.bind_texture(0, gl::TEXTURE_CUBE_MAP, texture));
+        gl_debug::label(gl::TEXTURE, texture, "skybox");
+
+        for (i, face) in faces.iter().enumerate() {
+            let image = match image::open(face) {
+                Ok(image) => image.into_rgb8(),
+                Err(error) => return Err(format!("ERROR::SCENE::SKYBOX_NOT_SUCCESFULLY_LOADED: {}: {}", face, error)),
+            };
+            unsafe {
+                gl_check!(gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum, 0, gl::RGB as GLint,
+                                         image.width() as GLsizei, image.height() as GLsizei, 0, gl::RGB,
+                                         gl::UNSIGNED_BYTE, image.as_raw().as_ptr() as *const GLvoid));
+            }
+        }
+
+        unsafe {
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as GLint);
+        }
+
+        // the positions of a cube, seen from the inside
+        let (vertices, indices) = Primitive::Cube.geometry();
+        let positions: Vec<glm::Vec3> = indices.iter().map(|&i| vertices[i as usize].position).collect();
+
+        let (mut vao, mut vbo) = (0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl_state::with(|gl| {
+                gl.bind_vertex_array(vao);
+                gl.bind_buffer(gl::ARRAY_BUFFER, vbo);
+            });
+            gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(positions.as_slice()) as GLsizeiptr,
+                           positions.as_ptr() as *const GLvoid, gl::STATIC_DRAW);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, std::mem::size_of::<glm::Vec3>() as GLsizei, std::ptr::null());
+            gl_state::with(|gl| gl.bind_vertex_array(0));
+        }
+
+        Ok(Skybox { texture, vao, vbo })
+    }
+
+    // draws the skybox with a shader that takes the "projection" and "view" matrices and the "skybox" sampler. Draw
+    // it after the scene, the shader puts it at the far plane so it only fills the pixels nothing else was drawn to.
+    pub fn draw(&self, shader: &Shader, projection: &glm::Mat4, view: &glm::Mat4) {
+        // remove the translation from the view matrix, the skybox is always around the camera
+        let view = glm::mat3_to_mat4(&glm::mat4_to_mat3(view));
+
+        // change the depth function so the depth test passes when the values are equal to the depth buffer's content,
+        // it is set back when the guard is dropped
+        let _depth = gl_state::with_state(|state| state.depth_func = gl::LEQUAL);
+        shader.use_program();
+        shader.set_mat4("projection", projection);
+        shader.set_mat4("view", &view);
+        shader.set_int("skybox", 0);
+
+        gl_state::with(|gl| {
+            gl.bind_vertex_array(self.vao);
+            gl.bind_texture(0, gl::TEXTURE_CUBE_MAP, self.texture);
+        });
+        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 36) };
+    }
+
+    pub fn delete(&self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteTextures(1, &self.texture);
+        }
+        // GL unbinds deleted objects and may hand their names out again
+        gl_state::with(|gl| gl.invalidate());
+    }
+}
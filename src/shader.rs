@@ -1,7 +1,9 @@
-use std::ffi::CString;
-use std::{fs, ptr};
+use std::fs;
 use gl::{self, types::*};
 use nalgebra_glm as glm;
+use crate::gl_api;
+use crate::gl_debug;
+use crate::gl_state;
 
 pub struct Shader {
     pub id: GLuint,
@@ -22,40 +24,41 @@ impl Shader {
             Err(error) => return Err(format!("ERROR::SHADER::FILE_NOT_SUCCESFULLY_READ: {}", error)),
         };
 
-        let v_shader_code = CString::new(&vertex_code[..]).unwrap();
-        let f_shader_code = CString::new(&fragment_code[..]).unwrap();
-
         // 2. compile shaders
         // vertex shader
-        let vertex = unsafe { gl::CreateShader(gl::VERTEX_SHADER) };
-        unsafe {
-            gl::ShaderSource(vertex, 1, &v_shader_code.as_ptr(), ptr::null());
-            gl::CompileShader(vertex);
-        }
+        let vertex = gl_api::with(|gl| {
+            let vertex = gl.create_shader(gl::VERTEX_SHADER);
+            gl.shader_source(vertex, &vertex_code);
+            gl.compile_shader(vertex);
+            vertex
+        });
         shader.check_compile_errors(vertex, "VERTEX")?;
 
         // fragment Shader
-        let fragment = unsafe { gl::CreateShader(gl::FRAGMENT_SHADER) };
-        unsafe {
-            gl::ShaderSource(fragment, 1, &f_shader_code.as_ptr(), ptr::null());
-            gl::CompileShader(fragment);
-        }
+        let fragment = gl_api::with(|gl| {
+            let fragment = gl.create_shader(gl::FRAGMENT_SHADER);
+            gl.shader_source(fragment, &fragment_code);
+            gl.compile_shader(fragment);
+            fragment
+        });
         shader.check_compile_errors(fragment, "FRAGMENT")?;
 
         // shader Program
-        shader.id = unsafe { gl::CreateProgram() };
-        unsafe {
-            gl::AttachShader(shader.id, vertex);
-            gl::AttachShader(shader.id, fragment);
-            gl::LinkProgram(shader.id);
-        }
+        shader.id = gl_api::with(|gl| {
+            let program = gl.create_program();
+            gl.attach_shader(program, vertex);
+            gl.attach_shader(program, fragment);
+            gl.link_program(program);
+            program
+        });
         shader.check_compile_errors(shader.id, "PROGRAM")?;
+        gl_debug::label(gl::PROGRAM, shader.id, &format!("{} + {}", vertex_path, fragment_path));
 
         // delete the shaders as they're linked into our program now and no longer necessary
-        unsafe {
-            gl::DeleteShader(vertex);
-            gl::DeleteShader(fragment);
-        }
+        gl_api::with(|gl| {
+            gl.delete_shader(vertex);
+            gl.delete_shader(fragment);
+        });
 
         Ok(shader)
     }
@@ -63,54 +66,67 @@ impl Shader {
     // activate the shader
     // ------------------------------------------------------------------------
     pub fn use_program(&self) {
-        unsafe { gl::UseProgram(self.id); }
+        gl_state::with(|gl| gl.use_program(self.id));
     }
 
     // utility uniform functions
     // ------------------------------------------------------------------------
     #[allow(dead_code)]
     pub fn set_bool(&self, name: &str, value: bool) {
-        let name = CString::new(name).unwrap();
-        unsafe { gl::Uniform1i(gl::GetUniformLocation(self.id, name.as_ptr()), value as GLint); }
+        gl_api::with(|gl| gl.uniform_1i(gl.get_uniform_location(self.id, name), value as GLint));
     }
     // ------------------------------------------------------------------------
     pub fn set_int(&self, name: &str, value: GLint) {
-        let name = CString::new(name).unwrap();
-        unsafe { gl::Uniform1i(gl::GetUniformLocation(self.id, name.as_ptr()), value); }
+        gl_api::with(|gl| gl.uniform_1i(gl.get_uniform_location(self.id, name), value));
     }
     // ------------------------------------------------------------------------
     pub fn set_float(&self, name: &str, value: GLfloat)
     {
-        let name = CString::new(name).unwrap();
-        unsafe { gl::Uniform1f(gl::GetUniformLocation(self.id, name.as_ptr()), value); }
+        gl_api::with(|gl| gl.uniform_1f(gl.get_uniform_location(self.id, name), value));
+    }
+    // ------------------------------------------------------------------------
+    pub fn set_vec2(&self, name: &str, x: f32, y: f32)
+    {
+        gl_api::with(|gl| gl.uniform_2f(gl.get_uniform_location(self.id, name), x, y));
     }
     // ------------------------------------------------------------------------
     pub fn set_vec3(&self, name: &str, x: f32, y: f32, z: f32)
     {
-        let name = CString::new(name).unwrap();
-        unsafe { gl::Uniform3f(gl::GetUniformLocation(self.id, name.as_ptr()), x, y, z); }
+        gl_api::with(|gl| gl.uniform_3f(gl.get_uniform_location(self.id, name), x, y, z));
     }
     // ------------------------------------------------------------------------
     pub fn set_vec3v(&self, name: &str, value: &glm::Vec3)
     {
-        let name = CString::new(name).unwrap();
-        unsafe { gl::Uniform3fv(gl::GetUniformLocation(self.id, name.as_ptr()), 1, value.as_ptr()); }
+        gl_api::with(|gl| gl.uniform_3f(gl.get_uniform_location(self.id, name), value.x, value.y, value.z));
     }
     // ------------------------------------------------------------------------
     pub fn set_mat4(&self, name: &str, mat: &glm::Mat4) {
-        let name = CString::new(name).unwrap();
-        unsafe { gl::UniformMatrix4fv(gl::GetUniformLocation(self.id, name.as_ptr()), 1, gl::FALSE, mat.as_ptr()); }
+        let value = mat.as_slice().try_into().unwrap();
+        gl_api::with(|gl| gl.uniform_matrix_4fv(gl.get_uniform_location(self.id, name), false, value));
+    }
+
+    // links the uniform block to a uniform buffer binding point. Returns the size of the block in bytes, or None if
+    // the shader doesn't declare (or doesn't use) the block.
+    // ------------------------------------------------------------------------
+    pub fn bind_uniform_block(&self, name: &str, binding: GLuint) -> Option<usize> {
+        gl_api::with(|gl| {
+            let index = gl.get_uniform_block_index(self.id, name);
+            if index == gl::INVALID_INDEX {
+                return None;
+            }
+
+            gl.uniform_block_binding(self.id, index, binding);
+            Some(gl.get_active_uniform_block_iv(self.id, index, gl::UNIFORM_BLOCK_DATA_SIZE) as usize)
+        })
     }
 
     // utility function for checking shader compilation/linking errors.
     // ------------------------------------------------------------------------
     fn check_compile_errors(&self, shader: GLuint, shader_type: &str) -> Result<(), String>
     {
-        let mut success: GLint = gl::TRUE as GLint;
-
         match shader_type {
             "PROGRAM" => {
-                unsafe { gl::GetProgramiv(shader, gl::LINK_STATUS, &mut success); }
+                let success = gl_api::with(|gl| gl.get_program_iv(shader, gl::LINK_STATUS));
 
                 if success == gl::FALSE as GLint {
                     return Err(format!(
@@ -119,7 +135,7 @@ impl Shader {
                 }
             }
             _ => {
-                unsafe { gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success); }
+                let success = gl_api::with(|gl| gl.get_shader_iv(shader, gl::COMPILE_STATUS));
 
                 if success == gl::FALSE as GLint {
                     return Err(format!(
@@ -133,19 +149,6 @@ impl Shader {
     }
 
     fn get_shader_info_log(&self, shader: GLuint) -> String {
-        let mut len: GLint = 0;
-
-        unsafe {
-            gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
-        }
-
-        let mut info_log: Vec<u8> = Vec::new();
-        info_log.resize(len as usize + 1, 0);
-
-        unsafe {
-            gl::GetShaderInfoLog(shader, len, ptr::null_mut(), info_log.as_ptr() as *mut GLchar);
-        }
-
-        String::from_utf8_lossy(&info_log).to_string()
+        gl_api::with(|gl| gl.get_shader_info_log(shader))
     }
 }
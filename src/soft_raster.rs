@@ -0,0 +1,452 @@
+use std::ops::{Add, Mul};
+use image::{DynamicImage, Rgba, RgbaImage};
+use nalgebra_glm as glm;
+use crate::gl_state::RenderState;
+
+// A rasterizer in plain Rust, to render the simple tutorials without a GPU (see soft_tutorials) and to show what the
+// GPU does between the vertex and the fragment shader. The shaders are closures: the vertex shader returns gl_Position
+// and the outputs for the fragment shader (the varyings), the fragment shader returns the color, or None to discard
+// the fragment.
+//
+// It follows the GL rules where they matter for comparing images: triangles are clipped against the near plane, the
+// pixel centers are at half coordinates with the top-left fill rule, the varyings are interpolated perspective-correct
+// and the framebuffer's first row is the bottom one. Of the render state it knows the depth test, culling (with
+// counter-clockwise front faces) and blending, the polygon mode and the stencil test are ignored. Textures are sampled
+// bilinear without mipmaps, so minified textures look noisier than on the GPU.
+
+// the outputs of the vertex shader, interpolated across the triangle for the fragment shader
+pub trait Varyings: Copy {
+    // the weighted sum of the values at the three corners, the weights add up to one
+    fn interpolate(values: [&Self; 3], weights: [f32; 3]) -> Self;
+}
+
+impl Varyings for () {
+    fn interpolate(_values: [&(); 3], _weights: [f32; 3]) {}
+}
+
+// the vector types interpolate component by component
+fn weighted_sum<T: Copy + Add<Output = T> + Mul<f32, Output = T>>(values: [&T; 3], weights: [f32; 3]) -> T {
+    *values[0] * weights[0] + *values[1] * weights[1] + *values[2] * weights[2]
+}
+
+impl Varyings for f32 {
+    fn interpolate(values: [&f32; 3], weights: [f32; 3]) -> f32 {
+        weighted_sum(values, weights)
+    }
+}
+
+impl Varyings for glm::Vec2 {
+    fn interpolate(values: [&glm::Vec2; 3], weights: [f32; 3]) -> glm::Vec2 {
+        weighted_sum(values, weights)
+    }
+}
+
+impl Varyings for glm::Vec3 {
+    fn interpolate(values: [&glm::Vec3; 3], weights: [f32; 3]) -> glm::Vec3 {
+        weighted_sum(values, weights)
+    }
+}
+
+impl Varyings for glm::Vec4 {
+    fn interpolate(values: [&glm::Vec4; 3], weights: [f32; 3]) -> glm::Vec4 {
+        weighted_sum(values, weights)
+    }
+}
+
+impl<A: Varyings, B: Varyings> Varyings for (A, B) {
+    fn interpolate(values: [&(A, B); 3], weights: [f32; 3]) -> (A, B) {
+        (A::interpolate(values.map(|value| &value.0), weights), B::interpolate(values.map(|value| &value.1), weights))
+    }
+}
+
+impl<A: Varyings, B: Varyings, C: Varyings> Varyings for (A, B, C) {
+    fn interpolate(values: [&(A, B, C); 3], weights: [f32; 3]) -> (A, B, C) {
+        (A::interpolate(values.map(|value| &value.0), weights), B::interpolate(values.map(|value| &value.1), weights),
+         C::interpolate(values.map(|value| &value.2), weights))
+    }
+}
+
+// the color and depth buffer, the viewport always covers all of it
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    // row by row, starting with the bottom one like glReadPixels
+    color: Vec<glm::Vec4>,
+    depth: Vec<f32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            color: vec![glm::vec4(0.0, 0.0, 0.0, 0.0); width * height],
+            depth: vec![1.0; width * height],
+        }
+    }
+
+    // glClearColor and glClear(GL_COLOR_BUFFER_BIT)
+    pub fn clear_color(&mut self, color: &glm::Vec4) {
+        self.color.fill(*color);
+    }
+
+    // glClear(GL_DEPTH_BUFFER_BIT) with the default clear depth of 1.0
+    pub fn clear_depth(&mut self) {
+        self.depth.fill(1.0);
+    }
+
+    // the color buffer with the top row first, like a screenshot of the window
+    pub fn to_image(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let color = self.color[(self.height - 1 - y as usize) * self.width + x as usize];
+            Rgba([color.x, color.y, color.z, color.w].map(|c| (c * 255.0).round() as u8))
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        self.to_image().save(path).map_err(|e| format!("ERROR::SOFT_RASTER::IMAGE_NOT_SUCCESFULLY_WRITTEN: {}: {}", path, e))
+    }
+
+    fn write(&mut self, index: usize, color: glm::Vec4, blend: Option<(u32, u32)>) {
+        let color = match blend {
+            Some((source, destination)) => {
+                let old = self.color[index];
+                color.component_mul(&blend_factor(source, &color, &old)) + old.component_mul(&blend_factor(destination, &color, &old))
+            }
+            None => color,
+        };
+        // the color buffer stores normalized fixed point values
+        self.color[index] = glm::clamp(&color, 0.0, 1.0);
+    }
+}
+
+// the factors of glBlendFunc that need no constant color
+fn blend_factor(factor: u32, source: &glm::Vec4, destination: &glm::Vec4) -> glm::Vec4 {
+    let one = glm::vec4(1.0, 1.0, 1.0, 1.0);
+    match factor {
+        gl::ZERO => glm::vec4(0.0, 0.0, 0.0, 0.0),
+        gl::SRC_COLOR => *source,
+        gl::ONE_MINUS_SRC_COLOR => one - source,
+        gl::DST_COLOR => *destination,
+        gl::ONE_MINUS_DST_COLOR => one - destination,
+        gl::SRC_ALPHA => glm::vec4(source.w, source.w, source.w, source.w),
+        gl::ONE_MINUS_SRC_ALPHA => one * (1.0 - source.w),
+        gl::DST_ALPHA => glm::vec4(destination.w, destination.w, destination.w, destination.w),
+        gl::ONE_MINUS_DST_ALPHA => one * (1.0 - destination.w),
+        _ => one,
+    }
+}
+
+// an RGBA texture with GL_REPEAT wrapping and GL_LINEAR filtering
+pub struct Texture {
+    width: usize,
+    height: usize,
+    // row by row, the first row is the first one of the image, at t = 0 like glTexImage2D
+    texels: Vec<glm::Vec4>,
+}
+
+impl Texture {
+    pub fn from_image(image: &DynamicImage) -> Texture {
+        let image = image.to_rgba32f();
+        Texture {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            texels: image.pixels().map(|pixel| glm::make_vec4(&pixel.0)).collect(),
+        }
+    }
+
+    // texture(sampler, uv) in GLSL
+    pub fn sample(&self, uv: &glm::Vec2) -> glm::Vec4 {
+        // the texel centers are at half coordinates
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |x: f32, y: f32| {
+            let x = (x as i64).rem_euclid(self.width as i64) as usize;
+            let y = (y as i64).rem_euclid(self.height as i64) as usize;
+            self.texels[y * self.width + x]
+        };
+        let bottom = glm::lerp(&texel(x0, y0), &texel(x0 + 1.0, y0), fx);
+        let top = glm::lerp(&texel(x0, y0 + 1.0), &texel(x0 + 1.0, y0 + 1.0), fx);
+        glm::lerp(&bottom, &top, fy)
+    }
+}
+
+// the precision of the window coordinates, in subpixels per pixel
+const SUBPIXELS: f64 = 256.0;
+
+// a vertex after the vertex shader: the clip space position and the varyings
+type ShadedVertex<O> = (glm::Vec4, O);
+
+// glDrawArrays(GL_TRIANGLES, ...), every three vertices make a triangle
+pub fn draw_arrays<V, O: Varyings>(framebuffer: &mut Framebuffer, state: &RenderState, vertices: &[V],
+                                   vertex_shader: impl Fn(&V) -> ShadedVertex<O>,
+                                   fragment_shader: impl Fn(&O) -> Option<glm::Vec4>) {
+    let shaded: Vec<ShadedVertex<O>> = vertices.iter().map(vertex_shader).collect();
+    for triangle in shaded.chunks_exact(3) {
+        draw_triangle(framebuffer, state, [&triangle[0], &triangle[1], &triangle[2]], &fragment_shader);
+    }
+}
+
+// glDrawElements(GL_TRIANGLES, ...), every three indices make a triangle
+pub fn draw_elements<V, O: Varyings>(framebuffer: &mut Framebuffer, state: &RenderState, vertices: &[V], indices: &[u32],
+                                     vertex_shader: impl Fn(&V) -> ShadedVertex<O>,
+                                     fragment_shader: impl Fn(&O) -> Option<glm::Vec4>) {
+    // every vertex is shaded once, however many triangles share it
+    let shaded: Vec<ShadedVertex<O>> = vertices.iter().map(vertex_shader).collect();
+    for triangle in indices.chunks_exact(3) {
+        let corners = [triangle[0], triangle[1], triangle[2]].map(|index| &shaded[index as usize]);
+        draw_triangle(framebuffer, state, corners, &fragment_shader);
+    }
+}
+
+fn draw_triangle<O: Varyings>(framebuffer: &mut Framebuffer, state: &RenderState, corners: [&ShadedVertex<O>; 3],
+                              fragment_shader: &impl Fn(&O) -> Option<glm::Vec4>) {
+    // clip against the near plane (z >= -w), which keeps w positive for the perspective division. The other planes
+    // only limit the pixels that are looked at, see rasterize.
+    let inside = |vertex: &ShadedVertex<O>| vertex.0.z >= -vertex.0.w;
+    let mut polygon: Vec<ShadedVertex<O>> = Vec::with_capacity(4);
+    for i in 0..3 {
+        let (current, next) = (corners[i], corners[(i + 1) % 3]);
+        if inside(current) {
+            polygon.push(*current);
+        }
+        if inside(current) != inside(next) {
+            // where the edge crosses z = -w
+            let (a, b) = (current.0.z + current.0.w, next.0.z + next.0.w);
+            let t = a / (a - b);
+            let position = glm::lerp(&current.0, &next.0, t);
+            polygon.push((position, O::interpolate([&current.1, &next.1, &next.1], [1.0 - t, t, 0.0])));
+        }
+    }
+
+    for i in 1..polygon.len().saturating_sub(1) {
+        rasterize(framebuffer, state, [&polygon[0], &polygon[i], &polygon[i + 1]], fragment_shader);
+    }
+}
+
+fn rasterize<O: Varyings>(framebuffer: &mut Framebuffer, state: &RenderState, corners: [&ShadedVertex<O>; 3],
+                          fragment_shader: &impl Fn(&O) -> Option<glm::Vec4>) {
+    let (width, height) = (framebuffer.width as f32, framebuffer.height as f32);
+
+    // the perspective division and the viewport transformation, with the depth range [0, 1]. Like GPUs, the positions
+    // snap to a grid of subpixels, with the edge functions in f64 that makes them exact, so a pixel center on the edge
+    // shared by two triangles is seen on the same side of it by both.
+    let snap = |value: f32| (value as f64 * SUBPIXELS).round() / SUBPIXELS;
+    let inverse_w = corners.map(|corner| 1.0 / corner.0.w);
+    let mut window = [[0.0; 2]; 3];
+    let mut depths = [0.0; 3];
+    for i in 0..3 {
+        let ndc = corners[i].0.xyz() * inverse_w[i];
+        window[i] = [snap((ndc.x + 1.0) / 2.0 * width), snap((ndc.y + 1.0) / 2.0 * height)];
+        depths[i] = (ndc.z + 1.0) / 2.0;
+    }
+
+    // twice the signed area, positive when the corners are counter-clockwise on screen
+    let edge = |a: &[f64; 2], b: &[f64; 2], x: f64, y: f64| (b[0] - a[0]) * (y - a[1]) - (b[1] - a[1]) * (x - a[0]);
+    let area = edge(&window[0], &window[1], window[2][0], window[2][1]);
+    if area == 0.0 {
+        return;
+    }
+    let front_facing = area > 0.0;
+    if let Some(face) = state.cull_face {
+        if face == gl::FRONT_AND_BACK || (face == gl::FRONT) == front_facing {
+            return;
+        }
+    }
+
+    // the pixels whose centers can be inside the triangle
+    let min = |i: usize| window.iter().map(|corner| corner[i]).fold(f64::INFINITY, f64::min);
+    let max = |i: usize| window.iter().map(|corner| corner[i]).fold(f64::NEG_INFINITY, f64::max);
+    let x_range = (min(0) - 0.5).ceil().max(0.0) as usize..((max(0) - 0.5).floor() + 1.0).clamp(0.0, width as f64) as usize;
+    let y_range = (min(1) - 0.5).ceil().max(0.0) as usize..((max(1) - 0.5).floor() + 1.0).clamp(0.0, height as f64) as usize;
+
+    // the edge opposite of each corner, turned so that the inside is positive. A pixel center right on an edge belongs
+    // to the triangle if the edge is a top edge or a left edge, so two triangles sharing an edge never both draw it.
+    let sign = if front_facing { 1.0 } else { -1.0 };
+    let edges = [(1, 2), (2, 0), (0, 1)];
+    let owns_edge = edges.map(|(a, b)| {
+        let (dx, dy) = ((window[b][0] - window[a][0]) * sign, (window[b][1] - window[a][1]) * sign);
+        // with y up and the inside on the left, a top edge goes left and a left edge goes down
+        (dy == 0.0 && dx < 0.0) || dy < 0.0
+    });
+
+    for y in y_range {
+        for x in x_range.clone() {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            let mut barycentric = [0.0; 3];
+            let mut covered = true;
+            for (i, &(a, b)) in edges.iter().enumerate() {
+                let value = edge(&window[a], &window[b], px, py) * sign;
+                if value < 0.0 || (value == 0.0 && !owns_edge[i]) {
+                    covered = false;
+                    break;
+                }
+                barycentric[i] = (value / (area * sign)) as f32;
+            }
+            if !covered {
+                continue;
+            }
+
+            // the depth is linear in screen space
+            let depth = barycentric[0] * depths[0] + barycentric[1] * depths[1] + barycentric[2] * depths[2];
+            if !(0.0..=1.0).contains(&depth) {
+                continue;
+            }
+            let index = y * framebuffer.width + x;
+            if state.depth_test && !depth_test_passes(state.depth_func, depth, framebuffer.depth[index]) {
+                continue;
+            }
+
+            // the varyings are not, they are linear in clip space
+            let weights = [0, 1, 2].map(|i| barycentric[i] * inverse_w[i]);
+            let sum: f32 = weights.iter().sum();
+            let varyings = O::interpolate(corners.map(|corner| &corner.1), weights.map(|weight| weight / sum));
+
+            if let Some(color) = fragment_shader(&varyings) {
+                framebuffer.write(index, color, state.blend);
+                // without the depth test the depth buffer isn't written either
+                if state.depth_test && state.depth_write {
+                    framebuffer.depth[index] = depth;
+                }
+            }
+        }
+    }
+}
+
+fn depth_test_passes(func: u32, depth: f32, stored: f32) -> bool {
+    match func {
+        gl::NEVER => false,
+        gl::LESS => depth < stored,
+        gl::EQUAL => depth == stored,
+        gl::LEQUAL => depth <= stored,
+        gl::GREATER => depth > stored,
+        gl::NOTEQUAL => depth != stored,
+        gl::GEQUAL => depth >= stored,
+        _ => true,
+    }
+}
+
+// reflect(i, n) in GLSL
+pub fn reflect(incident: &glm::Vec3, normal: &glm::Vec3) -> glm::Vec3 {
+    incident - normal * 2.0 * glm::dot(normal, incident)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: glm::Vec4 = glm::Vec4::new(1.0, 0.0, 0.0, 1.0);
+    const GREEN: glm::Vec4 = glm::Vec4::new(0.0, 1.0, 0.0, 1.0);
+    const CLEAR: glm::Vec4 = glm::Vec4::new(0.0, 0.0, 0.0, 0.0);
+
+    // the color of a pixel, y counts from the bottom
+    fn color_at(framebuffer: &Framebuffer, x: usize, y: usize) -> glm::Vec4 {
+        framebuffer.color[y * framebuffer.width + x]
+    }
+
+    // draws the triangles (in normalized device coordinates) in a single color
+    fn draw(framebuffer: &mut Framebuffer, state: &RenderState, triangles: &[[f32; 3]], color: glm::Vec4) {
+        draw_arrays(framebuffer, state, triangles, |position| (glm::vec4(position[0], position[1], position[2], 1.0), ()),
+                    |_| Some(color));
+    }
+
+    // adds up the colors, so a pixel drawn twice shows it
+    fn additive() -> RenderState {
+        RenderState { blend: Some((gl::ONE, gl::ONE)), ..RenderState::default() }
+    }
+
+    #[test]
+    fn a_triangle_covers_the_pixels_with_their_centers_inside() {
+        // the lower left half of an 8x8 framebuffer, the centers on the diagonal are on the edge
+        let mut framebuffer = Framebuffer::new(8, 8);
+        draw(&mut framebuffer, &RenderState::default(), &[[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, 1.0, 0.0]], RED);
+        for y in 0..8 {
+            for x in 0..8 {
+                // the diagonal goes up to the left, so it is neither a top nor a left edge
+                let expected = if x + y < 7 { RED } else { CLEAR };
+                assert_eq!(color_at(&framebuffer, x, y), expected, "{x}, {y}");
+            }
+        }
+
+        // the same triangle clockwise is drawn too without culling, and not at all when back faces are culled
+        let clockwise = [[-1.0, -1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0]];
+        let mut framebuffer = Framebuffer::new(8, 8);
+        draw(&mut framebuffer, &RenderState::default(), &clockwise, RED);
+        assert_eq!(color_at(&framebuffer, 0, 0), RED);
+        let mut framebuffer = Framebuffer::new(8, 8);
+        draw(&mut framebuffer, &RenderState { cull_face: Some(gl::BACK), ..RenderState::default() }, &clockwise, RED);
+        assert_eq!(color_at(&framebuffer, 0, 0), CLEAR);
+    }
+
+    #[test]
+    fn the_depth_test_keeps_the_nearest_fragment() {
+        let quad = |z: f32| [[-1.0, -1.0, z], [1.0, -1.0, z], [1.0, 1.0, z], [1.0, 1.0, z], [-1.0, 1.0, z], [-1.0, -1.0, z]];
+        let depth_test = RenderState { depth_test: true, ..RenderState::default() };
+
+        // the far quad comes second and is rejected, or first and is covered
+        for (first, second) in [((0.0, RED), (0.5, GREEN)), ((0.5, GREEN), (0.0, RED))] {
+            let mut framebuffer = Framebuffer::new(4, 4);
+            framebuffer.clear_depth();
+            draw(&mut framebuffer, &depth_test, &quad(first.0), first.1);
+            draw(&mut framebuffer, &depth_test, &quad(second.0), second.1);
+            assert!(framebuffer.color.iter().all(|&color| color == RED));
+        }
+
+        // without it, the last one wins
+        let mut framebuffer = Framebuffer::new(4, 4);
+        draw(&mut framebuffer, &RenderState::default(), &quad(0.0), RED);
+        draw(&mut framebuffer, &RenderState::default(), &quad(0.5), GREEN);
+        assert!(framebuffer.color.iter().all(|&color| color == GREEN));
+    }
+
+    #[test]
+    fn pixels_on_a_shared_edge_go_to_the_left_and_top_edges() {
+        // a vertical edge through the centers of the third column: the triangle on the right of it draws them
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let x = 2.5 / 4.0 * 2.0 - 1.0;
+        draw(&mut framebuffer, &additive(), &[[-1.0, -1.0, 0.0], [x, -1.0, 0.0], [x, 1.0, 0.0]], RED);
+        draw(&mut framebuffer, &additive(), &[[x, -1.0, 0.0], [1.0, -1.0, 0.0], [x, 1.0, 0.0]], GREEN);
+        for y in 0..4 {
+            assert_eq!(color_at(&framebuffer, 2, y), GREEN, "{y}");
+        }
+
+        // a horizontal edge through the centers of the third row: the triangle below it draws them
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let y = 2.5 / 4.0 * 2.0 - 1.0;
+        draw(&mut framebuffer, &additive(), &[[-1.0, -1.0, 0.0], [1.0, y, 0.0], [-1.0, y, 0.0]], RED);
+        draw(&mut framebuffer, &additive(), &[[-1.0, y, 0.0], [1.0, y, 0.0], [1.0, 1.0, 0.0]], GREEN);
+        for x in 0..3 {
+            assert_eq!(color_at(&framebuffer, x, 2), RED, "{x}");
+        }
+    }
+
+    #[test]
+    fn shared_edges_leave_no_gaps() {
+        // a fan of triangles with edges at odd angles, every pixel inside of the fan has to be drawn exactly once
+        let corners: Vec<[f32; 3]> = (0..7).map(|i| {
+            let angle = (i as f32 * 51.0 + 10.0).to_radians();
+            [0.9 * angle.cos(), 0.85 * angle.sin(), 0.0]
+        }).collect();
+        let center = [0.03, -0.02, 0.0];
+        let triangles: Vec<[f32; 3]> = (0..7).flat_map(|i| [center, corners[i], corners[(i + 1) % 7]]).collect();
+
+        let mut framebuffer = Framebuffer::new(37, 29);
+        let quarter = glm::vec4(0.25, 0.25, 0.25, 0.25);
+        draw(&mut framebuffer, &additive(), &triangles, quarter);
+
+        let mut inside = 0;
+        for color in &framebuffer.color {
+            assert!(*color == quarter || *color == CLEAR, "{color:?}");
+            inside += (*color == quarter) as usize;
+        }
+        assert!(inside > 0);
+        // the whole fan drawn as one polygon covers the same pixels
+        let mut outline = Framebuffer::new(37, 29);
+        let polygon: Vec<[f32; 3]> = (1..6).flat_map(|i| [corners[0], corners[i], corners[i + 1]]).collect();
+        draw(&mut outline, &additive(), &polygon, quarter);
+        assert_eq!(outline.color.iter().filter(|&&color| color == quarter).count(), inside);
+    }
+}
@@ -0,0 +1,340 @@
+use nalgebra_glm as glm;
+use crate::camera::Camera;
+use crate::gl_state::RenderState;
+use crate::soft_raster::{self, Framebuffer, Texture};
+
+// Some of the tutorials again, drawn by the software rasterizer in soft_raster: the same vertices, transformations and
+// clear colors, and shader closures that mirror the GLSL files line by line. The images are what the window shows
+// after the first frame (at a fixed time for the animated ones), to compare the GL output against:
+//
+//     cargo run soft 1.6.2 container_cube.png
+//     cargo run soft all reference_images
+//
+// The tests render them at a smaller size and compare them with resources/soft_snapshots, earlier output of the
+// rasterizer itself. These regression snapshots catch changes to what it draws, they don't check it against GL. Run
+// the tests with UPDATE_SOFT_SNAPSHOTS=1 to write new ones after changing what a tutorial draws.
+
+// settings
+const SCR_WIDTH: usize = 800;
+const SCR_HEIGHT: usize = 600;
+
+// the time the animated tutorials are rendered at, in seconds
+const TIME: f32 = 1.0;
+
+// renders a tutorial into a framebuffer of the given size, at the given time
+type Render = fn(usize, usize, f32) -> Result<Framebuffer, String>;
+
+const TUTORIALS: [(&str, Render); 4] = [
+    ("1.2.1", render_1_2_1),
+    ("1.4.1", render_1_4_1),
+    ("1.6.2", render_1_6_2),
+    ("2.2.2", render_2_2_2),
+];
+
+// renders a tutorial to an image file (named after the tutorial by default), or all of them to a directory
+pub fn render(args: &[String]) {
+    let find = |name: &str| TUTORIALS.iter().find(|(tutorial, _)| *tutorial == name).map(|&(_, render)| render);
+    let images: Vec<(Render, String)> = match args {
+        [name, ..] if name == "all" && args.len() <= 2 => {
+            let directory = args.get(1).map_or(".", String::as_str);
+            if let Err(e) = std::fs::create_dir_all(directory) {
+                eprintln!("ERROR::SOFT_RASTER::DIRECTORY_NOT_SUCCESFULLY_CREATED: {}: {}", directory, e);
+                std::process::exit(1);
+            }
+            TUTORIALS.iter().map(|&(name, render)| (render, format!("{directory}/{name}.png"))).collect()
+        }
+        [name, ..] if find(name).is_some() && args.len() <= 2 => {
+            vec![(find(name).unwrap(), args.get(1).cloned().unwrap_or_else(|| format!("{name}.png")))]
+        }
+        _ => {
+            let names: Vec<&str> = TUTORIALS.iter().map(|(name, _)| *name).collect();
+            eprintln!("usage: soft <{}|all> [<image file or directory>]", names.join("|"));
+            std::process::exit(2);
+        }
+    };
+
+    for (render, path) in images {
+        match render(SCR_WIDTH, SCR_HEIGHT, TIME).and_then(|framebuffer| framebuffer.save(&path).map(|_| framebuffer)) {
+            Ok(framebuffer) => println!("{path} ({}x{})", framebuffer.width, framebuffer.height),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn load_texture(path: &str, flip: bool) -> Result<Texture, String> {
+    match image::open(path) {
+        Ok(image) => Ok(Texture::from_image(&if flip { image.flipv() } else { image })),
+        Err(e) => Err(format!("ERROR::SOFT_RASTER::TEXTURE_NOT_SUCCESFULLY_LOADED: {}: {}", path, e)),
+    }
+}
+
+// 1.2.1 Hello Triangle, an orange triangle
+fn render_1_2_1(width: usize, height: usize, _time: f32) -> Result<Framebuffer, String> {
+    let vertices: [[f32; 3]; 3] = [
+        [-0.5, -0.5, 0.0], // left
+        [ 0.5, -0.5, 0.0], // right
+        [ 0.0,  0.5, 0.0], // top
+    ];
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    framebuffer.clear_color(&glm::vec4(0.2, 0.3, 0.3, 1.0));
+
+    soft_raster::draw_arrays(&mut framebuffer, &RenderState::default(), &vertices,
+        // gl_Position = vec4(aPos.x, aPos.y, aPos.z, 1.0);
+        |a_pos| (glm::vec4(a_pos[0], a_pos[1], a_pos[2], 1.0), ()),
+        // FragColor = vec4(1.0f, 0.5f, 0.2f, 1.0f);
+        |_| Some(glm::vec4(1.0, 0.5, 0.2, 1.0)));
+
+    Ok(framebuffer)
+}
+
+// 1.4.1 Textures, a quad with the container texture (upside down, the image isn't flipped in that tutorial yet)
+fn render_1_4_1(width: usize, height: usize, _time: f32) -> Result<Framebuffer, String> {
+    let vertices: [[f32; 8]; 4] = [
+        // positions       // colors        // texture coords
+        [ 0.5,  0.5, 0.0,  1.0, 0.0, 0.0,  1.0, 1.0], // top right
+        [ 0.5, -0.5, 0.0,  0.0, 1.0, 0.0,  1.0, 0.0], // bottom right
+        [-0.5, -0.5, 0.0,  0.0, 0.0, 1.0,  0.0, 0.0], // bottom left
+        [-0.5,  0.5, 0.0,  1.0, 1.0, 0.0,  0.0, 1.0], // top left
+    ];
+    let indices: [u32; 6] = [
+        0, 1, 3, // first Triangle
+        1, 2, 3, // second Triangle
+    ];
+    let our_texture = load_texture("resources/textures/container.jpg", false)?;
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    framebuffer.clear_color(&glm::vec4(0.2, 0.3, 0.3, 1.0));
+
+    // 4.1.shader.vert and 4.1.shader.frag
+    soft_raster::draw_elements(&mut framebuffer, &RenderState::default(), &vertices, &indices,
+        |vertex| {
+            let (a_pos, a_color, a_tex_coord) = (glm::vec3(vertex[0], vertex[1], vertex[2]),
+                                                 glm::vec3(vertex[3], vertex[4], vertex[5]), glm::vec2(vertex[6], vertex[7]));
+            let gl_position = glm::vec4(a_pos.x, a_pos.y, a_pos.z, 1.0);
+            let our_color = a_color;
+            let tex_coord = a_tex_coord;
+            (gl_position, (our_color, tex_coord))
+        },
+        |&(_our_color, tex_coord)| Some(our_texture.sample(&tex_coord)));
+
+    Ok(framebuffer)
+}
+
+// 1.6.2 Coordinate Systems with depth, a rotating cube with the container and the awesome face mixed
+fn render_1_6_2(width: usize, height: usize, time: f32) -> Result<Framebuffer, String> {
+    let vertices: [[f32; 5]; 36] = [
+    [-0.5, -0.5, -0.5,  0.0,  0.0],
+    [ 0.5, -0.5, -0.5,  1.0,  0.0],
+    [ 0.5,  0.5, -0.5,  1.0,  1.0],
+    [ 0.5,  0.5, -0.5,  1.0,  1.0],
+    [-0.5,  0.5, -0.5,  0.0,  1.0],
+    [-0.5, -0.5, -0.5,  0.0,  0.0],
+
+    [-0.5, -0.5,  0.5,  0.0,  0.0],
+    [ 0.5, -0.5,  0.5,  1.0,  0.0],
+    [ 0.5,  0.5,  0.5,  1.0,  1.0],
+    [ 0.5,  0.5,  0.5,  1.0,  1.0],
+    [-0.5,  0.5,  0.5,  0.0,  1.0],
+    [-0.5, -0.5,  0.5,  0.0,  0.0],
+
+    [-0.5,  0.5,  0.5,  1.0,  0.0],
+    [-0.5,  0.5, -0.5,  1.0,  1.0],
+    [-0.5, -0.5, -0.5,  0.0,  1.0],
+    [-0.5, -0.5, -0.5,  0.0,  1.0],
+    [-0.5, -0.5,  0.5,  0.0,  0.0],
+    [-0.5,  0.5,  0.5,  1.0,  0.0],
+
+    [ 0.5,  0.5,  0.5,  1.0,  0.0],
+    [ 0.5,  0.5, -0.5,  1.0,  1.0],
+    [ 0.5, -0.5, -0.5,  0.0,  1.0],
+    [ 0.5, -0.5, -0.5,  0.0,  1.0],
+    [ 0.5, -0.5,  0.5,  0.0,  0.0],
+    [ 0.5,  0.5,  0.5,  1.0,  0.0],
+
+    [-0.5, -0.5, -0.5,  0.0,  1.0],
+    [ 0.5, -0.5, -0.5,  1.0,  1.0],
+    [ 0.5, -0.5,  0.5,  1.0,  0.0],
+    [ 0.5, -0.5,  0.5,  1.0,  0.0],
+    [-0.5, -0.5,  0.5,  0.0,  0.0],
+    [-0.5, -0.5, -0.5,  0.0,  1.0],
+
+    [-0.5,  0.5, -0.5,  0.0,  1.0],
+    [ 0.5,  0.5, -0.5,  1.0,  1.0],
+    [ 0.5,  0.5,  0.5,  1.0,  0.0],
+    [ 0.5,  0.5,  0.5,  1.0,  0.0],
+    [-0.5,  0.5,  0.5,  0.0,  0.0],
+    [-0.5,  0.5, -0.5,  0.0,  1.0],
+    ];
+    let texture1 = load_texture("resources/textures/container.jpg", true)?;
+    let texture2 = load_texture("resources/textures/awesomeface.png", true)?;
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    framebuffer.clear_color(&glm::vec4(0.2, 0.3, 0.3, 1.0));
+    framebuffer.clear_depth();
+
+    // create transformations, with glm::perspective like the tutorial, which comes before the camera class
+    let model = glm::rotate(&glm::identity(), time * f32::to_radians(50.0), &glm::vec3(0.5, 1.0, 0.0));
+    let view = glm::translate(&glm::identity(), &glm::vec3(0.0_f32, 0.0, -3.0));
+    let projection = glm::perspective(width as f32 / height as f32, f32::to_radians(45.0), 0.1, 100.0);
+
+    // 6.1.shader.vert and 5.1.shader.frag
+    let state = RenderState { depth_test: true, ..RenderState::default() };
+    soft_raster::draw_arrays(&mut framebuffer, &state, &vertices,
+        |vertex| {
+            let (a_pos, a_tex_coord) = (glm::vec3(vertex[0], vertex[1], vertex[2]), glm::vec2(vertex[3], vertex[4]));
+            let gl_position = projection * view * model * glm::vec4(a_pos.x, a_pos.y, a_pos.z, 1.0);
+            let tex_coord = glm::vec2(a_tex_coord.x, a_tex_coord.y);
+            (gl_position, tex_coord)
+        },
+        |tex_coord| Some(glm::mix(&texture1.sample(tex_coord), &texture2.sample(tex_coord), 0.2)));
+
+    Ok(framebuffer)
+}
+
+// 2.2.2 Basic Lighting with specular, a Phong lit cube and the lamp
+fn render_2_2_2(width: usize, height: usize, _time: f32) -> Result<Framebuffer, String> {
+    let vertices: [[f32; 6]; 36] = [
+    [-0.5, -0.5, -0.5,  0.0,  0.0, -1.0],
+    [ 0.5, -0.5, -0.5,  0.0,  0.0, -1.0],
+    [ 0.5,  0.5, -0.5,  0.0,  0.0, -1.0],
+    [ 0.5,  0.5, -0.5,  0.0,  0.0, -1.0],
+    [-0.5,  0.5, -0.5,  0.0,  0.0, -1.0],
+    [-0.5, -0.5, -0.5,  0.0,  0.0, -1.0],
+
+    [-0.5, -0.5,  0.5,  0.0,  0.0,  1.0],
+    [ 0.5, -0.5,  0.5,  0.0,  0.0,  1.0],
+    [ 0.5,  0.5,  0.5,  0.0,  0.0,  1.0],
+    [ 0.5,  0.5,  0.5,  0.0,  0.0,  1.0],
+    [-0.5,  0.5,  0.5,  0.0,  0.0,  1.0],
+    [-0.5, -0.5,  0.5,  0.0,  0.0,  1.0],
+
+    [-0.5,  0.5,  0.5, -1.0,  0.0,  0.0],
+    [-0.5,  0.5, -0.5, -1.0,  0.0,  0.0],
+    [-0.5, -0.5, -0.5, -1.0,  0.0,  0.0],
+    [-0.5, -0.5, -0.5, -1.0,  0.0,  0.0],
+    [-0.5, -0.5,  0.5, -1.0,  0.0,  0.0],
+    [-0.5,  0.5,  0.5, -1.0,  0.0,  0.0],
+
+    [ 0.5,  0.5,  0.5,  1.0,  0.0,  0.0],
+    [ 0.5,  0.5, -0.5,  1.0,  0.0,  0.0],
+    [ 0.5, -0.5, -0.5,  1.0,  0.0,  0.0],
+    [ 0.5, -0.5, -0.5,  1.0,  0.0,  0.0],
+    [ 0.5, -0.5,  0.5,  1.0,  0.0,  0.0],
+    [ 0.5,  0.5,  0.5,  1.0,  0.0,  0.0],
+
+    [-0.5, -0.5, -0.5,  0.0, -1.0,  0.0],
+    [ 0.5, -0.5, -0.5,  0.0, -1.0,  0.0],
+    [ 0.5, -0.5,  0.5,  0.0, -1.0,  0.0],
+    [ 0.5, -0.5,  0.5,  0.0, -1.0,  0.0],
+    [-0.5, -0.5,  0.5,  0.0, -1.0,  0.0],
+    [-0.5, -0.5, -0.5,  0.0, -1.0,  0.0],
+
+    [-0.5,  0.5, -0.5,  0.0,  1.0,  0.0],
+    [ 0.5,  0.5, -0.5,  0.0,  1.0,  0.0],
+    [ 0.5,  0.5,  0.5,  0.0,  1.0,  0.0],
+    [ 0.5,  0.5,  0.5,  0.0,  1.0,  0.0],
+    [-0.5,  0.5,  0.5,  0.0,  1.0,  0.0],
+    [-0.5,  0.5, -0.5,  0.0,  1.0,  0.0],
+    ];
+
+    // camera
+    let mut camera = Camera {
+        position: glm::vec3(0.0, 0.0, 3.0),
+        ..Camera::default()
+    };
+    camera.process_framebuffer_size(width as i32, height as i32);
+
+    // lighting
+    let light_pos = glm::vec3::<f32>(1.2, 1.0, 2.0);
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    framebuffer.clear_color(&glm::vec4(0.1, 0.1, 0.1, 1.0));
+    framebuffer.clear_depth();
+    let state = RenderState { depth_test: true, ..RenderState::default() };
+
+    // view/projection transformations
+    let projection = camera.get_projection_matrix();
+    let view = camera.get_view_matrix();
+
+    // the cube, 2.2.basic_lighting.vert and 2.2.basic_lighting.frag
+    let model: glm::Mat4 = glm::identity();
+    let normal_matrix = glm::mat4_to_mat3(&glm::transpose(&glm::inverse(&model)));
+    let (object_color, light_color, view_pos) = (glm::vec3(1.0, 0.5, 0.31), glm::vec3(1.0, 1.0, 1.0), camera.position);
+    soft_raster::draw_arrays(&mut framebuffer, &state, &vertices,
+        |vertex| {
+            let (a_pos, a_normal) = (glm::vec3(vertex[0], vertex[1], vertex[2]), glm::vec3(vertex[3], vertex[4], vertex[5]));
+            let frag_pos = (model * glm::vec4(a_pos.x, a_pos.y, a_pos.z, 1.0)).xyz();
+            let normal = normal_matrix * a_normal;
+            let gl_position = projection * view * glm::vec4(frag_pos.x, frag_pos.y, frag_pos.z, 1.0);
+            (gl_position, (frag_pos, normal))
+        },
+        |&(frag_pos, normal)| {
+            // ambient
+            let ambient_strength = 0.1;
+            let ambient = ambient_strength * light_color;
+
+            // diffuse
+            let norm = glm::normalize(&normal);
+            let light_dir = glm::normalize(&(light_pos - frag_pos));
+            let diff = glm::dot(&norm, &light_dir).max(0.0);
+            let diffuse = diff * light_color;
+
+            // specular
+            let specular_strength = 0.5;
+            let view_dir = glm::normalize(&(view_pos - frag_pos));
+            let reflect_dir = soft_raster::reflect(&-light_dir, &norm);
+            let spec = glm::dot(&view_dir, &reflect_dir).max(0.0).powf(32.0);
+            let specular = specular_strength * spec * light_color;
+
+            let result = (ambient + diffuse + specular).component_mul(&object_color);
+            Some(glm::vec4(result.x, result.y, result.z, 1.0))
+        });
+
+    // also draw the lamp object, 1.light_cube.vert and 1.light_cube.frag
+    let mut model = glm::translate(&glm::identity(), &light_pos);
+    model = glm::scale(&model, &glm::vec3(0.2, 0.2, 0.2));
+    soft_raster::draw_arrays(&mut framebuffer, &state, &vertices,
+        |vertex| (projection * view * model * glm::vec4(vertex[0], vertex[1], vertex[2], 1.0), ()),
+        // set all 4 vector values to 1.0
+        |_| Some(glm::vec4(1.0, 1.0, 1.0, 1.0)));
+
+    Ok(framebuffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // small enough to keep the tests fast and the snapshots in the repository small
+    const WIDTH: usize = 160;
+    const HEIGHT: usize = 120;
+
+    // a channel may be off by this much, rounding differs between platforms
+    const TOLERANCE: u8 = 2;
+    // and this many pixels may be off by more, on edges a pixel center can land on either side
+    const OUTLIERS: f32 = 0.005;
+
+    #[test]
+    fn tutorials_match_their_snapshots() {
+        for (name, render) in TUTORIALS {
+            let image = render(WIDTH, HEIGHT, TIME).unwrap().to_image();
+            let path = format!("resources/soft_snapshots/{name}.png");
+            if std::env::var_os("UPDATE_SOFT_SNAPSHOTS").is_some() {
+                std::fs::create_dir_all("resources/soft_snapshots").unwrap();
+                image.save(&path).unwrap();
+                continue;
+            }
+
+            let snapshot = image::open(&path).unwrap_or_else(|e| panic!("{path}: {e}")).to_rgba8();
+            assert_eq!(snapshot.dimensions(), image.dimensions(), "{name}");
+            let outliers = image.pixels().zip(snapshot.pixels())
+                .filter(|(pixel, expected)| pixel.0.iter().zip(expected.0).any(|(&c, e)| c.abs_diff(e) > TOLERANCE))
+                .count();
+            assert!(outliers as f32 <= OUTLIERS * (WIDTH * HEIGHT) as f32, "{name}: {outliers} pixels differ");
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::marker::PhantomData;
+use std::mem;
+use gl::types::*;
+use nalgebra_glm as glm;
+use memoffset::offset_of;
+use crate::gl_debug::{self, gl_check};
+use crate::gl_state;
+use crate::shader::Shader;
+
+// A vec3 as it is stored in a std140 uniform block: aligned to 16 bytes and followed by 4 bytes of padding, unless the
+// next member is a float which is allowed to fill the gap (then use a plain glm::Vec3 instead)
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Default)]
+pub struct Std140Vec3(pub glm::Vec3);
+
+impl From<glm::Vec3> for Std140Vec3 {
+    fn from(value: glm::Vec3) -> Std140Vec3 {
+        Std140Vec3(value)
+    }
+}
+
+/// Implemented by #[repr(C)] structs that mirror a std140 uniform block, so the struct can be copied into a uniform
+/// buffer as it is.
+///
+/// # Safety
+///
+/// The struct has to be #[repr(C)], may only contain plain data (floats, ints, glm vectors and matrices,
+/// arrays of them) and has to place every member at the offset std140 requires, which assert_layout checks.
+pub unsafe trait Std140: Sized {
+    // the name of the uniform block in the shaders
+    const BLOCK_NAME: &'static str;
+
+    // panics if a member isn't at its std140 offset, see check_offset
+    fn assert_layout();
+}
+
+// compares the offset of a struct member with the offset std140 gives it in the block
+pub fn check_offset(block: &str, member: &str, offset: usize, std140_offset: usize) {
+    assert_eq!(offset, std140_offset,
+               "ERROR::UNIFORM_BUFFER::LAYOUT_MISMATCH: {}.{} is at offset {} but std140 expects {}",
+               block, member, offset, std140_offset);
+}
+
+// A uniform buffer object holding one T, bound to a fixed binding point. Every shader that declares the uniform block
+// reads from the same buffer, so the data only has to be uploaded once per frame instead of once per shader.
+pub struct UniformBuffer<T: Std140> {
+    pub id: GLuint,
+    pub binding: GLuint,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Std140> UniformBuffer<T> {
+    // creates the buffer and binds it to the binding point
+    pub fn new(binding: GLuint) -> UniformBuffer<T> {
+        T::assert_layout();
+
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+            gl_state::with(|gl| gl.bind_buffer(gl::UNIFORM_BUFFER, id));
+            gl::BufferData(gl::UNIFORM_BUFFER, mem::size_of::<T>() as GLsizeiptr, std::ptr::null(), gl::DYNAMIC_DRAW);
+            gl_debug::label(gl::BUFFER, id, T::BLOCK_NAME);
+
+            // link the whole buffer to the binding point (which binds it to gl::UNIFORM_BUFFER too, as the tracker expects)
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, id);
+        }
+
+        UniformBuffer { id, binding, phantom: PhantomData }
+    }
+
+    // links the uniform block of every shader that declares it to the binding point of this buffer. The size of the
+    // block as the shader compiler laid it out is compared with the size of T, to catch a struct that no longer matches.
+    pub fn bind_to_shaders(&self, shaders: &[&Shader]) {
+        for shader in shaders {
+            if let Some(size) = shader.bind_uniform_block(T::BLOCK_NAME, self.binding) {
+                if size != mem::size_of::<T>() {
+                    eprintln!("ERROR::UNIFORM_BUFFER::SIZE_MISMATCH: block {} has {} bytes in shader {} but {} in Rust",
+                              T::BLOCK_NAME, size, shader.id, mem::size_of::<T>());
+                }
+            }
+        }
+    }
+
+    // uploads the whole block
+    pub fn update(&self, data: &T) {
+        gl_state::with(|gl| gl.bind_buffer(gl::UNIFORM_BUFFER, self.id));
+        unsafe {
+            gl_check!(gl::BufferSubData(gl::UNIFORM_BUFFER, 0, mem::size_of::<T>() as GLsizeiptr, data as *const T as *const GLvoid));
+        }
+    }
+}
+
+// The camera matrices shared by all shaders of a scene:
+//
+// layout (std140) uniform Matrices
+// {
+//     mat4 projection;
+//     mat4 view;
+//     vec3 viewPos;
+// };
+#[repr(C)]
+pub struct CameraMatrices {
+    pub projection: glm::Mat4,
+    pub view: glm::Mat4,
+    pub view_pos: Std140Vec3,
+}
+
+unsafe impl Std140 for CameraMatrices {
+    const BLOCK_NAME: &'static str = "Matrices";
+
+    fn assert_layout() {
+        check_offset(Self::BLOCK_NAME, "projection", offset_of!(CameraMatrices, projection), 0);
+        check_offset(Self::BLOCK_NAME, "view", offset_of!(CameraMatrices, view), 64);
+        check_offset(Self::BLOCK_NAME, "viewPos", offset_of!(CameraMatrices, view_pos), 128);
+    }
+}